@@ -0,0 +1,93 @@
+//! Runs a broker in process with a producer publishing random numbers to `testing_topic` and a
+//! client consuming them over the flow protocol. Stop it with Ctrl-C.
+//!
+//! ```sh
+//! cargo run --example demo
+//! ```
+
+use std::error::Error;
+use std::sync::Mutex;
+
+use futures::FutureExt;
+use tokio::time::Duration;
+use tracing::{debug, info};
+
+use packline_cli::client::connect;
+use packline_core::{
+    app::{channel::Record, App, ChannelConfig},
+    connector::{Connector, TCPConnector, TCPListenerConfig},
+};
+use packline_flow::connector::FlowConnector;
+use rand::{rngs::StdRng, Rng, SeedableRng};
+
+#[tokio::main]
+async fn main() -> Result<(), Box<dyn Error + Send + Sync + 'static>> {
+    tracing_subscriber::fmt()
+        .with_max_level(tracing::Level::DEBUG)
+        .try_init()?;
+
+    let (tx, rx) = tokio::sync::oneshot::channel();
+    let tx = Mutex::new(Some(tx));
+    ctrlc::set_handler(move || {
+        if let Some(tx) = tx.lock().unwrap().take() {
+            let _ = tx.send(true);
+        }
+    })?;
+
+    let mut app = App::new();
+
+    let listener_config = TCPListenerConfig::default();
+    let address = listener_config.socket_addr();
+    let mut connector = TCPConnector::with_config(listener_config, Box::new(FlowConnector::new(app.clone())));
+
+    let _ = app
+        .create_channel(ChannelConfig {
+            name: "testing_topic".to_string(),
+            partitions: 1,
+        })
+        .await;
+
+    let channel = app.get_channel(&("testing_topic".to_string(), 1)).await.unwrap();
+    let mut producer = channel.producer();
+
+    tokio::spawn(async move {
+        let mut data = vec![Record::from_value(0u32.to_be_bytes())];
+        producer.produce(&mut data).await;
+
+        tokio::time::sleep(Duration::from_millis(100)).await;
+
+        let mut data = vec![Record::from_value(10u32.to_be_bytes())];
+        producer.produce(&mut data).await;
+
+        let mut interval = tokio::time::interval(Duration::from_millis(10));
+
+        let mut rng = StdRng::from_entropy();
+        loop {
+            interval.tick().await;
+
+            let value: u32 = rng.gen_range(0u32..100u32);
+            producer
+                .produce(&mut vec![Record::from_value(value.to_be_bytes())])
+                .await;
+        }
+    });
+
+    tokio::spawn(async move {
+        let mut client = connect(address).await.unwrap();
+
+        let _subscription = client
+            .consume("testing_topic".to_string(), |record| {
+                debug!("Handling packets {:?}", record.value);
+            })
+            .await;
+
+        std::future::pending::<()>().await;
+    });
+
+    connector
+        .run(&mut app, tokio::runtime::Handle::current(), &mut rx.fuse())
+        .await?;
+
+    info!("Demo stopped");
+    Ok(())
+}
@@ -0,0 +1,183 @@
+use std::path::PathBuf;
+
+use clap::Parser;
+
+use crate::config::{Config, ConfigError, ListenerConfig, MetricsConfig, TopicConfig, UnixListenerConfig};
+
+/// Runs a Packline broker.
+///
+/// Settings are read from the configuration file, when given, and overridden by the options.
+#[derive(Debug, Parser)]
+#[command(name = "packline", version)]
+pub struct Args {
+    /// TOML configuration file.
+    #[arg(short, long, value_name = "PATH")]
+    pub config: Option<PathBuf>,
+
+    /// Serves a protocol (flow, websocket, mqtt, amqp, stomp, kafka or http) on an address. Replaces
+    /// the listeners of the configuration file, can be repeated.
+    #[arg(long = "listen", value_name = "PROTOCOL://ADDRESS:PORT")]
+    pub listeners: Vec<ListenerConfig>,
+
+    /// Serves flow on a Unix domain socket at this path. Replaces the Unix listeners of the
+    /// configuration file, can be repeated.
+    #[arg(long = "listen-unix", value_name = "PATH")]
+    pub unix_listeners: Vec<UnixListenerConfig>,
+
+    /// Directory channels are persisted to.
+    #[arg(long, value_name = "DIR")]
+    pub data_dir: Option<PathBuf>,
+
+    /// One of trace, debug, info, warn or error.
+    #[arg(long, value_name = "LEVEL")]
+    pub log_level: Option<String>,
+
+    /// Partitions of the topics that don't specify their own.
+    #[arg(long, value_name = "COUNT")]
+    pub partitions: Option<u16>,
+
+    /// Creates a topic on startup, in addition to the ones of the configuration file. Can be
+    /// repeated.
+    #[arg(long = "topic", value_name = "NAME[:PARTITIONS]")]
+    pub topics: Vec<TopicConfig>,
+
+    /// File of `username:password` entries clients must authenticate with.
+    #[arg(long, value_name = "PATH")]
+    pub credentials_file: Option<PathBuf>,
+
+    /// File of ACL rules every operation is checked against.
+    #[arg(long, value_name = "PATH")]
+    pub acl_file: Option<PathBuf>,
+
+    /// Seconds connections are given to finish on shutdown.
+    #[arg(long, value_name = "SECONDS")]
+    pub shutdown_timeout: Option<u64>,
+
+    /// Serves Prometheus metrics and health probes over HTTP on this address.
+    #[arg(long, value_name = "ADDRESS:PORT")]
+    pub metrics: Option<MetricsConfig>,
+}
+
+impl Args {
+    /// Loads the configuration file, if any, applies the overrides and validates the result.
+    pub fn config(&self) -> Result<Config, ConfigError> {
+        let mut config = match &self.config {
+            Some(path) => Config::load(path)?,
+            None => Config::default(),
+        };
+
+        if !self.listeners.is_empty() {
+            config.listeners = self.listeners.clone();
+        }
+
+        if !self.unix_listeners.is_empty() {
+            config.unix_listeners = self.unix_listeners.clone();
+        }
+
+        if let Some(dir) = &self.data_dir {
+            config.storage.dir = dir.clone();
+        }
+
+        if let Some(log_level) = &self.log_level {
+            config.log_level = log_level.clone();
+        }
+
+        if let Some(partitions) = self.partitions {
+            config.channels.partitions = partitions;
+        }
+
+        if let Some(path) = &self.credentials_file {
+            config.auth.credentials_file = Some(path.clone());
+        }
+
+        if let Some(path) = &self.acl_file {
+            config.auth.acl_file = Some(path.clone());
+        }
+
+        if let Some(shutdown_timeout) = self.shutdown_timeout {
+            config.shutdown_timeout = shutdown_timeout;
+        }
+
+        config.topics.extend(self.topics.iter().cloned());
+
+        if let Some(metrics) = &self.metrics {
+            config.metrics = Some(metrics.clone());
+        }
+
+        config.validate()?;
+        Ok(config)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::config::Protocol;
+
+    use super::*;
+
+    fn parse(args: &[&str]) -> Args {
+        Args::try_parse_from([&["packline"], args].concat()).unwrap()
+    }
+
+    #[test]
+    fn test_options_override_config_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("packline.toml");
+        std::fs::write(
+            &path,
+            "log_level = \"warn\"\n[[listeners]]\nprotocol = \"flow\"\nport = 1883\n[[topics]]\nname = \"orders\"\n",
+        )
+        .unwrap();
+
+        let config = parse(&[
+            "--config",
+            path.to_str().unwrap(),
+            "--listen",
+            "stomp://0.0.0.0:61613",
+            "--listen",
+            "mqtt://0.0.0.0:1883",
+            "--log-level",
+            "debug",
+            "--topic",
+            "audit:2",
+            "--data-dir",
+            "/tmp/packline",
+            "--metrics",
+            "0.0.0.0:9090",
+            "--acl-file",
+            "acl.txt",
+            "--listen-unix",
+            "/run/packline.sock",
+        ])
+        .config()
+        .unwrap();
+
+        assert_eq!(
+            vec![Protocol::Stomp, Protocol::Mqtt],
+            config.listeners.iter().map(|l| l.protocol).collect::<Vec<_>>()
+        );
+        assert_eq!("debug", config.log_level);
+        assert_eq!(
+            vec!["orders", "audit"],
+            config.topics.iter().map(|t| t.name.as_str()).collect::<Vec<_>>()
+        );
+        assert_eq!(PathBuf::from("/tmp/packline"), config.storage.dir);
+        assert_eq!(9090, config.metrics.unwrap().port);
+        assert_eq!(None, config.auth.credentials_file);
+        assert_eq!(Some(PathBuf::from("acl.txt")), config.auth.acl_file);
+        assert_eq!(PathBuf::from("/run/packline.sock"), config.unix_listeners[0].path);
+    }
+
+    #[test]
+    fn test_config_errors_are_reported() {
+        assert!(matches!(
+            parse(&["--config", "/nonexistent/packline.toml"]).config(),
+            Err(ConfigError::Read(_, _))
+        ));
+        assert!(matches!(
+            parse(&["--partitions", "0"]).config(),
+            Err(ConfigError::Invalid(_))
+        ));
+        assert!(Args::try_parse_from(["packline", "--listen", "smtp://127.0.0.1:25"]).is_err());
+    }
+}
@@ -0,0 +1,603 @@
+//! Broker configuration, loaded from a TOML file and overridden from the command line.
+//!
+//! Every setting has a default, an empty file runs a flow listener on `127.0.0.1:1883` without
+//! authentication. See `packline.example.toml` for all of them.
+
+use std::collections::HashSet;
+use std::fmt::{Display, Formatter};
+use std::io;
+use std::net::{IpAddr, Ipv4Addr, SocketAddr};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::time::Duration;
+
+use serde::Deserialize;
+
+use packline_core::connector::{TCPListenerConfig, TLSConfig};
+
+#[derive(Clone, Debug, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    /// One of `trace`, `debug`, `info`, `warn` or `error`.
+    pub log_level: String,
+
+    /// Seconds connections are given to finish once a shutdown was requested.
+    pub shutdown_timeout: u64,
+    pub storage: StorageConfig,
+    pub channels: ChannelDefaults,
+    pub auth: AuthConfig,
+    pub listeners: Vec<ListenerConfig>,
+
+    /// Flow listeners on Unix domain sockets, for clients running on the same host.
+    pub unix_listeners: Vec<UnixListenerConfig>,
+
+    /// Topics created on startup when they don't exist.
+    pub topics: Vec<TopicConfig>,
+
+    /// Serves the broker metrics when set.
+    pub metrics: Option<MetricsConfig>,
+}
+
+#[derive(Clone, Debug, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct StorageConfig {
+    /// Directory channels are persisted to on shutdown and restored from on startup.
+    pub dir: PathBuf,
+}
+
+/// Client authentication and authorization. Both are disabled unless their file is set.
+///
+/// Kafka and HTTP clients can't authenticate, they always act as `ANONYMOUS`. Only the ACL rules
+/// restrict them, so listeners of either protocol require `acl_file` whenever `credentials_file` is
+/// set, the broker refusing to start otherwise.
+#[derive(Clone, Debug, Default, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct AuthConfig {
+    /// Users allowed to connect, one `username:password` entry per line. Flow, WebSocket, MQTT,
+    /// AMQP and STOMP connections must authenticate when set.
+    pub credentials_file: Option<PathBuf>,
+
+    /// ACL rules checked on every operation, one `<allow|deny> <principal> <type>:<name>
+    /// <operation>` rule per line. Operations are only denied when it's unset.
+    pub acl_file: Option<PathBuf>,
+}
+
+/// Settings of the channels that don't specify their own.
+#[derive(Clone, Debug, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ChannelDefaults {
+    pub partitions: u16,
+}
+
+#[derive(Clone, Debug, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ListenerConfig {
+    pub protocol: Protocol,
+    #[serde(default = "default_address")]
+    pub address: IpAddr,
+    pub port: u16,
+    #[serde(default = "default_nodelay")]
+    pub nodelay: bool,
+
+    /// Seconds of idle time before keepalive probes are sent, disabled when unset.
+    #[serde(default)]
+    pub keepalive: Option<u64>,
+    pub tls: Option<TLSListenerConfig>,
+}
+
+#[derive(Clone, Debug, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct UnixListenerConfig {
+    /// Path of the socket. A socket file left there by a previous run is replaced, any other file
+    /// makes the broker fail to start.
+    pub path: PathBuf,
+}
+
+#[derive(Clone, Debug, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct TLSListenerConfig {
+    pub cert: PathBuf,
+    pub key: PathBuf,
+
+    /// Requires clients to present a certificate signed by one of these CAs when set.
+    pub client_ca: Option<PathBuf>,
+}
+
+#[derive(Clone, Debug, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct TopicConfig {
+    pub name: String,
+
+    /// Falls back to the channel defaults when unset.
+    pub partitions: Option<u16>,
+}
+
+/// HTTP listener serving the broker metrics on `/metrics`, in the Prometheus text format, and its
+/// health probes on `/health/live` and `/health/ready`.
+#[derive(Clone, Debug, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct MetricsConfig {
+    #[serde(default = "default_address")]
+    pub address: IpAddr,
+    pub port: u16,
+}
+
+/// Protocol served by a listener.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Protocol {
+    Flow,
+
+    /// Flow packets carried in WebSocket frames.
+    WebSocket,
+    Mqtt,
+    Amqp,
+    Stomp,
+    Kafka,
+
+    /// Produce, consume and topic administration over HTTP.
+    Http,
+}
+
+/// Error loading or validating the configuration.
+#[derive(Debug)]
+pub enum ConfigError {
+    Read(PathBuf, io::Error),
+    Parse(PathBuf, String),
+
+    /// Every problem found in an otherwise well-formed configuration.
+    Invalid(Vec<String>),
+}
+
+impl Config {
+    /// Reads the configuration file at `path`.
+    pub fn load(path: &Path) -> Result<Config, ConfigError> {
+        let content = std::fs::read_to_string(path).map_err(|e| ConfigError::Read(path.to_path_buf(), e))?;
+        Config::parse(&content).map_err(|e| ConfigError::Parse(path.to_path_buf(), e))
+    }
+
+    pub fn parse(content: &str) -> Result<Config, String> {
+        toml::from_str(content).map_err(|e| e.to_string())
+    }
+
+    /// Checks the settings serde can't, reporting all the problems found at once.
+    pub fn validate(&self) -> Result<(), ConfigError> {
+        let mut problems = vec![];
+
+        if self.log_level.parse::<tracing::Level>().is_err() {
+            problems.push(format!(
+                "log_level {:?} isn't one of trace, debug, info, warn or error",
+                self.log_level
+            ));
+        }
+
+        if self.channels.partitions == 0 {
+            problems.push("channels.partitions must be at least 1".to_string());
+        }
+
+        if self.listeners.is_empty() && self.unix_listeners.is_empty() {
+            problems.push("at least one listener is required".to_string());
+        }
+
+        let mut paths = HashSet::new();
+        for listener in &self.unix_listeners {
+            if !paths.insert(&listener.path) {
+                problems.push(format!("more than one listener on {}", listener.path.display()));
+            }
+        }
+
+        let mut addresses = HashSet::new();
+        for listener in &self.listeners {
+            if !addresses.insert(listener.socket_addr()) {
+                problems.push(format!("more than one listener on {}", listener.socket_addr()));
+            }
+        }
+
+        if self.auth.credentials_file.is_some() && self.auth.acl_file.is_none() {
+            for listener in &self.listeners {
+                if matches!(listener.protocol, Protocol::Kafka | Protocol::Http) {
+                    problems.push(format!(
+                        "the {:?} listener on {} doesn't authenticate clients, auth.acl_file is required \
+                         along with auth.credentials_file to restrict what ANONYMOUS may do",
+                        listener.protocol,
+                        listener.socket_addr()
+                    ));
+                }
+            }
+        }
+
+        if let Some(metrics) = &self.metrics {
+            if addresses.contains(&metrics.socket_addr()) {
+                problems.push(format!(
+                    "metrics can't be served on {}, a listener uses it",
+                    metrics.socket_addr()
+                ));
+            }
+        }
+
+        let mut names = HashSet::new();
+        for topic in &self.topics {
+            if topic.name.is_empty() {
+                problems.push("topic names can't be empty".to_string());
+            } else if !names.insert(&topic.name) {
+                problems.push(format!("topic {} is declared more than once", topic.name));
+            }
+
+            if topic.partitions == Some(0) {
+                problems.push(format!("topic {} must have at least 1 partition", topic.name));
+            }
+        }
+
+        match problems.is_empty() {
+            true => Ok(()),
+            false => Err(ConfigError::Invalid(problems)),
+        }
+    }
+
+    /// Level of the log messages written. Only valid once the configuration was validated.
+    pub fn log_level(&self) -> tracing::Level {
+        self.log_level.parse().unwrap_or(tracing::Level::INFO)
+    }
+
+    pub fn shutdown_timeout(&self) -> Duration {
+        Duration::from_secs(self.shutdown_timeout)
+    }
+
+    pub fn partitions(&self, topic: &TopicConfig) -> u16 {
+        topic.partitions.unwrap_or(self.channels.partitions)
+    }
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Config {
+            log_level: "info".to_string(),
+            shutdown_timeout: 30,
+            storage: StorageConfig::default(),
+            channels: ChannelDefaults::default(),
+            auth: AuthConfig::default(),
+            listeners: vec![ListenerConfig::new(Protocol::Flow, default_address(), 1883)],
+            unix_listeners: vec![],
+            topics: vec![],
+            metrics: None,
+        }
+    }
+}
+
+impl Default for StorageConfig {
+    fn default() -> Self {
+        StorageConfig {
+            dir: PathBuf::from("data"),
+        }
+    }
+}
+
+impl Default for ChannelDefaults {
+    fn default() -> Self {
+        ChannelDefaults { partitions: 1 }
+    }
+}
+
+impl ListenerConfig {
+    pub fn new(protocol: Protocol, address: IpAddr, port: u16) -> ListenerConfig {
+        ListenerConfig {
+            protocol,
+            address,
+            port,
+            nodelay: default_nodelay(),
+            keepalive: None,
+            tls: None,
+        }
+    }
+
+    pub fn socket_addr(&self) -> SocketAddr {
+        SocketAddr::new(self.address, self.port)
+    }
+
+    pub fn tcp_config(&self) -> TCPListenerConfig {
+        TCPListenerConfig {
+            address: self.address,
+            port: self.port,
+            nodelay: self.nodelay,
+            keepalive: self.keepalive.map(Duration::from_secs),
+            tls: self.tls.as_ref().map(|tls| TLSConfig {
+                cert_path: tls.cert.clone(),
+                key_path: tls.key.clone(),
+                client_ca_path: tls.client_ca.clone(),
+            }),
+            ..Default::default()
+        }
+    }
+}
+
+impl MetricsConfig {
+    pub fn socket_addr(&self) -> SocketAddr {
+        SocketAddr::new(self.address, self.port)
+    }
+
+    pub fn tcp_config(&self) -> TCPListenerConfig {
+        TCPListenerConfig {
+            address: self.address,
+            port: self.port,
+            ..Default::default()
+        }
+    }
+}
+
+/// Parses `<address>:<port>`, as given on the command line.
+impl FromStr for MetricsConfig {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let address = s
+            .parse::<SocketAddr>()
+            .map_err(|e| format!("invalid metrics address {:?}: {}", s, e))?;
+
+        Ok(MetricsConfig {
+            address: address.ip(),
+            port: address.port(),
+        })
+    }
+}
+
+/// Parses `<protocol>://<address>:<port>`, as given on the command line.
+impl FromStr for ListenerConfig {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (protocol, address) = s
+            .split_once("://")
+            .ok_or_else(|| format!("expected <protocol>://<address>:<port>, got {:?}", s))?;
+
+        let address = address
+            .parse::<SocketAddr>()
+            .map_err(|e| format!("invalid listener address {:?}: {}", address, e))?;
+
+        Ok(ListenerConfig::new(protocol.parse()?, address.ip(), address.port()))
+    }
+}
+
+/// Parses the socket path, as given on the command line.
+impl FromStr for UnixListenerConfig {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.is_empty() {
+            true => Err("the socket path can't be empty".to_string()),
+            false => Ok(UnixListenerConfig { path: PathBuf::from(s) }),
+        }
+    }
+}
+
+/// Parses `<name>` or `<name>:<partitions>`, as given on the command line.
+impl FromStr for TopicConfig {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (name, partitions) = match s.rsplit_once(':') {
+            Some((name, partitions)) => {
+                let partitions = partitions
+                    .parse()
+                    .map_err(|e| format!("invalid partition count in {:?}: {}", s, e))?;
+                (name, Some(partitions))
+            }
+            None => (s, None),
+        };
+
+        Ok(TopicConfig {
+            name: name.to_string(),
+            partitions,
+        })
+    }
+}
+
+impl FromStr for Protocol {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let protocol = match s {
+            "flow" => Protocol::Flow,
+            "websocket" => Protocol::WebSocket,
+            "mqtt" => Protocol::Mqtt,
+            "amqp" => Protocol::Amqp,
+            "stomp" => Protocol::Stomp,
+            "kafka" => Protocol::Kafka,
+            "http" => Protocol::Http,
+            _ => {
+                return Err(format!(
+                    "unknown protocol {:?}, expected flow, websocket, mqtt, amqp, stomp, kafka or http",
+                    s
+                ))
+            }
+        };
+
+        Ok(protocol)
+    }
+}
+
+impl Display for ConfigError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ConfigError::Read(path, e) => write!(f, "failed to read {}: {}", path.display(), e),
+            ConfigError::Parse(path, e) => write!(f, "invalid configuration file {}: {}", path.display(), e),
+            ConfigError::Invalid(problems) => {
+                write!(f, "invalid configuration:")?;
+                for problem in problems {
+                    write!(f, "\n  - {}", problem)?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+impl std::error::Error for ConfigError {}
+
+fn default_address() -> IpAddr {
+    IpAddr::V4(Ipv4Addr::LOCALHOST)
+}
+
+fn default_nodelay() -> bool {
+    true
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_full_config() {
+        let config = Config::parse(
+            r#"
+            log_level = "debug"
+            shutdown_timeout = 5
+
+            [storage]
+            dir = "/var/lib/packline"
+
+            [channels]
+            partitions = 3
+
+            [auth]
+            credentials_file = "users.txt"
+            acl_file = "acl.txt"
+
+            [[listeners]]
+            protocol = "flow"
+            port = 1883
+
+            [[listeners]]
+            protocol = "mqtt"
+            address = "0.0.0.0"
+            port = 8883
+            keepalive = 60
+            tls = { cert = "cert.pem", key = "key.pem" }
+
+            [[listeners]]
+            protocol = "http"
+            port = 8080
+
+            [[unix_listeners]]
+            path = "/run/packline.sock"
+
+            [[topics]]
+            name = "orders"
+
+            [[topics]]
+            name = "audit"
+            partitions = 1
+
+            [metrics]
+            port = 9090
+            "#,
+        )
+        .unwrap();
+
+        assert!(config.validate().is_ok());
+        assert_eq!(tracing::Level::DEBUG, config.log_level());
+        assert_eq!(Duration::from_secs(5), config.shutdown_timeout());
+        assert_eq!(PathBuf::from("/var/lib/packline"), config.storage.dir);
+
+        assert_eq!(
+            ListenerConfig::new(Protocol::Flow, default_address(), 1883),
+            config.listeners[0]
+        );
+        let mqtt = config.listeners[1].tcp_config();
+        assert_eq!("0.0.0.0:8883".parse::<SocketAddr>().unwrap(), mqtt.socket_addr());
+        assert_eq!(Some(Duration::from_secs(60)), mqtt.keepalive);
+        assert_eq!(PathBuf::from("cert.pem"), mqtt.tls.unwrap().cert_path);
+        assert_eq!(Protocol::Http, config.listeners[2].protocol);
+        assert_eq!(PathBuf::from("/run/packline.sock"), config.unix_listeners[0].path);
+
+        assert_eq!(Some(PathBuf::from("users.txt")), config.auth.credentials_file);
+        assert_eq!(Some(PathBuf::from("acl.txt")), config.auth.acl_file);
+
+        assert_eq!(3, config.partitions(&config.topics[0]));
+        assert_eq!(1, config.partitions(&config.topics[1]));
+
+        assert_eq!(
+            "127.0.0.1:9090".parse::<SocketAddr>().unwrap(),
+            config.metrics.unwrap().socket_addr()
+        );
+    }
+
+    #[test]
+    fn test_empty_config_uses_defaults() {
+        let config = Config::parse("").unwrap();
+
+        assert_eq!(Config::default(), config);
+        assert!(config.validate().is_ok());
+    }
+
+    #[test]
+    fn test_parse_rejects_unknown_settings() {
+        assert!(Config::parse("log_levle = \"debug\"").is_err());
+        assert!(Config::parse("[[listeners]]\nprotocol = \"smtp\"\nport = 25").is_err());
+        assert!(Config::parse("[[listeners]]\nprotocol = \"flow\"").is_err());
+    }
+
+    #[test]
+    fn test_validate_reports_every_problem() {
+        let mut config = Config {
+            log_level: "loud".to_string(),
+            ..Default::default()
+        };
+        config.channels.partitions = 0;
+        config.listeners.push(config.listeners[0].clone());
+        config.topics = vec!["orders".parse().unwrap(), "orders:0".parse().unwrap()];
+        config.metrics = Some("127.0.0.1:1883".parse().unwrap());
+        config.unix_listeners = vec!["packline.sock".parse().unwrap(), "packline.sock".parse().unwrap()];
+
+        match config.validate() {
+            Err(ConfigError::Invalid(problems)) => assert_eq!(7, problems.len(), "{:?}", problems),
+            result => panic!("unexpected result {:?}", result),
+        }
+    }
+
+    #[test]
+    fn test_validate_requires_acl_file_for_unauthenticated_listeners() {
+        let mut config = Config {
+            listeners: vec![
+                "flow://127.0.0.1:1883".parse().unwrap(),
+                "kafka://127.0.0.1:9092".parse().unwrap(),
+                "http://127.0.0.1:8080".parse().unwrap(),
+            ],
+            ..Default::default()
+        };
+        assert!(config.validate().is_ok());
+
+        config.auth.credentials_file = Some(PathBuf::from("users.txt"));
+        match config.validate() {
+            Err(ConfigError::Invalid(problems)) => assert_eq!(2, problems.len(), "{:?}", problems),
+            result => panic!("unexpected result {:?}", result),
+        }
+
+        config.auth.acl_file = Some(PathBuf::from("acl.txt"));
+        assert!(config.validate().is_ok());
+
+        config.auth.acl_file = None;
+        config.listeners.truncate(1);
+        assert!(config.validate().is_ok());
+    }
+
+    #[test]
+    fn test_parse_listener_and_topic() {
+        let listener: ListenerConfig = "kafka://[::1]:9092".parse().unwrap();
+        assert_eq!(Protocol::Kafka, listener.protocol);
+        assert_eq!("[::1]:9092".parse::<SocketAddr>().unwrap(), listener.socket_addr());
+
+        assert!("kafka://localhost".parse::<ListenerConfig>().is_err());
+        assert!("127.0.0.1:9092".parse::<ListenerConfig>().is_err());
+
+        let topic: TopicConfig = "orders:4".parse().unwrap();
+        assert_eq!(("orders", Some(4)), (topic.name.as_str(), topic.partitions));
+        assert_eq!(None, "orders".parse::<TopicConfig>().unwrap().partitions);
+        assert!("orders:many".parse::<TopicConfig>().is_err());
+    }
+
+    #[test]
+    fn test_example_config_is_valid() {
+        let path = Path::new(env!("CARGO_MANIFEST_DIR")).join("packline.example.toml");
+        assert!(Config::load(&path).unwrap().validate().is_ok());
+    }
+}
@@ -1,83 +1,243 @@
 use std::error::Error;
+use std::process::ExitCode;
+use std::sync::Mutex;
 
+use clap::Parser;
 use futures::FutureExt;
-use tokio::time::Duration;
-use tracing::{debug, info};
+use tracing::{error, info, warn};
 
-use packline_cli::client::connect;
+use packline_amqp::connector::AMQPConnector;
 use packline_core::{
-    app::ChannelConfig,
-    connector::{Connector, TCPConnector},
+    app::acl::Authorizer,
+    app::health::{LISTENERS, STORAGE, TOPICS},
+    app::{App, ChannelConfig},
+    connector::{Connector, HTTPConnector, MetricsConnector, TCPConnector, TCPConnectorHandler, UnixConnector},
 };
-use packline_flow::connector::FlowConnector;
-use rand::{rngs::StdRng, Rng, SeedableRng};
+use packline_flow::auth::{Authenticator, CredentialStore};
+use packline_flow::connector::{FlowConnector, FlowWebSocketConnector};
+use packline_kafka::connector::KafkaConnector;
+use packline_mqtt::connector::MQTTConnector;
+use packline_stomp::connector::STOMPConnector;
+
+use crate::cli::Args;
+use crate::config::{Config, Protocol};
+
+mod cli;
+mod config;
 
 #[tokio::main]
-async fn main() -> Result<(), Box<dyn Error + Send + Sync + 'static>> {
-    tracing_subscriber::fmt()
-        .with_max_level(tracing::Level::DEBUG)
-        .try_init()?;
+async fn main() -> ExitCode {
+    let config = match Args::parse().config() {
+        Ok(config) => config,
+        Err(e) => {
+            eprintln!("packline: {}", e);
+            return ExitCode::from(2);
+        }
+    };
+
+    if let Err(e) = tracing_subscriber::fmt().with_max_level(config.log_level()).try_init() {
+        eprintln!("packline: failed to set up logging: {}", e);
+        return ExitCode::FAILURE;
+    }
+
+    match run(config).await {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            error!("{}", e);
+            ExitCode::FAILURE
+        }
+    }
+}
 
+async fn run(config: Config) -> Result<(), Box<dyn Error + Send + Sync + 'static>> {
     info!("Starting Packline");
-    let _ = tokio::spawn(async {
-        let mut app = packline_core::app::App::new();
 
-        //TODO: detect program shutdown step and send oneshot signal.
-        let (_tx, rx) = tokio::sync::oneshot::channel();
-        let (client_tx, client_rx) = tokio::sync::oneshot::channel();
+    // SIGINT and SIGTERM both request a shutdown, only the first one is acted on.
+    let (shutdown_tx, shutdown_rx) = tokio::sync::oneshot::channel();
+    let shutdown_tx = Mutex::new(Some(shutdown_tx));
+    ctrlc::set_handler(move || {
+        if let Some(tx) = shutdown_tx.lock().unwrap().take() {
+            let _ = tx.send(());
+        }
+    })?;
+
+    let credentials = match &config.auth.credentials_file {
+        Some(path) => {
+            let credentials = CredentialStore::from_file(path)
+                .await
+                .map_err(|e| format!("failed to load credentials from {}: {}", path.display(), e))?;
+            info!("Clients authenticate with the credentials of {}", path.display());
+            Some(credentials)
+        }
+        None => None,
+    };
+
+    let app = match &config.auth.acl_file {
+        Some(path) => {
+            let authorizer = Authorizer::open(path)
+                .map_err(|e| format!("failed to load ACL rules from {}: {}", path.display(), e))?;
+            info!("Authorizing operations with the ACL rules of {}", path.display());
+            App::with_authorizer(authorizer)
+        }
+        None => App::new(),
+    };
+    app.health().require(LISTENERS);
+    app.health().require(STORAGE);
+    app.health().require(TOPICS);
+
+    // The metrics listener serves the health probes, so it starts before the app is ready. It isn't
+    // drained on shutdown, scrapes in flight are simply dropped.
+    let _metrics = config.metrics.as_ref().map(|metrics| {
+        info!("Serving metrics and health probes on http://{}", metrics.socket_addr());
+
+        let mut connector = MetricsConnector::new(metrics.tcp_config());
+        let mut app = app.clone();
+        let (tx, rx) = tokio::sync::oneshot::channel::<bool>();
+        tokio::spawn(async move {
+            if let Err(e) = connector
+                .run(&mut app, tokio::runtime::Handle::current(), &mut rx.fuse())
+                .await
+            {
+                error!("Metrics listener failed: {}", e);
+            }
+        });
+
+        tx
+    });
 
-        let mut connector = TCPConnector::new(Box::new(FlowConnector { app: app.clone() }));
+    app.restore(&config.storage.dir).await.map_err(|e| {
+        format!(
+            "failed to restore channels from {}: {}",
+            config.storage.dir.display(),
+            e
+        )
+    })?;
+    app.health().pass(STORAGE);
 
-        let _ = app
+    for topic in &config.topics {
+        let created = app
             .create_channel(ChannelConfig {
-                name: "testing_topic".to_string(),
-                partitions: 1,
+                name: topic.name.clone(),
+                partitions: config.partitions(topic),
             })
             .await;
 
-        let channel = app.get_channel(&("testing_topic".to_string(), 1)).await.unwrap();
-        let mut producer = channel.producer();
-
-        tokio::spawn(async move {
-            let mut data = vec![0u32];
-            producer.produce(&mut data).await;
-
-            tokio::time::sleep(Duration::from_millis(100)).await;
-
-            let mut data = vec![10u32];
-            producer.produce(&mut data).await;
-
-            let mut interval = tokio::time::interval(Duration::from_millis(10));
-
-            let mut rng = StdRng::from_entropy();
-            loop {
-                interval.tick().await;
-
-                let value: u32 = rng.gen_range(0u32..100u32);
-                producer.produce(&mut vec![value]).await;
-            }
+        if created.is_ok() {
+            info!("Created topic {}", topic.name);
+        }
+    }
+    app.health().pass(TOPICS);
+
+    // HTTP and Unix socket listeners run their own connector, every other protocol is served by a
+    // single TCP one.
+    let authenticator = credentials.clone().map(Authenticator::new);
+    let mut connectors: Vec<Box<dyn Connector>> = vec![];
+    let mut tcp: Option<TCPConnector> = None;
+    for listener in &config.listeners {
+        info!("Serving {:?} on {}", listener.protocol, listener.socket_addr());
+
+        if listener.protocol == Protocol::Http {
+            connectors.push(Box::new(HTTPConnector::new(listener.tcp_config())));
+            continue;
+        }
+
+        let listener_handler = handler(listener.protocol, &app, &config, &credentials, &authenticator);
+        tcp = Some(match tcp {
+            Some(connector) => connector.listener(listener.tcp_config(), listener_handler),
+            None => TCPConnector::with_config(listener.tcp_config(), listener_handler),
         });
-
-        tokio::spawn(async move {
-            let mut client = connect("127.0.0.1:1883").await.unwrap();
-
-            client
-                .consume("testing_topic".to_string(), |record| {
-                    debug!("Handling packets {}", record);
-                })
-                .await;
-
-            let _ = client_rx.await;
+    }
+    connectors.extend(tcp.map(|connector| Box::new(connector) as Box<dyn Connector>));
+
+    for listener in &config.unix_listeners {
+        info!("Serving Flow on {}", listener.path.display());
+
+        let handler = Box::new(flow_connector(&app, &authenticator));
+        connectors.push(Box::new(UnixConnector::new(&listener.path, handler)));
+    }
+
+    let mut signals = vec![];
+    let mut runs = vec![];
+    for mut connector in connectors {
+        let (tx, rx) = tokio::sync::oneshot::channel();
+        let mut app = app.clone();
+        signals.push(tx);
+        runs.push(async move {
+            connector
+                .run(&mut app, tokio::runtime::Handle::current(), &mut rx.fuse())
+                .await
         });
+    }
+
+    let shutdown_timeout = config.shutdown_timeout();
+
+    let result = {
+        let run = futures::future::try_join_all(runs);
+        tokio::pin!(run);
+
+        tokio::select! {
+            result = &mut run => result.map(|_| ()),
+            _ = shutdown_rx => {
+                info!("Shutting down, waiting up to {:?} for connections to finish", shutdown_timeout);
+                for tx in signals {
+                    let _ = tx.send(true);
+                }
+
+                match tokio::time::timeout(shutdown_timeout, &mut run).await {
+                    Ok(result) => result.map(|_| ()),
+                    Err(_) => {
+                        warn!("Connections didn't finish within {:?}, closing them", shutdown_timeout);
+                        Ok(())
+                    }
+                }
+            }
+        }
+    };
+
+    // Channels are persisted even when the connector failed, so that nothing accepted is lost.
+    let persisted = app.persist(&config.storage.dir).await;
+    match &persisted {
+        Ok(()) => info!("Persisted channels to {}", config.storage.dir.display()),
+        Err(e) => error!("Failed to persist channels to {}: {}", config.storage.dir.display(), e),
+    }
+
+    result?;
+    Ok(persisted?)
+}
 
-        let _ = connector
-            .run(&mut app, tokio::runtime::Handle::current(), &mut rx.fuse())
-            .await;
-
-        let _ = client_tx.send(true);
-        info!("After run!")
-    })
-    .await;
+fn flow_connector(app: &App, authenticator: &Option<Authenticator>) -> FlowConnector {
+    let connector = FlowConnector::new(app.clone());
+    match authenticator {
+        Some(authenticator) => connector.with_authenticator(authenticator.clone()),
+        None => connector,
+    }
+}
 
-    Ok(())
+fn handler(
+    protocol: Protocol,
+    app: &App,
+    config: &Config,
+    credentials: &Option<CredentialStore>,
+    authenticator: &Option<Authenticator>,
+) -> Box<dyn TCPConnectorHandler> {
+    let flow = || flow_connector(app, authenticator);
+
+    match (protocol, credentials.clone()) {
+        (Protocol::Flow, _) => Box::new(flow()),
+        (Protocol::WebSocket, _) => Box::new(FlowWebSocketConnector::new(flow())),
+        (Protocol::Mqtt, Some(credentials)) => Box::new(MQTTConnector::new(app.clone()).with_credentials(credentials)),
+        (Protocol::Mqtt, None) => Box::new(MQTTConnector::new(app.clone())),
+        (Protocol::Amqp, Some(credentials)) => Box::new(AMQPConnector::new(app.clone()).with_credentials(credentials)),
+        (Protocol::Amqp, None) => Box::new(AMQPConnector::new(app.clone())),
+        (Protocol::Stomp, Some(credentials)) => {
+            Box::new(STOMPConnector::new(app.clone()).with_credentials(credentials))
+        }
+        (Protocol::Stomp, None) => Box::new(STOMPConnector::new(app.clone())),
+        (Protocol::Kafka, _) => {
+            let mut connector = KafkaConnector::new(app.clone());
+            connector.default_partitions = config.channels.partitions;
+            Box::new(connector)
+        }
+        (Protocol::Http, _) => unreachable!("HTTP listeners run their own connector"),
+    }
 }
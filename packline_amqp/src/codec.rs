@@ -0,0 +1,194 @@
+use std::io::{Error, ErrorKind};
+
+use bytes::{Buf, BufMut, BytesMut};
+use tokio_util::codec::{Decoder, Encoder};
+
+use crate::frame::{ContentHeader, Frame, Method, FRAME_END};
+
+/// Largest frame accepted until the connection is tuned, the minimum every peer has to accept.
+pub const MIN_FRAME_SIZE: usize = 4096;
+
+const FRAME_METHOD: u8 = 1;
+const FRAME_HEADER: u8 = 2;
+const FRAME_BODY: u8 = 3;
+const FRAME_HEARTBEAT: u8 = 8;
+
+/// Type, channel and payload size preceding the payload of every frame.
+const FRAME_HEADER_LEN: usize = 7;
+
+/// Decodes and encodes AMQP 0-9-1 frames. The protocol header a connection starts with is
+/// exchanged before framing the stream.
+pub struct AMQPCodec {
+    max_frame_size: usize,
+}
+
+impl AMQPCodec {
+    pub fn new() -> AMQPCodec {
+        AMQPCodec {
+            max_frame_size: MIN_FRAME_SIZE,
+        }
+    }
+
+    /// Sets the largest frame accepted, headers and frame end included, as tuned with the peer.
+    pub fn set_max_frame_size(&mut self, max_frame_size: usize) {
+        self.max_frame_size = max_frame_size.max(MIN_FRAME_SIZE);
+    }
+}
+
+impl Default for AMQPCodec {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Decoder for AMQPCodec {
+    type Item = Frame;
+    type Error = Error;
+
+    fn decode(&mut self, src: &mut BytesMut) -> Result<Option<Self::Item>, Self::Error> {
+        if src.len() < FRAME_HEADER_LEN {
+            return Ok(None);
+        }
+
+        let frame_type = src[0];
+        let channel = u16::from_be_bytes([src[1], src[2]]);
+        let size = u32::from_be_bytes([src[3], src[4], src[5], src[6]]) as usize;
+
+        let frame_len = FRAME_HEADER_LEN + size + 1;
+        if frame_len > self.max_frame_size {
+            return Err(Error::new(
+                ErrorKind::InvalidData,
+                "frame exceeds the maximum frame size",
+            ));
+        }
+
+        if src.len() < frame_len {
+            src.reserve(frame_len - src.len());
+            return Ok(None);
+        }
+
+        src.advance(FRAME_HEADER_LEN);
+        let payload = src.split_to(size).freeze();
+        if src.get_u8() != FRAME_END {
+            return Err(Error::new(
+                ErrorKind::InvalidData,
+                "frame doesn't end with the frame end octet",
+            ));
+        }
+
+        let frame = match frame_type {
+            FRAME_METHOD => Frame::Method(channel, Method::decode(payload)?),
+            FRAME_HEADER => Frame::Header(channel, ContentHeader::decode(payload)?),
+            FRAME_BODY => Frame::Body(channel, payload),
+            FRAME_HEARTBEAT => Frame::Heartbeat,
+            _ => return Err(Error::new(ErrorKind::InvalidData, "unknown frame type")),
+        };
+
+        Ok(Some(frame))
+    }
+}
+
+impl Encoder<Frame> for AMQPCodec {
+    type Error = Error;
+
+    fn encode(&mut self, frame: Frame, dst: &mut BytesMut) -> Result<(), Self::Error> {
+        let (frame_type, channel) = match &frame {
+            Frame::Method(channel, _) => (FRAME_METHOD, *channel),
+            Frame::Header(channel, _) => (FRAME_HEADER, *channel),
+            Frame::Body(channel, _) => (FRAME_BODY, *channel),
+            Frame::Heartbeat => (FRAME_HEARTBEAT, 0),
+        };
+
+        dst.put_u8(frame_type);
+        dst.put_u16(channel);
+
+        let size_position = dst.len();
+        dst.put_u32(0);
+
+        match frame {
+            Frame::Method(_, method) => method.encode(dst),
+            Frame::Header(_, header) => header.encode(dst),
+            Frame::Body(_, body) => dst.put_slice(&body),
+            Frame::Heartbeat => {}
+        }
+
+        let size = (dst.len() - size_position - 4) as u32;
+        dst[size_position..size_position + 4].copy_from_slice(&size.to_be_bytes());
+        dst.put_u8(FRAME_END);
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bytes::Bytes;
+
+    use super::*;
+
+    #[test]
+    fn test_frame_round_trip() {
+        let frames = vec![
+            Frame::Method(
+                1,
+                Method::BasicPublish {
+                    exchange: "amq.topic".to_string(),
+                    routing_key: "orders.created".to_string(),
+                    mandatory: true,
+                    immediate: false,
+                },
+            ),
+            Frame::Header(1, ContentHeader::basic(5)),
+            Frame::Body(1, Bytes::from_static(b"hello")),
+            Frame::Heartbeat,
+        ];
+
+        let mut codec = AMQPCodec::new();
+        let mut buf = BytesMut::new();
+        for frame in frames.clone() {
+            codec.encode(frame, &mut buf).unwrap();
+        }
+
+        let mut decoded = vec![];
+        while let Some(frame) = codec.decode(&mut buf).unwrap() {
+            decoded.push(frame);
+        }
+
+        assert_eq!(frames, decoded);
+    }
+
+    #[test]
+    fn test_decode_waits_for_whole_frame() {
+        let mut codec = AMQPCodec::new();
+        let mut buf = BytesMut::new();
+        codec
+            .encode(Frame::Body(1, Bytes::from_static(b"hello")), &mut buf)
+            .unwrap();
+
+        let mut partial = buf.split_to(buf.len() - 1);
+        assert_eq!(None, codec.decode(&mut partial).unwrap());
+
+        partial.unsplit(buf);
+        assert_eq!(
+            Some(Frame::Body(1, Bytes::from_static(b"hello"))),
+            codec.decode(&mut partial).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_decode_rejects_oversized_and_malformed_frames() {
+        let mut codec = AMQPCodec::new();
+
+        let mut buf = BytesMut::new();
+        codec
+            .encode(Frame::Body(1, Bytes::from(vec![0; MIN_FRAME_SIZE])), &mut buf)
+            .unwrap();
+        assert!(codec.decode(&mut buf).is_err());
+
+        let mut buf = BytesMut::new();
+        codec.encode(Frame::Heartbeat, &mut buf).unwrap();
+        let last = buf.len() - 1;
+        buf[last] = 0;
+        assert!(codec.decode(&mut buf).is_err());
+    }
+}
@@ -0,0 +1,1740 @@
+use std::collections::{BTreeMap, HashMap};
+use std::io::{Error, ErrorKind};
+use std::net::SocketAddr;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+use async_trait::async_trait;
+use bytes::{Bytes, BytesMut};
+use futures::{SinkExt, StreamExt};
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+use tokio::sync::mpsc::{self, UnboundedReceiver, UnboundedSender};
+use tokio::sync::{Mutex, Semaphore};
+use tokio::task::JoinHandle;
+use tokio::time::{Instant, Interval};
+use tokio_util::codec::Framed;
+use tracing::{debug, info, warn};
+
+use packline_core::app::acl::{Operation, Resource, ANONYMOUS};
+use packline_core::app::binding::{Binding, ExchangeKind};
+use packline_core::app::channel::Record;
+use packline_core::app::{App, ChannelConfig};
+use packline_core::connector::{TCPConnectionHandler, TCPConnectorHandler, TCPStream};
+use packline_flow::auth::{plain, CredentialStore};
+
+use crate::codec::AMQPCodec;
+use crate::frame::reply_code::*;
+use crate::frame::{field_table, Close, ContentHeader, FieldValue, Frame, Method, PROTOCOL_HEADER};
+use crate::queue::{Message, Queue};
+
+/// Time a client has to go through the connection handshake steps.
+const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(10);
+
+const CHANNEL_MAX: u16 = 2047;
+const FRAME_MAX: u32 = 128 * 1024;
+
+/// Heartbeat interval proposed to clients, in seconds.
+const HEARTBEAT: u16 = 60;
+
+/// Frame type, channel, payload size and frame end surrounding the payload of a frame.
+const FRAME_OVERHEAD: usize = 8;
+
+/// Class and method ids of `basic.publish`, which content frames belong to.
+const BASIC_PUBLISH: (u16, u16) = (60, 40);
+
+/// Serves AMQP 0-9-1 clients, mapping AMQP queues onto app channels of the same name.
+///
+/// Exchanges and bindings are kept by the app, see [`packline_core::app::binding`]. Messages are
+/// routed to the first partition of the channels of the queues bound, declaring a queue creates
+/// its channel with a single partition when needed. The consumers of a queue compete for its
+/// messages, which are delivered again when they're rejected or their channel closes before they
+/// are acknowledged.
+///
+/// Messages only keep their body and routing key, properties aren't stored. Queues and exchanges
+/// are always durable, exclusive and auto-delete queues aren't supported and declared as regular
+/// ones.
+pub struct AMQPConnector {
+    pub app: App,
+
+    /// Requires clients to log in with PLAIN and a username and password from the store when set.
+    /// Otherwise clients act as the anonymous principal whatever they send.
+    pub credentials: Option<Arc<CredentialStore>>,
+
+    broker: Arc<BrokerState>,
+}
+
+impl AMQPConnector {
+    pub fn new(app: App) -> AMQPConnector {
+        AMQPConnector {
+            app,
+            credentials: None,
+            broker: Arc::new(BrokerState::default()),
+        }
+    }
+
+    pub fn with_credentials(mut self, credentials: CredentialStore) -> AMQPConnector {
+        self.credentials = Some(Arc::new(credentials));
+        self
+    }
+
+    /// Creates the handler running AMQP over `stream`. `peer` identifies the remote end in logs.
+    pub fn connection_handler<S: AMQPStream>(&self, stream: S, peer: String) -> AMQPConnectionHandler<S> {
+        AMQPConnectionHandler {
+            app: self.app.clone(),
+            peer,
+            stream: Some(stream),
+            credentials: self.credentials.clone(),
+            broker: self.broker.clone(),
+        }
+    }
+}
+
+#[async_trait]
+impl TCPConnectorHandler for AMQPConnector {
+    fn handle_connection(&self, conn: (TCPStream, SocketAddr)) -> Box<dyn TCPConnectionHandler> {
+        Box::new(self.connection_handler(conn.0, conn.1.to_string()))
+    }
+
+    fn name(&self) -> &'static str {
+        "amqp"
+    }
+}
+
+/// Byte stream AMQP can run on.
+pub trait AMQPStream: AsyncRead + AsyncWrite + Send + Sync + Unpin + 'static {}
+
+impl<T: AsyncRead + AsyncWrite + Send + Sync + Unpin + 'static> AMQPStream for T {}
+
+/// State shared by the connections of a connector.
+#[derive(Default)]
+struct BrokerState {
+    queues: Mutex<HashMap<String, Arc<Queue>>>,
+
+    /// Source of the ids of generated queue names, consumer tags and consumers.
+    next_id: AtomicU64,
+}
+
+impl BrokerState {
+    /// Returns the queue of the channel `name`, or `None` when the channel doesn't exist.
+    async fn queue(&self, app: &App, name: &str) -> Option<Arc<Queue>> {
+        let mut queues = self.queues.lock().await;
+        if let Some(queue) = queues.get(name) {
+            return Some(queue.clone());
+        }
+
+        let channel = app.get_channel(&(name.to_string(), 1)).await?;
+        let queue = Queue::open(name, channel).await;
+        queues.insert(name.to_string(), queue.clone());
+
+        Some(queue)
+    }
+
+    fn next_id(&self) -> u64 {
+        self.next_id.fetch_add(1, Ordering::Relaxed)
+    }
+}
+
+pub struct AMQPConnectionHandler<S: AMQPStream> {
+    app: App,
+    peer: String,
+    stream: Option<S>,
+
+    credentials: Option<Arc<CredentialStore>>,
+    broker: Arc<BrokerState>,
+}
+
+/// Outcome of the connection handshake.
+struct Session {
+    principal: String,
+    frame_max: usize,
+    heartbeat: u16,
+}
+
+#[async_trait]
+impl<S: AMQPStream> TCPConnectionHandler for AMQPConnectionHandler<S> {
+    async fn handle(&mut self) -> Result<(), Error> {
+        debug!("New AMQP Connection: {}", self.peer);
+
+        let mut stream = self.stream.take().unwrap();
+        let mut header = [0; 8];
+        match tokio::time::timeout(HANDSHAKE_TIMEOUT, stream.read_exact(&mut header)).await {
+            Ok(Ok(_)) if &header == PROTOCOL_HEADER => {}
+            Ok(Ok(_)) => {
+                // Clients of other protocol versions are told the one supported.
+                debug!("{} sent an unsupported protocol header", self.peer);
+                stream.write_all(PROTOCOL_HEADER).await?;
+                return Ok(());
+            }
+            _ => {
+                debug!("{} didn't send the protocol header", self.peer);
+                return Ok(());
+            }
+        }
+
+        let mut framed = Framed::new(stream, AMQPCodec::new());
+        let session = match self.open(&mut framed).await? {
+            Some(session) => session,
+            None => return Ok(()),
+        };
+
+        info!("AMQP client {} connected from {}", session.principal, self.peer);
+
+        let (deliveries_tx, deliveries) = mpsc::unbounded_channel();
+        let mut connection = Connection {
+            app: self.app.clone(),
+            broker: self.broker.clone(),
+            principal: session.principal,
+            framed,
+            frame_max: session.frame_max,
+            heartbeat: session.heartbeat,
+            channels: HashMap::new(),
+            deliveries_tx,
+            deliveries,
+        };
+
+        connection.run().await;
+        connection.shutdown();
+
+        debug!("AMQP connection finished");
+        Ok(())
+    }
+}
+
+impl<S: AMQPStream> AMQPConnectionHandler<S> {
+    /// Goes through the connection handshake. Returns `None` when the client went away or was
+    /// refused.
+    async fn open(&self, framed: &mut Framed<S, AMQPCodec>) -> Result<Option<Session>, Error> {
+        framed
+            .send(Frame::Method(
+                0,
+                Method::ConnectionStart {
+                    server_properties: server_properties(),
+                    mechanisms: "PLAIN".to_string(),
+                    locales: "en_US".to_string(),
+                },
+            ))
+            .await?;
+
+        let (mechanism, response) = match self.next_method(framed).await {
+            Some(Method::ConnectionStartOk {
+                mechanism, response, ..
+            }) => (mechanism, response),
+            _ => return Ok(None),
+        };
+
+        let principal = match self.authenticate(&mechanism, &response).await {
+            Some(principal) => principal,
+            None => {
+                debug!("Refused AMQP connection from {}: login refused", self.peer);
+                let close = Close {
+                    reply_code: ACCESS_REFUSED,
+                    reply_text: "ACCESS_REFUSED - login refused".to_string(),
+                    class_id: 10,
+                    method_id: 11,
+                };
+                framed.send(Frame::Method(0, Method::ConnectionClose(close))).await?;
+                return Ok(None);
+            }
+        };
+
+        framed
+            .send(Frame::Method(
+                0,
+                Method::ConnectionTune {
+                    channel_max: CHANNEL_MAX,
+                    frame_max: FRAME_MAX,
+                    heartbeat: HEARTBEAT,
+                },
+            ))
+            .await?;
+
+        let (frame_max, heartbeat) = match self.next_method(framed).await {
+            Some(Method::ConnectionTuneOk {
+                frame_max, heartbeat, ..
+            }) => (frame_max, heartbeat),
+            _ => return Ok(None),
+        };
+
+        // Zero means the client has no limit, leaving ours.
+        let frame_max = match frame_max {
+            0 => FRAME_MAX,
+            frame_max => frame_max.min(FRAME_MAX),
+        } as usize;
+        framed.codec_mut().set_max_frame_size(frame_max);
+
+        match self.next_method(framed).await {
+            Some(Method::ConnectionOpen { .. }) => {}
+            _ => return Ok(None),
+        }
+
+        framed.send(Frame::Method(0, Method::ConnectionOpenOk)).await?;
+
+        Ok(Some(Session {
+            principal,
+            frame_max,
+            heartbeat,
+        }))
+    }
+
+    /// Reads the next method of the handshake, which are all sent on channel zero.
+    async fn next_method(&self, framed: &mut Framed<S, AMQPCodec>) -> Option<Method> {
+        match tokio::time::timeout(HANDSHAKE_TIMEOUT, framed.next()).await {
+            Ok(Some(Ok(Frame::Method(0, method)))) => Some(method),
+            Ok(Some(Err(e))) => {
+                debug!("Failed to read AMQP handshake from {}: {}", self.peer, e);
+                None
+            }
+            _ => {
+                debug!("{} didn't go through the AMQP handshake", self.peer);
+                None
+            }
+        }
+    }
+
+    /// Returns the principal the connection acts as, or `None` when the login is refused.
+    async fn authenticate(&self, mechanism: &str, response: &[u8]) -> Option<String> {
+        let credentials = match &self.credentials {
+            Some(credentials) => credentials,
+            None => return Some(ANONYMOUS.to_string()),
+        };
+
+        if mechanism != "PLAIN" {
+            return None;
+        }
+
+        let (username, password) = plain::decode(response).ok()?;
+        credentials.verify(&username, &password).await.then_some(username)
+    }
+}
+
+fn server_properties() -> Bytes {
+    let capabilities = ["publisher_confirms", "basic.nack", "per_consumer_qos"]
+        .iter()
+        .map(|capability| (capability.to_string(), FieldValue::Bool(true)))
+        .collect();
+
+    field_table(&[
+        ("product".to_string(), FieldValue::LongString("packline".to_string())),
+        (
+            "version".to_string(),
+            FieldValue::LongString(env!("CARGO_PKG_VERSION").to_string()),
+        ),
+        ("capabilities".to_string(), FieldValue::Table(capabilities)),
+    ])
+}
+
+/// Error raised while handling a frame. Channel exceptions close the channel the frame was sent
+/// on, connection exceptions the whole connection.
+enum Exception {
+    Io(Error),
+    Channel(u16, String),
+    Connection(u16, String),
+}
+
+impl From<Error> for Exception {
+    fn from(e: Error) -> Self {
+        Exception::Io(e)
+    }
+}
+
+struct Connection<S: AMQPStream> {
+    app: App,
+    broker: Arc<BrokerState>,
+    principal: String,
+
+    framed: Framed<S, AMQPCodec>,
+    frame_max: usize,
+    heartbeat: u16,
+
+    channels: HashMap<u16, ChannelState>,
+
+    /// Messages taken from queues by the consumers of the connection, waiting to be sent.
+    deliveries_tx: UnboundedSender<Delivery>,
+    deliveries: UnboundedReceiver<Delivery>,
+}
+
+struct ChannelState {
+    /// Set once the channel was closed because of an exception, until the client confirms.
+    closing: bool,
+    prefetch: u16,
+
+    /// Sequence number of the last message published, once publisher confirms are enabled.
+    confirms: Option<u64>,
+
+    /// Queue declared last, which methods naming no queue refer to.
+    last_queue: Option<String>,
+
+    next_delivery_tag: u64,
+    unacked: BTreeMap<u64, Unacked>,
+    consumers: HashMap<String, ConsumerState>,
+
+    /// Message being published, until all its content is received.
+    publish: Option<PendingPublish>,
+}
+
+struct ConsumerState {
+    id: u64,
+    queue: Arc<Queue>,
+    no_ack: bool,
+    task: JoinHandle<()>,
+}
+
+/// Message delivered and waiting for the client to acknowledge it.
+struct Unacked {
+    queue: Arc<Queue>,
+    message: Message,
+
+    /// Deliveries the consumer can still take, released once the message is settled.
+    credit: Option<Arc<Semaphore>>,
+}
+
+struct PendingPublish {
+    exchange: String,
+    routing_key: String,
+    mandatory: bool,
+    header: Option<ContentHeader>,
+    body: BytesMut,
+}
+
+struct Delivery {
+    channel: u16,
+    consumer_tag: String,
+    consumer_id: u64,
+    queue: Arc<Queue>,
+    message: Message,
+    credit: Option<Arc<Semaphore>>,
+}
+
+enum Event {
+    Frame(Option<Result<Frame, Error>>),
+    Delivery(Delivery),
+    Heartbeat,
+}
+
+impl ChannelState {
+    fn new() -> ChannelState {
+        ChannelState {
+            closing: false,
+            prefetch: 0,
+            confirms: None,
+            last_queue: None,
+            next_delivery_tag: 1,
+            unacked: BTreeMap::new(),
+            consumers: HashMap::new(),
+            publish: None,
+        }
+    }
+
+    fn stop_consumers(&mut self) {
+        for (_, consumer) in self.consumers.drain() {
+            consumer.task.abort();
+            consumer.queue.remove_consumer();
+        }
+    }
+
+    /// Puts the messages not acknowledged back in their queues, in the order they were delivered.
+    fn requeue_unacked(&mut self) {
+        for (_, unacked) in std::mem::take(&mut self.unacked).into_iter().rev() {
+            unacked.queue.requeue(unacked.message);
+        }
+    }
+
+    fn release(&mut self) {
+        self.stop_consumers();
+        self.requeue_unacked();
+        self.publish = None;
+    }
+}
+
+impl<S: AMQPStream> Connection<S> {
+    async fn run(&mut self) {
+        let period = Duration::from_secs(self.heartbeat as u64);
+        let mut heartbeat =
+            (self.heartbeat > 0).then(|| tokio::time::interval_at(Instant::now() + period / 2, period / 2));
+        let mut last_received = Instant::now();
+
+        loop {
+            let event = tokio::select! {
+                frame = self.framed.next() => Event::Frame(frame),
+                Some(delivery) = self.deliveries.recv() => Event::Delivery(delivery),
+                _ = tick(&mut heartbeat) => Event::Heartbeat,
+            };
+
+            let result = match event {
+                Event::Frame(None) => break,
+                Event::Frame(Some(Err(e))) => {
+                    debug!("Failed to read from AMQP connection: {}", e);
+                    if e.kind() == ErrorKind::InvalidData {
+                        let _ = self.close_connection(FRAME_ERROR, e.to_string(), (0, 0)).await;
+                    }
+                    break;
+                }
+                Event::Frame(Some(Ok(frame))) => {
+                    last_received = Instant::now();
+                    self.handle_frame(frame).await
+                }
+                Event::Delivery(delivery) => self.deliver(delivery).await.map(|_| true),
+                Event::Heartbeat => {
+                    // Clients are given two heartbeat intervals to send anything.
+                    if last_received.elapsed() > period * 2 {
+                        debug!("AMQP heartbeat of {} expired", self.principal);
+                        break;
+                    }
+
+                    self.framed.send(Frame::Heartbeat).await.map(|_| true)
+                }
+            };
+
+            match result {
+                Ok(true) => {}
+                Ok(false) => break,
+                Err(e) => {
+                    warn!("Closing AMQP connection of {}: {}", self.principal, e);
+                    break;
+                }
+            }
+        }
+    }
+
+    /// Stops the consumers of the connection and puts the messages it didn't acknowledge back in
+    /// their queues.
+    fn shutdown(&mut self) {
+        for state in self.channels.values_mut() {
+            state.stop_consumers();
+        }
+
+        // Messages taken by consumers but not delivered yet were taken after the unacknowledged
+        // ones, so they're requeued first.
+        self.deliveries.close();
+        let mut pending = vec![];
+        while let Ok(delivery) = self.deliveries.try_recv() {
+            pending.push(delivery);
+        }
+
+        for delivery in pending.into_iter().rev() {
+            delivery.queue.requeue(delivery.message);
+        }
+
+        for (_, mut state) in self.channels.drain() {
+            state.release();
+        }
+    }
+
+    /// Handles a frame from the client. Returns false when the connection is closed.
+    async fn handle_frame(&mut self, frame: Frame) -> Result<bool, Error> {
+        let (channel, method_id) = match &frame {
+            Frame::Method(channel, method) => (*channel, method.id()),
+            Frame::Header(channel, _) | Frame::Body(channel, _) => (*channel, BASIC_PUBLISH),
+            Frame::Heartbeat => return Ok(true),
+        };
+
+        let result = match frame {
+            Frame::Method(0, method) => return self.handle_connection_method(method).await,
+            Frame::Method(channel, method) => self.handle_method(channel, method).await,
+            Frame::Header(channel, header) => self.handle_header(channel, header).await,
+            Frame::Body(channel, body) => self.handle_body(channel, body).await,
+            Frame::Heartbeat => Ok(()),
+        };
+
+        match result {
+            Ok(()) => Ok(true),
+            Err(Exception::Io(e)) => Err(e),
+            Err(Exception::Channel(reply_code, reply_text)) => {
+                self.close_channel(channel, reply_code, reply_text, method_id).await?;
+                Ok(true)
+            }
+            Err(Exception::Connection(reply_code, reply_text)) => {
+                self.close_connection(reply_code, reply_text, method_id).await?;
+                Ok(false)
+            }
+        }
+    }
+
+    async fn handle_connection_method(&mut self, method: Method) -> Result<bool, Error> {
+        match method {
+            Method::ConnectionClose(close) => {
+                debug!(
+                    "AMQP client {} closed the connection: {} {}",
+                    self.principal, close.reply_code, close.reply_text
+                );
+                self.framed.send(Frame::Method(0, Method::ConnectionCloseOk)).await?;
+            }
+            Method::ConnectionCloseOk => {}
+            method => {
+                let reply_text = format!("COMMAND_INVALID - unexpected method {:?} on channel 0", method.id());
+                self.close_connection(COMMAND_INVALID, reply_text, method.id()).await?;
+            }
+        }
+
+        Ok(false)
+    }
+
+    async fn handle_method(&mut self, channel: u16, method: Method) -> Result<(), Exception> {
+        let state = match self.channels.get_mut(&channel) {
+            Some(state) => state,
+            None if method == Method::ChannelOpen && channel <= CHANNEL_MAX => {
+                self.channels.insert(channel, ChannelState::new());
+                return self.send(channel, Method::ChannelOpenOk).await;
+            }
+            None => {
+                return Err(Exception::Connection(
+                    CHANNEL_ERROR,
+                    format!("CHANNEL_ERROR - channel {} isn't open", channel),
+                ))
+            }
+        };
+
+        if state.closing {
+            // Methods sent before the client saw the channel close are dropped until it confirms.
+            match method {
+                Method::ChannelCloseOk => {
+                    self.channels.remove(&channel);
+                }
+                Method::ChannelClose(_) => {
+                    self.channels.remove(&channel);
+                    self.send(channel, Method::ChannelCloseOk).await?;
+                }
+                _ => {}
+            }
+
+            return Ok(());
+        }
+
+        if state.publish.is_some() {
+            return Err(Exception::Connection(
+                UNEXPECTED_FRAME,
+                "UNEXPECTED_FRAME - expected content of basic.publish".to_string(),
+            ));
+        }
+
+        match method {
+            Method::ChannelClose(_) => {
+                if let Some(mut state) = self.channels.remove(&channel) {
+                    state.release();
+                }
+
+                self.send(channel, Method::ChannelCloseOk).await
+            }
+            Method::ChannelOpen => Err(Exception::Connection(
+                CHANNEL_ERROR,
+                format!("CHANNEL_ERROR - channel {} is already open", channel),
+            )),
+            Method::ExchangeDeclare {
+                exchange,
+                kind,
+                passive,
+                no_wait,
+                ..
+            } => self.exchange_declare(channel, exchange, kind, passive, no_wait).await,
+            Method::ExchangeDelete {
+                exchange,
+                if_unused,
+                no_wait,
+            } => self.exchange_delete(channel, exchange, if_unused, no_wait).await,
+            Method::QueueDeclare {
+                queue,
+                passive,
+                no_wait,
+                ..
+            } => self.queue_declare(channel, queue, passive, no_wait).await,
+            Method::QueueBind {
+                queue,
+                exchange,
+                routing_key,
+                no_wait,
+                ..
+            } => {
+                let binding = self.binding(channel, queue, exchange, routing_key).await?;
+                self.app
+                    .bind(binding.clone())
+                    .map_err(|_| no_exchange(&binding.exchange))?;
+
+                match no_wait {
+                    true => Ok(()),
+                    false => self.send(channel, Method::QueueBindOk).await,
+                }
+            }
+            Method::QueueUnbind {
+                queue,
+                exchange,
+                routing_key,
+                ..
+            } => {
+                let binding = self.binding(channel, queue, exchange, routing_key).await?;
+                self.app.unbind(&binding);
+
+                self.send(channel, Method::QueueUnbindOk).await
+            }
+            Method::BasicQos { prefetch_count, .. } => {
+                // The prefetch applies to the consumers started afterwards, whether global or not.
+                self.state(channel).prefetch = prefetch_count;
+                self.send(channel, Method::BasicQosOk).await
+            }
+            Method::BasicConsume {
+                queue,
+                consumer_tag,
+                no_ack,
+                no_wait,
+                ..
+            } => self.basic_consume(channel, queue, consumer_tag, no_ack, no_wait).await,
+            Method::BasicCancel { consumer_tag, no_wait } => {
+                if let Some(consumer) = self.state(channel).consumers.remove(&consumer_tag) {
+                    consumer.task.abort();
+                    consumer.queue.remove_consumer();
+                }
+
+                match no_wait {
+                    true => Ok(()),
+                    false => self.send(channel, Method::BasicCancelOk { consumer_tag }).await,
+                }
+            }
+            Method::BasicPublish {
+                exchange,
+                routing_key,
+                mandatory,
+                ..
+            } => {
+                self.state(channel).publish = Some(PendingPublish {
+                    exchange,
+                    routing_key,
+                    mandatory,
+                    header: None,
+                    body: BytesMut::new(),
+                });
+
+                Ok(())
+            }
+            Method::BasicAck { delivery_tag, multiple } => self.settle(channel, delivery_tag, multiple, false).await,
+            Method::BasicReject { delivery_tag, requeue } => self.settle(channel, delivery_tag, false, requeue).await,
+            Method::BasicNack {
+                delivery_tag,
+                multiple,
+                requeue,
+            } => self.settle(channel, delivery_tag, multiple, requeue).await,
+            Method::ConfirmSelect { no_wait } => {
+                let state = self.state(channel);
+                state.confirms = state.confirms.or(Some(0));
+
+                match no_wait {
+                    true => Ok(()),
+                    false => self.send(channel, Method::ConfirmSelectOk).await,
+                }
+            }
+            Method::Unsupported { class_id, method_id } => Err(Exception::Connection(
+                NOT_IMPLEMENTED,
+                format!("NOT_IMPLEMENTED - method {}.{} isn't supported", class_id, method_id),
+            )),
+            method => Err(Exception::Connection(
+                COMMAND_INVALID,
+                format!("COMMAND_INVALID - unexpected method {:?}", method.id()),
+            )),
+        }
+    }
+
+    async fn exchange_declare(
+        &mut self,
+        channel: u16,
+        exchange: String,
+        kind: String,
+        passive: bool,
+        no_wait: bool,
+    ) -> Result<(), Exception> {
+        if passive {
+            if !exchange.is_empty() && self.app.exchange(&exchange).is_none() {
+                return Err(no_exchange(&exchange));
+            }
+        } else {
+            let kind = kind.parse::<ExchangeKind>().map_err(|_| {
+                Exception::Connection(
+                    COMMAND_INVALID,
+                    format!("COMMAND_INVALID - unknown exchange type '{}'", kind),
+                )
+            })?;
+
+            // Reserved exchanges can only be declared again as they are.
+            if is_reserved(&exchange) && self.app.exchange(&exchange) != Some(kind) {
+                return Err(Exception::Channel(
+                    ACCESS_REFUSED,
+                    format!("ACCESS_REFUSED - exchange name '{}' is reserved", exchange),
+                ));
+            }
+
+            self.authorize(&Resource::cluster(), Operation::Create)?;
+            self.app.declare_exchange(&exchange, kind).map_err(|_| {
+                Exception::Channel(
+                    PRECONDITION_FAILED,
+                    format!(
+                        "PRECONDITION_FAILED - exchange '{}' was declared with another type",
+                        exchange
+                    ),
+                )
+            })?;
+        }
+
+        match no_wait {
+            true => Ok(()),
+            false => self.send(channel, Method::ExchangeDeclareOk).await,
+        }
+    }
+
+    async fn exchange_delete(
+        &mut self,
+        channel: u16,
+        exchange: String,
+        if_unused: bool,
+        no_wait: bool,
+    ) -> Result<(), Exception> {
+        if is_reserved(&exchange) {
+            return Err(Exception::Channel(
+                ACCESS_REFUSED,
+                format!("ACCESS_REFUSED - exchange '{}' can't be deleted", exchange),
+            ));
+        }
+
+        self.authorize(&Resource::cluster(), Operation::Delete)?;
+
+        if if_unused && self.app.bindings().iter().any(|binding| binding.exchange == exchange) {
+            return Err(Exception::Channel(
+                PRECONDITION_FAILED,
+                format!("PRECONDITION_FAILED - exchange '{}' is in use", exchange),
+            ));
+        }
+
+        if !self.app.delete_exchange(&exchange) {
+            return Err(no_exchange(&exchange));
+        }
+
+        match no_wait {
+            true => Ok(()),
+            false => self.send(channel, Method::ExchangeDeleteOk).await,
+        }
+    }
+
+    async fn queue_declare(
+        &mut self,
+        channel: u16,
+        queue: String,
+        passive: bool,
+        no_wait: bool,
+    ) -> Result<(), Exception> {
+        let name = match queue.is_empty() {
+            true => format!("amq.gen-{}", self.broker.next_id()),
+            false => queue,
+        };
+
+        let operation = match passive {
+            true => Operation::Describe,
+            false => Operation::Create,
+        };
+        self.authorize(&Resource::topic(&name), operation)?;
+
+        if !passive && self.app.get_channel(&(name.clone(), 1)).await.is_none() {
+            // Another connection may have created it in the meantime, which is fine.
+            let _ = self
+                .app
+                .create_channel(ChannelConfig {
+                    name: name.clone(),
+                    partitions: 1,
+                })
+                .await;
+        }
+
+        let queue = self
+            .broker
+            .queue(&self.app, &name)
+            .await
+            .ok_or_else(|| no_queue(&name))?;
+
+        self.state(channel).last_queue = Some(name.clone());
+
+        match no_wait {
+            true => Ok(()),
+            false => {
+                let declare_ok = Method::QueueDeclareOk {
+                    queue: name,
+                    message_count: queue.message_count(),
+                    consumer_count: queue.consumer_count(),
+                };
+
+                self.send(channel, declare_ok).await
+            }
+        }
+    }
+
+    /// Checks a binding to be added or removed.
+    async fn binding(
+        &mut self,
+        channel: u16,
+        queue: String,
+        exchange: String,
+        routing_key: String,
+    ) -> Result<Binding, Exception> {
+        let queue = self.queue_name(channel, queue)?;
+        self.authorize(&Resource::topic(&queue), Operation::Create)?;
+
+        if exchange.is_empty() {
+            return Err(Exception::Channel(
+                ACCESS_REFUSED,
+                "ACCESS_REFUSED - the default exchange can't be bound".to_string(),
+            ));
+        }
+
+        if self.app.get_channel(&(queue.clone(), 1)).await.is_none() {
+            return Err(no_queue(&queue));
+        }
+
+        Ok(Binding {
+            exchange,
+            queue,
+            routing_key,
+        })
+    }
+
+    async fn basic_consume(
+        &mut self,
+        channel: u16,
+        queue: String,
+        consumer_tag: String,
+        no_ack: bool,
+        no_wait: bool,
+    ) -> Result<(), Exception> {
+        let name = self.queue_name(channel, queue)?;
+        self.authorize(&Resource::topic(&name), Operation::Consume)?;
+
+        let queue = self
+            .broker
+            .queue(&self.app, &name)
+            .await
+            .ok_or_else(|| no_queue(&name))?;
+
+        let consumer_tag = match consumer_tag.is_empty() {
+            true => format!("amq.ctag-{}", self.broker.next_id()),
+            false => consumer_tag,
+        };
+
+        let state = self.state(channel);
+        if state.consumers.contains_key(&consumer_tag) {
+            return Err(Exception::Connection(
+                NOT_ALLOWED,
+                format!("NOT_ALLOWED - consumer tag '{}' is already in use", consumer_tag),
+            ));
+        }
+
+        // Messages consumed without acknowledgement aren't limited by the prefetch.
+        let credit = (!no_ack && state.prefetch > 0).then(|| Arc::new(Semaphore::new(state.prefetch as usize)));
+
+        if !no_wait {
+            let consume_ok = Method::BasicConsumeOk {
+                consumer_tag: consumer_tag.clone(),
+            };
+            self.send(channel, consume_ok).await?;
+        }
+
+        let id = self.broker.next_id();
+        let task = tokio::spawn(consume(
+            channel,
+            consumer_tag.clone(),
+            id,
+            queue.clone(),
+            credit,
+            self.deliveries_tx.clone(),
+        ));
+
+        queue.add_consumer();
+        self.state(channel).consumers.insert(
+            consumer_tag,
+            ConsumerState {
+                id,
+                queue,
+                no_ack,
+                task,
+            },
+        );
+
+        Ok(())
+    }
+
+    /// Acknowledges or rejects the deliveries up to `delivery_tag` when `multiple` is set, or only
+    /// that one. A zero tag with `multiple` settles every outstanding delivery. Rejected messages
+    /// are either requeued or dropped.
+    async fn settle(
+        &mut self,
+        channel: u16,
+        delivery_tag: u64,
+        multiple: bool,
+        requeue: bool,
+    ) -> Result<(), Exception> {
+        let state = self.state(channel);
+
+        if !(multiple && delivery_tag == 0) && !state.unacked.contains_key(&delivery_tag) {
+            return Err(Exception::Channel(
+                PRECONDITION_FAILED,
+                format!("PRECONDITION_FAILED - unknown delivery tag {}", delivery_tag),
+            ));
+        }
+
+        let settled = match (multiple, delivery_tag) {
+            (true, 0) => std::mem::take(&mut state.unacked),
+            (true, _) => {
+                let rest = state.unacked.split_off(&delivery_tag.saturating_add(1));
+                std::mem::replace(&mut state.unacked, rest)
+            }
+            (false, _) => state.unacked.remove_entry(&delivery_tag).into_iter().collect(),
+        };
+
+        for (_, unacked) in settled.into_iter().rev() {
+            match requeue {
+                true => unacked.queue.requeue(unacked.message),
+                false => unacked.queue.ack(unacked.message.record.offset).await,
+            }
+
+            if let Some(credit) = unacked.credit {
+                credit.add_permits(1);
+            }
+        }
+
+        Ok(())
+    }
+
+    async fn handle_header(&mut self, channel: u16, header: ContentHeader) -> Result<(), Exception> {
+        let body_size = header.body_size;
+
+        match self.publish(channel)? {
+            Some(publish) if publish.header.is_none() => publish.header = Some(header),
+            Some(_) => return Err(unexpected_content()),
+            None => return Ok(()),
+        }
+
+        match body_size {
+            0 => self.finish_publish(channel).await,
+            _ => Ok(()),
+        }
+    }
+
+    async fn handle_body(&mut self, channel: u16, body: Bytes) -> Result<(), Exception> {
+        let publish = match self.publish(channel)? {
+            Some(publish) => publish,
+            None => return Ok(()),
+        };
+
+        let body_size = match &publish.header {
+            Some(header) => header.body_size,
+            None => return Err(unexpected_content()),
+        };
+
+        publish.body.extend_from_slice(&body);
+        if publish.body.len() as u64 > body_size {
+            return Err(Exception::Connection(
+                FRAME_ERROR,
+                "FRAME_ERROR - content exceeds the body size of its header".to_string(),
+            ));
+        }
+
+        match publish.body.len() as u64 == body_size {
+            true => self.finish_publish(channel).await,
+            false => Ok(()),
+        }
+    }
+
+    /// Returns the message being published on `channel`, which content frames belong to. Returns
+    /// `None` when the channel is closing and content is dropped.
+    fn publish(&mut self, channel: u16) -> Result<Option<&mut PendingPublish>, Exception> {
+        match self.channels.get_mut(&channel) {
+            Some(state) if state.closing => Ok(None),
+            Some(ChannelState {
+                publish: Some(publish), ..
+            }) => Ok(Some(publish)),
+            _ => Err(unexpected_content()),
+        }
+    }
+
+    /// Routes a message whose content was all received to the queues bound.
+    async fn finish_publish(&mut self, channel: u16) -> Result<(), Exception> {
+        let publish = self.state(channel).publish.take().unwrap();
+        let body = publish.body.freeze();
+
+        let queues = self
+            .app
+            .route(&publish.exchange, &publish.routing_key)
+            .ok_or_else(|| no_exchange(&publish.exchange))?;
+
+        let mut channels = Vec::with_capacity(queues.len());
+        for queue in queues {
+            self.authorize(&Resource::topic(&queue), Operation::Produce)?;
+
+            // The default exchange routes to queues that may not exist.
+            if let Some(channel) = self.app.get_channel(&(queue, 1)).await {
+                channels.push(channel);
+            }
+        }
+
+        for target in &channels {
+            target
+                .producer()
+                .produce(&mut vec![Record::new(publish.routing_key.as_bytes(), body.to_vec())])
+                .await;
+        }
+
+        if channels.is_empty() && publish.mandatory {
+            let basic_return = Method::BasicReturn {
+                reply_code: NO_ROUTE,
+                reply_text: "NO_ROUTE".to_string(),
+                exchange: publish.exchange,
+                routing_key: publish.routing_key,
+            };
+            self.send_content(channel, basic_return, body).await?;
+        }
+
+        if let Some(sequence) = self.state(channel).confirms.as_mut() {
+            *sequence += 1;
+            let ack = Method::BasicAck {
+                delivery_tag: *sequence,
+                multiple: false,
+            };
+            self.send(channel, ack).await?;
+        }
+
+        Ok(())
+    }
+
+    /// Sends a message taken by a consumer, unless the consumer was cancelled in the meantime.
+    async fn deliver(&mut self, delivery: Delivery) -> Result<(), Error> {
+        let state = match self.channels.get_mut(&delivery.channel) {
+            Some(state) if !state.closing => state,
+            _ => {
+                delivery.queue.requeue(delivery.message);
+                return Ok(());
+            }
+        };
+
+        let no_ack = match state.consumers.get(&delivery.consumer_tag) {
+            Some(consumer) if consumer.id == delivery.consumer_id => consumer.no_ack,
+            _ => {
+                delivery.queue.requeue(delivery.message);
+                return Ok(());
+            }
+        };
+
+        let delivery_tag = state.next_delivery_tag;
+        state.next_delivery_tag += 1;
+
+        let record = &delivery.message.record;
+        let deliver = Method::BasicDeliver {
+            consumer_tag: delivery.consumer_tag,
+            delivery_tag,
+            redelivered: delivery.message.redelivered,
+            exchange: String::new(),
+            routing_key: String::from_utf8_lossy(&record.key).into_owned(),
+        };
+        let body = Bytes::from(record.value.clone());
+
+        match no_ack {
+            true => delivery.queue.ack(record.offset).await,
+            false => {
+                state.unacked.insert(
+                    delivery_tag,
+                    Unacked {
+                        queue: delivery.queue,
+                        message: delivery.message,
+                        credit: delivery.credit,
+                    },
+                );
+            }
+        }
+
+        self.send_content(delivery.channel, deliver, body).await
+    }
+
+    fn queue_name(&mut self, channel: u16, queue: String) -> Result<String, Exception> {
+        if !queue.is_empty() {
+            return Ok(queue);
+        }
+
+        self.state(channel)
+            .last_queue
+            .clone()
+            .ok_or_else(|| Exception::Channel(NOT_FOUND, "NOT_FOUND - no previously declared queue".to_string()))
+    }
+
+    fn authorize(&self, resource: &Resource, operation: Operation) -> Result<(), Exception> {
+        match self.app.authorize(&self.principal, resource, operation) {
+            true => Ok(()),
+            false => Err(Exception::Channel(
+                ACCESS_REFUSED,
+                format!(
+                    "ACCESS_REFUSED - not allowed to {} {} {}",
+                    operation, resource.resource_type, resource.name
+                ),
+            )),
+        }
+    }
+
+    fn state(&mut self, channel: u16) -> &mut ChannelState {
+        self.channels.get_mut(&channel).expect("channel is open")
+    }
+
+    async fn close_channel(
+        &mut self,
+        channel: u16,
+        reply_code: u16,
+        reply_text: String,
+        (class_id, method_id): (u16, u16),
+    ) -> Result<(), Error> {
+        debug!("Closing AMQP channel {} of {}: {}", channel, self.principal, reply_text);
+
+        if let Some(state) = self.channels.get_mut(&channel) {
+            state.release();
+            state.closing = true;
+        }
+
+        let close = Close {
+            reply_code,
+            reply_text,
+            class_id,
+            method_id,
+        };
+        self.framed
+            .send(Frame::Method(channel, Method::ChannelClose(close)))
+            .await
+    }
+
+    async fn close_connection(
+        &mut self,
+        reply_code: u16,
+        reply_text: String,
+        (class_id, method_id): (u16, u16),
+    ) -> Result<(), Error> {
+        debug!("Closing AMQP connection of {}: {}", self.principal, reply_text);
+
+        let close = Close {
+            reply_code,
+            reply_text,
+            class_id,
+            method_id,
+        };
+        self.framed.send(Frame::Method(0, Method::ConnectionClose(close))).await
+    }
+
+    async fn send(&mut self, channel: u16, method: Method) -> Result<(), Exception> {
+        Ok(self.framed.send(Frame::Method(channel, method)).await?)
+    }
+
+    /// Sends a method carrying a message, splitting its body so that frames fit the frame max.
+    async fn send_content(&mut self, channel: u16, method: Method, mut body: Bytes) -> Result<(), Error> {
+        self.framed.feed(Frame::Method(channel, method)).await?;
+        self.framed
+            .feed(Frame::Header(channel, ContentHeader::basic(body.len() as u64)))
+            .await?;
+
+        let chunk_size = self.frame_max - FRAME_OVERHEAD;
+        while !body.is_empty() {
+            let chunk = body.split_to(body.len().min(chunk_size));
+            self.framed.feed(Frame::Body(channel, chunk)).await?;
+        }
+
+        self.framed.flush().await
+    }
+}
+
+/// Takes messages from a queue for a consumer, as long as it has credit left.
+async fn consume(
+    channel: u16,
+    consumer_tag: String,
+    consumer_id: u64,
+    queue: Arc<Queue>,
+    credit: Option<Arc<Semaphore>>,
+    deliveries: UnboundedSender<Delivery>,
+) {
+    loop {
+        if let Some(credit) = &credit {
+            match credit.acquire().await {
+                Ok(permit) => permit.forget(),
+                Err(_) => return,
+            }
+        }
+
+        let message = queue.next().await;
+        let delivery = Delivery {
+            channel,
+            consumer_tag: consumer_tag.clone(),
+            consumer_id,
+            queue: queue.clone(),
+            message,
+            credit: credit.clone(),
+        };
+
+        if let Err(e) = deliveries.send(delivery) {
+            queue.requeue(e.0.message);
+            return;
+        }
+    }
+}
+
+async fn tick(heartbeat: &mut Option<Interval>) {
+    match heartbeat {
+        Some(interval) => {
+            interval.tick().await;
+        }
+        None => std::future::pending().await,
+    }
+}
+
+/// Exchanges clients can't declare or delete: the default exchange and the `amq.` ones.
+fn is_reserved(exchange: &str) -> bool {
+    exchange.is_empty() || exchange.starts_with("amq.")
+}
+
+fn no_exchange(exchange: &str) -> Exception {
+    Exception::Channel(NOT_FOUND, format!("NOT_FOUND - no exchange '{}'", exchange))
+}
+
+fn no_queue(queue: &str) -> Exception {
+    Exception::Channel(NOT_FOUND, format!("NOT_FOUND - no queue '{}'", queue))
+}
+
+fn unexpected_content() -> Exception {
+    Exception::Connection(
+        UNEXPECTED_FRAME,
+        "UNEXPECTED_FRAME - content without basic.publish".to_string(),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use packline_core::app::channel::consumer_group_id;
+    use tokio::io::DuplexStream;
+
+    use super::*;
+
+    type Client = Framed<DuplexStream, AMQPCodec>;
+
+    fn open(connector: &AMQPConnector) -> DuplexStream {
+        let (client, server) = tokio::io::duplex(256 * 1024);
+        let mut handler = connector.connection_handler(server, "test".to_string());
+        tokio::spawn(async move { handler.handle().await });
+
+        client
+    }
+
+    async fn start(connector: &AMQPConnector, response: &'static [u8]) -> Client {
+        let mut stream = open(connector);
+        stream.write_all(PROTOCOL_HEADER).await.unwrap();
+
+        let mut client = Framed::new(stream, AMQPCodec::new());
+        assert!(matches!(method(&mut client).await, Method::ConnectionStart { .. }));
+
+        client
+            .send(Frame::Method(
+                0,
+                Method::ConnectionStartOk {
+                    client_properties: Bytes::new(),
+                    mechanism: "PLAIN".to_string(),
+                    response: Bytes::from_static(response),
+                    locale: "en_US".to_string(),
+                },
+            ))
+            .await
+            .unwrap();
+
+        client
+    }
+
+    /// Connects and opens channel 1.
+    async fn connect(connector: &AMQPConnector) -> Client {
+        let mut client = start(connector, b"\0guest\0guest").await;
+        assert!(matches!(method(&mut client).await, Method::ConnectionTune { .. }));
+
+        let tune_ok = Method::ConnectionTuneOk {
+            channel_max: CHANNEL_MAX,
+            frame_max: FRAME_MAX,
+            heartbeat: 0,
+        };
+        client.send(Frame::Method(0, tune_ok)).await.unwrap();
+        client.codec_mut().set_max_frame_size(FRAME_MAX as usize);
+
+        let open = Method::ConnectionOpen {
+            virtual_host: "/".to_string(),
+        };
+        client.send(Frame::Method(0, open)).await.unwrap();
+        assert_eq!(Method::ConnectionOpenOk, method(&mut client).await);
+
+        assert_eq!(Method::ChannelOpenOk, call(&mut client, 1, Method::ChannelOpen).await);
+        client
+    }
+
+    async fn next(client: &mut Client) -> Frame {
+        tokio::time::timeout(Duration::from_secs(2), client.next())
+            .await
+            .expect("timed out waiting for a frame")
+            .expect("connection closed")
+            .unwrap()
+    }
+
+    async fn method(client: &mut Client) -> Method {
+        match next(client).await {
+            Frame::Method(_, method) => method,
+            frame => panic!("unexpected frame {:?}", frame),
+        }
+    }
+
+    async fn call(client: &mut Client, channel: u16, method: Method) -> Method {
+        client.send(Frame::Method(channel, method)).await.unwrap();
+        self::method(client).await
+    }
+
+    /// Reads a method carrying content along with its body.
+    async fn content(client: &mut Client) -> (Method, Bytes) {
+        let method = method(client).await;
+        let size = match next(client).await {
+            Frame::Header(_, header) => header.body_size as usize,
+            frame => panic!("unexpected frame {:?}", frame),
+        };
+
+        let mut body = BytesMut::new();
+        while body.len() < size {
+            match next(client).await {
+                Frame::Body(_, chunk) => body.extend_from_slice(&chunk),
+                frame => panic!("unexpected frame {:?}", frame),
+            }
+        }
+
+        (method, body.freeze())
+    }
+
+    async fn publish(client: &mut Client, exchange: &str, routing_key: &str, body: &'static [u8], mandatory: bool) {
+        let publish = Method::BasicPublish {
+            exchange: exchange.to_string(),
+            routing_key: routing_key.to_string(),
+            mandatory,
+            immediate: false,
+        };
+
+        client.feed(Frame::Method(1, publish)).await.unwrap();
+        client
+            .feed(Frame::Header(1, ContentHeader::basic(body.len() as u64)))
+            .await
+            .unwrap();
+        client.feed(Frame::Body(1, Bytes::from_static(body))).await.unwrap();
+        client.flush().await.unwrap();
+    }
+
+    async fn declare_queue(client: &mut Client, queue: &str) {
+        let declare = Method::QueueDeclare {
+            queue: queue.to_string(),
+            passive: false,
+            durable: true,
+            exclusive: false,
+            auto_delete: false,
+            no_wait: false,
+            arguments: Bytes::new(),
+        };
+
+        assert!(matches!(call(client, 1, declare).await, Method::QueueDeclareOk { .. }));
+    }
+
+    async fn consume(client: &mut Client, channel: u16, queue: &str) {
+        let consume = Method::BasicConsume {
+            queue: queue.to_string(),
+            consumer_tag: "ctag".to_string(),
+            no_local: false,
+            no_ack: false,
+            exclusive: false,
+            no_wait: false,
+            arguments: Bytes::new(),
+        };
+
+        assert_eq!(
+            Method::BasicConsumeOk {
+                consumer_tag: "ctag".to_string()
+            },
+            call(client, channel, consume).await
+        );
+    }
+
+    /// Waits for the methods sent before to be handled.
+    async fn sync(client: &mut Client) {
+        let qos = Method::BasicQos {
+            prefetch_size: 0,
+            prefetch_count: 0,
+            global: false,
+        };
+
+        assert_eq!(Method::BasicQosOk, call(client, 1, qos).await);
+    }
+
+    fn delivered(method: Method) -> (u64, bool) {
+        match method {
+            Method::BasicDeliver {
+                delivery_tag,
+                redelivered,
+                ..
+            } => (delivery_tag, redelivered),
+            method => panic!("unexpected method {:?}", method),
+        }
+    }
+
+    async fn committed(app: &App, queue: &str) -> Option<u64> {
+        let channel = app.get_channel(&(queue.to_string(), 1)).await.unwrap();
+        channel.offsets(consumer_group_id(&format!("amqp/{}", queue))).await.0
+    }
+
+    #[tokio::test]
+    async fn test_publish_consume_and_ack() {
+        let connector = AMQPConnector::new(App::new());
+        let mut client = connect(&connector).await;
+
+        declare_queue(&mut client, "billing").await;
+        publish(&mut client, "", "billing", b"first", false).await;
+        publish(&mut client, "", "billing", b"second", false).await;
+        consume(&mut client, 1, "billing").await;
+
+        let (deliver, body) = content(&mut client).await;
+        assert_eq!((1, false), delivered(deliver));
+        assert_eq!(&b"first"[..], &body[..]);
+
+        let (deliver, body) = content(&mut client).await;
+        assert_eq!((2, false), delivered(deliver));
+        assert_eq!(&b"second"[..], &body[..]);
+
+        let ack = Method::BasicAck {
+            delivery_tag: 2,
+            multiple: true,
+        };
+        client.send(Frame::Method(1, ack)).await.unwrap();
+        sync(&mut client).await;
+
+        assert_eq!(Some(2), committed(&connector.app, "billing").await);
+    }
+
+    #[tokio::test]
+    async fn test_topic_exchange_routing_with_confirms() {
+        let connector = AMQPConnector::new(App::new());
+        let mut client = connect(&connector).await;
+
+        let declare = Method::ExchangeDeclare {
+            exchange: "events".to_string(),
+            kind: "topic".to_string(),
+            passive: false,
+            durable: true,
+            auto_delete: false,
+            internal: false,
+            no_wait: false,
+            arguments: Bytes::new(),
+        };
+        assert_eq!(Method::ExchangeDeclareOk, call(&mut client, 1, declare).await);
+
+        for (queue, routing_key) in [("eu", "*.eu"), ("all", "#")] {
+            declare_queue(&mut client, queue).await;
+
+            let bind = Method::QueueBind {
+                queue: queue.to_string(),
+                exchange: "events".to_string(),
+                routing_key: routing_key.to_string(),
+                no_wait: false,
+                arguments: Bytes::new(),
+            };
+            assert_eq!(Method::QueueBindOk, call(&mut client, 1, bind).await);
+        }
+
+        let select = Method::ConfirmSelect { no_wait: false };
+        assert_eq!(Method::ConfirmSelectOk, call(&mut client, 1, select).await);
+
+        for (sequence, routing_key) in [(1, "orders.eu"), (2, "orders.us")] {
+            publish(&mut client, "events", routing_key, b"order", false).await;
+            assert_eq!(
+                Method::BasicAck {
+                    delivery_tag: sequence,
+                    multiple: false
+                },
+                method(&mut client).await
+            );
+        }
+
+        let end_offset = |queue: &str| {
+            let app = connector.app.clone();
+            let queue = queue.to_string();
+            async move { app.get_channel(&(queue, 1)).await.unwrap().end_offset() }
+        };
+        assert_eq!(1, end_offset("eu").await);
+        assert_eq!(2, end_offset("all").await);
+    }
+
+    #[tokio::test]
+    async fn test_prefetch_limits_unacknowledged_deliveries() {
+        let connector = AMQPConnector::new(App::new());
+        let mut client = connect(&connector).await;
+
+        declare_queue(&mut client, "billing").await;
+        publish(&mut client, "", "billing", b"first", false).await;
+        publish(&mut client, "", "billing", b"second", false).await;
+
+        let qos = Method::BasicQos {
+            prefetch_size: 0,
+            prefetch_count: 1,
+            global: false,
+        };
+        assert_eq!(Method::BasicQosOk, call(&mut client, 1, qos).await);
+        consume(&mut client, 1, "billing").await;
+
+        let (deliver, _) = content(&mut client).await;
+        assert_eq!((1, false), delivered(deliver));
+        assert!(
+            tokio::time::timeout(Duration::from_millis(200), client.next())
+                .await
+                .is_err(),
+            "delivered past the prefetch"
+        );
+
+        let ack = Method::BasicAck {
+            delivery_tag: 1,
+            multiple: false,
+        };
+        client.send(Frame::Method(1, ack)).await.unwrap();
+
+        let (deliver, body) = content(&mut client).await;
+        assert_eq!((2, false), delivered(deliver));
+        assert_eq!(&b"second"[..], &body[..]);
+    }
+
+    #[tokio::test]
+    async fn test_nack_requeues_or_drops() {
+        let connector = AMQPConnector::new(App::new());
+        let mut client = connect(&connector).await;
+
+        declare_queue(&mut client, "billing").await;
+        publish(&mut client, "", "billing", b"first", false).await;
+        consume(&mut client, 1, "billing").await;
+
+        let (deliver, _) = content(&mut client).await;
+        assert_eq!((1, false), delivered(deliver));
+
+        let nack = Method::BasicNack {
+            delivery_tag: 1,
+            multiple: false,
+            requeue: true,
+        };
+        client.send(Frame::Method(1, nack)).await.unwrap();
+
+        let (deliver, body) = content(&mut client).await;
+        assert_eq!((2, true), delivered(deliver));
+        assert_eq!(&b"first"[..], &body[..]);
+
+        let reject = Method::BasicReject {
+            delivery_tag: 2,
+            requeue: false,
+        };
+        client.send(Frame::Method(1, reject)).await.unwrap();
+        sync(&mut client).await;
+
+        assert_eq!(Some(1), committed(&connector.app, "billing").await);
+    }
+
+    #[tokio::test]
+    async fn test_closing_channel_requeues_unacknowledged() {
+        let connector = AMQPConnector::new(App::new());
+        let mut client = connect(&connector).await;
+
+        declare_queue(&mut client, "billing").await;
+        publish(&mut client, "", "billing", b"first", false).await;
+        consume(&mut client, 1, "billing").await;
+        content(&mut client).await;
+
+        let close = Method::ChannelClose(Close {
+            reply_code: REPLY_SUCCESS,
+            reply_text: "bye".to_string(),
+            class_id: 0,
+            method_id: 0,
+        });
+        assert_eq!(Method::ChannelCloseOk, call(&mut client, 1, close).await);
+
+        assert_eq!(Method::ChannelOpenOk, call(&mut client, 2, Method::ChannelOpen).await);
+        consume(&mut client, 2, "billing").await;
+
+        let (deliver, body) = content(&mut client).await;
+        assert_eq!((1, true), delivered(deliver));
+        assert_eq!(&b"first"[..], &body[..]);
+    }
+
+    #[tokio::test]
+    async fn test_passive_declare_of_missing_queue_closes_channel() {
+        let connector = AMQPConnector::new(App::new());
+        let mut client = connect(&connector).await;
+
+        let declare = Method::QueueDeclare {
+            queue: "missing".to_string(),
+            passive: true,
+            durable: false,
+            exclusive: false,
+            auto_delete: false,
+            no_wait: false,
+            arguments: Bytes::new(),
+        };
+
+        match call(&mut client, 1, declare).await {
+            Method::ChannelClose(close) => {
+                assert_eq!(NOT_FOUND, close.reply_code);
+                assert_eq!((50, 10), (close.class_id, close.method_id));
+            }
+            method => panic!("unexpected method {:?}", method),
+        }
+
+        // The channel ignores anything but the close confirmation, then can be opened again.
+        let qos = Method::BasicQos {
+            prefetch_size: 0,
+            prefetch_count: 0,
+            global: false,
+        };
+        client.send(Frame::Method(1, qos)).await.unwrap();
+        client.send(Frame::Method(1, Method::ChannelCloseOk)).await.unwrap();
+        assert_eq!(Method::ChannelOpenOk, call(&mut client, 1, Method::ChannelOpen).await);
+    }
+
+    #[tokio::test]
+    async fn test_mandatory_publish_without_route_is_returned() {
+        let connector = AMQPConnector::new(App::new());
+        let mut client = connect(&connector).await;
+
+        publish(&mut client, "amq.direct", "nowhere", b"lost", true).await;
+
+        let (basic_return, body) = content(&mut client).await;
+        assert_eq!(
+            Method::BasicReturn {
+                reply_code: NO_ROUTE,
+                reply_text: "NO_ROUTE".to_string(),
+                exchange: "amq.direct".to_string(),
+                routing_key: "nowhere".to_string(),
+            },
+            basic_return
+        );
+        assert_eq!(&b"lost"[..], &body[..]);
+    }
+
+    #[tokio::test]
+    async fn test_unsupported_protocol_header() {
+        let connector = AMQPConnector::new(App::new());
+        let mut stream = open(&connector);
+
+        stream.write_all(b"AMQP\x01\x01\x00\x09").await.unwrap();
+
+        let mut header = [0; 8];
+        stream.read_exact(&mut header).await.unwrap();
+        assert_eq!(PROTOCOL_HEADER, &header);
+    }
+
+    #[tokio::test]
+    async fn test_refuses_bad_credentials() {
+        let mut store = CredentialStore::new();
+        store.add_user("alice", "secret").await;
+        let connector = AMQPConnector::new(App::new()).with_credentials(store);
+
+        let mut client = start(&connector, b"\0alice\0wrong").await;
+        match method(&mut client).await {
+            Method::ConnectionClose(close) => assert_eq!(ACCESS_REFUSED, close.reply_code),
+            method => panic!("unexpected method {:?}", method),
+        }
+
+        let mut client = start(&connector, b"\0alice\0secret").await;
+        assert!(matches!(method(&mut client).await, Method::ConnectionTune { .. }));
+    }
+}
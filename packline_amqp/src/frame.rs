@@ -0,0 +1,925 @@
+use std::io::{Error, ErrorKind};
+
+use bytes::{BufMut, Bytes, BytesMut};
+
+/// Header a client opens the connection with, announcing AMQP 0-9-1.
+pub const PROTOCOL_HEADER: &[u8; 8] = b"AMQP\x00\x00\x09\x01";
+
+/// Octet closing every frame.
+pub const FRAME_END: u8 = 0xCE;
+
+/// Reply codes used to close channels and connections, named as in the AMQP specification.
+pub mod reply_code {
+    pub const REPLY_SUCCESS: u16 = 200;
+    pub const NO_ROUTE: u16 = 312;
+    pub const ACCESS_REFUSED: u16 = 403;
+    pub const NOT_FOUND: u16 = 404;
+    pub const PRECONDITION_FAILED: u16 = 406;
+    pub const FRAME_ERROR: u16 = 501;
+    pub const SYNTAX_ERROR: u16 = 502;
+    pub const COMMAND_INVALID: u16 = 503;
+    pub const CHANNEL_ERROR: u16 = 504;
+    pub const UNEXPECTED_FRAME: u16 = 505;
+    pub const NOT_ALLOWED: u16 = 530;
+    pub const NOT_IMPLEMENTED: u16 = 540;
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Frame {
+    Method(u16, Method),
+    Header(u16, ContentHeader),
+    Body(u16, Bytes),
+    Heartbeat,
+}
+
+/// Header preceding the body of a published or delivered message.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ContentHeader {
+    pub class_id: u16,
+    pub body_size: u64,
+
+    /// Property flags followed by the properties they announce, kept undecoded.
+    pub properties: Bytes,
+}
+
+impl ContentHeader {
+    /// Header of a basic message without properties.
+    pub fn basic(body_size: u64) -> ContentHeader {
+        ContentHeader {
+            class_id: CLASS_BASIC,
+            body_size,
+            properties: Bytes::from_static(&[0, 0]),
+        }
+    }
+}
+
+const CLASS_CONNECTION: u16 = 10;
+const CLASS_CHANNEL: u16 = 20;
+const CLASS_EXCHANGE: u16 = 40;
+const CLASS_QUEUE: u16 = 50;
+const CLASS_BASIC: u16 = 60;
+const CLASS_CONFIRM: u16 = 85;
+
+/// Methods of the classes the connector implements. Methods of other classes are decoded as
+/// [`Method::Unsupported`] so that the peer can be told.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Method {
+    ConnectionStart {
+        server_properties: Bytes,
+        mechanisms: String,
+        locales: String,
+    },
+    ConnectionStartOk {
+        client_properties: Bytes,
+        mechanism: String,
+        response: Bytes,
+        locale: String,
+    },
+    ConnectionTune {
+        channel_max: u16,
+        frame_max: u32,
+        heartbeat: u16,
+    },
+    ConnectionTuneOk {
+        channel_max: u16,
+        frame_max: u32,
+        heartbeat: u16,
+    },
+    ConnectionOpen {
+        virtual_host: String,
+    },
+    ConnectionOpenOk,
+    ConnectionClose(Close),
+    ConnectionCloseOk,
+    ChannelOpen,
+    ChannelOpenOk,
+    ChannelClose(Close),
+    ChannelCloseOk,
+    ExchangeDeclare {
+        exchange: String,
+        kind: String,
+        passive: bool,
+        durable: bool,
+        auto_delete: bool,
+        internal: bool,
+        no_wait: bool,
+        arguments: Bytes,
+    },
+    ExchangeDeclareOk,
+    ExchangeDelete {
+        exchange: String,
+        if_unused: bool,
+        no_wait: bool,
+    },
+    ExchangeDeleteOk,
+    QueueDeclare {
+        queue: String,
+        passive: bool,
+        durable: bool,
+        exclusive: bool,
+        auto_delete: bool,
+        no_wait: bool,
+        arguments: Bytes,
+    },
+    QueueDeclareOk {
+        queue: String,
+        message_count: u32,
+        consumer_count: u32,
+    },
+    QueueBind {
+        queue: String,
+        exchange: String,
+        routing_key: String,
+        no_wait: bool,
+        arguments: Bytes,
+    },
+    QueueBindOk,
+    QueueUnbind {
+        queue: String,
+        exchange: String,
+        routing_key: String,
+        arguments: Bytes,
+    },
+    QueueUnbindOk,
+    BasicQos {
+        prefetch_size: u32,
+        prefetch_count: u16,
+        global: bool,
+    },
+    BasicQosOk,
+    BasicConsume {
+        queue: String,
+        consumer_tag: String,
+        no_local: bool,
+        no_ack: bool,
+        exclusive: bool,
+        no_wait: bool,
+        arguments: Bytes,
+    },
+    BasicConsumeOk {
+        consumer_tag: String,
+    },
+    BasicCancel {
+        consumer_tag: String,
+        no_wait: bool,
+    },
+    BasicCancelOk {
+        consumer_tag: String,
+    },
+    BasicPublish {
+        exchange: String,
+        routing_key: String,
+        mandatory: bool,
+        immediate: bool,
+    },
+    BasicReturn {
+        reply_code: u16,
+        reply_text: String,
+        exchange: String,
+        routing_key: String,
+    },
+    BasicDeliver {
+        consumer_tag: String,
+        delivery_tag: u64,
+        redelivered: bool,
+        exchange: String,
+        routing_key: String,
+    },
+    BasicAck {
+        delivery_tag: u64,
+        multiple: bool,
+    },
+    BasicReject {
+        delivery_tag: u64,
+        requeue: bool,
+    },
+    BasicNack {
+        delivery_tag: u64,
+        multiple: bool,
+        requeue: bool,
+    },
+    ConfirmSelect {
+        no_wait: bool,
+    },
+    ConfirmSelectOk,
+    Unsupported {
+        class_id: u16,
+        method_id: u16,
+    },
+}
+
+/// Arguments of `connection.close` and `channel.close`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Close {
+    pub reply_code: u16,
+    pub reply_text: String,
+
+    /// Method that caused the close, zero when there's none.
+    pub class_id: u16,
+    pub method_id: u16,
+}
+
+impl Method {
+    /// Returns the class and method ids identifying the method.
+    pub fn id(&self) -> (u16, u16) {
+        match self {
+            Method::ConnectionStart { .. } => (CLASS_CONNECTION, 10),
+            Method::ConnectionStartOk { .. } => (CLASS_CONNECTION, 11),
+            Method::ConnectionTune { .. } => (CLASS_CONNECTION, 30),
+            Method::ConnectionTuneOk { .. } => (CLASS_CONNECTION, 31),
+            Method::ConnectionOpen { .. } => (CLASS_CONNECTION, 40),
+            Method::ConnectionOpenOk => (CLASS_CONNECTION, 41),
+            Method::ConnectionClose(_) => (CLASS_CONNECTION, 50),
+            Method::ConnectionCloseOk => (CLASS_CONNECTION, 51),
+            Method::ChannelOpen => (CLASS_CHANNEL, 10),
+            Method::ChannelOpenOk => (CLASS_CHANNEL, 11),
+            Method::ChannelClose(_) => (CLASS_CHANNEL, 40),
+            Method::ChannelCloseOk => (CLASS_CHANNEL, 41),
+            Method::ExchangeDeclare { .. } => (CLASS_EXCHANGE, 10),
+            Method::ExchangeDeclareOk => (CLASS_EXCHANGE, 11),
+            Method::ExchangeDelete { .. } => (CLASS_EXCHANGE, 20),
+            Method::ExchangeDeleteOk => (CLASS_EXCHANGE, 21),
+            Method::QueueDeclare { .. } => (CLASS_QUEUE, 10),
+            Method::QueueDeclareOk { .. } => (CLASS_QUEUE, 11),
+            Method::QueueBind { .. } => (CLASS_QUEUE, 20),
+            Method::QueueBindOk => (CLASS_QUEUE, 21),
+            Method::QueueUnbind { .. } => (CLASS_QUEUE, 50),
+            Method::QueueUnbindOk => (CLASS_QUEUE, 51),
+            Method::BasicQos { .. } => (CLASS_BASIC, 10),
+            Method::BasicQosOk => (CLASS_BASIC, 11),
+            Method::BasicConsume { .. } => (CLASS_BASIC, 20),
+            Method::BasicConsumeOk { .. } => (CLASS_BASIC, 21),
+            Method::BasicCancel { .. } => (CLASS_BASIC, 30),
+            Method::BasicCancelOk { .. } => (CLASS_BASIC, 31),
+            Method::BasicPublish { .. } => (CLASS_BASIC, 40),
+            Method::BasicReturn { .. } => (CLASS_BASIC, 50),
+            Method::BasicDeliver { .. } => (CLASS_BASIC, 60),
+            Method::BasicAck { .. } => (CLASS_BASIC, 80),
+            Method::BasicReject { .. } => (CLASS_BASIC, 90),
+            Method::BasicNack { .. } => (CLASS_BASIC, 120),
+            Method::ConfirmSelect { .. } => (CLASS_CONFIRM, 10),
+            Method::ConfirmSelectOk => (CLASS_CONFIRM, 11),
+            Method::Unsupported { class_id, method_id } => (*class_id, *method_id),
+        }
+    }
+
+    /// Checks whether the method is followed by a content header and body.
+    pub fn has_content(&self) -> bool {
+        matches!(
+            self,
+            Method::BasicPublish { .. } | Method::BasicReturn { .. } | Method::BasicDeliver { .. }
+        )
+    }
+
+    pub fn decode(payload: Bytes) -> Result<Method, Error> {
+        let mut r = Reader::new(payload);
+        let class_id = r.u16()?;
+        let method_id = r.u16()?;
+
+        let method = match (class_id, method_id) {
+            (CLASS_CONNECTION, 10) => {
+                let _version = r.u16()?;
+                Method::ConnectionStart {
+                    server_properties: r.table()?,
+                    mechanisms: r.long_string_utf8()?,
+                    locales: r.long_string_utf8()?,
+                }
+            }
+            (CLASS_CONNECTION, 11) => Method::ConnectionStartOk {
+                client_properties: r.table()?,
+                mechanism: r.short_string()?,
+                response: r.long_string()?,
+                locale: r.short_string()?,
+            },
+            (CLASS_CONNECTION, 30) => Method::ConnectionTune {
+                channel_max: r.u16()?,
+                frame_max: r.u32()?,
+                heartbeat: r.u16()?,
+            },
+            (CLASS_CONNECTION, 31) => Method::ConnectionTuneOk {
+                channel_max: r.u16()?,
+                frame_max: r.u32()?,
+                heartbeat: r.u16()?,
+            },
+            (CLASS_CONNECTION, 40) => Method::ConnectionOpen {
+                virtual_host: r.short_string()?,
+            },
+            (CLASS_CONNECTION, 41) => Method::ConnectionOpenOk,
+            (CLASS_CONNECTION, 50) => Method::ConnectionClose(r.close()?),
+            (CLASS_CONNECTION, 51) => Method::ConnectionCloseOk,
+            (CLASS_CHANNEL, 10) => Method::ChannelOpen,
+            (CLASS_CHANNEL, 11) => Method::ChannelOpenOk,
+            (CLASS_CHANNEL, 40) => Method::ChannelClose(r.close()?),
+            (CLASS_CHANNEL, 41) => Method::ChannelCloseOk,
+            (CLASS_EXCHANGE, 10) => {
+                let _reserved = r.u16()?;
+                let exchange = r.short_string()?;
+                let kind = r.short_string()?;
+                let bits = r.u8()?;
+                Method::ExchangeDeclare {
+                    exchange,
+                    kind,
+                    passive: bit(bits, 0),
+                    durable: bit(bits, 1),
+                    auto_delete: bit(bits, 2),
+                    internal: bit(bits, 3),
+                    no_wait: bit(bits, 4),
+                    arguments: r.table()?,
+                }
+            }
+            (CLASS_EXCHANGE, 11) => Method::ExchangeDeclareOk,
+            (CLASS_EXCHANGE, 20) => {
+                let _reserved = r.u16()?;
+                let exchange = r.short_string()?;
+                let bits = r.u8()?;
+                Method::ExchangeDelete {
+                    exchange,
+                    if_unused: bit(bits, 0),
+                    no_wait: bit(bits, 1),
+                }
+            }
+            (CLASS_EXCHANGE, 21) => Method::ExchangeDeleteOk,
+            (CLASS_QUEUE, 10) => {
+                let _reserved = r.u16()?;
+                let queue = r.short_string()?;
+                let bits = r.u8()?;
+                Method::QueueDeclare {
+                    queue,
+                    passive: bit(bits, 0),
+                    durable: bit(bits, 1),
+                    exclusive: bit(bits, 2),
+                    auto_delete: bit(bits, 3),
+                    no_wait: bit(bits, 4),
+                    arguments: r.table()?,
+                }
+            }
+            (CLASS_QUEUE, 11) => Method::QueueDeclareOk {
+                queue: r.short_string()?,
+                message_count: r.u32()?,
+                consumer_count: r.u32()?,
+            },
+            (CLASS_QUEUE, 20) => {
+                let _reserved = r.u16()?;
+                Method::QueueBind {
+                    queue: r.short_string()?,
+                    exchange: r.short_string()?,
+                    routing_key: r.short_string()?,
+                    no_wait: bit(r.u8()?, 0),
+                    arguments: r.table()?,
+                }
+            }
+            (CLASS_QUEUE, 21) => Method::QueueBindOk,
+            (CLASS_QUEUE, 50) => {
+                let _reserved = r.u16()?;
+                Method::QueueUnbind {
+                    queue: r.short_string()?,
+                    exchange: r.short_string()?,
+                    routing_key: r.short_string()?,
+                    arguments: r.table()?,
+                }
+            }
+            (CLASS_QUEUE, 51) => Method::QueueUnbindOk,
+            (CLASS_BASIC, 10) => Method::BasicQos {
+                prefetch_size: r.u32()?,
+                prefetch_count: r.u16()?,
+                global: bit(r.u8()?, 0),
+            },
+            (CLASS_BASIC, 11) => Method::BasicQosOk,
+            (CLASS_BASIC, 20) => {
+                let _reserved = r.u16()?;
+                let queue = r.short_string()?;
+                let consumer_tag = r.short_string()?;
+                let bits = r.u8()?;
+                Method::BasicConsume {
+                    queue,
+                    consumer_tag,
+                    no_local: bit(bits, 0),
+                    no_ack: bit(bits, 1),
+                    exclusive: bit(bits, 2),
+                    no_wait: bit(bits, 3),
+                    arguments: r.table()?,
+                }
+            }
+            (CLASS_BASIC, 21) => Method::BasicConsumeOk {
+                consumer_tag: r.short_string()?,
+            },
+            (CLASS_BASIC, 30) => Method::BasicCancel {
+                consumer_tag: r.short_string()?,
+                no_wait: bit(r.u8()?, 0),
+            },
+            (CLASS_BASIC, 31) => Method::BasicCancelOk {
+                consumer_tag: r.short_string()?,
+            },
+            (CLASS_BASIC, 40) => {
+                let _reserved = r.u16()?;
+                let exchange = r.short_string()?;
+                let routing_key = r.short_string()?;
+                let bits = r.u8()?;
+                Method::BasicPublish {
+                    exchange,
+                    routing_key,
+                    mandatory: bit(bits, 0),
+                    immediate: bit(bits, 1),
+                }
+            }
+            (CLASS_BASIC, 50) => Method::BasicReturn {
+                reply_code: r.u16()?,
+                reply_text: r.short_string()?,
+                exchange: r.short_string()?,
+                routing_key: r.short_string()?,
+            },
+            (CLASS_BASIC, 60) => Method::BasicDeliver {
+                consumer_tag: r.short_string()?,
+                delivery_tag: r.u64()?,
+                redelivered: bit(r.u8()?, 0),
+                exchange: r.short_string()?,
+                routing_key: r.short_string()?,
+            },
+            (CLASS_BASIC, 80) => Method::BasicAck {
+                delivery_tag: r.u64()?,
+                multiple: bit(r.u8()?, 0),
+            },
+            (CLASS_BASIC, 90) => Method::BasicReject {
+                delivery_tag: r.u64()?,
+                requeue: bit(r.u8()?, 0),
+            },
+            (CLASS_BASIC, 120) => {
+                let delivery_tag = r.u64()?;
+                let bits = r.u8()?;
+                Method::BasicNack {
+                    delivery_tag,
+                    multiple: bit(bits, 0),
+                    requeue: bit(bits, 1),
+                }
+            }
+            (CLASS_CONFIRM, 10) => Method::ConfirmSelect {
+                no_wait: bit(r.u8()?, 0),
+            },
+            (CLASS_CONFIRM, 11) => Method::ConfirmSelectOk,
+            (class_id, method_id) => Method::Unsupported { class_id, method_id },
+        };
+
+        Ok(method)
+    }
+
+    pub fn encode(&self, buf: &mut BytesMut) {
+        let (class_id, method_id) = self.id();
+        buf.put_u16(class_id);
+        buf.put_u16(method_id);
+
+        match self {
+            Method::ConnectionStart {
+                server_properties,
+                mechanisms,
+                locales,
+            } => {
+                buf.put_u8(0);
+                buf.put_u8(9);
+                put_table(buf, server_properties);
+                put_long_string(buf, mechanisms.as_bytes());
+                put_long_string(buf, locales.as_bytes());
+            }
+            Method::ConnectionStartOk {
+                client_properties,
+                mechanism,
+                response,
+                locale,
+            } => {
+                put_table(buf, client_properties);
+                put_short_string(buf, mechanism);
+                put_long_string(buf, response);
+                put_short_string(buf, locale);
+            }
+            Method::ConnectionTune {
+                channel_max,
+                frame_max,
+                heartbeat,
+            }
+            | Method::ConnectionTuneOk {
+                channel_max,
+                frame_max,
+                heartbeat,
+            } => {
+                buf.put_u16(*channel_max);
+                buf.put_u32(*frame_max);
+                buf.put_u16(*heartbeat);
+            }
+            Method::ConnectionOpen { virtual_host } => {
+                put_short_string(buf, virtual_host);
+                put_short_string(buf, "");
+                buf.put_u8(0);
+            }
+            Method::ConnectionOpenOk => put_short_string(buf, ""),
+            Method::ConnectionClose(close) | Method::ChannelClose(close) => {
+                buf.put_u16(close.reply_code);
+                put_short_string(buf, &close.reply_text);
+                buf.put_u16(close.class_id);
+                buf.put_u16(close.method_id);
+            }
+            Method::ChannelOpen => put_short_string(buf, ""),
+            Method::ChannelOpenOk => put_long_string(buf, b""),
+            Method::ExchangeDeclare {
+                exchange,
+                kind,
+                passive,
+                durable,
+                auto_delete,
+                internal,
+                no_wait,
+                arguments,
+            } => {
+                buf.put_u16(0);
+                put_short_string(buf, exchange);
+                put_short_string(buf, kind);
+                buf.put_u8(bits(&[*passive, *durable, *auto_delete, *internal, *no_wait]));
+                put_table(buf, arguments);
+            }
+            Method::ExchangeDelete {
+                exchange,
+                if_unused,
+                no_wait,
+            } => {
+                buf.put_u16(0);
+                put_short_string(buf, exchange);
+                buf.put_u8(bits(&[*if_unused, *no_wait]));
+            }
+            Method::QueueDeclare {
+                queue,
+                passive,
+                durable,
+                exclusive,
+                auto_delete,
+                no_wait,
+                arguments,
+            } => {
+                buf.put_u16(0);
+                put_short_string(buf, queue);
+                buf.put_u8(bits(&[*passive, *durable, *exclusive, *auto_delete, *no_wait]));
+                put_table(buf, arguments);
+            }
+            Method::QueueDeclareOk {
+                queue,
+                message_count,
+                consumer_count,
+            } => {
+                put_short_string(buf, queue);
+                buf.put_u32(*message_count);
+                buf.put_u32(*consumer_count);
+            }
+            Method::QueueBind {
+                queue,
+                exchange,
+                routing_key,
+                no_wait,
+                arguments,
+            } => {
+                buf.put_u16(0);
+                put_short_string(buf, queue);
+                put_short_string(buf, exchange);
+                put_short_string(buf, routing_key);
+                buf.put_u8(bits(&[*no_wait]));
+                put_table(buf, arguments);
+            }
+            Method::QueueUnbind {
+                queue,
+                exchange,
+                routing_key,
+                arguments,
+            } => {
+                buf.put_u16(0);
+                put_short_string(buf, queue);
+                put_short_string(buf, exchange);
+                put_short_string(buf, routing_key);
+                put_table(buf, arguments);
+            }
+            Method::BasicQos {
+                prefetch_size,
+                prefetch_count,
+                global,
+            } => {
+                buf.put_u32(*prefetch_size);
+                buf.put_u16(*prefetch_count);
+                buf.put_u8(bits(&[*global]));
+            }
+            Method::BasicConsume {
+                queue,
+                consumer_tag,
+                no_local,
+                no_ack,
+                exclusive,
+                no_wait,
+                arguments,
+            } => {
+                buf.put_u16(0);
+                put_short_string(buf, queue);
+                put_short_string(buf, consumer_tag);
+                buf.put_u8(bits(&[*no_local, *no_ack, *exclusive, *no_wait]));
+                put_table(buf, arguments);
+            }
+            Method::BasicConsumeOk { consumer_tag } | Method::BasicCancelOk { consumer_tag } => {
+                put_short_string(buf, consumer_tag)
+            }
+            Method::BasicCancel { consumer_tag, no_wait } => {
+                put_short_string(buf, consumer_tag);
+                buf.put_u8(bits(&[*no_wait]));
+            }
+            Method::BasicPublish {
+                exchange,
+                routing_key,
+                mandatory,
+                immediate,
+            } => {
+                buf.put_u16(0);
+                put_short_string(buf, exchange);
+                put_short_string(buf, routing_key);
+                buf.put_u8(bits(&[*mandatory, *immediate]));
+            }
+            Method::BasicReturn {
+                reply_code,
+                reply_text,
+                exchange,
+                routing_key,
+            } => {
+                buf.put_u16(*reply_code);
+                put_short_string(buf, reply_text);
+                put_short_string(buf, exchange);
+                put_short_string(buf, routing_key);
+            }
+            Method::BasicDeliver {
+                consumer_tag,
+                delivery_tag,
+                redelivered,
+                exchange,
+                routing_key,
+            } => {
+                put_short_string(buf, consumer_tag);
+                buf.put_u64(*delivery_tag);
+                buf.put_u8(bits(&[*redelivered]));
+                put_short_string(buf, exchange);
+                put_short_string(buf, routing_key);
+            }
+            Method::BasicAck { delivery_tag, multiple } => {
+                buf.put_u64(*delivery_tag);
+                buf.put_u8(bits(&[*multiple]));
+            }
+            Method::BasicReject { delivery_tag, requeue } => {
+                buf.put_u64(*delivery_tag);
+                buf.put_u8(bits(&[*requeue]));
+            }
+            Method::BasicNack {
+                delivery_tag,
+                multiple,
+                requeue,
+            } => {
+                buf.put_u64(*delivery_tag);
+                buf.put_u8(bits(&[*multiple, *requeue]));
+            }
+            Method::ConfirmSelect { no_wait } => buf.put_u8(bits(&[*no_wait])),
+            Method::ConnectionCloseOk
+            | Method::ChannelCloseOk
+            | Method::ExchangeDeclareOk
+            | Method::ExchangeDeleteOk
+            | Method::QueueBindOk
+            | Method::QueueUnbindOk
+            | Method::BasicQosOk
+            | Method::ConfirmSelectOk
+            | Method::Unsupported { .. } => {}
+        }
+    }
+}
+
+impl ContentHeader {
+    pub fn decode(payload: Bytes) -> Result<ContentHeader, Error> {
+        let mut r = Reader::new(payload);
+        let class_id = r.u16()?;
+        let _weight = r.u16()?;
+        let body_size = r.u64()?;
+
+        Ok(ContentHeader {
+            class_id,
+            body_size,
+            properties: r.rest(),
+        })
+    }
+
+    pub fn encode(&self, buf: &mut BytesMut) {
+        buf.put_u16(self.class_id);
+        buf.put_u16(0);
+        buf.put_u64(self.body_size);
+        buf.put_slice(&self.properties);
+    }
+}
+
+/// Value of a field table entry. Only the types the connector sends are supported.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum FieldValue {
+    Bool(bool),
+    LongString(String),
+    Table(Vec<(String, FieldValue)>),
+}
+
+/// Encodes a field table, without its length prefix.
+pub fn field_table(entries: &[(String, FieldValue)]) -> Bytes {
+    let mut buf = BytesMut::new();
+    for (name, value) in entries {
+        put_short_string(&mut buf, name);
+        match value {
+            FieldValue::Bool(value) => {
+                buf.put_u8(b't');
+                buf.put_u8(*value as u8);
+            }
+            FieldValue::LongString(value) => {
+                buf.put_u8(b'S');
+                put_long_string(&mut buf, value.as_bytes());
+            }
+            FieldValue::Table(entries) => {
+                buf.put_u8(b'F');
+                put_table(&mut buf, &field_table(entries));
+            }
+        }
+    }
+
+    buf.freeze()
+}
+
+fn bit(bits: u8, index: u8) -> bool {
+    bits & (1 << index) != 0
+}
+
+fn bits(values: &[bool]) -> u8 {
+    values
+        .iter()
+        .enumerate()
+        .fold(0, |bits, (i, value)| bits | ((*value as u8) << i))
+}
+
+fn put_short_string(buf: &mut BytesMut, value: &str) {
+    // Short strings are limited to 255 bytes, longer values are cut.
+    let value = &value.as_bytes()[..value.len().min(u8::MAX as usize)];
+    buf.put_u8(value.len() as u8);
+    buf.put_slice(value);
+}
+
+fn put_long_string(buf: &mut BytesMut, value: &[u8]) {
+    buf.put_u32(value.len() as u32);
+    buf.put_slice(value);
+}
+
+fn put_table(buf: &mut BytesMut, table: &[u8]) {
+    put_long_string(buf, table)
+}
+
+struct Reader {
+    buf: Bytes,
+}
+
+impl Reader {
+    fn new(buf: Bytes) -> Reader {
+        Reader { buf }
+    }
+
+    fn take(&mut self, len: usize) -> Result<Bytes, Error> {
+        if len > self.buf.len() {
+            return Err(Error::new(ErrorKind::InvalidData, "method frame is truncated"));
+        }
+
+        Ok(self.buf.split_to(len))
+    }
+
+    fn rest(&mut self) -> Bytes {
+        self.buf.split_off(0)
+    }
+
+    fn u8(&mut self) -> Result<u8, Error> {
+        Ok(self.take(1)?[0])
+    }
+
+    fn u16(&mut self) -> Result<u16, Error> {
+        Ok(u16::from_be_bytes(self.take(2)?[..].try_into().unwrap()))
+    }
+
+    fn u32(&mut self) -> Result<u32, Error> {
+        Ok(u32::from_be_bytes(self.take(4)?[..].try_into().unwrap()))
+    }
+
+    fn u64(&mut self) -> Result<u64, Error> {
+        Ok(u64::from_be_bytes(self.take(8)?[..].try_into().unwrap()))
+    }
+
+    fn short_string(&mut self) -> Result<String, Error> {
+        let len = self.u8()? as usize;
+        utf8(self.take(len)?)
+    }
+
+    fn long_string(&mut self) -> Result<Bytes, Error> {
+        let len = self.u32()? as usize;
+        self.take(len)
+    }
+
+    fn long_string_utf8(&mut self) -> Result<String, Error> {
+        let value = self.long_string()?;
+        utf8(value)
+    }
+
+    /// Reads a field table, which is kept undecoded.
+    fn table(&mut self) -> Result<Bytes, Error> {
+        self.long_string()
+    }
+
+    fn close(&mut self) -> Result<Close, Error> {
+        Ok(Close {
+            reply_code: self.u16()?,
+            reply_text: self.short_string()?,
+            class_id: self.u16()?,
+            method_id: self.u16()?,
+        })
+    }
+}
+
+fn utf8(value: Bytes) -> Result<String, Error> {
+    String::from_utf8(value.to_vec()).map_err(|_| Error::new(ErrorKind::InvalidData, "string isn't valid UTF-8"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn round_trip(method: Method) {
+        let mut buf = BytesMut::new();
+        method.encode(&mut buf);
+
+        assert_eq!(method, Method::decode(buf.freeze()).unwrap());
+    }
+
+    #[test]
+    fn test_method_round_trip() {
+        round_trip(Method::ConnectionStartOk {
+            client_properties: field_table(&[("product".to_string(), FieldValue::LongString("test".to_string()))]),
+            mechanism: "PLAIN".to_string(),
+            response: Bytes::from_static(b"\0guest\0guest"),
+            locale: "en_US".to_string(),
+        });
+        round_trip(Method::ExchangeDeclare {
+            exchange: "events".to_string(),
+            kind: "topic".to_string(),
+            passive: false,
+            durable: true,
+            auto_delete: false,
+            internal: false,
+            no_wait: true,
+            arguments: Bytes::new(),
+        });
+        round_trip(Method::BasicConsume {
+            queue: "billing".to_string(),
+            consumer_tag: "ctag".to_string(),
+            no_local: false,
+            no_ack: true,
+            exclusive: false,
+            no_wait: false,
+            arguments: Bytes::new(),
+        });
+        round_trip(Method::BasicDeliver {
+            consumer_tag: "ctag".to_string(),
+            delivery_tag: 42,
+            redelivered: true,
+            exchange: "".to_string(),
+            routing_key: "billing".to_string(),
+        });
+        round_trip(Method::BasicNack {
+            delivery_tag: 7,
+            multiple: false,
+            requeue: true,
+        });
+        round_trip(Method::ChannelClose(Close {
+            reply_code: reply_code::NOT_FOUND,
+            reply_text: "NOT_FOUND".to_string(),
+            class_id: 50,
+            method_id: 10,
+        }));
+    }
+
+    #[test]
+    fn test_decode_unsupported_method() {
+        let payload = Bytes::from_static(&[0, 90, 0, 10]);
+
+        assert_eq!(
+            Method::Unsupported {
+                class_id: 90,
+                method_id: 10
+            },
+            Method::decode(payload).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_decode_truncated_method_fails() {
+        let mut buf = BytesMut::new();
+        Method::BasicAck {
+            delivery_tag: 1,
+            multiple: false,
+        }
+        .encode(&mut buf);
+
+        assert!(Method::decode(buf.freeze().slice(..8)).is_err());
+    }
+}
@@ -0,0 +1,4 @@
+pub mod codec;
+pub mod connector;
+pub mod frame;
+mod queue;
@@ -0,0 +1,238 @@
+use std::collections::{BTreeSet, VecDeque};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use tokio::sync::Notify;
+
+use packline_core::app::channel::{Channel, Record};
+
+/// Messages read ahead from a queue's channel, waiting for a consumer.
+const READY_LIMIT: usize = 1000;
+
+/// Time records are collected for before being handed to consumers.
+const DELIVERY_LINGER: Duration = Duration::from_millis(5);
+
+/// Message of a queue handed to a consumer.
+#[derive(Clone, Debug)]
+pub(crate) struct Message {
+    pub record: Record,
+
+    /// Set when the message was delivered before and requeued.
+    pub redelivered: bool,
+}
+
+/// AMQP queue backed by the first partition of the channel of the same name.
+///
+/// A single task reads the channel with the consumer group `amqp/<queue>` and hands its records to
+/// the consumers of the queue one at a time, so that they compete for them. The group offset
+/// committed to the channel only moves past a record once it is acknowledged, a queue resumes
+/// from there when it is opened again.
+pub(crate) struct Queue {
+    channel: Channel,
+    group: u128,
+    state: Mutex<QueueState>,
+
+    /// Notified when messages become ready.
+    available: Notify,
+
+    /// Notified when ready messages are taken, making room to read more.
+    taken: Notify,
+    consumers: AtomicUsize,
+}
+
+struct QueueState {
+    ready: VecDeque<Message>,
+
+    /// Offsets read from the channel that weren't acknowledged yet.
+    outstanding: BTreeSet<u64>,
+
+    /// Offset following the last record read from the channel.
+    next_offset: u64,
+}
+
+impl Queue {
+    pub async fn open(name: &str, channel: Channel) -> Arc<Queue> {
+        let group = channel.register_group(&format!("amqp/{}", name));
+
+        let (committed, position) = channel.offsets(group).await;
+        let next_offset = match committed {
+            Some(committed) if channel.seek(group, committed).await.is_ok() => committed,
+            _ => position,
+        };
+
+        let queue = Arc::new(Queue {
+            channel,
+            group,
+            state: Mutex::new(QueueState {
+                ready: VecDeque::new(),
+                outstanding: BTreeSet::new(),
+                next_offset,
+            }),
+            available: Notify::new(),
+            taken: Notify::new(),
+            consumers: AtomicUsize::new(0),
+        });
+
+        tokio::spawn(queue.clone().read());
+        queue
+    }
+
+    /// Reads the channel into the ready messages, as long as there's room for them.
+    async fn read(self: Arc<Self>) {
+        let consumer = self.channel.consumer(self.group).with_timeout(DELIVERY_LINGER);
+
+        loop {
+            loop {
+                let taken = self.taken.notified();
+                tokio::pin!(taken);
+                taken.as_mut().enable();
+
+                if self.state.lock().unwrap().ready.len() < READY_LIMIT {
+                    break;
+                }
+
+                taken.await;
+            }
+
+            let records = consumer.consume().await;
+            {
+                let mut state = self.state.lock().unwrap();
+                for record in records {
+                    state.outstanding.insert(record.offset);
+                    state.next_offset = record.offset + 1;
+                    state.ready.push_back(Message {
+                        record,
+                        redelivered: false,
+                    });
+                }
+            }
+
+            self.available.notify_waiters();
+        }
+    }
+
+    /// Waits for the next ready message and takes it.
+    pub async fn next(&self) -> Message {
+        loop {
+            let available = self.available.notified();
+            tokio::pin!(available);
+            available.as_mut().enable();
+
+            let message = self.state.lock().unwrap().ready.pop_front();
+            if let Some(message) = message {
+                self.taken.notify_waiters();
+                return message;
+            }
+
+            available.await;
+        }
+    }
+
+    /// Puts a message taken by a consumer back in front of the queue.
+    pub fn requeue(&self, mut message: Message) {
+        message.redelivered = true;
+        self.state.lock().unwrap().ready.push_front(message);
+        self.available.notify_waiters();
+    }
+
+    /// Marks the message at `offset` as processed, committing the offset the queue would resume
+    /// from.
+    pub async fn ack(&self, offset: u64) {
+        let committed = {
+            let mut state = self.state.lock().unwrap();
+            state.outstanding.remove(&offset);
+            state.outstanding.first().copied().unwrap_or(state.next_offset)
+        };
+
+        let _ = self.channel.commit_offset(self.group, committed).await;
+    }
+
+    /// Returns the number of messages waiting for a consumer.
+    pub fn message_count(&self) -> u32 {
+        let state = self.state.lock().unwrap();
+        let unread = self.channel.end_offset().saturating_sub(state.next_offset);
+
+        (state.ready.len() as u64 + unread).min(u32::MAX as u64) as u32
+    }
+
+    pub fn consumer_count(&self) -> u32 {
+        self.consumers.load(Ordering::Relaxed) as u32
+    }
+
+    pub fn add_consumer(&self) {
+        self.consumers.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn remove_consumer(&self) {
+        self.consumers.fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use packline_core::app::channel::consumer_group_id;
+    use packline_core::app::{App, ChannelConfig};
+
+    use super::*;
+
+    async fn channel(app: &App) -> Channel {
+        app.create_channel(ChannelConfig {
+            name: "billing".to_string(),
+            partitions: 1,
+        })
+        .await
+        .unwrap();
+
+        app.get_channel(&("billing".to_string(), 1)).await.unwrap()
+    }
+
+    async fn next(queue: &Queue) -> Message {
+        tokio::time::timeout(Duration::from_secs(2), queue.next())
+            .await
+            .expect("timed out waiting for a message")
+    }
+
+    #[tokio::test]
+    async fn test_requeued_message_is_redelivered_first() {
+        let app = App::new();
+        let channel = channel(&app).await;
+        channel
+            .producer()
+            .produce(&mut vec![Record::from_value("a"), Record::from_value("b")])
+            .await;
+
+        let queue = Queue::open("billing", channel).await;
+
+        let first = next(&queue).await;
+        assert!(!first.redelivered);
+        queue.requeue(first);
+
+        let again = next(&queue).await;
+        assert_eq!(b"a".to_vec(), again.record.value);
+        assert!(again.redelivered);
+        assert_eq!(b"b".to_vec(), next(&queue).await.record.value);
+    }
+
+    #[tokio::test]
+    async fn test_ack_commits_lowest_outstanding_offset() {
+        let app = App::new();
+        let channel = channel(&app).await;
+        channel
+            .producer()
+            .produce(&mut vec![Record::from_value("a"), Record::from_value("b")])
+            .await;
+
+        let queue = Queue::open("billing", channel.clone()).await;
+        let first = next(&queue).await;
+        let second = next(&queue).await;
+
+        let group = consumer_group_id("amqp/billing");
+
+        queue.ack(second.record.offset).await;
+        assert_eq!(Some(0), channel.offsets(group).await.0);
+
+        queue.ack(first.record.offset).await;
+        assert_eq!(Some(2), channel.offsets(group).await.0);
+    }
+}
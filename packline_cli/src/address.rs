@@ -0,0 +1,133 @@
+use std::fmt::{Display, Formatter};
+use std::net::SocketAddr;
+use std::path::PathBuf;
+
+const TCP_SCHEME: &str = "tcp://";
+const UNIX_SCHEME: &str = "unix://";
+const WS_SCHEME: &str = "ws://";
+const WSS_SCHEME: &str = "wss://";
+
+/// Location of a broker listener.
+///
+/// Parsed from strings such as `127.0.0.1:1883`, `tcp://localhost:1883`,
+/// `unix:///run/packline.sock` or `ws://localhost:8080/flow`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Address {
+    Tcp(String),
+    Unix(PathBuf),
+
+    /// `ws://` or `wss://` URL of a WebSocket listener.
+    WebSocket(String),
+}
+
+impl Address {
+    /// Returns the `host:port` a WebSocket URL points to, using the scheme's default port when the
+    /// URL has none.
+    pub(crate) fn websocket_authority(url: &str) -> String {
+        let (rest, default_port) = match url.strip_prefix(WSS_SCHEME) {
+            Some(rest) => (rest, 443),
+            None => (url.strip_prefix(WS_SCHEME).unwrap_or(url), 80),
+        };
+
+        let authority = rest.split('/').next().unwrap_or(rest);
+        if authority
+            .rsplit_once(':')
+            .is_some_and(|(_, port)| port.parse::<u16>().is_ok())
+        {
+            authority.to_string()
+        } else {
+            format!("{}:{}", authority, default_port)
+        }
+    }
+
+    pub(crate) fn is_secure_websocket(url: &str) -> bool {
+        url.starts_with(WSS_SCHEME)
+    }
+}
+
+impl From<&str> for Address {
+    fn from(address: &str) -> Self {
+        if let Some(path) = address.strip_prefix(UNIX_SCHEME) {
+            return Address::Unix(PathBuf::from(path));
+        }
+
+        if address.starts_with(WS_SCHEME) || address.starts_with(WSS_SCHEME) {
+            return Address::WebSocket(address.to_string());
+        }
+
+        Address::Tcp(address.strip_prefix(TCP_SCHEME).unwrap_or(address).to_string())
+    }
+}
+
+impl From<String> for Address {
+    fn from(address: String) -> Self {
+        Address::from(address.as_str())
+    }
+}
+
+impl From<SocketAddr> for Address {
+    fn from(address: SocketAddr) -> Self {
+        Address::Tcp(address.to_string())
+    }
+}
+
+impl Display for Address {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Address::Tcp(address) => write!(f, "{}{}", TCP_SCHEME, address),
+            Address::Unix(path) => write!(f, "{}{}", UNIX_SCHEME, path.display()),
+            Address::WebSocket(url) => write!(f, "{}", url),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use super::Address;
+
+    #[test]
+    fn test_parse_address() {
+        assert_eq!(
+            Address::Tcp("127.0.0.1:1883".to_string()),
+            Address::from("127.0.0.1:1883")
+        );
+        assert_eq!(
+            Address::Tcp("localhost:1883".to_string()),
+            Address::from("tcp://localhost:1883")
+        );
+        assert_eq!(
+            Address::Unix(PathBuf::from("/run/packline.sock")),
+            Address::from("unix:///run/packline.sock")
+        );
+        assert_eq!(
+            Address::WebSocket("ws://localhost:8080/flow".to_string()),
+            Address::from("ws://localhost:8080/flow")
+        );
+    }
+
+    #[test]
+    fn test_websocket_authority() {
+        assert_eq!(
+            "localhost:8080",
+            Address::websocket_authority("ws://localhost:8080/flow")
+        );
+        assert_eq!("localhost:80", Address::websocket_authority("ws://localhost"));
+        assert_eq!(
+            "example.com:443",
+            Address::websocket_authority("wss://example.com/flow")
+        );
+    }
+
+    #[test]
+    fn test_display_round_trips() {
+        for address in [
+            "tcp://localhost:1883",
+            "unix:///run/packline.sock",
+            "wss://localhost/flow",
+        ] {
+            assert_eq!(address, Address::from(address).to_string());
+        }
+    }
+}
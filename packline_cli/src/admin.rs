@@ -0,0 +1,127 @@
+//! Descriptions of the topics, consumer groups and health of a broker, as returned by the administration
+//! methods of [`Client`](crate::client::Client).
+
+use packline_flow::messages::group::{GroupListingV1, GroupOffsetV1, NO_COMMITTED_OFFSET};
+use packline_flow::messages::health::HealthCheckResponseV1;
+use packline_flow::messages::topic::{PartitionMetadataV1, TopicMetadataV1};
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct TopicDescription {
+    pub name: String,
+    pub partitions: Vec<PartitionDescription>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct PartitionDescription {
+    pub partition: u16,
+
+    /// Offset of the first record the partition still stores.
+    pub start_offset: u64,
+
+    /// Offset the next record produced to the partition gets.
+    pub end_offset: u64,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct GroupListing {
+    /// Group name, or `#` followed by the group id for groups the broker doesn't know the name of.
+    pub name: String,
+
+    /// Topics the group has offsets on.
+    pub topics: Vec<String>,
+}
+
+/// Offsets of a consumer group on a topic partition.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct GroupOffset {
+    pub topic: String,
+    pub partition: u16,
+    pub committed: Option<u64>,
+
+    /// Offset of the next record the group consumes.
+    pub position: u64,
+    pub end_offset: u64,
+}
+
+impl GroupOffset {
+    /// Returns how many records the group has left to consume.
+    pub fn lag(&self) -> u64 {
+        self.end_offset.saturating_sub(self.position)
+    }
+}
+
+/// Where [`Client::reset_group_offsets`](crate::client::Client::reset_group_offsets) moves a
+/// consumer group to.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum OffsetReset {
+    /// First record still stored.
+    Earliest,
+
+    /// Next produced record, skipping every stored one.
+    Latest,
+
+    /// Given offset, or the closest one a partition stores.
+    Offset(u64),
+}
+
+/// Readiness of a broker, as returned by [`Client::health`](crate::client::Client::health).
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Health {
+    pub ready: bool,
+
+    /// Readiness checks that didn't pass yet, such as `storage` while the broker restores its
+    /// channels, or `shutdown` once it started shutting down.
+    pub pending: Vec<String>,
+}
+
+impl From<TopicMetadataV1> for TopicDescription {
+    fn from(topic: TopicMetadataV1) -> Self {
+        TopicDescription {
+            name: topic.name,
+            partitions: topic.partitions.into_iter().map(PartitionDescription::from).collect(),
+        }
+    }
+}
+
+impl From<PartitionMetadataV1> for PartitionDescription {
+    fn from(partition: PartitionMetadataV1) -> Self {
+        PartitionDescription {
+            partition: partition.partition,
+            start_offset: partition.start_offset,
+            end_offset: partition.end_offset,
+        }
+    }
+}
+
+impl From<GroupListingV1> for GroupListing {
+    fn from(group: GroupListingV1) -> Self {
+        GroupListing {
+            name: group.group,
+            topics: group.topics,
+        }
+    }
+}
+
+impl From<GroupOffsetV1> for GroupOffset {
+    fn from(offset: GroupOffsetV1) -> Self {
+        GroupOffset {
+            topic: offset.topic,
+            partition: offset.partition,
+            committed: match offset.committed {
+                NO_COMMITTED_OFFSET => None,
+                committed => Some(committed as u64),
+            },
+            position: offset.position,
+            end_offset: offset.end_offset,
+        }
+    }
+}
+
+impl From<HealthCheckResponseV1> for Health {
+    fn from(health: HealthCheckResponseV1) -> Self {
+        Health {
+            ready: health.ready != 0,
+            pending: health.pending,
+        }
+    }
+}
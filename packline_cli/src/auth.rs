@@ -0,0 +1,85 @@
+use packline_flow::auth::scram::ScramClient;
+use packline_flow::auth::{plain, PLAIN, SCRAM_SHA_256};
+use packline_flow::messages::authenticate::AuthenticateRequestV1;
+use packline_flow::messages::Message;
+
+use crate::client::unexpected;
+use crate::connection::Connection;
+use crate::error::ClientError;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Mechanism {
+    Plain,
+    ScramSha256,
+}
+
+impl Mechanism {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Mechanism::Plain => PLAIN,
+            Mechanism::ScramSha256 => SCRAM_SHA_256,
+        }
+    }
+}
+
+/// Credentials sent to the broker when establishing a connection.
+#[derive(Clone)]
+pub struct Credentials {
+    pub mechanism: Mechanism,
+    pub username: String,
+    pub password: String,
+}
+
+impl Credentials {
+    pub fn plain(username: &str, password: &str) -> Credentials {
+        Credentials {
+            mechanism: Mechanism::Plain,
+            username: username.to_string(),
+            password: password.to_string(),
+        }
+    }
+
+    pub fn scram_sha_256(username: &str, password: &str) -> Credentials {
+        Credentials {
+            mechanism: Mechanism::ScramSha256,
+            username: username.to_string(),
+            password: password.to_string(),
+        }
+    }
+}
+
+pub(crate) async fn authenticate(connection: &Connection, credentials: &Credentials) -> Result<(), ClientError> {
+    match credentials.mechanism {
+        Mechanism::Plain => {
+            let payload = plain::encode(&credentials.username, &credentials.password);
+            exchange(connection, credentials.mechanism, payload).await?;
+        }
+        Mechanism::ScramSha256 => {
+            let mut scram = ScramClient::new(&credentials.username, &credentials.password);
+
+            let server_first = exchange(connection, credentials.mechanism, scram.client_first()).await?;
+            let client_final = scram.client_final(&server_first)?;
+            let server_final = exchange(connection, credentials.mechanism, client_final).await?;
+            scram.verify_server_final(&server_final)?;
+        }
+    }
+
+    Ok(())
+}
+
+async fn exchange(connection: &Connection, mechanism: Mechanism, payload: Vec<u8>) -> Result<Vec<u8>, ClientError> {
+    let response = connection
+        .send(
+            (9, 1),
+            Message::AuthenticateRequestV1(AuthenticateRequestV1 {
+                mechanism: mechanism.name().to_string(),
+                payload,
+            }),
+        )
+        .await?;
+
+    match response {
+        Message::AuthenticateResponseV1(r) => Ok(r.payload),
+        response => Err(unexpected(response)),
+    }
+}
@@ -0,0 +1,271 @@
+use std::path::PathBuf;
+
+use clap::{Args as ClapArgs, Parser, Subcommand, ValueEnum};
+
+use packline_cli::admin::OffsetReset;
+use packline_cli::auth::Credentials;
+use packline_cli::client::ConnectOptions;
+use packline_cli::tls::ClientTLSConfig;
+
+/// Produces, consumes and administers topics of a Packline broker over the flow protocol.
+#[derive(Debug, Parser)]
+#[command(name = "packline-cli", version)]
+pub struct Args {
+    /// Broker address: `host:port`, `unix:///path/to.sock` or a `ws://` URL.
+    #[arg(short, long, global = true, default_value = "127.0.0.1:1883")]
+    pub broker: String,
+
+    /// Authenticates as this user.
+    #[arg(long, global = true, requires = "password")]
+    pub user: Option<String>,
+
+    #[arg(long, global = true, requires = "user")]
+    pub password: Option<String>,
+
+    /// Authentication mechanism used along with `--user`.
+    #[arg(long, global = true, value_enum, default_value_t = MechanismArg::ScramSha256)]
+    pub mechanism: MechanismArg,
+
+    /// Connects over TLS, trusting the CAs of this PEM file.
+    #[arg(long, global = true, value_name = "PATH")]
+    pub tls_ca: Option<PathBuf>,
+
+    /// Name the broker certificate is verified against, defaults to the broker host.
+    #[arg(long, global = true, requires = "tls_ca")]
+    pub tls_server_name: Option<String>,
+
+    #[command(subcommand)]
+    pub command: Command,
+}
+
+#[derive(Debug, Clone, Copy, ValueEnum)]
+pub enum MechanismArg {
+    Plain,
+    ScramSha256,
+}
+
+#[derive(Debug, Subcommand)]
+pub enum Command {
+    /// Produces the lines of the standard input, or of a file, as records.
+    Produce(ProduceArgs),
+
+    /// Prints the records of a topic as they are produced.
+    Consume(ConsumeArgs),
+
+    /// Manages topics.
+    #[command(subcommand)]
+    Topics(TopicsCommand),
+
+    /// Inspects and moves consumer groups.
+    #[command(subcommand)]
+    Groups(GroupsCommand),
+
+    /// Checks whether the broker is ready, exiting with a failure status when it isn't.
+    Health,
+}
+
+#[derive(Debug, ClapArgs)]
+pub struct ProduceArgs {
+    #[arg(short, long)]
+    pub topic: String,
+
+    /// Reads the records from this file instead of the standard input.
+    #[arg(short, long, value_name = "PATH")]
+    pub file: Option<PathBuf>,
+
+    /// Splits every line into a key and a value at the first occurrence of this separator. Lines
+    /// without it are rejected.
+    #[arg(long, value_name = "SEPARATOR")]
+    pub key_separator: Option<String>,
+
+    /// Records sent to the broker at once.
+    #[arg(long, default_value_t = 100, value_parser = clap::value_parser!(u32).range(1..))]
+    pub batch_size: u32,
+}
+
+#[derive(Debug, ClapArgs)]
+pub struct ConsumeArgs {
+    #[arg(short, long)]
+    pub topic: String,
+
+    /// Consumes as a member of this consumer group, resuming from where it stopped. Without it, a
+    /// new group is used and only records produced from now on are printed.
+    #[arg(short, long)]
+    pub group: Option<String>,
+
+    /// Starts from the first record still stored.
+    #[arg(long)]
+    pub from_beginning: bool,
+
+    /// Exits after printing this many records.
+    #[arg(short = 'n', long, value_name = "COUNT")]
+    pub max_messages: Option<u64>,
+
+    #[arg(long, value_enum, default_value_t = OutputFormat::Text)]
+    pub format: OutputFormat,
+
+    /// Prints the key of every record before its value, in the text format.
+    #[arg(long)]
+    pub print_key: bool,
+
+    /// Separator printed between keys and values.
+    #[arg(long, default_value = "\t", value_name = "SEPARATOR")]
+    pub key_separator: String,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum OutputFormat {
+    /// Record values, one per line.
+    Text,
+
+    /// One JSON object per record, with its offset, key and value.
+    Json,
+}
+
+#[derive(Debug, Subcommand)]
+pub enum TopicsCommand {
+    Create {
+        name: String,
+
+        #[arg(short, long, default_value_t = 1, value_parser = clap::value_parser!(u16).range(1..))]
+        partitions: u16,
+    },
+
+    /// Prints the name of every topic.
+    List,
+
+    /// Prints the offsets of every partition of a topic.
+    Describe { name: String },
+
+    /// Deletes a topic along with its records.
+    Delete { name: String },
+}
+
+#[derive(Debug, Subcommand)]
+pub enum GroupsCommand {
+    /// Prints every consumer group with the topics it consumes from.
+    List,
+
+    /// Prints the offsets and lag of a consumer group on every partition it consumed from.
+    Describe { group: String },
+
+    /// Moves a consumer group on every partition of a topic.
+    ResetOffsets {
+        group: String,
+
+        #[arg(short, long)]
+        topic: String,
+
+        #[command(flatten)]
+        target: ResetTarget,
+    },
+}
+
+#[derive(Debug, ClapArgs)]
+#[group(required = true, multiple = false)]
+pub struct ResetTarget {
+    /// Moves to the first record still stored.
+    #[arg(long)]
+    pub to_earliest: bool,
+
+    /// Moves past the last record, skipping every stored one.
+    #[arg(long)]
+    pub to_latest: bool,
+
+    #[arg(long, value_name = "OFFSET")]
+    pub to_offset: Option<u64>,
+}
+
+impl Args {
+    pub fn connect_options(&self) -> ConnectOptions {
+        let credentials = match (&self.user, &self.password) {
+            (Some(user), Some(password)) => Some(match self.mechanism {
+                MechanismArg::Plain => Credentials::plain(user, password),
+                MechanismArg::ScramSha256 => Credentials::scram_sha_256(user, password),
+            }),
+            _ => None,
+        };
+
+        let tls = self.tls_ca.as_ref().map(|ca_path| ClientTLSConfig {
+            ca_path: ca_path.clone(),
+            server_name: self
+                .tls_server_name
+                .clone()
+                .unwrap_or_else(|| broker_host(&self.broker).to_string()),
+            client_cert: None,
+        });
+
+        ConnectOptions {
+            credentials,
+            tls,
+            ..ConnectOptions::default()
+        }
+    }
+}
+
+impl ResetTarget {
+    pub fn reset(&self) -> OffsetReset {
+        match self.to_offset {
+            Some(offset) => OffsetReset::Offset(offset),
+            None if self.to_earliest => OffsetReset::Earliest,
+            None => OffsetReset::Latest,
+        }
+    }
+}
+
+/// Returns the host of a `[scheme://]host:port` broker address.
+fn broker_host(broker: &str) -> &str {
+    let address = broker.split_once("://").map_or(broker, |(_, address)| address);
+    let authority = address.split('/').next().unwrap_or(address);
+    authority.rsplit_once(':').map_or(authority, |(host, _)| host)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(args: &[&str]) -> Result<Args, clap::Error> {
+        Args::try_parse_from([&["packline-cli"], args].concat())
+    }
+
+    #[test]
+    fn test_reset_offsets_requires_a_single_target() {
+        let args = parse(&[
+            "groups",
+            "reset-offsets",
+            "billing",
+            "--topic",
+            "orders",
+            "--to-offset",
+            "4",
+        ])
+        .unwrap();
+        match args.command {
+            Command::Groups(GroupsCommand::ResetOffsets { target, .. }) => {
+                assert_eq!(OffsetReset::Offset(4), target.reset())
+            }
+            command => panic!("unexpected command {:?}", command),
+        }
+
+        assert!(parse(&["groups", "reset-offsets", "billing", "--topic", "orders"]).is_err());
+        assert!(parse(&[
+            "groups",
+            "reset-offsets",
+            "billing",
+            "--topic",
+            "orders",
+            "--to-earliest",
+            "--to-latest"
+        ])
+        .is_err());
+    }
+
+    #[test]
+    fn test_tls_server_name_defaults_to_broker_host() {
+        let args = parse(&["--broker", "broker.local:1883", "--tls-ca", "ca.pem", "topics", "list"]).unwrap();
+        assert_eq!("broker.local", args.connect_options().tls.unwrap().server_name);
+
+        assert_eq!("localhost", broker_host("ws://localhost:8080/flow"));
+        assert!(parse(&["--user", "alice", "topics", "list"]).is_err());
+    }
+}
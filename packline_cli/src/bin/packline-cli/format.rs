@@ -0,0 +1,146 @@
+use packline_cli::admin::{GroupOffset, TopicDescription};
+use packline_cli::client::Record;
+
+use crate::args::OutputFormat;
+
+/// Builds the record produced for an input line, splitting it at the first `key_separator` when
+/// one is given. Returns `None` when the line doesn't contain the separator.
+pub fn parse_record(line: &str, key_separator: Option<&str>) -> Option<Record> {
+    match key_separator {
+        Some(separator) => line.split_once(separator).map(|(key, value)| Record::new(key, value)),
+        None => Some(Record::from_value(line)),
+    }
+}
+
+/// Renders a consumed record on a single line. Keys and values that aren't valid UTF-8 are printed
+/// lossily.
+pub fn format_record(record: &Record, format: OutputFormat, print_key: bool, key_separator: &str) -> String {
+    let key = String::from_utf8_lossy(&record.key);
+    let value = String::from_utf8_lossy(&record.value);
+
+    match format {
+        OutputFormat::Text if print_key => format!("{}{}{}", key, key_separator, value),
+        OutputFormat::Text => value.into_owned(),
+        OutputFormat::Json => serde_json::json!({
+            "offset": record.offset,
+            "key": if record.key.is_empty() { None } else { Some(key) },
+            "value": value,
+        })
+        .to_string(),
+    }
+}
+
+pub fn topic_table(topic: &TopicDescription) -> String {
+    table(
+        &["TOPIC", "PARTITION", "START", "END"],
+        topic
+            .partitions
+            .iter()
+            .map(|partition| {
+                vec![
+                    topic.name.clone(),
+                    partition.partition.to_string(),
+                    partition.start_offset.to_string(),
+                    partition.end_offset.to_string(),
+                ]
+            })
+            .collect(),
+    )
+}
+
+pub fn group_table(offsets: &[GroupOffset]) -> String {
+    table(
+        &["TOPIC", "PARTITION", "COMMITTED", "POSITION", "END", "LAG"],
+        offsets
+            .iter()
+            .map(|offset| {
+                vec![
+                    offset.topic.clone(),
+                    offset.partition.to_string(),
+                    offset
+                        .committed
+                        .map_or("-".to_string(), |committed| committed.to_string()),
+                    offset.position.to_string(),
+                    offset.end_offset.to_string(),
+                    offset.lag().to_string(),
+                ]
+            })
+            .collect(),
+    )
+}
+
+/// Lays out rows in left aligned columns, separated by two spaces.
+fn table(header: &[&str], rows: Vec<Vec<String>>) -> String {
+    let mut widths = header.iter().map(|column| column.len()).collect::<Vec<_>>();
+    for row in &rows {
+        for (width, cell) in widths.iter_mut().zip(row) {
+            *width = (*width).max(cell.chars().count());
+        }
+    }
+
+    let header = header.iter().map(|column| column.to_string()).collect();
+    std::iter::once(header)
+        .chain(rows)
+        .map(|row| {
+            let line = row
+                .iter()
+                .zip(&widths)
+                .map(|(cell, width)| format!("{:<width$}", cell, width = width))
+                .collect::<Vec<_>>()
+                .join("  ");
+            line.trim_end().to_string()
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_record() {
+        let record = parse_record("user-1=signed up=today", Some("=")).unwrap();
+        assert_eq!(b"user-1".to_vec(), record.key);
+        assert_eq!(b"signed up=today".to_vec(), record.value);
+
+        assert!(parse_record("no separator", Some("=")).is_none());
+
+        let record = parse_record("a=b", None).unwrap();
+        assert!(record.key.is_empty());
+        assert_eq!(b"a=b".to_vec(), record.value);
+    }
+
+    #[test]
+    fn test_format_record() {
+        let mut record = Record::new("k", "v");
+        record.offset = 7;
+
+        assert_eq!("v", format_record(&record, OutputFormat::Text, false, ":"));
+        assert_eq!("k:v", format_record(&record, OutputFormat::Text, true, ":"));
+        assert_eq!(
+            r#"{"key":"k","offset":7,"value":"v"}"#,
+            format_record(&record, OutputFormat::Json, false, ":")
+        );
+        assert_eq!(
+            r#"{"key":null,"offset":0,"value":"v"}"#,
+            format_record(&Record::from_value("v"), OutputFormat::Json, false, ":")
+        );
+    }
+
+    #[test]
+    fn test_group_table() {
+        let offsets = vec![GroupOffset {
+            topic: "orders".to_string(),
+            partition: 1,
+            committed: None,
+            position: 3,
+            end_offset: 10,
+        }];
+
+        assert_eq!(
+            "TOPIC   PARTITION  COMMITTED  POSITION  END  LAG\norders  1          -          3         10   7",
+            group_table(&offsets)
+        );
+    }
+}
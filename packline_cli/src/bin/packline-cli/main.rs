@@ -0,0 +1,171 @@
+use std::error::Error;
+use std::process::ExitCode;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use clap::Parser;
+use futures::StreamExt;
+use tokio::io::{AsyncBufReadExt, AsyncRead, BufReader};
+
+use packline_cli::admin::OffsetReset;
+use packline_cli::client::{connect_with_options, Client};
+
+use crate::args::{Args, Command, ConsumeArgs, GroupsCommand, ProduceArgs, TopicsCommand};
+use crate::format::{format_record, group_table, parse_record, topic_table};
+
+mod args;
+mod format;
+
+#[tokio::main]
+async fn main() -> ExitCode {
+    match run(Args::parse()).await {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("packline-cli: {}", e);
+            ExitCode::FAILURE
+        }
+    }
+}
+
+async fn run(args: Args) -> Result<(), Box<dyn Error>> {
+    let client = connect_with_options(args.broker.as_str(), args.connect_options())
+        .await
+        .map_err(|e| format!("failed to connect to {}: {}", args.broker, e))?;
+
+    match args.command {
+        Command::Produce(produce_args) => produce(&client, produce_args).await,
+        Command::Consume(consume_args) => consume(&client, consume_args).await,
+        Command::Topics(command) => topics(&client, command).await,
+        Command::Groups(command) => groups(&client, command).await,
+        Command::Health => health(&client).await,
+    }
+}
+
+async fn produce(client: &Client, args: ProduceArgs) -> Result<(), Box<dyn Error>> {
+    let input: Box<dyn AsyncRead + Unpin> = match &args.file {
+        Some(path) => Box::new(
+            tokio::fs::File::open(path)
+                .await
+                .map_err(|e| format!("failed to open {}: {}", path.display(), e))?,
+        ),
+        None => Box::new(tokio::io::stdin()),
+    };
+
+    let batch_size = args.batch_size as usize;
+    let mut batch = Vec::with_capacity(batch_size);
+    let mut lines = BufReader::new(input).lines();
+    let mut line_number = 0;
+
+    while let Some(line) = lines.next_line().await? {
+        line_number += 1;
+        if line.is_empty() {
+            continue;
+        }
+
+        let record = parse_record(&line, args.key_separator.as_deref()).ok_or_else(|| {
+            format!(
+                "line {}: missing key separator {:?}",
+                line_number,
+                args.key_separator.as_deref().unwrap_or_default()
+            )
+        })?;
+        batch.push(record);
+
+        if batch.len() == batch_size {
+            client
+                .produce(
+                    args.topic.clone(),
+                    std::mem::replace(&mut batch, Vec::with_capacity(batch_size)),
+                )
+                .await?;
+        }
+    }
+
+    if !batch.is_empty() {
+        client.produce(args.topic, batch).await?;
+    }
+
+    Ok(())
+}
+
+async fn consume(client: &Client, args: ConsumeArgs) -> Result<(), Box<dyn Error>> {
+    // Without a group, a fresh one is used so that every record is printed, even when other
+    // consumers are running.
+    let group = match &args.group {
+        Some(group) => group.clone(),
+        None => format!(
+            "packline-cli-{}-{}",
+            std::process::id(),
+            SystemTime::now().duration_since(UNIX_EPOCH)?.as_millis()
+        ),
+    };
+
+    if args.from_beginning {
+        client
+            .reset_group_offsets(group.clone(), args.topic.clone(), OffsetReset::Earliest)
+            .await?;
+    } else if args.group.is_none() {
+        client
+            .reset_group_offsets(group.clone(), args.topic.clone(), OffsetReset::Latest)
+            .await?;
+    }
+
+    let mut subscriber = client.subscribe(args.topic.clone(), group).await?;
+
+    let mut consumed = 0;
+    while let Some(record) = subscriber.next().await {
+        println!(
+            "{}",
+            format_record(&record?, args.format, args.print_key, &args.key_separator)
+        );
+
+        consumed += 1;
+        if args.max_messages.is_some_and(|max| consumed >= max) {
+            subscriber.close().await?;
+            return Ok(());
+        }
+    }
+
+    Err(format!("subscription to {} was closed by the broker", args.topic).into())
+}
+
+async fn topics(client: &Client, command: TopicsCommand) -> Result<(), Box<dyn Error>> {
+    match command {
+        TopicsCommand::Create { name, partitions } => client.create_topic(name, partitions).await?,
+        TopicsCommand::List => {
+            for topic in client.list_topics().await? {
+                println!("{}", topic.name);
+            }
+        }
+        TopicsCommand::Describe { name } => println!("{}", topic_table(&client.describe_topic(name).await?)),
+        TopicsCommand::Delete { name } => client.delete_topic(name).await?,
+    }
+
+    Ok(())
+}
+
+async fn groups(client: &Client, command: GroupsCommand) -> Result<(), Box<dyn Error>> {
+    match command {
+        GroupsCommand::List => {
+            for group in client.list_groups().await? {
+                println!("{}\t{}", group.name, group.topics.join(","));
+            }
+        }
+        GroupsCommand::Describe { group } => println!("{}", group_table(&client.describe_group(group).await?)),
+        GroupsCommand::ResetOffsets { group, topic, target } => {
+            let offsets = client.reset_group_offsets(group, topic, target.reset()).await?;
+            println!("{}", group_table(&offsets));
+        }
+    }
+
+    Ok(())
+}
+
+async fn health(client: &Client) -> Result<(), Box<dyn Error>> {
+    let health = client.health().await?;
+    if !health.ready {
+        return Err(format!("broker not ready: {}", health.pending.join(", ")).into());
+    }
+
+    println!("ready");
+    Ok(())
+}
@@ -1,61 +1,755 @@
-use tokio::net::{TcpStream, ToSocketAddrs};
+use tokio::net::TcpStream;
+#[cfg(unix)]
+use tokio::net::UnixStream;
 
-use packline_flow::messages::connect::ConnectRequestV1;
+use std::convert::TryFrom;
+use std::sync::Arc;
+use std::time::Duration;
+
+use packline_core::app::acl::AclRule;
+pub use packline_core::app::channel::{Record, RecordBatch};
+use packline_flow::codec::{Compression, FlowCodec};
+use packline_flow::messages::acl::{CreateAclRequestV1, DeleteAclRequestV1, ListAclsRequestV1};
+use packline_flow::messages::connect::{ConnectRequestV1, ConnectRequestV2};
+use packline_flow::messages::group::{
+    DescribeGroupRequestV1, ListGroupsRequestV1, ResetGroupOffsetsRequestV1, RESET_TO_EARLIEST, RESET_TO_LATEST,
+    RESET_TO_OFFSET,
+};
+use packline_flow::messages::health::HealthCheckRequestV1;
+use packline_flow::messages::produce::{ProduceBatchRequestV1, ProduceRequestV1};
+use packline_flow::messages::record::RecordV1;
+use packline_flow::messages::topic::{
+    CreateTopicRequestV1, DeleteTopicRequestV1, DescribeTopicRequestV1, ListTopicsRequestV1,
+};
 use packline_flow::messages::Message;
+use packline_flow::websocket;
 
-use crate::connection::Connection;
+use crate::address::Address;
+use crate::admin::{GroupListing, GroupOffset, Health, OffsetReset, TopicDescription};
+use crate::auth::{authenticate, Credentials};
+use crate::connection::{Connection, ConnectionStream, Contexts};
+use crate::error::ClientError;
+use crate::producer::{Producer, ProducerConfig};
+use crate::session::{Dial, Session};
+use crate::subscriber::Subscriber;
+use crate::tls::ClientTLSConfig;
 
-use packline_flow::messages::subscribe::SubscribeTopicRequestV1;
-use tokio::sync::mpsc::Sender;
+use futures::StreamExt;
+use tokio::sync::oneshot;
+use tokio::task::JoinHandle;
 
 pub struct Client {
-    connection: Connection,
+    connection: Session,
+}
+
+/// Settings applied when establishing a connection with the broker.
+#[derive(Clone)]
+pub struct ConnectOptions {
+    /// Compression used for packets sent in both directions on this connection.
+    pub compression: Compression,
+
+    /// Connects over TLS when set.
+    pub tls: Option<ClientTLSConfig>,
+
+    /// Authenticates right after connecting when set.
+    pub credentials: Option<Credentials>,
+
+    /// How the connection is re-established when lost.
+    pub reconnect: ReconnectPolicy,
+
+    /// Time a request waits for its response before failing with [`ClientError::Timeout`].
+    pub request_timeout: Duration,
 
-    #[allow(dead_code)]
-    consumers: Vec<Sender<bool>>,
+    /// How requests failing with a retriable error are sent again.
+    pub retry: RetryPolicy,
 }
 
-pub async fn connect<T: ToSocketAddrs>(addr: T) -> Result<Client, Box<dyn std::error::Error>> {
-    let socket = TcpStream::connect(addr).await?;
+impl Default for ConnectOptions {
+    fn default() -> Self {
+        ConnectOptions {
+            compression: Compression::default(),
+            tls: None,
+            credentials: None,
+            reconnect: ReconnectPolicy::default(),
+            request_timeout: Duration::from_secs(30),
+            retry: RetryPolicy::default(),
+        }
+    }
+}
+
+/// Retries of requests failing with a retriable error, see [`ClientError::is_retriable`].
+///
+/// Requests that read or reset state are retried whenever they fail that way. Other requests, such as
+/// produce requests, are only retried when they certainly weren't applied: when they couldn't be sent
+/// or the broker rejected a corrupted batch. A produce request timing out isn't retried, as doing so
+/// could append its records twice.
+#[derive(Clone, Debug)]
+pub struct RetryPolicy {
+    /// Retries made after the first attempt, `0` disabling retries.
+    pub max_retries: u32,
 
-    let mut connection = Connection::new(socket);
+    /// Delay before the first retry, doubled after every failed one.
+    pub backoff: Duration,
+}
 
-    connection
-        .send((1, 1), Message::ConnectRequestV1(ConnectRequestV1 {}))
-        .await?;
+impl RetryPolicy {
+    pub fn disabled() -> RetryPolicy {
+        RetryPolicy {
+            max_retries: 0,
+            ..RetryPolicy::default()
+        }
+    }
+
+    /// Returns the delay before the retry numbered `retry`, counting from `0`.
+    pub(crate) fn backoff(&self, retry: u32) -> Duration {
+        self.backoff.saturating_mul(2u32.saturating_pow(retry))
+    }
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        RetryPolicy {
+            max_retries: 3,
+            backoff: Duration::from_millis(100),
+        }
+    }
+}
+
+/// Exponential backoff between the attempts to re-establish a lost connection. The first attempt
+/// is made right away.
+#[derive(Clone, Debug)]
+pub struct ReconnectPolicy {
+    /// Delay before the second attempt, doubled after every failed one up to `max_backoff`.
+    pub initial_backoff: Duration,
+    pub max_backoff: Duration,
+
+    /// Attempts made before giving up, `None` retrying forever. `Some(0)` disables reconnection.
+    pub max_attempts: Option<u32>,
+}
+
+impl ReconnectPolicy {
+    pub fn disabled() -> ReconnectPolicy {
+        ReconnectPolicy {
+            max_attempts: Some(0),
+            ..ReconnectPolicy::default()
+        }
+    }
+
+    /// Returns the delay before the attempt numbered `attempt`, counting from `0`.
+    pub(crate) fn backoff(&self, attempt: u32) -> Duration {
+        match attempt {
+            0 => Duration::ZERO,
+            attempt => self
+                .initial_backoff
+                .saturating_mul(2u32.saturating_pow(attempt - 1))
+                .min(self.max_backoff),
+        }
+    }
+}
+
+impl Default for ReconnectPolicy {
+    fn default() -> Self {
+        ReconnectPolicy {
+            initial_backoff: Duration::from_millis(100),
+            max_backoff: Duration::from_secs(10),
+            max_attempts: None,
+        }
+    }
+}
+
+/// Handle for a topic subscription consumed by a handler, used to stop consuming from it.
+pub struct Subscription {
+    stop: oneshot::Sender<()>,
+    task: JoinHandle<Result<(), ClientError>>,
+}
+
+/// Connects to the broker at `addr`, which can be a `host:port` pair, a `unix://` socket path or a
+/// `ws://` URL. The connection is re-established when lost, see [`ReconnectPolicy`].
+pub async fn connect<A: Into<Address>>(addr: A) -> Result<Client, Box<dyn std::error::Error>> {
+    connect_with_options(addr, ConnectOptions::default()).await
+}
+
+pub async fn connect_with_options<A: Into<Address>>(
+    addr: A,
+    options: ConnectOptions,
+) -> Result<Client, Box<dyn std::error::Error>> {
+    let address = addr.into();
+    let policy = options.reconnect.clone();
+    let retry = options.retry.clone();
+    let dial: Dial = Box::new(move |contexts| {
+        let address = address.clone();
+        let options = options.clone();
+        Box::pin(async move { establish(&address, &options, contexts).await })
+    });
 
     Ok(Client {
-        connection,
-        consumers: Vec::new(),
+        connection: Session::open(dial, policy, retry).await?,
+    })
+}
+
+/// Opens a connection to `address` and runs the connect handshake on it, authenticating when the
+/// options hold credentials.
+async fn establish(
+    address: &Address,
+    options: &ConnectOptions,
+    contexts: Arc<Contexts>,
+) -> Result<Connection, ClientError> {
+    let connection = match address {
+        Address::Tcp(addr) => open(TcpStream::connect(addr).await?, options, contexts).await?,
+        Address::WebSocket(url) => {
+            if Address::is_secure_websocket(url) && options.tls.is_none() {
+                return Err(ClientError::Io(std::io::Error::new(
+                    std::io::ErrorKind::InvalidInput,
+                    "wss:// addresses require a TLS configuration",
+                )));
+            }
+
+            let socket = TcpStream::connect(Address::websocket_authority(url)).await?;
+            open_websocket(url, socket, options, contexts).await?
+        }
+        #[cfg(unix)]
+        Address::Unix(path) => open(UnixStream::connect(path).await?, options, contexts).await?,
+        #[cfg(not(unix))]
+        Address::Unix(_) => {
+            return Err(ClientError::Io(std::io::Error::new(
+                std::io::ErrorKind::Unsupported,
+                "unix sockets are not supported on this platform",
+            )))
+        }
+    };
+
+    // Brokers that don't support compression only know the first version of the request.
+    match options.compression {
+        Compression::None => {
+            connection
+                .send((1, 1), Message::ConnectRequestV1(ConnectRequestV1 {}))
+                .await?
+        }
+        compression => {
+            connection
+                .send(
+                    (1, 2),
+                    Message::ConnectRequestV2(ConnectRequestV2 {
+                        compression: compression as u8,
+                    }),
+                )
+                .await?
+        }
+    };
+
+    if let Some(credentials) = &options.credentials {
+        authenticate(&connection, credentials).await?;
+    }
+
+    Ok(connection)
+}
+
+async fn open<S: ConnectionStream + 'static>(
+    stream: S,
+    options: &ConnectOptions,
+    contexts: Arc<Contexts>,
+) -> Result<Connection, std::io::Error> {
+    let codec = FlowCodec::with_compression(options.compression);
+
+    Ok(match &options.tls {
+        Some(tls) => Connection::new(tls.connect(stream).await?, codec, contexts, options.request_timeout),
+        None => Connection::new(stream, codec, contexts, options.request_timeout),
+    })
+}
+
+async fn open_websocket<S: ConnectionStream + 'static>(
+    url: &str,
+    stream: S,
+    options: &ConnectOptions,
+    contexts: Arc<Contexts>,
+) -> Result<Connection, std::io::Error> {
+    let codec = FlowCodec::with_compression(options.compression);
+
+    Ok(match &options.tls {
+        Some(tls) => Connection::new(
+            websocket::connect(url, tls.connect(stream).await?).await?,
+            codec,
+            contexts,
+            options.request_timeout,
+        ),
+        None => Connection::new(
+            websocket::connect(url, stream).await?,
+            codec,
+            contexts,
+            options.request_timeout,
+        ),
     })
 }
 
 impl Client {
+    pub async fn produce(&self, topic: String, records: Vec<Record>) -> Result<(), ClientError> {
+        let records = records.into_iter().map(RecordV1::from).collect();
+        let response = self
+            .connection
+            .send((6, 1), Message::ProduceRequestV1(ProduceRequestV1::new(topic, records)))
+            .await?;
+
+        match response {
+            Message::ProduceResponseV1(_) => Ok(()),
+            response => Err(unexpected(response)),
+        }
+    }
+
+    /// Produces a batch compressed beforehand, see [`RecordBatch::compress`]. The broker stores it
+    /// as is.
+    pub async fn produce_batch(&self, topic: String, batch: RecordBatch) -> Result<(), ClientError> {
+        let response = self
+            .connection
+            .send(
+                (29, 1),
+                Message::ProduceBatchRequestV1(ProduceBatchRequestV1::new(topic, batch)),
+            )
+            .await?;
+
+        match response {
+            Message::ProduceResponseV1(_) => Ok(()),
+            response => Err(unexpected(response)),
+        }
+    }
+
+    pub async fn create_topic(&self, name: String, partitions: u16) -> Result<(), ClientError> {
+        let response = self
+            .connection
+            .send(
+                (11, 1),
+                Message::CreateTopicRequestV1(CreateTopicRequestV1 { name, partitions }),
+            )
+            .await?;
+
+        match response {
+            Message::CreateTopicRequestV1(_) => Ok(()),
+            response => Err(unexpected(response)),
+        }
+    }
+
+    /// Adds an ACL rule on the broker. Requires the `create` operation on the cluster.
+    pub async fn create_acl(&self, rule: AclRule) -> Result<(), ClientError> {
+        let response = self
+            .connection
+            .send(
+                (12, 1),
+                Message::CreateAclRequestV1(CreateAclRequestV1 { acl: rule.into() }),
+            )
+            .await?;
+
+        match response {
+            Message::CreateAclRequestV1(_) => Ok(()),
+            response => Err(unexpected(response)),
+        }
+    }
+
+    /// Removes an ACL rule from the broker. Requires the `delete` operation on the cluster.
+    pub async fn delete_acl(&self, rule: AclRule) -> Result<(), ClientError> {
+        let response = self
+            .connection
+            .send(
+                (13, 1),
+                Message::DeleteAclRequestV1(DeleteAclRequestV1 { acl: rule.into() }),
+            )
+            .await?;
+
+        match response {
+            Message::DeleteAclRequestV1(_) => Ok(()),
+            response => Err(unexpected(response)),
+        }
+    }
+
+    pub async fn list_acls(&self) -> Result<Vec<AclRule>, ClientError> {
+        let response = self
+            .connection
+            .send_idempotent((14, 1), Message::ListAclsRequestV1(ListAclsRequestV1 {}))
+            .await?;
+
+        match response {
+            Message::ListAclsResponseV1(list) => Ok(list
+                .acls
+                .into_iter()
+                .map(AclRule::try_from)
+                .collect::<Result<Vec<_>, _>>()?),
+            response => Err(unexpected(response)),
+        }
+    }
+
+    /// Lists the topics the connection may describe, sorted by name.
+    pub async fn list_topics(&self) -> Result<Vec<TopicDescription>, ClientError> {
+        let response = self
+            .connection
+            .send_idempotent((16, 1), Message::ListTopicsRequestV1(ListTopicsRequestV1 {}))
+            .await?;
+
+        match response {
+            Message::ListTopicsResponseV1(list) => Ok(list.topics.into_iter().map(TopicDescription::from).collect()),
+            response => Err(unexpected(response)),
+        }
+    }
+
+    pub async fn describe_topic(&self, name: String) -> Result<TopicDescription, ClientError> {
+        let response = self
+            .connection
+            .send_idempotent(
+                (18, 1),
+                Message::DescribeTopicRequestV1(DescribeTopicRequestV1 { name }),
+            )
+            .await?;
+
+        match response {
+            Message::DescribeTopicResponseV1(describe) => Ok(describe.topic.into()),
+            response => Err(unexpected(response)),
+        }
+    }
+
+    /// Deletes every partition of a topic along with its records.
+    pub async fn delete_topic(&self, name: String) -> Result<(), ClientError> {
+        let response = self
+            .connection
+            .send((20, 1), Message::DeleteTopicRequestV1(DeleteTopicRequestV1 { name }))
+            .await?;
+
+        match response {
+            Message::DeleteTopicRequestV1(_) => Ok(()),
+            response => Err(unexpected(response)),
+        }
+    }
+
+    /// Lists the consumer groups the connection may describe, sorted by name.
+    pub async fn list_groups(&self) -> Result<Vec<GroupListing>, ClientError> {
+        let response = self
+            .connection
+            .send_idempotent((21, 1), Message::ListGroupsRequestV1(ListGroupsRequestV1 {}))
+            .await?;
+
+        match response {
+            Message::ListGroupsResponseV1(list) => Ok(list.groups.into_iter().map(GroupListing::from).collect()),
+            response => Err(unexpected(response)),
+        }
+    }
+
+    /// Returns the offsets of a consumer group on every partition it consumed from.
+    pub async fn describe_group(&self, group: String) -> Result<Vec<GroupOffset>, ClientError> {
+        let response = self
+            .connection
+            .send_idempotent(
+                (23, 1),
+                Message::DescribeGroupRequestV1(DescribeGroupRequestV1 { group }),
+            )
+            .await?;
+
+        match response {
+            Message::DescribeGroupResponseV1(describe) => {
+                Ok(describe.offsets.into_iter().map(GroupOffset::from).collect())
+            }
+            response => Err(unexpected(response)),
+        }
+    }
+
+    /// Moves a consumer group on every partition of `topic`, returning its new offsets there.
+    pub async fn reset_group_offsets(
+        &self,
+        group: String,
+        topic: String,
+        reset: OffsetReset,
+    ) -> Result<Vec<GroupOffset>, ClientError> {
+        let (target, offset) = match reset {
+            OffsetReset::Earliest => (RESET_TO_EARLIEST, 0),
+            OffsetReset::Latest => (RESET_TO_LATEST, 0),
+            OffsetReset::Offset(offset) => (RESET_TO_OFFSET, offset),
+        };
+
+        let response = self
+            .connection
+            .send_idempotent(
+                (25, 1),
+                Message::ResetGroupOffsetsRequestV1(ResetGroupOffsetsRequestV1 {
+                    group,
+                    topic,
+                    target,
+                    offset,
+                }),
+            )
+            .await?;
+
+        match response {
+            Message::DescribeGroupResponseV1(describe) => {
+                Ok(describe.offsets.into_iter().map(GroupOffset::from).collect())
+            }
+            response => Err(unexpected(response)),
+        }
+    }
+
+    /// Returns whether the broker is ready to serve clients.
+    pub async fn health(&self) -> Result<Health, ClientError> {
+        let response = self
+            .connection
+            .send_idempotent((26, 1), Message::HealthCheckRequestV1(HealthCheckRequestV1 {}))
+            .await?;
+
+        match response {
+            Message::HealthCheckResponseV1(health) => Ok(Health::from(health)),
+            response => Err(unexpected(response)),
+        }
+    }
+
+    /// Creates a producer sending records in batches, see [`Producer`].
+    pub fn producer(&self, config: ProducerConfig) -> Producer {
+        Producer::new(self.connection.clone(), config)
+    }
+
+    /// Subscribes to `topic` as a member of the consumer group `group`, returning a stream of its
+    /// records. An empty group consumes the topic on its own from its first stored record, without
+    /// committing offsets.
+    pub async fn subscribe(&self, topic: String, group: String) -> Result<Subscriber, ClientError> {
+        Subscriber::open(self.connection.clone(), topic, group).await
+    }
+
+    /// Subscribes to `topic`, calling `handler` for every record received. Batches failing checksum
+    /// verification end the subscription with [`ClientError::CorruptBatch`].
     #[allow(clippy::unused_unit)]
-    pub async fn consume<F>(&mut self, topic: String, handler: F)
+    pub async fn consume<F>(&mut self, topic: String, handler: F) -> Result<Subscription, ClientError>
     where
-        F: Fn(u32) -> () + Send + 'static,
+        F: Fn(Record) -> () + Send + 'static,
     {
-        let mut stream = self
-            .connection
-            .open_stream(
-                (2, 1),
-                Message::SubscribeTopicRequestV1(SubscribeTopicRequestV1 {
-                    topic: topic.clone(),
-                    consumer_group_id: "".to_string(),
-                }),
+        self.consume_group(topic, String::new(), handler).await
+    }
+
+    /// Subscribes to `topic` as a member of the consumer group `group`, which shares the records of
+    /// the topic between its members and resumes from where it stopped.
+    #[allow(clippy::unused_unit)]
+    pub async fn consume_group<F>(
+        &mut self,
+        topic: String,
+        group: String,
+        handler: F,
+    ) -> Result<Subscription, ClientError>
+    where
+        F: Fn(Record) -> () + Send + 'static,
+    {
+        let mut subscriber = self.subscribe(topic, group).await?;
+        let (stop, mut stopped) = oneshot::channel();
+
+        let task = tokio::spawn(async move {
+            loop {
+                tokio::select! {
+                    record = subscriber.next() => match record {
+                        Some(record) => handler(record?),
+                        None => return Ok(()),
+                    },
+                    _ = &mut stopped => return subscriber.close().await,
+                }
+            }
+        });
+
+        Ok(Subscription { stop, task })
+    }
+}
+
+/// Maps a response that didn't match the request to the error it carries.
+pub(crate) fn unexpected(response: Message) -> ClientError {
+    match response {
+        Message::ErrorResponseV1(e) => ClientError::Broker {
+            code: e.code,
+            message: e.message,
+        },
+        _ => ClientError::UnexpectedResponse,
+    }
+}
+
+impl Subscription {
+    /// Closes the subscription stream on the broker. Records already in flight are not delivered
+    /// to the handler once this returns.
+    pub async fn unsubscribe(self) -> Result<(), ClientError> {
+        let _ = self.stop.send(());
+
+        join(self.task).await
+    }
+
+    /// Waits until the subscription ends, returning the error that ended it, if any.
+    pub async fn closed(self) -> Result<(), ClientError> {
+        join(self.task).await
+    }
+}
+
+async fn join(task: JoinHandle<Result<(), ClientError>>) -> Result<(), ClientError> {
+    match task.await {
+        Ok(result) => result,
+        Err(e) => Err(ClientError::Io(e.into())),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::SocketAddr;
+    use std::sync::Mutex;
+
+    use packline_flow::broker::Broker;
+    use tokio::net::TcpListener;
+    use tokio::task::AbortHandle;
+
+    use super::*;
+
+    /// Forwards connections to `target`. Aborting the returned handles cuts the live ones.
+    async fn proxy(target: SocketAddr) -> (SocketAddr, Arc<Mutex<Vec<AbortHandle>>>) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap();
+        let connections = Arc::new(Mutex::new(vec![]));
+
+        let handles = connections.clone();
+        tokio::spawn(async move {
+            while let Ok((mut inbound, _)) = listener.accept().await {
+                let mut outbound = TcpStream::connect(target).await.unwrap();
+                let task = tokio::spawn(async move {
+                    let _ = tokio::io::copy_bidirectional(&mut inbound, &mut outbound).await;
+                });
+                handles.lock().unwrap().push(task.abort_handle());
+            }
+        });
+
+        (address, connections)
+    }
+
+    async fn next_value(rx: &mut tokio::sync::mpsc::UnboundedReceiver<Vec<u8>>) -> Vec<u8> {
+        tokio::time::timeout(Duration::from_secs(5), rx.recv())
+            .await
+            .expect("no record received")
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_reconnect_resumes_subscription() {
+        for group in ["billing", ""] {
+            reconnect_resumes_subscription(group).await;
+        }
+    }
+
+    async fn reconnect_resumes_subscription(group: &str) {
+        let broker = Broker::builder().topic("orders", 1).start().await.unwrap();
+        let (address, connections) = proxy(broker.addr()).await;
+
+        let options = ConnectOptions {
+            reconnect: ReconnectPolicy {
+                initial_backoff: Duration::from_millis(10),
+                ..ReconnectPolicy::default()
+            },
+            ..ConnectOptions::default()
+        };
+        let mut client = connect_with_options(address, options).await.unwrap();
+
+        let (tx, mut rx) = tokio::sync::mpsc::unbounded_channel();
+        let _subscription = client
+            .consume_group("orders".to_string(), group.to_string(), move |record| {
+                let _ = tx.send(record.value);
+            })
+            .await
+            .unwrap();
+
+        client
+            .produce(
+                "orders".to_string(),
+                vec![Record::from_value("a"), Record::from_value("b")],
             )
             .await
             .unwrap();
+        assert_eq!(b"a".to_vec(), next_value(&mut rx).await);
+        assert_eq!(b"b".to_vec(), next_value(&mut rx).await);
 
-        tokio::spawn(async move {
-            while let Some(message) = stream.recv().await {
-                if let Message::ConsumeV1(c) = message {
-                    for record in c.records {
-                        handler(record);
-                    }
-                }
+        for connection in connections.lock().unwrap().drain(..) {
+            connection.abort();
+        }
+
+        // Produced while the client is away, the broker may consume it on the lost connection.
+        let channel = broker.app().get_channel(&("orders".to_string(), 1)).await.unwrap();
+        channel.producer().produce(&mut vec![Record::from_value("c")]).await;
+
+        loop {
+            match client.health().await {
+                Ok(_) => break,
+                Err(ClientError::Disconnected) => tokio::time::sleep(Duration::from_millis(10)).await,
+                Err(e) => panic!("unexpected error {}", e),
             }
-        });
+        }
+
+        assert_eq!(b"c".to_vec(), next_value(&mut rx).await);
+        client
+            .produce("orders".to_string(), vec![Record::from_value("d")])
+            .await
+            .unwrap();
+        assert_eq!(b"d".to_vec(), next_value(&mut rx).await);
+    }
+
+    #[tokio::test]
+    async fn test_subscriptions_end_on_unsubscribe_and_stream_close() {
+        let broker = Broker::builder().topic("orders", 1).start().await.unwrap();
+        let mut client = connect(broker.addr()).await.unwrap();
+
+        let (tx, mut rx) = tokio::sync::mpsc::unbounded_channel();
+        let subscription = client
+            .consume_group("orders".to_string(), "billing".to_string(), move |record| {
+                let _ = tx.send(record.value);
+            })
+            .await
+            .unwrap();
+
+        client
+            .produce("orders".to_string(), vec![Record::from_value("a")])
+            .await
+            .unwrap();
+        assert_eq!(b"a".to_vec(), next_value(&mut rx).await);
+
+        // Unsubscribing stops the broker from consuming on behalf of the group.
+        subscription.unsubscribe().await.unwrap();
+        client
+            .produce("orders".to_string(), vec![Record::from_value("b")])
+            .await
+            .unwrap();
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        let offsets = client.describe_group("billing".to_string()).await.unwrap();
+        assert_eq!(1, offsets[0].position);
+        assert!(rx.recv().await.is_none());
+
+        // A stream closed by the broker ends the subscription.
+        let subscription = client.consume("missing".to_string(), |_| {}).await.unwrap();
+        let closed = tokio::time::timeout(Duration::from_secs(5), subscription.closed());
+        assert!(closed.await.expect("subscription wasn't closed").is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_produce_batch() {
+        let broker = Broker::builder().topic("orders", 1).start().await.unwrap();
+        let mut client = connect(broker.addr()).await.unwrap();
+
+        let (tx, mut rx) = tokio::sync::mpsc::unbounded_channel();
+        let _subscription = client
+            .consume("orders".to_string(), move |record| {
+                let _ = tx.send(record.value);
+            })
+            .await
+            .unwrap();
+
+        let batch =
+            RecordBatch::compress(&[Record::from_value("a"), Record::from_value("b")], Compression::Lz4).unwrap();
+        client.produce_batch("orders".to_string(), batch).await.unwrap();
+        assert_eq!(b"a".to_vec(), next_value(&mut rx).await);
+        assert_eq!(b"b".to_vec(), next_value(&mut rx).await);
+    }
+
+    #[test]
+    fn test_reconnect_backoff() {
+        let policy = ReconnectPolicy {
+            initial_backoff: Duration::from_millis(100),
+            max_backoff: Duration::from_millis(500),
+            max_attempts: None,
+        };
+
+        assert_eq!(
+            vec![0, 100, 200, 400, 500, 500],
+            (0..6)
+                .map(|attempt| policy.backoff(attempt).as_millis())
+                .collect::<Vec<_>>()
+        );
+        assert_eq!(Duration::from_millis(500), policy.backoff(u32::MAX));
     }
 }
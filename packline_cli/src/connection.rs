@@ -2,95 +2,649 @@ use futures::stream::SplitSink;
 use futures::{SinkExt, StreamExt};
 use log::debug;
 use packline_flow::codec::FlowCodec;
+use packline_flow::messages::subscribe::SubscribeTopicRequestV2;
+use packline_flow::messages::unsubscribe::UnsubscribeRequestV1;
 use packline_flow::messages::{Message, Packet, PacketType, RouteWithVersion};
-use std::collections::HashMap;
-use std::sync::Arc;
-use tokio::net::TcpStream;
-use tokio::sync::mpsc::{channel, Receiver, Sender};
+use std::collections::{HashMap, VecDeque};
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::sync::{Arc, Mutex as StdMutex};
+use std::time::Duration;
+use tokio::io::{AsyncRead, AsyncWrite};
+use tokio::sync::mpsc::error::TrySendError;
+use tokio::sync::mpsc::Sender;
 use tokio::sync::oneshot::{channel as oneshot_channel, Sender as OneshotSender};
-use tokio::sync::Mutex;
+use tokio::sync::{watch, Mutex};
 use tokio_util::codec::Framed;
 
+use crate::error::ClientError;
+
+/// Byte stream a [`Connection`] runs on, such as a plain TCP socket or a TLS session.
+pub trait ConnectionStream: AsyncRead + AsyncWrite + Send + Unpin {}
+
+impl<T: AsyncRead + AsyncWrite + Send + Unpin> ConnectionStream for T {}
+
+type BoxedStream = Box<dyn ConnectionStream>;
+
+type FlowSink = Arc<Mutex<SplitSink<Framed<BoxedStream, FlowCodec>, Packet>>>;
+
+type Requests = Arc<StdMutex<HashMap<u32, OneshotSender<Packet>>>>;
+
+/// Most messages kept for a subscriber that has no room for them, past which its stream is paused
+/// on the broker until they are forwarded.
+const MAX_BACKLOG: usize = 64;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum StreamState {
+    Running,
+
+    /// Unsubscribed from the broker because the backlog is full, until the broker closes the stream.
+    Pausing,
+
+    /// Closed on the broker, re-opened once the backlog is forwarded.
+    Paused,
+}
+
+/// Subscription stream, routed by context id.
+pub(crate) struct StreamEntry {
+    sender: Sender<Message>,
+
+    /// Request re-opening the subscription on a new connection. Its offset follows the records
+    /// received, so that the subscription resumes right after the last one.
+    pub subscribe: SubscribeTopicRequestV2,
+
+    /// Messages the subscriber had no room for yet, forwarded in order as it makes room.
+    backlog: VecDeque<Message>,
+
+    /// Set once the broker closed the stream, which ends as soon as its backlog is forwarded.
+    closed: bool,
+    state: StreamState,
+}
+
+impl StreamEntry {
+    pub fn new(sender: Sender<Message>, subscribe: SubscribeTopicRequestV2) -> StreamEntry {
+        StreamEntry {
+            sender,
+            subscribe,
+            backlog: VecDeque::new(),
+            closed: false,
+            state: StreamState::Running,
+        }
+    }
+}
+
+/// Context ids and streams shared by the connections of a [`Session`](crate::session::Session), so
+/// that streams survive reconnections and context ids aren't reused within a session.
+#[derive(Default)]
+pub(crate) struct Contexts {
+    next_id: AtomicU32,
+    pub streams: Mutex<HashMap<u32, StreamEntry>>,
+
+    /// Sink of the current connection, which paused streams are re-opened on.
+    sink: StdMutex<Option<FlowSink>>,
+}
+
+impl Contexts {
+    /// Allocates context ids in increasing order, wrapping around after `u32::MAX`.
+    pub fn next_id(&self) -> u32 {
+        self.next_id.fetch_add(1, Ordering::Relaxed)
+    }
+
+    /// Creates a request packet with the next context id.
+    pub fn packet(&self, route: RouteWithVersion, message: Message) -> Packet {
+        Packet::new_with_context_id(self.next_id(), route, message)
+    }
+
+    /// Makes `connection` the one paused streams are re-opened on, returning the subscriptions to
+    /// re-open on it: those the broker didn't close, except paused ones with messages left to forward.
+    pub async fn attach(&self, connection: &Connection) -> Vec<(u32, SubscribeTopicRequestV2)> {
+        // The streams are locked while swapping the sink, so that a stream is re-opened either here
+        // or by its backlog forwarder, never by both.
+        let mut streams = self.streams.lock().await;
+        *self.sink.lock().unwrap() = Some(connection.sink.clone());
+
+        let mut subscriptions = vec![];
+        for (stream_id, entry) in streams.iter_mut().filter(|(_, entry)| !entry.closed) {
+            if entry.state != StreamState::Running && !entry.backlog.is_empty() {
+                // The stream isn't open on the new connection, whose broker end won't close it.
+                entry.state = StreamState::Paused;
+                continue;
+            }
+
+            entry.state = StreamState::Running;
+            subscriptions.push((*stream_id, entry.subscribe.clone()));
+        }
+
+        subscriptions
+    }
+
+    /// Hands a streamed message to the subscriber of the stream without waiting on it, so that a
+    /// slow subscriber doesn't hold back the rest of the connection. Returns `false` when the broker
+    /// should stop sending the stream: nobody reads it anymore, or its subscriber has
+    /// [`MAX_BACKLOG`] messages left to receive, in which case it is re-opened once they are.
+    async fn route(self: &Arc<Self>, stream_id: u32, message: Message) -> bool {
+        let mut streams = self.streams.lock().await;
+        let entry = match streams.get_mut(&stream_id) {
+            Some(entry) => entry,
+            None => return true,
+        };
+
+        if entry.sender.is_closed() {
+            streams.remove(&stream_id);
+            return false;
+        }
+
+        // Messages still in flight once paused are dropped, the stream resumes before them.
+        if entry.state != StreamState::Running {
+            return true;
+        }
+
+        if let Message::ConsumeV1(consume) = &message {
+            if let Some(last) = consume.records.last() {
+                entry.subscribe.offset = last.offset as i64 + 1;
+            }
+        }
+
+        if !entry.backlog.is_empty() {
+            entry.backlog.push_back(message);
+            if entry.backlog.len() >= MAX_BACKLOG {
+                debug!("stream {} not read, pausing", stream_id);
+                entry.state = StreamState::Pausing;
+                return false;
+            }
+            return true;
+        }
+
+        match entry.sender.try_send(message) {
+            Ok(()) => true,
+            Err(TrySendError::Full(message)) => {
+                entry.backlog.push_back(message);
+                tokio::spawn(self.clone().forward_backlog(stream_id));
+                true
+            }
+            Err(TrySendError::Closed(_)) => {
+                streams.remove(&stream_id);
+                false
+            }
+        }
+    }
+
+    /// Ends the stream for its subscriber once the messages it has yet to receive are forwarded.
+    /// Paused streams are re-opened instead, as the broker closed them on request.
+    async fn close(&self, stream_id: u32) {
+        let mut streams = self.streams.lock().await;
+        let resume = match streams.get_mut(&stream_id) {
+            Some(entry) if entry.state == StreamState::Pausing => {
+                entry.state = StreamState::Paused;
+                match entry.backlog.is_empty() {
+                    true => self.resume(stream_id, entry),
+                    false => None,
+                }
+            }
+            Some(entry) if entry.state == StreamState::Paused => None,
+            Some(entry) if !entry.backlog.is_empty() => {
+                entry.closed = true;
+                None
+            }
+            _ => {
+                streams.remove(&stream_id);
+                None
+            }
+        };
+
+        drop(streams);
+        send_resume(resume).await;
+    }
+
+    /// Marks a paused stream as running, returning the request re-opening it along with the sink of
+    /// the current connection to send it on.
+    fn resume(&self, stream_id: u32, entry: &mut StreamEntry) -> Option<(FlowSink, Packet)> {
+        let sink = self.sink.lock().unwrap().clone()?;
+        entry.state = StreamState::Running;
+
+        debug!("resuming stream {} from offset {}", stream_id, entry.subscribe.offset);
+        let subscribe = Message::SubscribeTopicRequestV2(entry.subscribe.clone());
+        Some((sink, Packet::new_with_context_id(stream_id, (2, 2), subscribe)))
+    }
+
+    /// Forwards the backlog of a stream as its subscriber makes room for it. Runs while the backlog
+    /// isn't empty, started by [`Contexts::route`] when it stops being so.
+    async fn forward_backlog(self: Arc<Self>, stream_id: u32) {
+        loop {
+            let sender = match self.streams.lock().await.get(&stream_id) {
+                Some(entry) => entry.sender.clone(),
+                None => return,
+            };
+
+            let permit = match sender.reserve_owned().await {
+                Ok(permit) => permit,
+                Err(_) => {
+                    self.streams.lock().await.remove(&stream_id);
+                    return;
+                }
+            };
+
+            let mut streams = self.streams.lock().await;
+            let entry = match streams.get_mut(&stream_id) {
+                Some(entry) => entry,
+                None => return,
+            };
+
+            if let Some(message) = entry.backlog.pop_front() {
+                permit.send(message);
+            }
+
+            if entry.backlog.is_empty() {
+                let resume = if entry.closed {
+                    streams.remove(&stream_id);
+                    None
+                } else if entry.state == StreamState::Paused {
+                    self.resume(stream_id, entry)
+                } else {
+                    None
+                };
+
+                drop(streams);
+                send_resume(resume).await;
+                return;
+            }
+        }
+    }
+}
+
+/// Sends the request re-opening a paused stream, if any. A failure leaves the stream to be re-opened
+/// on the next connection.
+async fn send_resume(resume: Option<(FlowSink, Packet)>) {
+    if let Some((sink, packet)) = resume {
+        let _ = sink.lock().await.send(packet).await;
+    }
+}
+
+/// Failure of a request, telling whether it was written to the broker. Requests that weren't can
+/// be retried whatever they do.
+#[derive(Debug)]
+pub(crate) struct RequestError {
+    pub error: ClientError,
+    pub sent: bool,
+}
+
+impl RequestError {
+    pub fn unsent(error: ClientError) -> RequestError {
+        RequestError { error, sent: false }
+    }
+
+    fn sent(error: ClientError) -> RequestError {
+        RequestError { error, sent: true }
+    }
+}
+
+impl From<RequestError> for ClientError {
+    fn from(e: RequestError) -> Self {
+        e.error
+    }
+}
+
+/// Entry of a request in the request table, removed once the request is answered, times out or is
+/// dropped by its caller.
+struct PendingRequest<'a> {
+    requests: &'a Requests,
+    context_id: u32,
+}
+
+impl Drop for PendingRequest<'_> {
+    fn drop(&mut self) {
+        self.requests.lock().unwrap().remove(&self.context_id);
+    }
+}
+
+/// Flow protocol exchange over a single byte stream. Once the stream ends, pending and later
+/// requests fail with [`ClientError::Disconnected`].
+#[derive(Clone)]
 pub struct Connection {
-    requests: Arc<Mutex<HashMap<u32, OneshotSender<Packet>>>>,
-    streams: Arc<Mutex<HashMap<u32, Sender<Message>>>>,
-    sink: SplitSink<Framed<TcpStream, FlowCodec>, Packet>,
+    contexts: Arc<Contexts>,
+    requests: Requests,
+    sink: FlowSink,
+    closed: watch::Receiver<bool>,
+
+    /// Time a request waits for its response before failing with [`ClientError::Timeout`].
+    request_timeout: Duration,
 }
 
 impl Connection {
-    pub fn new(stream: TcpStream) -> Connection {
-        let (sink, mut stream) = Framed::new(stream, FlowCodec::new()).split();
-        let requests: Arc<Mutex<HashMap<u32, OneshotSender<Packet>>>> = Arc::new(Mutex::new(HashMap::new()));
-        let streams: Arc<Mutex<HashMap<u32, Sender<Message>>>> = Arc::new(Mutex::new(HashMap::new()));
+    pub(crate) fn new<S: ConnectionStream + 'static>(
+        stream: S,
+        codec: FlowCodec,
+        contexts: Arc<Contexts>,
+        request_timeout: Duration,
+    ) -> Connection {
+        let (sink, mut stream) = Framed::new(Box::new(stream) as BoxedStream, codec).split();
+        let sink: FlowSink = Arc::new(Mutex::new(sink));
+        let requests: Requests = Arc::new(StdMutex::new(HashMap::new()));
+        let (closed_tx, closed) = watch::channel(false);
 
         let clone = requests.clone();
-        let streams_clone = streams.clone();
+        let contexts_clone = contexts.clone();
+        let sink_clone = sink.clone();
         tokio::spawn(async move {
             loop {
-                let packet = stream.next().await;
-                match packet {
+                let packet = match stream.next().await {
+                    Some(Ok(packet)) => packet,
+                    Some(Err(e)) => {
+                        debug!("connection failed: {}", e);
+                        break;
+                    }
                     None => break,
-                    Some(r) => {
-                        let packet = r.unwrap();
+                };
 
-                        match packet.packet_type {
-                            PacketType::Stream => {
-                                let stream_table = streams_clone.lock().await;
-                                let sender = stream_table.get(&packet.context_id);
+                match packet.packet_type {
+                    PacketType::Stream => {
+                        if let Message::StreamCloseV1(_) = packet.message {
+                            debug!("stream {} closed by broker", packet.context_id);
+                            contexts_clone.close(packet.context_id).await;
+                        } else if !contexts_clone.route(packet.context_id, packet.message).await {
+                            // The broker is asked to stop sending a stream nobody reads, or that is
+                            // paused until its subscriber catches up.
+                            debug!("unsubscribing from stream {}", packet.context_id);
+                            let unsubscribe = contexts_clone.packet(
+                                (4, 1),
+                                Message::UnsubscribeRequestV1(UnsubscribeRequestV1 {
+                                    stream_id: packet.context_id,
+                                }),
+                            );
+                            let _ = sink_clone.lock().await.send(unsubscribe).await;
+                        }
+                    }
+                    PacketType::Request => {
+                        let sender = clone.lock().unwrap().remove(&packet.context_id);
 
-                                let _ = sender.unwrap().send(packet.message).await;
+                        debug!("received response {:?}", packet);
+                        match sender {
+                            Some(sender) => {
+                                let _ = sender.send(packet);
                             }
-                            PacketType::Request => {
-                                let mut request_table = clone.lock().await;
-                                let sender = request_table.remove(&packet.context_id);
-
-                                debug!("received response {:?}", &packet);
-                                let _ = sender.unwrap().send(packet);
+                            // Requests opening a stream are answered on the stream itself when rejected.
+                            None => {
+                                if contexts_clone.route(packet.context_id, packet.message).await {
+                                    contexts_clone.close(packet.context_id).await;
+                                }
                             }
                         }
                     }
                 }
             }
+
+            // Dropping the senders of pending requests fails them. The flag is set under the lock so
+            // that no request is registered afterwards.
+            let mut requests = clone.lock().unwrap();
+            let _ = closed_tx.send(true);
+            requests.clear();
         });
 
         Connection {
-            streams,
+            contexts,
             requests,
             sink,
+            closed,
+            request_timeout,
         }
     }
 
-    pub async fn send(&mut self, route: RouteWithVersion, message: Message) -> Result<Message, std::io::Error> {
+    pub async fn send(&self, route: RouteWithVersion, message: Message) -> Result<Message, RequestError> {
         let (tx, rx) = oneshot_channel::<Packet>();
 
-        let packet = Packet::new(route, message);
-        {
-            let mut requests_table = self.requests.lock().await;
+        let packet = self.contexts.packet(route, message);
+        let _pending = {
+            let mut requests_table = self.requests.lock().unwrap();
+            if self.is_closed() {
+                return Err(RequestError::unsent(ClientError::Disconnected));
+            }
+
             requests_table.insert(packet.context_id, tx);
+            PendingRequest {
+                requests: &self.requests,
+                context_id: packet.context_id,
+            }
+        };
+
+        self.sink
+            .lock()
+            .await
+            .send(packet)
+            .await
+            .map_err(|e| RequestError::sent(e.into()))?;
+
+        match tokio::time::timeout(self.request_timeout, rx).await {
+            Ok(Ok(packet)) => Ok(packet.message),
+            Ok(Err(_)) => Err(RequestError::sent(ClientError::Disconnected)),
+            Err(_) => Err(RequestError::sent(ClientError::Timeout)),
+        }
+    }
+
+    /// Sends a request opening a stream, whose packets are routed through the stream table under
+    /// the context id of the request.
+    pub(crate) async fn send_stream_request(&self, packet: Packet) -> Result<(), ClientError> {
+        if self.is_closed() {
+            return Err(ClientError::Disconnected);
+        }
+
+        Ok(self.sink.lock().await.send(packet).await?)
+    }
+
+    /// Asks the broker to close the stream.
+    pub(crate) async fn close_stream(&self, stream_id: u32) -> Result<(), ClientError> {
+        self.send(
+            (4, 1),
+            Message::UnsubscribeRequestV1(UnsubscribeRequestV1 { stream_id }),
+        )
+        .await?;
+
+        Ok(())
+    }
+
+    pub fn is_closed(&self) -> bool {
+        *self.closed.borrow()
+    }
+
+    /// Waits until the byte stream ends.
+    pub async fn closed(&self) {
+        let mut closed = self.closed.clone();
+        let _ = closed.wait_for(|closed| *closed).await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use packline_flow::messages::consume::ConsumeV1;
+    use packline_flow::messages::health::{HealthCheckRequestV1, HealthCheckResponseV1};
+    use packline_flow::messages::record::RecordV1;
+    use packline_flow::messages::subscribe::GROUP_POSITION;
+    use packline_flow::messages::unsubscribe::StreamCloseV1;
+
+    use super::*;
+
+    fn health_check() -> Message {
+        Message::HealthCheckRequestV1(HealthCheckRequestV1 {})
+    }
+
+    #[tokio::test]
+    async fn test_pending_requests_fail_when_disconnected() {
+        let (client, server) = tokio::io::duplex(4096);
+        let connection = Connection::new(client, FlowCodec::new(), Arc::default(), Duration::from_secs(30));
+
+        let request = {
+            let connection = connection.clone();
+            tokio::spawn(async move { connection.send((26, 1), health_check()).await })
+        };
+
+        // Drops the broker end once the request reached it, leaving it unanswered.
+        let mut server = Framed::new(server, FlowCodec::new());
+        server.next().await.unwrap().unwrap();
+        drop(server);
+
+        let error = request.await.unwrap().unwrap_err();
+        assert!(matches!(error.error, ClientError::Disconnected));
+        assert!(error.sent);
+
+        connection.closed().await;
+        let error = connection.send((26, 1), health_check()).await.unwrap_err();
+        assert!(matches!(error.error, ClientError::Disconnected));
+        assert!(!error.sent);
+    }
+
+    #[tokio::test]
+    async fn test_requests_time_out_and_release_their_entry() {
+        let (client, server) = tokio::io::duplex(4096);
+        let connection = Connection::new(client, FlowCodec::new(), Arc::default(), Duration::from_millis(50));
+        let mut server = Framed::new(server, FlowCodec::new());
+
+        let error = connection.send((26, 1), health_check()).await.unwrap_err();
+        assert!(matches!(error.error, ClientError::Timeout));
+        assert!(connection.requests.lock().unwrap().is_empty());
+
+        // A dropped request releases its entry as well.
+        let request = {
+            let connection = connection.clone();
+            tokio::spawn(async move { connection.send((26, 1), health_check()).await })
+        };
+        let first = server.next().await.unwrap().unwrap().context_id;
+        let second = server.next().await.unwrap().unwrap().context_id;
+        assert_eq!(first + 1, second);
+        assert_eq!(1, connection.requests.lock().unwrap().len());
+
+        request.abort();
+        let _ = request.await;
+        assert!(connection.requests.lock().unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_unread_streams_do_not_stall_the_connection() {
+        let (client, server) = tokio::io::duplex(4096);
+        let contexts = Arc::<Contexts>::default();
+        let connection = Connection::new(client, FlowCodec::new(), contexts.clone(), Duration::from_secs(5));
+        let mut server = Framed::new(server, FlowCodec::new());
+
+        let (tx, mut rx) = tokio::sync::mpsc::channel(1);
+        let subscribe = SubscribeTopicRequestV2 {
+            topic: "orders".to_string(),
+            consumer_group_id: String::new(),
+            offset: GROUP_POSITION,
+        };
+        contexts.streams.lock().await.insert(7, StreamEntry::new(tx, subscribe));
+
+        let request = {
+            let connection = connection.clone();
+            tokio::spawn(async move { connection.send((26, 1), health_check()).await })
+        };
+        let request_id = server.next().await.unwrap().unwrap().context_id;
+
+        // More batches than the subscriber has room for, then the stream end and a response.
+        for offset in 0..3 {
+            let record = RecordV1 {
+                offset,
+                key: vec![],
+                value: vec![offset as u8],
+            };
+            let consume = ConsumeV1::new("orders".to_string(), vec![record]);
+            server
+                .send(Packet::new_stream_packet(7, (3, 1), Message::ConsumeV1(consume)))
+                .await
+                .unwrap();
         }
+        server
+            .send(Packet::new_stream_packet(
+                7,
+                (5, 1),
+                Message::StreamCloseV1(StreamCloseV1 {}),
+            ))
+            .await
+            .unwrap();
+        let health = HealthCheckResponseV1 {
+            ready: 1,
+            pending: vec![],
+        };
+        server
+            .send(Packet::new_with_context_id(
+                request_id,
+                (27, 1),
+                Message::HealthCheckResponseV1(health),
+            ))
+            .await
+            .unwrap();
 
-        self.sink.send(packet).await?;
+        assert!(request.await.unwrap().is_ok());
 
-        rx.await
-            .map(|packet| packet.message)
-            .map_err(|_| std::io::Error::new(std::io::ErrorKind::Other, "oh no!"))
+        for offset in 0..3 {
+            match rx.recv().await.unwrap() {
+                Message::ConsumeV1(consume) => assert_eq!(offset, consume.records[0].offset),
+                message => panic!("unexpected message {:?}", message),
+            }
+        }
+        assert!(rx.recv().await.is_none());
+        assert!(contexts.streams.lock().await.is_empty());
     }
 
-    pub async fn open_stream(
-        &mut self,
-        route: RouteWithVersion,
-        message: Message,
-    ) -> Result<Receiver<Message>, std::io::Error> {
-        let (tx, rx) = channel::<Message>(16);
+    #[tokio::test]
+    async fn test_full_backlogs_pause_streams_until_forwarded() {
+        let (client, server) = tokio::io::duplex(4096);
+        let contexts = Arc::<Contexts>::default();
+        let connection = Connection::new(client, FlowCodec::new(), contexts.clone(), Duration::from_secs(5));
+        contexts.attach(&connection).await;
+        let mut server = Framed::new(server, FlowCodec::new());
+
+        let (tx, mut rx) = tokio::sync::mpsc::channel(1);
+        let subscribe = SubscribeTopicRequestV2 {
+            topic: "orders".to_string(),
+            consumer_group_id: String::new(),
+            offset: GROUP_POSITION,
+        };
+        contexts.streams.lock().await.insert(7, StreamEntry::new(tx, subscribe));
+
+        let consume = |offset: u64| {
+            let record = RecordV1 {
+                offset,
+                key: vec![],
+                value: vec![],
+            };
+            let consume = ConsumeV1::new("orders".to_string(), vec![record]);
+            Packet::new_stream_packet(7, (3, 1), Message::ConsumeV1(consume))
+        };
 
-        let packet = Packet::new(route, message);
-        {
-            let mut streams_table = self.streams.lock().await;
-            streams_table.insert(packet.context_id, tx);
+        // One message fits the subscriber and the backlog is filled, the next ones are dropped.
+        let sent = MAX_BACKLOG as u64 + 3;
+        for offset in 0..sent {
+            server.send(consume(offset)).await.unwrap();
         }
+        match server.next().await.unwrap().unwrap().message {
+            Message::UnsubscribeRequestV1(unsubscribe) => assert_eq!(7, unsubscribe.stream_id),
+            message => panic!("unexpected message {:?}", message),
+        }
+        server
+            .send(Packet::new_stream_packet(
+                7,
+                (5, 1),
+                Message::StreamCloseV1(StreamCloseV1 {}),
+            ))
+            .await
+            .unwrap();
 
-        self.sink.send(packet).await?;
+        for offset in 0..=MAX_BACKLOG as u64 {
+            match rx.recv().await.unwrap() {
+                Message::ConsumeV1(consume) => assert_eq!(offset, consume.records[0].offset),
+                message => panic!("unexpected message {:?}", message),
+            }
+        }
+
+        // Once forwarded, the stream resumes right after the last message kept.
+        let resume = server.next().await.unwrap().unwrap();
+        assert_eq!(7, resume.context_id);
+        match resume.message {
+            Message::SubscribeTopicRequestV2(subscribe) => assert_eq!(MAX_BACKLOG as i64 + 1, subscribe.offset),
+            message => panic!("unexpected message {:?}", message),
+        }
 
-        Ok(rx)
+        server.send(consume(MAX_BACKLOG as u64 + 1)).await.unwrap();
+        match rx.recv().await.unwrap() {
+            Message::ConsumeV1(consume) => assert_eq!(MAX_BACKLOG as u64 + 1, consume.records[0].offset),
+            message => panic!("unexpected message {:?}", message),
+        }
     }
 }
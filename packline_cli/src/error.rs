@@ -0,0 +1,95 @@
+use std::fmt::{Display, Formatter};
+
+use packline_flow::auth::AuthError;
+use packline_flow::messages::error;
+
+#[derive(Debug)]
+pub enum ClientError {
+    Io(std::io::Error),
+
+    /// The broker answered the request with an error response.
+    Broker {
+        code: u16,
+        message: String,
+    },
+
+    /// A received record batch didn't match its checksum. Its records are never handed to consumers.
+    CorruptBatch {
+        topic: String,
+        expected: u32,
+        actual: u32,
+    },
+
+    /// The authentication exchange couldn't be completed on the client side.
+    Authentication(AuthError),
+
+    UnexpectedResponse,
+
+    /// The connection to the broker was lost before the request was answered, or is being
+    /// re-established.
+    Disconnected,
+
+    /// The broker didn't answer the request within the request timeout.
+    Timeout,
+
+    /// A record key or value couldn't be converted from or to bytes.
+    Serialization(Box<dyn std::error::Error + Send + Sync>),
+}
+
+impl ClientError {
+    /// Returns whether the request may succeed if sent again, as after losing the connection or
+    /// when the broker received a corrupted batch.
+    pub fn is_retriable(&self) -> bool {
+        match self {
+            ClientError::Io(_) | ClientError::Disconnected | ClientError::Timeout => true,
+            ClientError::Broker { code, .. } => *code == error::CORRUPT_BATCH,
+            _ => false,
+        }
+    }
+}
+
+impl Display for ClientError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ClientError::Io(e) => write!(f, "{}", e),
+            ClientError::Broker { code, message } => write!(f, "broker error {}: {}", code, message),
+            ClientError::CorruptBatch {
+                topic,
+                expected,
+                actual,
+            } => write!(
+                f,
+                "corrupted batch on topic {}: expected checksum {:#010x}, got {:#010x}",
+                topic, expected, actual
+            ),
+            ClientError::Authentication(e) => write!(f, "authentication failed: {}", e),
+            ClientError::UnexpectedResponse => write!(f, "unexpected response from broker"),
+            ClientError::Disconnected => write!(f, "disconnected from broker"),
+            ClientError::Timeout => write!(f, "request timed out"),
+            ClientError::Serialization(e) => write!(f, "serialization failed: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for ClientError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            ClientError::Io(e) => Some(e),
+            ClientError::Authentication(e) => Some(e),
+            ClientError::Serialization(e) => Some(e.as_ref()),
+            _ => None,
+        }
+    }
+}
+
+impl From<std::io::Error> for ClientError {
+    fn from(e: std::io::Error) -> Self {
+        ClientError::Io(e)
+    }
+}
+
+impl From<AuthError> for ClientError {
+    fn from(e: AuthError) -> Self {
+        ClientError::Authentication(e)
+    }
+}
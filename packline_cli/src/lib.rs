@@ -1,2 +1,11 @@
+pub mod address;
+pub mod admin;
+pub mod auth;
 pub mod client;
 mod connection;
+pub mod error;
+pub mod producer;
+pub mod serializer;
+mod session;
+pub mod subscriber;
+pub mod tls;
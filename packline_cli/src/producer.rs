@@ -0,0 +1,412 @@
+use std::collections::HashMap;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex, Weak};
+use std::task::{Context, Poll};
+use std::time::Duration;
+
+use packline_core::app::channel::Record;
+use packline_flow::messages::produce::ProduceRequestV2;
+use packline_flow::messages::record::RecordV1;
+use packline_flow::messages::Message;
+use packline_flow::SizedSchema;
+use tokio::sync::mpsc::{unbounded_channel, UnboundedSender};
+use tokio::sync::{oneshot, watch, OwnedSemaphorePermit, Semaphore};
+
+use crate::client::unexpected;
+use crate::error::ClientError;
+use crate::session::Session;
+
+/// Settings of a [`Producer`].
+#[derive(Clone, Debug)]
+pub struct ProducerConfig {
+    /// Time a record waits for others to join its batch before the batch is sent.
+    pub linger: Duration,
+
+    /// Size of the records a batch is sent at, without waiting for `linger`.
+    pub max_batch_bytes: usize,
+
+    /// Size of the records buffered or in flight, past which [`Producer::send`] waits for room.
+    pub buffer_bytes: usize,
+}
+
+impl Default for ProducerConfig {
+    fn default() -> Self {
+        ProducerConfig {
+            linger: Duration::from_millis(5),
+            max_batch_bytes: 16 * 1024,
+            buffer_bytes: 32 * 1024 * 1024,
+        }
+    }
+}
+
+/// Outcome of a sent record, resolved once the broker acknowledged or rejected its batch.
+pub struct Delivery {
+    receiver: oneshot::Receiver<Result<(), ClientError>>,
+}
+
+impl Future for Delivery {
+    type Output = Result<(), ClientError>;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        Pin::new(&mut self.receiver)
+            .poll(cx)
+            .map(|result| result.unwrap_or(Err(ClientError::Disconnected)))
+    }
+}
+
+/// Producer gathering records into batches, one per partition of a topic, sent as a single produce
+/// request each.
+///
+/// A batch is sent once its records reach [`ProducerConfig::max_batch_bytes`], once its first
+/// record waited for [`ProducerConfig::linger`], or on [`Producer::flush`]. Batches of a partition
+/// are sent one at a time, in order. Records still buffered when the last handle is dropped are sent in
+/// the background.
+#[derive(Clone)]
+pub struct Producer {
+    inner: Arc<Inner>,
+}
+
+struct Inner {
+    session: Session,
+    config: ProducerConfig,
+
+    /// Bytes of records that may be buffered or in flight.
+    memory: Arc<Semaphore>,
+    state: Mutex<State>,
+
+    /// Number of batches sent and not yet answered.
+    in_flight: Arc<watch::Sender<usize>>,
+}
+
+/// Topic and partition a batch is produced to.
+type PartitionKey = (String, u16);
+
+#[derive(Default)]
+struct State {
+    next_id: u64,
+    batches: HashMap<PartitionKey, Batch>,
+
+    /// Senders of the tasks producing the batches of each partition.
+    workers: HashMap<PartitionKey, UnboundedSender<Batch>>,
+}
+
+struct Batch {
+    id: u64,
+    records: Vec<RecordV1>,
+    bytes: usize,
+    deliveries: Vec<oneshot::Sender<Result<(), ClientError>>>,
+
+    /// Memory taken by the records, released once the batch is answered.
+    permits: Option<OwnedSemaphorePermit>,
+}
+
+impl Producer {
+    pub(crate) fn new(session: Session, config: ProducerConfig) -> Producer {
+        let (in_flight, _) = watch::channel(0);
+
+        Producer {
+            inner: Arc::new(Inner {
+                session,
+                memory: Arc::new(Semaphore::new(config.buffer_bytes.min(Semaphore::MAX_PERMITS))),
+                config,
+                state: Mutex::new(State::default()),
+                in_flight: Arc::new(in_flight),
+            }),
+        }
+    }
+
+    /// Adds a record to the batch of the first partition of `topic`, see
+    /// [`Producer::send_to_partition`].
+    pub async fn send(&self, topic: &str, record: Record) -> Result<Delivery, ClientError> {
+        self.send_to_partition(topic, 1, record).await
+    }
+
+    /// Adds a record to the batch of the partition `partition` of `topic`, numbered from 1, waiting
+    /// while the buffer is full. The returned delivery resolves once the batch is answered.
+    pub async fn send_to_partition(
+        &self,
+        topic: &str,
+        partition: u16,
+        record: Record,
+    ) -> Result<Delivery, ClientError> {
+        let record = RecordV1::from(record);
+        let size = record.size();
+        let permits = match u32::try_from(size) {
+            Ok(permits) if size <= self.inner.config.buffer_bytes => permits,
+            _ => {
+                return Err(ClientError::Io(std::io::Error::new(
+                    std::io::ErrorKind::InvalidInput,
+                    format!("record of {} bytes doesn't fit in the producer buffer", size),
+                )))
+            }
+        };
+
+        let permit = self
+            .inner
+            .memory
+            .clone()
+            .acquire_many_owned(permits)
+            .await
+            .map_err(|_| ClientError::Disconnected)?;
+        let (sender, receiver) = oneshot::channel();
+
+        let key = (topic.to_string(), partition);
+        let mut state = self.inner.state.lock().unwrap();
+        if !state.batches.contains_key(&key) {
+            let id = state.next_id;
+            state.next_id += 1;
+            state.batches.insert(
+                key.clone(),
+                Batch {
+                    id,
+                    records: vec![],
+                    bytes: 0,
+                    deliveries: vec![],
+                    permits: None,
+                },
+            );
+
+            tokio::spawn(linger(
+                Arc::downgrade(&self.inner),
+                key.clone(),
+                id,
+                self.inner.config.linger,
+            ));
+        }
+
+        let batch = state.batches.get_mut(&key).unwrap();
+        batch.records.push(record);
+        batch.bytes += size;
+        batch.deliveries.push(sender);
+        match &mut batch.permits {
+            Some(permits) => permits.merge(permit),
+            None => batch.permits = Some(permit),
+        }
+
+        if batch.bytes >= self.inner.config.max_batch_bytes {
+            let batch = state.batches.remove(&key).unwrap();
+            self.inner.dispatch(&mut state, key, batch);
+        }
+
+        Ok(Delivery { receiver })
+    }
+
+    /// Sends every buffered batch, then waits until every batch sent is answered.
+    pub async fn flush(&self) {
+        {
+            let mut state = self.inner.state.lock().unwrap();
+            let batches: Vec<(PartitionKey, Batch)> = state.batches.drain().collect();
+            for (key, batch) in batches {
+                self.inner.dispatch(&mut state, key, batch);
+            }
+        }
+
+        let mut in_flight = self.inner.in_flight.subscribe();
+        let _ = in_flight.wait_for(|in_flight| *in_flight == 0).await;
+    }
+}
+
+impl Inner {
+    /// Hands a batch to the task producing the batches of its partition.
+    fn dispatch(&self, state: &mut State, key: PartitionKey, batch: Batch) {
+        self.in_flight.send_modify(|in_flight| *in_flight += 1);
+
+        let worker = state
+            .workers
+            .entry(key.clone())
+            .or_insert_with(|| worker(self.session.clone(), key, self.in_flight.clone()));
+        let _ = worker.send(batch);
+    }
+}
+
+impl Drop for Inner {
+    fn drop(&mut self) {
+        if tokio::runtime::Handle::try_current().is_err() {
+            return;
+        }
+
+        let mut state = std::mem::take(self.state.get_mut().unwrap());
+        let batches: Vec<(PartitionKey, Batch)> = state.batches.drain().collect();
+        for (key, batch) in batches {
+            self.dispatch(&mut state, key, batch);
+        }
+    }
+}
+
+/// Sends the batch `id` of a partition once it lingered, unless it was sent already.
+async fn linger(inner: Weak<Inner>, key: PartitionKey, id: u64, linger: Duration) {
+    tokio::time::sleep(linger).await;
+
+    if let Some(inner) = inner.upgrade() {
+        let mut state = inner.state.lock().unwrap();
+        if state.batches.get(&key).is_some_and(|batch| batch.id == id) {
+            let batch = state.batches.remove(&key).unwrap();
+            inner.dispatch(&mut state, key, batch);
+        }
+    }
+}
+
+/// Spawns the task producing the batches of a partition in the order they are received.
+fn worker(session: Session, key: PartitionKey, in_flight: Arc<watch::Sender<usize>>) -> UnboundedSender<Batch> {
+    let (sender, mut receiver) = unbounded_channel::<Batch>();
+
+    tokio::spawn(async move {
+        let (topic, partition) = key;
+        while let Some(batch) = receiver.recv().await {
+            let request = ProduceRequestV2::new(topic.clone(), partition, batch.records);
+            let result = match session.send((6, 2), Message::ProduceRequestV2(request)).await {
+                Ok(Message::ProduceResponseV1(_)) => Ok(()),
+                Ok(response) => Err(unexpected(response)),
+                Err(e) => Err(e),
+            };
+
+            for delivery in batch.deliveries {
+                let _ = delivery.send(result.as_ref().map(|_| ()).map_err(copy_error));
+            }
+
+            drop(batch.permits);
+            in_flight.send_modify(|in_flight| *in_flight -= 1);
+        }
+    });
+
+    sender
+}
+
+/// Copies the error failing a batch for each of its records.
+fn copy_error(e: &ClientError) -> ClientError {
+    match e {
+        ClientError::Broker { code, message } => ClientError::Broker {
+            code: *code,
+            message: message.clone(),
+        },
+        ClientError::CorruptBatch {
+            topic,
+            expected,
+            actual,
+        } => ClientError::CorruptBatch {
+            topic: topic.clone(),
+            expected: *expected,
+            actual: *actual,
+        },
+        ClientError::UnexpectedResponse => ClientError::UnexpectedResponse,
+        ClientError::Disconnected => ClientError::Disconnected,
+        ClientError::Timeout => ClientError::Timeout,
+        ClientError::Io(e) => ClientError::Io(std::io::Error::new(e.kind(), e.to_string())),
+        e => ClientError::Io(std::io::Error::other(e.to_string())),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use packline_flow::broker::Broker;
+
+    use crate::client::connect;
+
+    use super::*;
+
+    async fn end_offset(broker: &Broker) -> u64 {
+        partition_end_offset(broker, 1).await
+    }
+
+    async fn partition_end_offset(broker: &Broker, partition: u16) -> u64 {
+        let channel = broker
+            .app()
+            .get_channel(&("orders".to_string(), partition))
+            .await
+            .unwrap();
+        channel.end_offset()
+    }
+
+    #[tokio::test]
+    async fn test_producer_batches_until_flushed_or_full() {
+        let broker = Broker::builder().topic("orders", 1).start().await.unwrap();
+        let client = connect(broker.addr()).await.unwrap();
+
+        let producer = client.producer(ProducerConfig {
+            linger: Duration::from_secs(60),
+            max_batch_bytes: 1024,
+            ..ProducerConfig::default()
+        });
+        let first = producer.send("orders", Record::from_value("a")).await.unwrap();
+        let second = producer.send("orders", Record::from_value("b")).await.unwrap();
+        assert_eq!(0, end_offset(&broker).await);
+
+        producer.flush().await;
+        assert_eq!(2, end_offset(&broker).await);
+        assert!(first.await.is_ok());
+        assert!(second.await.is_ok());
+
+        // A batch reaching its maximum size is sent without lingering.
+        let delivery = producer
+            .send("orders", Record::from_value(vec![0; 1024]))
+            .await
+            .unwrap();
+        assert!(delivery.await.is_ok());
+        assert_eq!(3, end_offset(&broker).await);
+
+        let delivery = producer.send("missing", Record::from_value("c")).await.unwrap();
+        producer.flush().await;
+        assert!(matches!(delivery.await, Err(ClientError::Broker { .. })));
+    }
+
+    #[tokio::test]
+    async fn test_producer_lingers_and_bounds_its_buffer() {
+        let broker = Broker::builder().topic("orders", 1).start().await.unwrap();
+        let client = connect(broker.addr()).await.unwrap();
+
+        let record = Record::from_value(vec![0; 64]);
+        let producer = client.producer(ProducerConfig {
+            linger: Duration::from_millis(50),
+            buffer_bytes: RecordV1::from(record.clone()).size(),
+            ..ProducerConfig::default()
+        });
+
+        let first = producer.send("orders", record.clone()).await.unwrap();
+        assert!(
+            tokio::time::timeout(Duration::from_millis(10), producer.send("orders", record.clone()))
+                .await
+                .is_err()
+        );
+
+        // Room is made once the first batch lingered and was answered.
+        let second = producer.send("orders", record.clone()).await.unwrap();
+        assert!(first.await.is_ok());
+        assert!(second.await.is_ok());
+        assert_eq!(2, end_offset(&broker).await);
+
+        let error = producer.send("orders", Record::from_value(vec![0; 65])).await;
+        assert!(matches!(error, Err(ClientError::Io(_))));
+    }
+
+    #[tokio::test]
+    async fn test_producer_batches_per_partition() {
+        let broker = Broker::builder().topic("orders", 2).start().await.unwrap();
+        let client = connect(broker.addr()).await.unwrap();
+
+        let producer = client.producer(ProducerConfig {
+            linger: Duration::from_secs(60),
+            ..ProducerConfig::default()
+        });
+        let mut deliveries = vec![];
+        for (partition, value) in [(1, "a"), (2, "b"), (2, "c")] {
+            let delivery = producer
+                .send_to_partition("orders", partition, Record::from_value(value))
+                .await
+                .unwrap();
+            deliveries.push(delivery);
+        }
+        let missing = producer
+            .send_to_partition("orders", 3, Record::from_value("d"))
+            .await
+            .unwrap();
+
+        producer.flush().await;
+        for delivery in deliveries {
+            assert!(delivery.await.is_ok());
+        }
+        assert!(matches!(missing.await, Err(ClientError::Broker { .. })));
+        assert_eq!(1, partition_end_offset(&broker, 1).await);
+        assert_eq!(2, partition_end_offset(&broker, 2).await);
+    }
+}
@@ -0,0 +1,349 @@
+//! Conversion of record keys and values from and to bytes, used by [`TypedProducer`] and
+//! [`TypedSubscriber`].
+//!
+//! ```no_run
+//! # async fn example(client: packline_cli::client::Client) -> Result<(), packline_cli::error::ClientError> {
+//! use packline_cli::producer::ProducerConfig;
+//! use packline_cli::serializer::{Json, TypedProducer, Utf8};
+//!
+//! let producer = TypedProducer::new(client.producer(ProducerConfig::default()), Utf8, Json);
+//! producer.send("orders", Some("user-1"), &vec![1, 2, 3]).await?.await?;
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! [`TypedSubscriber`]: crate::serializer::TypedSubscriber
+
+use std::pin::Pin;
+use std::task::{Context, Poll};
+
+use bytes::BytesMut;
+use futures::Stream;
+use packline_core::app::channel::Record;
+use packline_flow::codec::decoder::ByteDecoder;
+use packline_flow::{DeserializableSchema, SerializableSchema};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+
+use crate::error::ClientError;
+use crate::producer::{Delivery, Producer};
+use crate::subscriber::Subscriber;
+
+pub trait Serializer<T: ?Sized> {
+    fn serialize(&self, value: &T) -> Result<Vec<u8>, ClientError>;
+}
+
+pub trait Deserializer<T> {
+    fn deserialize(&self, bytes: &[u8]) -> Result<T, ClientError>;
+}
+
+/// UTF-8 encoded strings.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Utf8;
+
+impl Serializer<str> for Utf8 {
+    fn serialize(&self, value: &str) -> Result<Vec<u8>, ClientError> {
+        Ok(value.as_bytes().to_vec())
+    }
+}
+
+impl Serializer<String> for Utf8 {
+    fn serialize(&self, value: &String) -> Result<Vec<u8>, ClientError> {
+        Ok(value.as_bytes().to_vec())
+    }
+}
+
+impl Deserializer<String> for Utf8 {
+    fn deserialize(&self, bytes: &[u8]) -> Result<String, ClientError> {
+        String::from_utf8(bytes.to_vec()).map_err(|e| ClientError::Serialization(Box::new(e)))
+    }
+}
+
+/// Bytes kept as they are.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct RawBytes;
+
+impl Serializer<[u8]> for RawBytes {
+    fn serialize(&self, value: &[u8]) -> Result<Vec<u8>, ClientError> {
+        Ok(value.to_vec())
+    }
+}
+
+impl Serializer<Vec<u8>> for RawBytes {
+    fn serialize(&self, value: &Vec<u8>) -> Result<Vec<u8>, ClientError> {
+        Ok(value.clone())
+    }
+}
+
+impl Deserializer<Vec<u8>> for RawBytes {
+    fn deserialize(&self, bytes: &[u8]) -> Result<Vec<u8>, ClientError> {
+        Ok(bytes.to_vec())
+    }
+}
+
+/// JSON documents of any type serde can serialize.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Json;
+
+impl<T: Serialize + ?Sized> Serializer<T> for Json {
+    fn serialize(&self, value: &T) -> Result<Vec<u8>, ClientError> {
+        serde_json::to_vec(value).map_err(|e| ClientError::Serialization(Box::new(e)))
+    }
+}
+
+impl<T: DeserializeOwned> Deserializer<T> for Json {
+    fn deserialize(&self, bytes: &[u8]) -> Result<T, ClientError> {
+        serde_json::from_slice(bytes).map_err(|e| ClientError::Serialization(Box::new(e)))
+    }
+}
+
+/// Values encoded as in the flow protocol, through the `packline_flow` schema traits.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct FlowSchema;
+
+impl<T: SerializableSchema + ?Sized> Serializer<T> for FlowSchema {
+    fn serialize(&self, value: &T) -> Result<Vec<u8>, ClientError> {
+        let mut encoder = BytesMut::with_capacity(value.size());
+        value.serialize(&mut encoder);
+
+        Ok(encoder.to_vec())
+    }
+}
+
+impl<T> Deserializer<T> for FlowSchema
+where
+    T: DeserializableSchema<Item = T>,
+    T::Error: std::error::Error + Send + Sync + 'static,
+{
+    fn deserialize(&self, bytes: &[u8]) -> Result<T, ClientError> {
+        let mut decoder = ByteDecoder::new(bytes);
+        let value = T::deserialize(&mut decoder).map_err(|e| ClientError::Serialization(Box::new(e)))?;
+
+        if decoder.failed() {
+            return Err(ClientError::Serialization(
+                format!("{} bytes are too short for the value", bytes.len()).into(),
+            ));
+        }
+
+        if decoder.offset() != bytes.len() {
+            return Err(ClientError::Serialization(
+                format!("{} trailing bytes after value", bytes.len() - decoder.offset()).into(),
+            ));
+        }
+
+        Ok(value)
+    }
+}
+
+/// [`Producer`] of keys and values converted by serializers.
+pub struct TypedProducer<K: ?Sized, V: ?Sized> {
+    producer: Producer,
+    key_serializer: Box<dyn Serializer<K> + Send + Sync>,
+    value_serializer: Box<dyn Serializer<V> + Send + Sync>,
+}
+
+impl<K: ?Sized, V: ?Sized> TypedProducer<K, V> {
+    pub fn new<KS, VS>(producer: Producer, key_serializer: KS, value_serializer: VS) -> TypedProducer<K, V>
+    where
+        KS: Serializer<K> + Send + Sync + 'static,
+        VS: Serializer<V> + Send + Sync + 'static,
+    {
+        TypedProducer {
+            producer,
+            key_serializer: Box::new(key_serializer),
+            value_serializer: Box::new(value_serializer),
+        }
+    }
+
+    /// Serializes the record and adds it to the batch of `topic`, see [`Producer::send`].
+    pub async fn send(&self, topic: &str, key: Option<&K>, value: &V) -> Result<Delivery, ClientError> {
+        let key = match key {
+            Some(key) => self.key_serializer.serialize(key)?,
+            None => vec![],
+        };
+        let value = self.value_serializer.serialize(value)?;
+
+        self.producer.send(topic, Record::new(key, value)).await
+    }
+
+    pub async fn flush(&self) {
+        self.producer.flush().await
+    }
+}
+
+/// Record whose key and value were converted by deserializers.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct TypedRecord<K, V> {
+    pub offset: u64,
+
+    /// Key of the record, `None` when it has none.
+    pub key: Option<K>,
+    pub value: V,
+}
+
+/// [`Subscriber`] yielding records whose key and value were converted by deserializers. Records
+/// failing to deserialize are yielded as errors, without ending the stream.
+pub struct TypedSubscriber<K, V> {
+    subscriber: Subscriber,
+    key_deserializer: Box<dyn Deserializer<K> + Send + Sync>,
+    value_deserializer: Box<dyn Deserializer<V> + Send + Sync>,
+}
+
+impl<K, V> TypedSubscriber<K, V> {
+    pub fn new<KD, VD>(subscriber: Subscriber, key_deserializer: KD, value_deserializer: VD) -> TypedSubscriber<K, V>
+    where
+        KD: Deserializer<K> + Send + Sync + 'static,
+        VD: Deserializer<V> + Send + Sync + 'static,
+    {
+        TypedSubscriber {
+            subscriber,
+            key_deserializer: Box::new(key_deserializer),
+            value_deserializer: Box::new(value_deserializer),
+        }
+    }
+
+    /// See [`Subscriber::commit`].
+    pub async fn commit(&self) -> Result<(), ClientError> {
+        self.subscriber.commit().await
+    }
+
+    /// See [`Subscriber::pause`].
+    pub async fn pause(&mut self) -> Result<(), ClientError> {
+        self.subscriber.pause().await
+    }
+
+    pub async fn resume(&mut self) -> Result<(), ClientError> {
+        self.subscriber.resume().await
+    }
+
+    pub async fn close(self) -> Result<(), ClientError> {
+        self.subscriber.close().await
+    }
+
+    fn convert(&self, record: Record) -> Result<TypedRecord<K, V>, ClientError> {
+        let key = match record.key.is_empty() {
+            true => None,
+            false => Some(self.key_deserializer.deserialize(&record.key)?),
+        };
+
+        Ok(TypedRecord {
+            offset: record.offset,
+            key,
+            value: self.value_deserializer.deserialize(&record.value)?,
+        })
+    }
+}
+
+impl<K, V> Stream for TypedSubscriber<K, V> {
+    type Item = Result<TypedRecord<K, V>, ClientError>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        Pin::new(&mut self.subscriber)
+            .poll_next(cx)
+            .map(|record| record.map(|record| record.and_then(|record| self.convert(record))))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use futures::StreamExt;
+    use packline_flow::broker::Broker;
+    use serde::Deserialize;
+    use std::time::Duration;
+
+    use crate::client::connect;
+    use crate::producer::ProducerConfig;
+
+    use super::*;
+
+    #[derive(Serialize, Deserialize, Debug, PartialEq)]
+    struct Order {
+        id: u32,
+        items: Vec<String>,
+    }
+
+    #[test]
+    fn test_serializers_round_trip() {
+        assert_eq!(
+            "é",
+            Deserializer::<String>::deserialize(&Utf8, &Utf8.serialize("é").unwrap()).unwrap()
+        );
+        assert!(Deserializer::<String>::deserialize(&Utf8, &[0xff]).is_err());
+
+        let bytes = RawBytes.serialize(&[1u8, 2][..]).unwrap();
+        assert_eq!(
+            vec![1, 2],
+            Deserializer::<Vec<u8>>::deserialize(&RawBytes, &bytes).unwrap()
+        );
+
+        let order = Order {
+            id: 1,
+            items: vec!["book".to_string()],
+        };
+        let bytes = Json.serialize(&order).unwrap();
+        assert_eq!(order, Json.deserialize(&bytes).unwrap());
+        assert!(Deserializer::<Order>::deserialize(&Json, b"{}").is_err());
+
+        let bytes = FlowSchema.serialize(&vec![1u32, 2]).unwrap();
+        assert_eq!(
+            vec![1u32, 2],
+            Deserializer::<Vec<u32>>::deserialize(&FlowSchema, &bytes).unwrap()
+        );
+        assert!(Deserializer::<u16>::deserialize(&FlowSchema, &[0, 1, 2]).is_err());
+
+        // Truncated values fail instead of panicking.
+        for len in 0..bytes.len() {
+            assert!(matches!(
+                Deserializer::<Vec<u32>>::deserialize(&FlowSchema, &bytes[..len]),
+                Err(ClientError::Serialization(_))
+            ));
+        }
+        assert!(Deserializer::<u32>::deserialize(&FlowSchema, &[0, 1]).is_err());
+        assert!(Deserializer::<String>::deserialize(&FlowSchema, &[0, 0, 0, 9, b'a']).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_typed_producer_and_subscriber() {
+        let broker = Broker::builder().topic("orders", 1).start().await.unwrap();
+        let client = connect(broker.addr()).await.unwrap();
+
+        let producer: TypedProducer<str, Order> =
+            TypedProducer::new(client.producer(ProducerConfig::default()), Utf8, Json);
+        let mut subscriber: TypedSubscriber<String, Order> = TypedSubscriber::new(
+            client
+                .subscribe("orders".to_string(), "billing".to_string())
+                .await
+                .unwrap(),
+            Utf8,
+            Json,
+        );
+
+        let order = Order {
+            id: 7,
+            items: vec!["pen".to_string()],
+        };
+        let delivery = producer.send("orders", Some("user-1"), &order).await.unwrap();
+        producer.flush().await;
+        delivery.await.unwrap();
+        client
+            .produce("orders".to_string(), vec![Record::from_value("not json")])
+            .await
+            .unwrap();
+
+        let next = tokio::time::timeout(Duration::from_secs(5), subscriber.next());
+        assert_eq!(
+            TypedRecord {
+                offset: 0,
+                key: Some("user-1".to_string()),
+                value: order,
+            },
+            next.await.unwrap().unwrap().unwrap()
+        );
+
+        let next = tokio::time::timeout(Duration::from_secs(5), subscriber.next());
+        assert!(matches!(
+            next.await.unwrap().unwrap(),
+            Err(ClientError::Serialization(_))
+        ));
+    }
+}
@@ -0,0 +1,318 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex, Weak};
+
+use futures::future::BoxFuture;
+use log::{debug, info, warn};
+use packline_flow::messages::error::CORRUPT_BATCH;
+use packline_flow::messages::subscribe::SubscribeTopicRequestV2;
+use packline_flow::messages::{Message, Packet, RouteWithVersion};
+use tokio::sync::mpsc::{channel, Receiver};
+
+use crate::client::{ReconnectPolicy, RetryPolicy};
+use crate::connection::{Connection, Contexts, RequestError, StreamEntry};
+use crate::error::ClientError;
+
+/// Opens a connection to the broker and runs the connect handshake on it.
+pub(crate) type Dial = Box<dyn Fn(Arc<Contexts>) -> BoxFuture<'static, Result<Connection, ClientError>> + Send + Sync>;
+
+/// Connection to the broker that is re-established when lost, following a [`ReconnectPolicy`].
+///
+/// Requests fail with [`ClientError::Disconnected`] while there is no connection. Subscriptions are
+/// re-opened on every new connection, resuming right after the last record received. Streams the
+/// broker closed, as it does when shutting down gracefully, are not re-opened.
+///
+/// Requests failing with a retriable error are sent again following a [`RetryPolicy`].
+#[derive(Clone)]
+pub(crate) struct Session {
+    inner: Arc<Inner>,
+}
+
+struct Inner {
+    connection: Mutex<Option<Connection>>,
+    contexts: Arc<Contexts>,
+    retry: RetryPolicy,
+
+    /// Set once the session gave up reconnecting.
+    closed: AtomicBool,
+}
+
+impl Session {
+    pub async fn open(dial: Dial, policy: ReconnectPolicy, retry: RetryPolicy) -> Result<Session, ClientError> {
+        let contexts = Arc::<Contexts>::default();
+        let connection = dial(contexts.clone()).await?;
+        contexts.attach(&connection).await;
+
+        let inner = Arc::new(Inner {
+            connection: Mutex::new(Some(connection.clone())),
+            contexts,
+            retry,
+            closed: AtomicBool::new(false),
+        });
+
+        tokio::spawn(supervise(Arc::downgrade(&inner), connection, dial, policy));
+        Ok(Session { inner })
+    }
+
+    fn connection(&self) -> Result<Connection, ClientError> {
+        self.inner
+            .connection
+            .lock()
+            .unwrap()
+            .clone()
+            .ok_or(ClientError::Disconnected)
+    }
+
+    /// Sends a request that may not be applied twice, only retrying it when it certainly wasn't
+    /// applied: when it couldn't be sent or the broker rejected it as corrupted.
+    pub async fn send(&self, route: RouteWithVersion, message: Message) -> Result<Message, ClientError> {
+        self.send_with_retries(route, message, false).await
+    }
+
+    /// Sends a request that can be applied any number of times, retrying it on any retriable error.
+    pub async fn send_idempotent(&self, route: RouteWithVersion, message: Message) -> Result<Message, ClientError> {
+        self.send_with_retries(route, message, true).await
+    }
+
+    async fn send_with_retries(
+        &self,
+        route: RouteWithVersion,
+        message: Message,
+        idempotent: bool,
+    ) -> Result<Message, ClientError> {
+        let mut retry = 0;
+        loop {
+            let result = match self.connection() {
+                Ok(connection) => connection.send(route, message.clone()).await,
+                Err(e) => Err(RequestError::unsent(e)),
+            };
+
+            let retriable = match &result {
+                Ok(Message::ErrorResponseV1(e)) => e.code == CORRUPT_BATCH,
+                Ok(_) => false,
+                Err(e) => e.error.is_retriable() && (idempotent || !e.sent),
+            };
+            if !retriable || retry >= self.inner.retry.max_retries {
+                return Ok(result?);
+            }
+
+            debug!("retrying request on route {:?} after {:?}", route, result);
+            tokio::time::sleep(self.inner.retry.backoff(retry)).await;
+            retry += 1;
+        }
+    }
+
+    /// Subscribes on the broker, returning the stream id and the receiver of streamed messages.
+    pub async fn open_stream(
+        &self,
+        subscribe: SubscribeTopicRequestV2,
+    ) -> Result<(u32, Receiver<Message>), ClientError> {
+        let connection = self.connection()?;
+        let (tx, rx) = channel::<Message>(16);
+
+        let packet = self
+            .inner
+            .contexts
+            .packet((2, 2), Message::SubscribeTopicRequestV2(subscribe.clone()));
+        let stream_id = packet.context_id;
+        self.inner
+            .contexts
+            .streams
+            .lock()
+            .await
+            .insert(stream_id, StreamEntry::new(tx, subscribe));
+
+        if let Err(e) = connection.send_stream_request(packet).await {
+            self.inner.contexts.streams.lock().await.remove(&stream_id);
+            return Err(e);
+        }
+
+        Ok((stream_id, rx))
+    }
+
+    /// Stops routing and resuming the stream, then asks the broker to close it.
+    pub async fn close_stream(&self, stream_id: u32) -> Result<(), ClientError> {
+        self.inner.contexts.streams.lock().await.remove(&stream_id);
+        self.connection()?.close_stream(stream_id).await
+    }
+
+    /// Returns whether the session gave up reconnecting, after which its streams are closed.
+    pub fn is_closed(&self) -> bool {
+        self.inner.closed.load(Ordering::Acquire)
+    }
+}
+
+/// Re-establishes the connection of a session each time it is lost, until the session is dropped
+/// or the policy gives up.
+async fn supervise(session: Weak<Inner>, mut connection: Connection, dial: Dial, policy: ReconnectPolicy) {
+    loop {
+        connection.closed().await;
+
+        let contexts = match session.upgrade() {
+            Some(inner) => {
+                inner.connection.lock().unwrap().take();
+                inner.contexts.clone()
+            }
+            None => return,
+        };
+        warn!("connection to broker lost, reconnecting");
+
+        let mut attempt = 0;
+        connection = loop {
+            if policy.max_attempts.is_some_and(|max| attempt >= max) {
+                warn!("giving up reconnecting to broker after {} attempts", attempt);
+                if let Some(inner) = session.upgrade() {
+                    inner.closed.store(true, Ordering::Release);
+                    inner.contexts.streams.lock().await.clear();
+                }
+                return;
+            }
+
+            tokio::time::sleep(policy.backoff(attempt)).await;
+            attempt += 1;
+
+            if session.strong_count() == 0 {
+                return;
+            }
+
+            match reconnect(&dial, &contexts).await {
+                Ok(connection) => break connection,
+                Err(e) => debug!("reconnection attempt {} failed: {}", attempt, e),
+            }
+        };
+
+        match session.upgrade() {
+            Some(inner) => *inner.connection.lock().unwrap() = Some(connection.clone()),
+            None => return,
+        }
+        info!("reconnected to broker after {} attempts", attempt);
+    }
+}
+
+/// Opens a new connection and re-opens the streams of the session on it.
+async fn reconnect(dial: &Dial, contexts: &Arc<Contexts>) -> Result<Connection, ClientError> {
+    let connection = dial(contexts.clone()).await?;
+
+    for (stream_id, subscribe) in contexts.attach(&connection).await {
+        let packet = Packet::new_with_context_id(stream_id, (2, 2), Message::SubscribeTopicRequestV2(subscribe));
+        connection.send_stream_request(packet).await?;
+    }
+
+    Ok(connection)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use futures::{SinkExt, StreamExt};
+    use packline_flow::codec::FlowCodec;
+    use packline_flow::messages::error::ErrorResponseV1;
+    use packline_flow::messages::health::{HealthCheckRequestV1, HealthCheckResponseV1};
+    use packline_flow::messages::produce::ProduceRequestV1;
+    use tokio::io::DuplexStream;
+    use tokio_util::codec::Framed;
+
+    use super::*;
+
+    /// Opens a session on an in-memory connection, returning the broker end of it.
+    async fn session(request_timeout: Duration) -> (Session, Framed<DuplexStream, FlowCodec>) {
+        let (client, server) = tokio::io::duplex(4096);
+        let client = Mutex::new(Some(client));
+        let dial: Dial = Box::new(move |contexts| {
+            let client = client.lock().unwrap().take();
+            Box::pin(async move {
+                let client = client.ok_or(ClientError::Disconnected)?;
+                Ok(Connection::new(client, FlowCodec::new(), contexts, request_timeout))
+            })
+        });
+
+        let retry = RetryPolicy {
+            max_retries: 2,
+            backoff: Duration::from_millis(10),
+        };
+        let session = Session::open(dial, ReconnectPolicy::disabled(), retry).await.unwrap();
+
+        (session, Framed::new(server, FlowCodec::new()))
+    }
+
+    #[tokio::test]
+    async fn test_corrupted_requests_are_retried() {
+        let (session, mut server) = session(Duration::from_secs(30)).await;
+
+        let request = {
+            let session = session.clone();
+            tokio::spawn(async move {
+                session
+                    .send(
+                        (6, 1),
+                        Message::ProduceRequestV1(ProduceRequestV1::new("orders".to_string(), vec![])),
+                    )
+                    .await
+            })
+        };
+
+        let first = server.next().await.unwrap().unwrap();
+        let error = ErrorResponseV1 {
+            code: CORRUPT_BATCH,
+            message: "corrupted batch".to_string(),
+        };
+        server
+            .send(Packet::new_with_context_id(
+                first.context_id,
+                (8, 1),
+                Message::ErrorResponseV1(error),
+            ))
+            .await
+            .unwrap();
+
+        // The retry is sent with a new context id.
+        let second = server.next().await.unwrap().unwrap();
+        assert_ne!(first.context_id, second.context_id);
+        let health = HealthCheckResponseV1 {
+            ready: 1,
+            pending: vec![],
+        };
+        server
+            .send(Packet::new_with_context_id(
+                second.context_id,
+                (27, 1),
+                Message::HealthCheckResponseV1(health),
+            ))
+            .await
+            .unwrap();
+
+        assert!(matches!(
+            request.await.unwrap().unwrap(),
+            Message::HealthCheckResponseV1(_)
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_only_idempotent_requests_are_retried_on_timeout() {
+        let (session, mut server) = session(Duration::from_millis(50)).await;
+
+        let error = session
+            .send(
+                (6, 1),
+                Message::ProduceRequestV1(ProduceRequestV1::new("orders".to_string(), vec![])),
+            )
+            .await
+            .unwrap_err();
+        assert!(matches!(error, ClientError::Timeout));
+        server.next().await.unwrap().unwrap();
+
+        let error = session
+            .send_idempotent((26, 1), Message::HealthCheckRequestV1(HealthCheckRequestV1 {}))
+            .await
+            .unwrap_err();
+        assert!(matches!(error, ClientError::Timeout));
+        for _ in 0..3 {
+            server.next().await.unwrap().unwrap();
+        }
+
+        // Nothing else was sent.
+        assert!(tokio::time::timeout(Duration::from_millis(100), server.next())
+            .await
+            .is_err());
+    }
+}
@@ -0,0 +1,275 @@
+use std::collections::VecDeque;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+
+use futures::{Stream, StreamExt};
+use packline_core::app::channel::Record;
+use packline_flow::messages::checksum::records_checksum;
+use packline_flow::messages::group::CommitOffsetRequestV1;
+use packline_flow::messages::subscribe::{SubscribeTopicRequestV2, GROUP_POSITION};
+use packline_flow::messages::Message;
+use tokio::sync::mpsc::Receiver;
+
+use crate::client::unexpected;
+use crate::error::ClientError;
+use crate::session::Session;
+
+/// Subscription to a topic, yielding its records as a [`Stream`].
+///
+/// Records received while the stream isn't polled are kept until it is, without holding back the
+/// other requests and subscriptions of the client. Past a bounded number of batches kept, the
+/// subscription is paused on the broker, and resumes right after the last record kept once they are
+/// yielded. [`Subscriber::pause`] a subscriber that won't be polled for long, so that it stops
+/// receiving records altogether. The stream ends when the broker closes the subscription, after
+/// yielding the error that closed it, if any.
+pub struct Subscriber {
+    session: Session,
+    topic: String,
+    group: String,
+
+    /// Broker stream the records come from, `None` while paused or once ended.
+    stream: Option<(u32, Receiver<Message>)>,
+    buffer: VecDeque<Record>,
+
+    /// Offset following the last record yielded, which the subscription resumes from.
+    position: Option<u64>,
+    ended: bool,
+}
+
+impl Subscriber {
+    pub(crate) async fn open(session: Session, topic: String, group: String) -> Result<Subscriber, ClientError> {
+        let mut subscriber = Subscriber {
+            session,
+            topic,
+            group,
+            stream: None,
+            buffer: VecDeque::new(),
+            position: None,
+            ended: false,
+        };
+        subscriber.resume().await?;
+
+        Ok(subscriber)
+    }
+
+    /// Returns the records of the next batch received, or those left from the previous one.
+    pub async fn next_batch(&mut self) -> Option<Result<Vec<Record>, ClientError>> {
+        let first = match self.next().await? {
+            Ok(record) => record,
+            Err(e) => return Some(Err(e)),
+        };
+
+        let mut batch = vec![first];
+        batch.extend(self.buffer.drain(..));
+        if let Some(last) = batch.last() {
+            self.position = Some(last.offset + 1);
+        }
+
+        Some(Ok(batch))
+    }
+
+    /// Commits the group offset past the last record yielded. Does nothing for subscriptions
+    /// without a group, or before any record was yielded.
+    pub async fn commit(&self) -> Result<(), ClientError> {
+        let offset = match self.position {
+            Some(offset) if !self.group.is_empty() => offset,
+            _ => return Ok(()),
+        };
+
+        let response = self
+            .session
+            .send_idempotent(
+                (28, 1),
+                Message::CommitOffsetRequestV1(CommitOffsetRequestV1 {
+                    group: self.group.clone(),
+                    topic: self.topic.clone(),
+                    partition: 1,
+                    offset,
+                }),
+            )
+            .await?;
+
+        match response {
+            Message::DescribeGroupResponseV1(_) => Ok(()),
+            response => Err(unexpected(response)),
+        }
+    }
+
+    /// Stops receiving records until resumed. Records already received are still yielded, and the
+    /// subscription resumes right after the last one. Polling a paused subscriber waits forever.
+    pub async fn pause(&mut self) -> Result<(), ClientError> {
+        let (stream_id, mut receiver) = match self.stream.take() {
+            Some(stream) => stream,
+            None => return Ok(()),
+        };
+
+        let mut result = Ok(());
+        while let Ok(message) = receiver.try_recv() {
+            if let Err(e) = self.receive(message) {
+                self.ended = true;
+                result = Err(e);
+                break;
+            }
+        }
+
+        self.session.close_stream(stream_id).await?;
+        result
+    }
+
+    pub async fn resume(&mut self) -> Result<(), ClientError> {
+        if self.stream.is_some() || self.ended {
+            return Ok(());
+        }
+
+        // Records left in the buffer are still yielded, so the subscription resumes after them.
+        let offset = match self.buffer.back() {
+            Some(record) => (record.offset + 1) as i64,
+            None => self.position.map_or(GROUP_POSITION, |offset| offset as i64),
+        };
+
+        self.stream = Some(
+            self.session
+                .open_stream(SubscribeTopicRequestV2 {
+                    topic: self.topic.clone(),
+                    consumer_group_id: self.group.clone(),
+                    offset,
+                })
+                .await?,
+        );
+
+        Ok(())
+    }
+
+    /// Closes the subscription on the broker. Records received but not yet yielded are dropped.
+    pub async fn close(mut self) -> Result<(), ClientError> {
+        match self.stream.take() {
+            Some((stream_id, _)) => self.session.close_stream(stream_id).await,
+            None => Ok(()),
+        }
+    }
+
+    /// Buffers the records of a streamed message, failing on the messages ending the stream.
+    fn receive(&mut self, message: Message) -> Result<(), ClientError> {
+        match message {
+            Message::ConsumeV1(consume) if !consume.is_valid() => Err(ClientError::CorruptBatch {
+                actual: records_checksum(&consume.records),
+                expected: consume.crc,
+                topic: consume.topic,
+            }),
+            Message::ConsumeV1(consume) => {
+                self.buffer.extend(consume.records.into_iter().map(Record::from));
+                Ok(())
+            }
+            Message::ErrorResponseV1(e) => Err(ClientError::Broker {
+                code: e.code,
+                message: e.message,
+            }),
+            _ => Ok(()),
+        }
+    }
+
+    /// Ends the stream, closing it on the broker in the background.
+    fn end(&mut self) {
+        self.ended = true;
+        if let Some((stream_id, _)) = self.stream.take() {
+            let session = self.session.clone();
+            tokio::spawn(async move { session.close_stream(stream_id).await });
+        }
+    }
+}
+
+impl Stream for Subscriber {
+    type Item = Result<Record, ClientError>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        loop {
+            if let Some(record) = self.buffer.pop_front() {
+                self.position = Some(record.offset + 1);
+                return Poll::Ready(Some(Ok(record)));
+            }
+
+            if self.ended {
+                return Poll::Ready(None);
+            }
+
+            // Resuming takes the subscriber mutably, so nothing polls it until then.
+            let receiver = match self.stream.as_mut() {
+                Some((_, receiver)) => receiver,
+                None => return Poll::Pending,
+            };
+
+            match receiver.poll_recv(cx) {
+                Poll::Ready(Some(message)) => {
+                    if let Err(e) = self.receive(message) {
+                        self.end();
+                        return Poll::Ready(Some(Err(e)));
+                    }
+                }
+                Poll::Ready(None) => {
+                    self.ended = true;
+                    self.stream = None;
+                    if self.session.is_closed() {
+                        return Poll::Ready(Some(Err(ClientError::Disconnected)));
+                    }
+                }
+                Poll::Pending => return Poll::Pending,
+            }
+        }
+    }
+}
+
+impl Drop for Subscriber {
+    fn drop(&mut self) {
+        if self.stream.is_some() && tokio::runtime::Handle::try_current().is_ok() {
+            self.end();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use packline_flow::broker::Broker;
+
+    use crate::client::connect;
+
+    use super::*;
+
+    async fn next_value(subscriber: &mut Subscriber) -> Vec<u8> {
+        tokio::time::timeout(Duration::from_secs(5), subscriber.next())
+            .await
+            .expect("no record received")
+            .unwrap()
+            .unwrap()
+            .value
+    }
+
+    #[tokio::test]
+    async fn test_subscriber_commits_pauses_and_resumes() {
+        let broker = Broker::builder().topic("orders", 1).start().await.unwrap();
+        let client = connect(broker.addr()).await.unwrap();
+        let produce = |value: &'static str| client.produce("orders".to_string(), vec![Record::from_value(value)]);
+
+        let mut subscriber = client
+            .subscribe("orders".to_string(), "billing".to_string())
+            .await
+            .unwrap();
+        produce("a").await.unwrap();
+        assert_eq!(b"a".to_vec(), next_value(&mut subscriber).await);
+        subscriber.commit().await.unwrap();
+
+        let offsets = client.describe_group("billing".to_string()).await.unwrap();
+        assert_eq!(Some(1), offsets[0].committed);
+
+        // Records produced while paused are received once resumed.
+        subscriber.pause().await.unwrap();
+        produce("b").await.unwrap();
+        subscriber.resume().await.unwrap();
+        assert_eq!(b"b".to_vec(), next_value(&mut subscriber).await);
+        subscriber.close().await.unwrap();
+
+        let mut unknown = client.subscribe("missing".to_string(), String::new()).await.unwrap();
+        assert!(unknown.next().await.is_none());
+    }
+}
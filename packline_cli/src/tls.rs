@@ -0,0 +1,78 @@
+use std::convert::TryFrom;
+use std::io::{Error, ErrorKind};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use tokio::io::{AsyncRead, AsyncWrite};
+use tokio_rustls::client::TlsStream;
+use tokio_rustls::rustls::crypto::ring::default_provider;
+use tokio_rustls::rustls::pki_types::pem::PemObject;
+use tokio_rustls::rustls::pki_types::{CertificateDer, PrivateKeyDer, ServerName};
+use tokio_rustls::rustls::{ClientConfig, RootCertStore};
+use tokio_rustls::TlsConnector;
+
+/// TLS settings used to connect to a broker listener with TLS enabled.
+#[derive(Clone, Debug)]
+pub struct ClientTLSConfig {
+    /// PEM file with the CAs trusted to sign the broker certificate.
+    pub ca_path: PathBuf,
+
+    /// Name the broker certificate is verified against.
+    pub server_name: String,
+
+    /// Certificate and key presented to brokers requiring client authentication.
+    pub client_cert: Option<(PathBuf, PathBuf)>,
+}
+
+impl ClientTLSConfig {
+    pub(crate) async fn connect<S>(&self, stream: S) -> Result<TlsStream<S>, Error>
+    where
+        S: AsyncRead + AsyncWrite + Unpin,
+    {
+        let server_name =
+            ServerName::try_from(self.server_name.clone()).map_err(|e| Error::new(ErrorKind::InvalidInput, e))?;
+
+        TlsConnector::from(Arc::new(self.client_config()?))
+            .connect(server_name, stream)
+            .await
+    }
+
+    fn client_config(&self) -> Result<ClientConfig, Error> {
+        let mut roots = RootCertStore::empty();
+        for cert in load_certs(&self.ca_path)? {
+            roots.add(cert).map_err(tls_error)?;
+        }
+
+        let builder = ClientConfig::builder_with_provider(Arc::new(default_provider()))
+            .with_safe_default_protocol_versions()
+            .map_err(tls_error)?
+            .with_root_certificates(roots);
+
+        match &self.client_cert {
+            Some((cert_path, key_path)) => {
+                let key = PrivateKeyDer::from_pem_file(key_path).map_err(|e| invalid_file(key_path, e))?;
+                builder
+                    .with_client_auth_cert(load_certs(cert_path)?, key)
+                    .map_err(tls_error)
+            }
+            None => Ok(builder.with_no_client_auth()),
+        }
+    }
+}
+
+fn load_certs(path: &Path) -> Result<Vec<CertificateDer<'static>>, Error> {
+    CertificateDer::pem_file_iter(path)
+        .and_then(|certs| certs.collect::<Result<Vec<_>, _>>())
+        .map_err(|e| invalid_file(path, e))
+}
+
+fn invalid_file(path: &Path, e: impl std::fmt::Display) -> Error {
+    Error::new(
+        ErrorKind::InvalidInput,
+        format!("failed to load {}: {}", path.display(), e),
+    )
+}
+
+fn tls_error(e: tokio_rustls::rustls::Error) -> Error {
+    Error::new(ErrorKind::InvalidInput, e)
+}
@@ -0,0 +1,529 @@
+use std::collections::HashSet;
+use std::convert::TryFrom;
+use std::fmt::{Display, Formatter};
+use std::io::{Error, ErrorKind};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::sync::RwLock;
+
+/// Principal used for connections that didn't authenticate.
+pub const ANONYMOUS: &str = "ANONYMOUS";
+
+/// Matches any principal or resource name in a rule.
+pub const WILDCARD: &str = "*";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[repr(u8)]
+pub enum ResourceType {
+    Topic = 0,
+    ConsumerGroup = 1,
+    Cluster = 2,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[repr(u8)]
+pub enum Operation {
+    Produce = 0,
+    Consume = 1,
+    Create = 2,
+    Delete = 3,
+    Describe = 4,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[repr(u8)]
+pub enum Permission {
+    Allow = 0,
+    Deny = 1,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Resource {
+    pub resource_type: ResourceType,
+    pub name: String,
+}
+
+impl Resource {
+    pub fn topic(name: &str) -> Resource {
+        Resource {
+            resource_type: ResourceType::Topic,
+            name: name.to_string(),
+        }
+    }
+
+    pub fn consumer_group(name: &str) -> Resource {
+        Resource {
+            resource_type: ResourceType::ConsumerGroup,
+            name: name.to_string(),
+        }
+    }
+
+    /// The broker itself, used for administrative operations such as managing ACLs.
+    pub fn cluster() -> Resource {
+        Resource {
+            resource_type: ResourceType::Cluster,
+            name: WILDCARD.to_string(),
+        }
+    }
+
+    fn matches(&self, resource: &Resource) -> bool {
+        self.resource_type == resource.resource_type && (self.name == WILDCARD || self.name == resource.name)
+    }
+}
+
+/// Grants or denies `principal` the `operation` on `resource`.
+///
+/// Rules are written as `<permission> <principal> <resource type>:<resource name> <operation>`,
+/// e.g. `allow alice topic:orders produce`. Principal and resource names can't be empty nor hold
+/// whitespace, control characters or `:`, see [`AclRule::validate`].
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct AclRule {
+    pub principal: String,
+    pub resource: Resource,
+    pub operation: Operation,
+    pub permission: Permission,
+}
+
+impl AclRule {
+    /// Checks that the rule can be written and read back: principal and resource names must be
+    /// non-empty and free of whitespace, control characters and `:`.
+    pub fn validate(&self) -> Result<(), Error> {
+        validate_name("principal", &self.principal)?;
+        validate_name("resource name", &self.resource.name)
+    }
+
+    fn matches(&self, principal: &str, resource: &Resource, operation: Operation) -> bool {
+        (self.principal == WILDCARD || self.principal == principal)
+            && self.resource.matches(resource)
+            && self.operation == operation
+    }
+}
+
+/// Decides which principals may perform which operations. Deny rules take precedence over allow
+/// rules, and anything not explicitly allowed is denied.
+pub struct Authorizer {
+    rules: RwLock<Vec<AclRule>>,
+    super_users: HashSet<String>,
+    path: Option<PathBuf>,
+}
+
+impl Authorizer {
+    /// Creates an authorizer keeping its rules in memory only.
+    pub fn new() -> Authorizer {
+        Authorizer {
+            rules: RwLock::new(vec![]),
+            super_users: HashSet::new(),
+            path: None,
+        }
+    }
+
+    /// Creates an authorizer persisting its rules to `path`, loading the rules already stored there.
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Authorizer, Error> {
+        let path = path.as_ref();
+        let rules = match std::fs::read_to_string(path) {
+            Ok(content) => parse_rules(&content, path)?,
+            Err(e) if e.kind() == ErrorKind::NotFound => vec![],
+            Err(e) => return Err(e),
+        };
+
+        Ok(Authorizer {
+            rules: RwLock::new(rules),
+            super_users: HashSet::new(),
+            path: Some(path.to_path_buf()),
+        })
+    }
+
+    /// Allows every operation to `principal`, regardless of the rules.
+    pub fn with_super_user(mut self, principal: &str) -> Authorizer {
+        self.super_users.insert(principal.to_string());
+        self
+    }
+
+    pub fn authorize(&self, principal: &str, resource: &Resource, operation: Operation) -> bool {
+        if self.super_users.contains(principal) {
+            return true;
+        }
+
+        let rules = self.rules.read().unwrap();
+        let mut allowed = false;
+
+        for rule in rules.iter().filter(|rule| rule.matches(principal, resource, operation)) {
+            match rule.permission {
+                Permission::Deny => return false,
+                Permission::Allow => allowed = true,
+            }
+        }
+
+        allowed
+    }
+
+    /// Adds `rule`, returning false if it already existed. The rule isn't kept when it is invalid,
+    /// see [`AclRule::validate`], or can't be persisted.
+    pub fn add_rule(&self, rule: AclRule) -> Result<bool, Error> {
+        rule.validate()?;
+
+        let mut rules = self.rules.write().unwrap();
+        if rules.contains(&rule) {
+            return Ok(false);
+        }
+
+        rules.push(rule);
+        if let Err(e) = self.persist(&rules) {
+            rules.pop();
+            return Err(e);
+        }
+
+        Ok(true)
+    }
+
+    /// Removes `rule`, returning false if it didn't exist. The rule is kept when the removal can't
+    /// be persisted.
+    pub fn remove_rule(&self, rule: &AclRule) -> Result<bool, Error> {
+        let mut rules = self.rules.write().unwrap();
+        let remaining = rules.iter().filter(|r| *r != rule).cloned().collect::<Vec<_>>();
+        if remaining.len() == rules.len() {
+            return Ok(false);
+        }
+
+        self.persist(&remaining)?;
+        *rules = remaining;
+        Ok(true)
+    }
+
+    pub fn rules(&self) -> Vec<AclRule> {
+        self.rules.read().unwrap().clone()
+    }
+
+    fn persist(&self, rules: &[AclRule]) -> Result<(), Error> {
+        let path = match &self.path {
+            Some(path) => path,
+            None => return Ok(()),
+        };
+
+        let content = rules.iter().map(|rule| format!("{}\n", rule)).collect::<String>();
+
+        // Written aside first so that a crash never leaves a truncated rule file behind.
+        let tmp_path = path.with_extension("tmp");
+        std::fs::write(&tmp_path, content)?;
+        std::fs::rename(tmp_path, path)
+    }
+}
+
+impl Default for Authorizer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+fn parse_rules(content: &str, path: &Path) -> Result<Vec<AclRule>, Error> {
+    content
+        .lines()
+        .enumerate()
+        .map(|(number, line)| (number, line.trim()))
+        .filter(|(_, line)| !line.is_empty() && !line.starts_with('#'))
+        .map(|(number, line)| {
+            line.parse().map_err(|e: Error| {
+                Error::new(
+                    ErrorKind::InvalidData,
+                    format!("{} at {}:{}", e, path.display(), number + 1),
+                )
+            })
+        })
+        .collect()
+}
+
+fn validate_name(kind: &str, name: &str) -> Result<(), Error> {
+    match name.is_empty() || name.contains(|c: char| c.is_whitespace() || c.is_control() || c == ':') {
+        true => Err(Error::new(
+            ErrorKind::InvalidInput,
+            format!("invalid {} {:?}", kind, name),
+        )),
+        false => Ok(()),
+    }
+}
+
+fn invalid(kind: &str, value: &str) -> Error {
+    Error::new(ErrorKind::InvalidData, format!("invalid {} {}", kind, value))
+}
+
+impl Display for ResourceType {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ResourceType::Topic => write!(f, "topic"),
+            ResourceType::ConsumerGroup => write!(f, "group"),
+            ResourceType::Cluster => write!(f, "cluster"),
+        }
+    }
+}
+
+impl FromStr for ResourceType {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "topic" => Ok(ResourceType::Topic),
+            "group" => Ok(ResourceType::ConsumerGroup),
+            "cluster" => Ok(ResourceType::Cluster),
+            _ => Err(invalid("resource type", s)),
+        }
+    }
+}
+
+impl TryFrom<u8> for ResourceType {
+    type Error = Error;
+
+    fn try_from(value: u8) -> Result<Self, Self::Error> {
+        match value {
+            0 => Ok(ResourceType::Topic),
+            1 => Ok(ResourceType::ConsumerGroup),
+            2 => Ok(ResourceType::Cluster),
+            _ => Err(invalid("resource type", &value.to_string())),
+        }
+    }
+}
+
+impl Display for Operation {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Operation::Produce => write!(f, "produce"),
+            Operation::Consume => write!(f, "consume"),
+            Operation::Create => write!(f, "create"),
+            Operation::Delete => write!(f, "delete"),
+            Operation::Describe => write!(f, "describe"),
+        }
+    }
+}
+
+impl FromStr for Operation {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "produce" => Ok(Operation::Produce),
+            "consume" => Ok(Operation::Consume),
+            "create" => Ok(Operation::Create),
+            "delete" => Ok(Operation::Delete),
+            "describe" => Ok(Operation::Describe),
+            _ => Err(invalid("operation", s)),
+        }
+    }
+}
+
+impl TryFrom<u8> for Operation {
+    type Error = Error;
+
+    fn try_from(value: u8) -> Result<Self, Self::Error> {
+        match value {
+            0 => Ok(Operation::Produce),
+            1 => Ok(Operation::Consume),
+            2 => Ok(Operation::Create),
+            3 => Ok(Operation::Delete),
+            4 => Ok(Operation::Describe),
+            _ => Err(invalid("operation", &value.to_string())),
+        }
+    }
+}
+
+impl Display for Permission {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Permission::Allow => write!(f, "allow"),
+            Permission::Deny => write!(f, "deny"),
+        }
+    }
+}
+
+impl FromStr for Permission {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "allow" => Ok(Permission::Allow),
+            "deny" => Ok(Permission::Deny),
+            _ => Err(invalid("permission", s)),
+        }
+    }
+}
+
+impl TryFrom<u8> for Permission {
+    type Error = Error;
+
+    fn try_from(value: u8) -> Result<Self, Self::Error> {
+        match value {
+            0 => Ok(Permission::Allow),
+            1 => Ok(Permission::Deny),
+            _ => Err(invalid("permission", &value.to_string())),
+        }
+    }
+}
+
+impl Display for AclRule {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{} {} {}:{} {}",
+            self.permission, self.principal, self.resource.resource_type, self.resource.name, self.operation
+        )
+    }
+}
+
+impl FromStr for AclRule {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let parts = s.split_whitespace().collect::<Vec<_>>();
+        let (permission, principal, resource, operation) = match parts.as_slice() {
+            [permission, principal, resource, operation] => (permission, principal, resource, operation),
+            _ => return Err(invalid("acl rule", s)),
+        };
+
+        let (resource_type, name) = resource.split_once(':').ok_or_else(|| invalid("resource", resource))?;
+
+        let rule = AclRule {
+            principal: principal.to_string(),
+            resource: Resource {
+                resource_type: resource_type.parse()?,
+                name: name.to_string(),
+            },
+            operation: operation.parse()?,
+            permission: permission.parse()?,
+        };
+        rule.validate().map_err(|_| invalid("acl rule", s))?;
+
+        Ok(rule)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{AclRule, Authorizer, Operation, Permission, Resource, ANONYMOUS};
+
+    fn rule(line: &str) -> AclRule {
+        line.parse().unwrap()
+    }
+
+    #[test]
+    fn test_denies_by_default() {
+        let authorizer = Authorizer::new();
+
+        assert!(!authorizer.authorize("alice", &Resource::topic("orders"), Operation::Produce));
+        assert!(!authorizer.authorize(ANONYMOUS, &Resource::cluster(), Operation::Describe));
+    }
+
+    #[test]
+    fn test_allow_rule_matches_exact_and_wildcard() {
+        let authorizer = Authorizer::new();
+        authorizer.add_rule(rule("allow alice topic:orders produce")).unwrap();
+        authorizer.add_rule(rule("allow * topic:* consume")).unwrap();
+
+        assert!(authorizer.authorize("alice", &Resource::topic("orders"), Operation::Produce));
+        assert!(!authorizer.authorize("alice", &Resource::topic("payments"), Operation::Produce));
+        assert!(!authorizer.authorize("bob", &Resource::topic("orders"), Operation::Produce));
+        assert!(authorizer.authorize("bob", &Resource::topic("payments"), Operation::Consume));
+        assert!(!authorizer.authorize("bob", &Resource::consumer_group("payments"), Operation::Consume));
+    }
+
+    #[test]
+    fn test_deny_takes_precedence() {
+        let authorizer = Authorizer::new();
+        authorizer.add_rule(rule("allow * topic:* consume")).unwrap();
+        authorizer.add_rule(rule("deny bob topic:secrets consume")).unwrap();
+
+        assert!(authorizer.authorize("alice", &Resource::topic("secrets"), Operation::Consume));
+        assert!(!authorizer.authorize("bob", &Resource::topic("secrets"), Operation::Consume));
+    }
+
+    #[test]
+    fn test_super_user_bypasses_rules() {
+        let authorizer = Authorizer::new().with_super_user("admin");
+        authorizer.add_rule(rule("deny * cluster:* create")).unwrap();
+
+        assert!(authorizer.authorize("admin", &Resource::cluster(), Operation::Create));
+    }
+
+    #[test]
+    fn test_add_and_remove_rule() {
+        let authorizer = Authorizer::new();
+        let acl = rule("allow alice group:billing consume");
+
+        assert!(authorizer.add_rule(acl.clone()).unwrap());
+        assert!(!authorizer.add_rule(acl.clone()).unwrap());
+        assert_eq!(vec![acl.clone()], authorizer.rules());
+
+        assert!(authorizer.remove_rule(&acl).unwrap());
+        assert!(!authorizer.remove_rule(&acl).unwrap());
+        assert!(authorizer.rules().is_empty());
+    }
+
+    #[test]
+    fn test_rules_are_persisted() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("acls");
+
+        let authorizer = Authorizer::open(&path).unwrap();
+        authorizer.add_rule(rule("allow alice topic:orders produce")).unwrap();
+        authorizer.add_rule(rule("deny bob cluster:* describe")).unwrap();
+        authorizer
+            .remove_rule(&rule("allow alice topic:orders produce"))
+            .unwrap();
+
+        let reopened = Authorizer::open(&path).unwrap();
+        assert_eq!(vec![rule("deny bob cluster:* describe")], reopened.rules());
+        assert_eq!(Permission::Deny, reopened.rules()[0].permission);
+    }
+
+    #[test]
+    fn test_rejects_invalid_names() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("acls");
+        let authorizer = Authorizer::open(&path).unwrap();
+
+        for (principal, name) in [
+            ("", "orders"),
+            ("alice bob", "orders"),
+            ("alice", "orders\nallow * cluster:* create"),
+            ("alice", "a:b"),
+        ] {
+            let acl = AclRule {
+                principal: principal.to_string(),
+                resource: Resource::topic(name),
+                operation: Operation::Produce,
+                permission: Permission::Allow,
+            };
+
+            assert_eq!(
+                std::io::ErrorKind::InvalidInput,
+                authorizer.add_rule(acl).unwrap_err().kind()
+            );
+        }
+
+        assert!(authorizer.rules().is_empty());
+        assert!("allow alice topic: produce".parse::<AclRule>().is_err());
+    }
+
+    #[test]
+    fn test_rules_are_kept_unchanged_when_persisting_fails() {
+        let dir = tempfile::tempdir().unwrap();
+        let authorizer = Authorizer::open(dir.path().join("acls")).unwrap();
+        let acl = rule("allow alice topic:orders produce");
+        authorizer.add_rule(acl.clone()).unwrap();
+
+        // The rules file can no longer be replaced.
+        std::fs::create_dir(dir.path().join("acls.tmp")).unwrap();
+
+        assert!(authorizer.add_rule(rule("allow bob topic:orders produce")).is_err());
+        assert!(authorizer.remove_rule(&acl).is_err());
+        assert_eq!(vec![acl], authorizer.rules());
+    }
+
+    #[test]
+    fn test_open_rejects_invalid_rule() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("acls");
+        std::fs::write(&path, "# comment\nallow alice topic:orders fly\n").unwrap();
+
+        let error = Authorizer::open(&path).err().unwrap();
+        assert_eq!(std::io::ErrorKind::InvalidData, error.kind());
+        assert!(error.to_string().contains(":2"));
+    }
+}
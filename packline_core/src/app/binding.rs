@@ -0,0 +1,263 @@
+use std::collections::{BTreeSet, HashMap};
+use std::fmt::{Display, Formatter};
+use std::str::FromStr;
+
+/// Exchanges declared when an app starts, one of each kind.
+pub const PREDECLARED_EXCHANGES: &[(&str, ExchangeKind)] = &[
+    ("amq.direct", ExchangeKind::Direct),
+    ("amq.fanout", ExchangeKind::Fanout),
+    ("amq.topic", ExchangeKind::Topic),
+];
+
+/// How an exchange picks the channels a message published to it is routed to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ExchangeKind {
+    /// Routes to the bindings whose key equals the routing key.
+    Direct,
+
+    /// Routes to every binding, whatever the routing key.
+    Fanout,
+
+    /// Routes to the bindings whose key matches the routing key, see [`topic_matches`].
+    Topic,
+}
+
+impl Display for ExchangeKind {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ExchangeKind::Direct => write!(f, "direct"),
+            ExchangeKind::Fanout => write!(f, "fanout"),
+            ExchangeKind::Topic => write!(f, "topic"),
+        }
+    }
+}
+
+impl FromStr for ExchangeKind {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "direct" => Ok(ExchangeKind::Direct),
+            "fanout" => Ok(ExchangeKind::Fanout),
+            "topic" => Ok(ExchangeKind::Topic),
+            _ => Err(()),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BindingError {
+    /// An exchange of another kind has the same name.
+    KindMismatch,
+
+    /// The exchange doesn't exist.
+    ExchangeNotFound,
+}
+
+/// Routes messages published to `exchange` with a matching routing key to the channel `queue`.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct Binding {
+    pub exchange: String,
+    pub queue: String,
+    pub routing_key: String,
+}
+
+/// Exchanges and the bindings routing their messages to channels.
+pub(crate) struct BindingTable {
+    exchanges: HashMap<String, ExchangeKind>,
+    bindings: BTreeSet<Binding>,
+}
+
+impl BindingTable {
+    pub fn new() -> BindingTable {
+        BindingTable {
+            exchanges: PREDECLARED_EXCHANGES
+                .iter()
+                .map(|(name, kind)| (name.to_string(), *kind))
+                .collect(),
+            bindings: BTreeSet::new(),
+        }
+    }
+
+    /// Declares an exchange, returning whether it didn't exist. Fails when an exchange of another
+    /// kind has the same name.
+    pub fn declare_exchange(&mut self, name: &str, kind: ExchangeKind) -> Result<bool, BindingError> {
+        match self.exchanges.get(name) {
+            Some(existing) if *existing == kind => Ok(false),
+            Some(_) => Err(BindingError::KindMismatch),
+            None => {
+                self.exchanges.insert(name.to_string(), kind);
+                Ok(true)
+            }
+        }
+    }
+
+    pub fn exchange(&self, name: &str) -> Option<ExchangeKind> {
+        self.exchanges.get(name).copied()
+    }
+
+    /// Deletes an exchange and its bindings, returning whether it existed.
+    pub fn delete_exchange(&mut self, name: &str) -> bool {
+        self.bindings.retain(|binding| binding.exchange != name);
+        self.exchanges.remove(name).is_some()
+    }
+
+    /// Adds a binding, returning whether it didn't exist. Fails when its exchange doesn't exist.
+    pub fn bind(&mut self, binding: Binding) -> Result<bool, BindingError> {
+        if !self.exchanges.contains_key(&binding.exchange) {
+            return Err(BindingError::ExchangeNotFound);
+        }
+
+        Ok(self.bindings.insert(binding))
+    }
+
+    pub fn unbind(&mut self, binding: &Binding) -> bool {
+        self.bindings.remove(binding)
+    }
+
+    pub fn bindings(&self) -> Vec<Binding> {
+        self.bindings.iter().cloned().collect()
+    }
+
+    /// Returns the channels a message published to `exchange` with `routing_key` goes to, sorted
+    /// and without duplicates, or `None` when the exchange doesn't exist.
+    ///
+    /// The default exchange, named with an empty string, routes every message to the channel named
+    /// after its routing key.
+    pub fn route(&self, exchange: &str, routing_key: &str) -> Option<Vec<String>> {
+        if exchange.is_empty() {
+            return Some(vec![routing_key.to_string()]);
+        }
+
+        let kind = self.exchanges.get(exchange)?;
+        let queues = self
+            .bindings
+            .iter()
+            .filter(|binding| binding.exchange == exchange)
+            .filter(|binding| match kind {
+                ExchangeKind::Direct => binding.routing_key == routing_key,
+                ExchangeKind::Fanout => true,
+                ExchangeKind::Topic => topic_matches(&binding.routing_key, routing_key),
+            })
+            .map(|binding| binding.queue.clone())
+            .collect::<BTreeSet<_>>();
+
+        Some(queues.into_iter().collect())
+    }
+}
+
+/// Checks whether `routing_key` is matched by the binding key `pattern` of a topic exchange. Both
+/// are made of words separated by dots, `*` in the pattern matches exactly one word and `#` zero
+/// or more words.
+pub fn topic_matches(pattern: &str, routing_key: &str) -> bool {
+    fn matches(pattern: &[&str], key: &[&str]) -> bool {
+        match (pattern.first(), key.first()) {
+            (None, None) => true,
+            (Some(&"#"), _) => matches(&pattern[1..], key) || (!key.is_empty() && matches(pattern, &key[1..])),
+            (Some(&"*"), Some(_)) => matches(&pattern[1..], &key[1..]),
+            (Some(word), Some(other)) if word == other => matches(&pattern[1..], &key[1..]),
+            _ => false,
+        }
+    }
+
+    let pattern = pattern.split('.').collect::<Vec<_>>();
+    let key = match routing_key.is_empty() {
+        true => vec![],
+        false => routing_key.split('.').collect::<Vec<_>>(),
+    };
+
+    matches(&pattern, &key)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn binding(exchange: &str, queue: &str, routing_key: &str) -> Binding {
+        Binding {
+            exchange: exchange.to_string(),
+            queue: queue.to_string(),
+            routing_key: routing_key.to_string(),
+        }
+    }
+
+    #[test]
+    fn test_topic_matches() {
+        let cases = [
+            ("orders.*", "orders.created", true),
+            ("orders.*", "orders", false),
+            ("orders.*", "orders.created.eu", false),
+            ("orders.#", "orders", true),
+            ("orders.#", "orders.created.eu", true),
+            ("#.eu", "orders.created.eu", true),
+            ("#", "", true),
+            ("*.created.*", "orders.created.eu", true),
+            ("orders.created", "orders.created", true),
+            ("orders.created", "orders.deleted", false),
+        ];
+
+        for (pattern, key, expected) in cases {
+            assert_eq!(expected, topic_matches(pattern, key), "{} {}", pattern, key);
+        }
+    }
+
+    #[test]
+    fn test_declare_exchange() {
+        let mut table = BindingTable::new();
+
+        assert_eq!(Ok(true), table.declare_exchange("events", ExchangeKind::Topic));
+        assert_eq!(Ok(false), table.declare_exchange("events", ExchangeKind::Topic));
+        assert_eq!(
+            Err(BindingError::KindMismatch),
+            table.declare_exchange("events", ExchangeKind::Fanout)
+        );
+        assert_eq!(Some(ExchangeKind::Direct), table.exchange("amq.direct"));
+    }
+
+    #[test]
+    fn test_route() {
+        let mut table = BindingTable::new();
+        table.declare_exchange("events", ExchangeKind::Topic).unwrap();
+
+        for binding in [
+            binding("amq.direct", "billing", "orders"),
+            binding("amq.direct", "audit", "payments"),
+            binding("amq.fanout", "billing", ""),
+            binding("amq.fanout", "audit", ""),
+            binding("events", "billing", "orders.*"),
+            binding("events", "billing", "#"),
+            binding("events", "audit", "*.eu"),
+        ] {
+            assert_eq!(Ok(true), table.bind(binding));
+        }
+
+        assert_eq!(Some(vec!["billing".to_string()]), table.route("amq.direct", "orders"));
+        assert_eq!(
+            Some(vec!["audit".to_string(), "billing".to_string()]),
+            table.route("amq.fanout", "anything")
+        );
+        assert_eq!(
+            Some(vec!["audit".to_string(), "billing".to_string()]),
+            table.route("events", "orders.eu")
+        );
+        assert_eq!(Some(vec!["orders".to_string()]), table.route("", "orders"));
+        assert_eq!(None, table.route("missing", "orders"));
+    }
+
+    #[test]
+    fn test_bind_requires_exchange() {
+        let mut table = BindingTable::new();
+
+        assert_eq!(
+            Err(BindingError::ExchangeNotFound),
+            table.bind(binding("missing", "billing", ""))
+        );
+
+        assert_eq!(Ok(true), table.bind(binding("amq.direct", "billing", "orders")));
+        assert_eq!(Ok(false), table.bind(binding("amq.direct", "billing", "orders")));
+
+        assert!(table.delete_exchange("amq.direct"));
+        assert!(table.bindings().is_empty());
+        assert!(!table.unbind(&binding("amq.direct", "billing", "orders")));
+    }
+}
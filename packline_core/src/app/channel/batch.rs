@@ -0,0 +1,125 @@
+use std::io::{Error, ErrorKind};
+
+use super::compression::Compression;
+use super::record::Record;
+
+/// Largest decompressed payload of a batch.
+pub const MAX_BATCH_SIZE: usize = 16 * 1024 * 1024;
+
+/// Records produced together, compressed as a whole by the producer. Channels store the batch as it
+/// was produced and only decompress it when its records are read.
+///
+/// Once decompressed, the payload holds the records back to back, each as its big-endian `u32` key
+/// length, key, `u32` value length, value and `u32` checksum, see [`Record::checksum`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RecordBatch {
+    pub compression: Compression,
+
+    /// Number of records in the batch.
+    pub count: u32,
+    pub payload: Vec<u8>,
+}
+
+impl RecordBatch {
+    /// Encodes `records` and compresses them with `compression`. Record offsets are left out, as
+    /// they are assigned by the channel.
+    pub fn compress(records: &[Record], compression: Compression) -> Result<RecordBatch, Error> {
+        let mut payload = Vec::with_capacity(records.iter().map(|r| r.checksum_len() + 4).sum());
+        for record in records {
+            payload.extend_from_slice(&(record.key.len() as u32).to_be_bytes());
+            payload.extend_from_slice(&record.key);
+            payload.extend_from_slice(&(record.value.len() as u32).to_be_bytes());
+            payload.extend_from_slice(&record.value);
+            payload.extend_from_slice(&record.crc.to_be_bytes());
+        }
+
+        Ok(RecordBatch {
+            compression,
+            count: records.len() as u32,
+            payload: compression.compress(&payload)?,
+        })
+    }
+
+    /// Decompresses the records of the batch, failing with [`ErrorKind::InvalidData`] when the
+    /// payload is malformed, holds another number of records than `count` or a record doesn't match
+    /// its checksum.
+    pub fn records(&self) -> Result<Vec<Record>, Error> {
+        let payload = self.compression.decompress(&self.payload, MAX_BATCH_SIZE)?;
+
+        let mut records = Vec::with_capacity((self.count as usize).min(payload.len() / 12));
+        let mut remaining = payload.as_slice();
+        while !remaining.is_empty() {
+            let key = take_bytes(&mut remaining)?;
+            let value = take_bytes(&mut remaining)?;
+            let crc = u32::from_be_bytes(take(&mut remaining, 4)?.try_into().unwrap());
+
+            let record = Record::new(key, value);
+            if record.crc != crc {
+                return Err(invalid_batch(format!("checksum mismatch on record {}", records.len())));
+            }
+            records.push(record);
+        }
+
+        match records.len() == self.count as usize {
+            true => Ok(records),
+            false => Err(invalid_batch(format!(
+                "batch holds {} records instead of {}",
+                records.len(),
+                self.count
+            ))),
+        }
+    }
+}
+
+fn take<'a>(data: &mut &'a [u8], len: usize) -> Result<&'a [u8], Error> {
+    if data.len() < len {
+        return Err(invalid_batch("truncated record".to_string()));
+    }
+
+    let (head, tail) = data.split_at(len);
+    *data = tail;
+    Ok(head)
+}
+
+fn take_bytes<'a>(data: &mut &'a [u8]) -> Result<&'a [u8], Error> {
+    let len = u32::from_be_bytes(take(data, 4)?.try_into().unwrap());
+    take(data, len as usize)
+}
+
+fn invalid_batch(message: String) -> Error {
+    Error::new(ErrorKind::InvalidData, message)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_batch_round_trip() {
+        let records = vec![Record::new("a", "1"), Record::from_value(vec![42u8; 1024])];
+
+        for compression in [Compression::None, Compression::Lz4, Compression::Zstd] {
+            let batch = RecordBatch::compress(&records, compression).unwrap();
+            assert_eq!(records, batch.records().unwrap());
+        }
+    }
+
+    #[test]
+    fn test_invalid_batches() {
+        let batch = RecordBatch::compress(&[Record::new("a", "1")], Compression::None).unwrap();
+
+        let miscounted = RecordBatch {
+            count: 2,
+            ..batch.clone()
+        };
+        assert!(miscounted.records().is_err());
+
+        let mut truncated = batch.clone();
+        truncated.payload.pop();
+        assert!(truncated.records().is_err());
+
+        let mut corrupted = batch;
+        corrupted.payload[4] ^= 1;
+        assert_eq!(ErrorKind::InvalidData, corrupted.records().unwrap_err().kind());
+    }
+}
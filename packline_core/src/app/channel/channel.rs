@@ -1,12 +1,14 @@
 use std::collections::HashMap;
 use std::sync::atomic::{AtomicUsize, Ordering};
-use std::sync::Arc;
+use std::sync::{Arc, Weak};
 
-use spin::RwLock as SpinRwLock;
+use spin::{Mutex as SpinMutex, RwLock as SpinRwLock};
 use tokio::sync::RwLock;
 
 use crate::app::channel::consumer::{BaseConsumerStrategy, Consumer, ConsumerWaker};
+use crate::app::channel::record::Record;
 use crate::app::channel::storage::VecStorage;
+use crate::app::metrics::ChannelMetrics;
 
 use super::consumer::ConsumerStrategy;
 use super::storage::ChannelStorage;
@@ -22,6 +24,14 @@ struct Inner {
     pub consumer_strategy: Option<Arc<dyn ConsumerStrategy>>,
 
     pub consumer_group_handlers: Arc<RwLock<HashMap<u128, Arc<ConsumerGroupHandler>>>>,
+
+    /// Names of the consumer groups registered through [`Channel::register_group`].
+    pub group_names: HashMap<u128, String>,
+
+    /// Handlers of the consumers created through [`Channel::private_consumer`], dropped with them.
+    pub private_handlers: SpinMutex<Vec<Weak<ConsumerGroupHandler>>>,
+
+    pub metrics: Arc<ChannelMetrics>,
 }
 
 impl Channel {
@@ -48,10 +58,102 @@ impl Channel {
         )
     }
 
+    /// Returns a consumer for the named consumer group, see [`consumer_group_id`].
+    pub fn group_consumer(&self, group: &str) -> Consumer {
+        self.consumer(self.register_group(group))
+    }
+
+    /// Returns a consumer of its own, outside of any consumer group, whose first consumed record is
+    /// the one at `offset`. It isn't listed nor persisted with the groups, and is gone once dropped.
+    pub fn private_consumer(&self, offset: u64) -> Consumer {
+        let inner = self.inner.read();
+        let handler = Arc::new(ConsumerGroupHandler::new(
+            inner.consumer_strategy.as_ref().unwrap().clone(),
+            inner.metrics.clone(),
+        ));
+        handler.seek(offset);
+        inner.private_handlers.lock().push(Arc::downgrade(&handler));
+
+        Consumer::new(0, handler)
+    }
+
+    /// Returns the id of the named consumer group, remembering its name so that the group can be
+    /// listed by it.
+    pub fn register_group(&self, group: &str) -> u128 {
+        let id = consumer_group_id(group);
+        if !self.inner.read().group_names.contains_key(&id) {
+            self.inner.write().group_names.insert(id, group.to_string());
+        }
+
+        id
+    }
+
+    /// Returns the name of a consumer group, if it was registered.
+    pub fn group_name(&self, consumer_id: u128) -> Option<String> {
+        self.inner.read().group_names.get(&consumer_id).cloned()
+    }
+
+    /// Returns the ids of the consumer groups that consumed, committed or sought on the channel.
+    pub async fn groups(&self) -> Vec<u128> {
+        let handlers = self.consumer_group_handlers();
+        let guard = handlers.read().await;
+        guard.keys().copied().collect()
+    }
+
+    /// Records `offset` as the position the consumer group has processed records up to.
+    pub async fn commit_offset(&self, consumer_id: u128, offset: u64) -> Result<(), ()> {
+        if offset > self.end_offset() {
+            return Err(());
+        }
+
+        self.consumer_group_handler(consumer_id).await.commit(offset);
+        Ok(())
+    }
+
+    /// Moves the consumer group so that the next consumed record is the one at `offset`.
+    pub async fn seek(&self, consumer_id: u128, offset: u64) -> Result<(), ()> {
+        if offset > self.end_offset() {
+            return Err(());
+        }
+
+        self.consumer_group_handler(consumer_id).await.seek(offset);
+        Ok(())
+    }
+
+    /// Reads up to `count` records starting at `offset`, without moving any consumer group.
+    pub fn read(&self, offset: u64, count: usize) -> Vec<Record> {
+        match self.storage() {
+            Some(storage) => storage.peek(offset as usize, count),
+            None => vec![],
+        }
+    }
+
+    /// Returns the offset of the first record still stored.
+    pub fn start_offset(&self) -> u64 {
+        self.storage().map(|storage| storage.start_offset()).unwrap_or(0) as u64
+    }
+
+    /// Returns the offset the next produced record will have.
+    pub fn end_offset(&self) -> u64 {
+        self.storage().map(|storage| storage.end_offset()).unwrap_or(0) as u64
+    }
+
+    /// Returns the offsets of the consumer group: the last committed one, if any, and the one the
+    /// next consumed record will have.
+    pub async fn offsets(&self, consumer_id: u128) -> (Option<u64>, u64) {
+        let handler = self.consumer_group_handler(consumer_id).await;
+        (handler.committed(), handler.position())
+    }
+
     pub fn producer(&self) -> Producer {
         Producer::new(self.clone())
     }
 
+    /// Returns the produce and consume counters of the channel.
+    pub fn metrics(&self) -> Arc<ChannelMetrics> {
+        self.inner.read().metrics.clone()
+    }
+
     pub(crate) async fn consumer_group_handler(&self, consumer_id: u128) -> Arc<ConsumerGroupHandler> {
         let inner = self.inner.read();
         let guard = inner.consumer_group_handlers.read().await;
@@ -64,6 +166,7 @@ impl Channel {
 
         let handler = Arc::new(ConsumerGroupHandler::new(
             inner.consumer_strategy.as_ref().unwrap().clone(),
+            inner.metrics.clone(),
         ));
 
         let mut guard = inner.consumer_group_handlers.write().await;
@@ -72,6 +175,15 @@ impl Channel {
         handler
     }
 
+    /// Returns the handlers of the private consumers still in use, forgetting the dropped ones.
+    pub(crate) fn private_handlers(&self) -> Vec<Arc<ConsumerGroupHandler>> {
+        let inner = self.inner.read();
+        let mut handlers = inner.private_handlers.lock();
+        handlers.retain(|handler| handler.strong_count() > 0);
+
+        handlers.iter().filter_map(Weak::upgrade).collect()
+    }
+
     pub(crate) fn storage(&self) -> Option<Arc<dyn ChannelStorage>> {
         self.inner.read().storage.clone()
     }
@@ -91,22 +203,40 @@ impl Inner {
             storage: None,
             consumer_strategy: None,
             consumer_group_handlers: Arc::new(RwLock::new(HashMap::new())),
+            group_names: HashMap::new(),
+            private_handlers: SpinMutex::new(vec![]),
+            metrics: Arc::new(ChannelMetrics::default()),
         }
     }
 }
 
+/// Maps a consumer group name to the id used by [`Channel::consumer`]. Ids are stable across
+/// restarts.
+pub fn consumer_group_id(name: &str) -> u128 {
+    // 128-bit FNV-1a.
+    const OFFSET_BASIS: u128 = 0x6c62272e07bb014262b821756295c58d;
+    const PRIME: u128 = 0x0000000001000000000000000000013b;
+
+    name.bytes()
+        .fold(OFFSET_BASIS, |hash, byte| (hash ^ byte as u128).wrapping_mul(PRIME))
+}
+
 pub(crate) struct ConsumerGroupHandler {
     offset: AtomicUsize,
+    committed: SpinMutex<Option<u64>>,
     consumer_strategy: Arc<dyn ConsumerStrategy>,
+    metrics: Arc<ChannelMetrics>,
 
     waker: Arc<ConsumerWaker>,
 }
 
 impl ConsumerGroupHandler {
-    pub fn new(consumer_strategy: Arc<dyn ConsumerStrategy>) -> ConsumerGroupHandler {
+    pub fn new(consumer_strategy: Arc<dyn ConsumerStrategy>, metrics: Arc<ChannelMetrics>) -> ConsumerGroupHandler {
         ConsumerGroupHandler {
             offset: AtomicUsize::new(0),
+            committed: SpinMutex::new(None),
             consumer_strategy,
+            metrics,
             waker: Arc::new(ConsumerWaker::new()),
         }
     }
@@ -115,12 +245,29 @@ impl ConsumerGroupHandler {
         self.waker.clone()
     }
 
-    pub async fn consume(&self, count: usize) -> Option<Vec<u32>> {
+    pub fn commit(&self, offset: u64) {
+        *self.committed.lock() = Some(offset);
+    }
+
+    pub fn seek(&self, offset: u64) {
+        self.offset.store(offset as usize, Ordering::Relaxed);
+    }
+
+    pub fn committed(&self) -> Option<u64> {
+        *self.committed.lock()
+    }
+
+    pub fn position(&self) -> u64 {
+        self.offset.load(Ordering::Relaxed) as u64
+    }
+
+    pub async fn consume(&self, count: usize) -> Option<Vec<Record>> {
         let current_offset = self.offset.load(Ordering::Relaxed);
 
         let result = self.consumer_strategy.consume(current_offset, count);
         if let Some(data) = &result {
             self.offset.store(current_offset + data.len(), Ordering::Relaxed);
+            self.metrics.consumed(data);
         }
 
         result
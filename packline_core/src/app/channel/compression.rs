@@ -0,0 +1,113 @@
+use std::convert::TryFrom;
+use std::io::{Error, ErrorKind, Read};
+
+const ZSTD_LEVEL: i32 = 3;
+
+/// Compression codec of a [`RecordBatch`](super::RecordBatch) payload. Connectors also use it to
+/// compress what they send over the wire.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[repr(u8)]
+pub enum Compression {
+    #[default]
+    None = 0,
+    Lz4 = 1,
+    Zstd = 2,
+}
+
+impl Compression {
+    pub fn compress(&self, data: &[u8]) -> Result<Vec<u8>, Error> {
+        match self {
+            Compression::None => Ok(data.to_vec()),
+            Compression::Lz4 => Ok(lz4_flex::compress_prepend_size(data)),
+            Compression::Zstd => zstd::bulk::compress(data, ZSTD_LEVEL),
+        }
+    }
+
+    /// Decompresses `data`, failing with [`ErrorKind::InvalidData`] when the result would be larger
+    /// than `max_size`.
+    pub fn decompress(&self, data: &[u8], max_size: usize) -> Result<Vec<u8>, Error> {
+        let result = match self {
+            Compression::None => data.to_vec(),
+            Compression::Lz4 => {
+                let size = data
+                    .get(..4)
+                    .map(|size| u32::from_le_bytes(size.try_into().unwrap()) as usize)
+                    .ok_or_else(|| Error::new(ErrorKind::InvalidData, "missing lz4 payload size"))?;
+                if size > max_size {
+                    return Err(too_large(max_size));
+                }
+
+                lz4_flex::decompress_size_prepended(data).map_err(|e| Error::new(ErrorKind::InvalidData, e))?
+            }
+            Compression::Zstd => {
+                let mut result = vec![];
+                zstd::stream::read::Decoder::new(data)?
+                    .take(max_size as u64 + 1)
+                    .read_to_end(&mut result)
+                    .map_err(|e| Error::new(ErrorKind::InvalidData, e))?;
+                result
+            }
+        };
+
+        match result.len() > max_size {
+            true => Err(too_large(max_size)),
+            false => Ok(result),
+        }
+    }
+}
+
+fn too_large(max_size: usize) -> Error {
+    Error::new(
+        ErrorKind::InvalidData,
+        format!("decompressed payload larger than {} bytes", max_size),
+    )
+}
+
+impl TryFrom<u8> for Compression {
+    type Error = Error;
+
+    fn try_from(value: u8) -> Result<Self, Self::Error> {
+        match value {
+            0 => Ok(Compression::None),
+            1 => Ok(Compression::Lz4),
+            2 => Ok(Compression::Zstd),
+            _ => Err(Error::new(
+                ErrorKind::InvalidData,
+                format!("unknown compression codec {}", value),
+            )),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_compression_round_trip() {
+        let data = vec![42u8; 4096];
+
+        for compression in [Compression::None, Compression::Lz4, Compression::Zstd] {
+            let compressed = compression.compress(&data).unwrap();
+            assert_eq!(data, compression.decompress(&compressed, data.len()).unwrap());
+        }
+    }
+
+    #[test]
+    fn test_decompression_is_bounded() {
+        let data = vec![42u8; 64 * 1024];
+
+        for compression in [Compression::None, Compression::Lz4, Compression::Zstd] {
+            let compressed = compression.compress(&data).unwrap();
+            let error = compression.decompress(&compressed, data.len() - 1).unwrap_err();
+            assert_eq!(ErrorKind::InvalidData, error.kind());
+        }
+
+        assert!(Compression::Lz4.decompress(&[0, 1], 1024).is_err());
+    }
+
+    #[test]
+    fn test_compression_from_unknown_codec() {
+        assert!(Compression::try_from(42u8).is_err());
+    }
+}
@@ -10,7 +10,9 @@ use futures::task::AtomicWaker;
 use futures::FutureExt;
 use tokio::time::{self, Duration};
 
+use super::batch::RecordBatch;
 use super::channel::ConsumerGroupHandler;
+use super::record::Record;
 use super::Channel;
 
 pub(crate) trait ConsumerStrategy: Send + Sync {
@@ -18,8 +20,9 @@ pub(crate) trait ConsumerStrategy: Send + Sync {
     where
         Self: Sized;
 
-    fn produce(&self, data: &mut Vec<u32>);
-    fn consume(&self, offset: usize, count: usize) -> Option<Vec<u32>>;
+    fn produce(&self, data: &mut Vec<Record>);
+    fn produce_batch(&self, batch: RecordBatch, size: usize);
+    fn consume(&self, offset: usize, count: usize) -> Option<Vec<Record>>;
 }
 
 pub struct BaseConsumerStrategy {
@@ -31,13 +34,19 @@ impl ConsumerStrategy for BaseConsumerStrategy {
         BaseConsumerStrategy { channel }
     }
 
-    fn produce(&self, data: &mut Vec<u32>) {
+    fn produce(&self, data: &mut Vec<Record>) {
         if let Some(storage) = self.channel.storage() {
             storage.enqueue(data);
         }
     }
 
-    fn consume(&self, offset: usize, count: usize) -> Option<Vec<u32>> {
+    fn produce_batch(&self, batch: RecordBatch, size: usize) {
+        if let Some(storage) = self.channel.storage() {
+            storage.enqueue_batch(batch, size);
+        }
+    }
+
+    fn consume(&self, offset: usize, count: usize) -> Option<Vec<Record>> {
         if let Some(storage) = self.channel.storage() {
             let result = storage.peek(offset, count);
 
@@ -133,7 +142,7 @@ struct ConsumerConfigs {
     timeout: u64,
 }
 
-impl<'a> Consumer {
+impl Consumer {
     pub(crate) fn new(consumer_id: u128, handler: Arc<ConsumerGroupHandler>) -> Self {
         Consumer {
             consumer_id,
@@ -142,6 +151,12 @@ impl<'a> Consumer {
         }
     }
 
+    /// Sets how long [`Consumer::consume`] keeps collecting records before returning them.
+    pub fn with_timeout(mut self, timeout: Duration) -> Consumer {
+        self.configs.timeout = timeout.as_millis() as u64;
+        self
+    }
+
     pub fn consume(&self) -> ConsumerFuture<time::Sleep> {
         ConsumerFuture::new(
             self.handler.waker().handle(),
@@ -151,7 +166,7 @@ impl<'a> Consumer {
     }
 }
 
-type PinConsumerFuture = Pin<Box<dyn Future<Output = Option<Vec<u32>>>>>;
+type PinConsumerFuture = Pin<Box<dyn Future<Output = Option<Vec<Record>>>>>;
 
 use crate::internal::time::sleep::SleepTrait;
 
@@ -161,13 +176,13 @@ pub struct ConsumerFuture<S: SleepTrait> {
     waker_handle: Arc<ConsumerWakerHandle>,
     handler: Arc<ConsumerGroupHandler>,
 
-    buffer: Vec<u32>,
+    buffer: Vec<Record>,
 }
 
 unsafe impl<S: SleepTrait> Send for ConsumerFuture<S> {}
 unsafe impl<S: SleepTrait> Sync for ConsumerFuture<S> {}
 
-impl<'a, S> ConsumerFuture<S>
+impl<S> ConsumerFuture<S>
 where
     S: SleepTrait,
 {
@@ -203,11 +218,11 @@ where
     }
 }
 
-impl<'a, S> Future for ConsumerFuture<S>
+impl<S> Future for ConsumerFuture<S>
 where
     S: SleepTrait,
 {
-    type Output = Vec<u32>;
+    type Output = Vec<Record>;
 
     fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
         macro_rules! try_recv {
@@ -258,7 +273,7 @@ mod tests {
     use futures::{task::noop_waker_ref, FutureExt};
 
     use crate::{
-        app::{App, ChannelConfig},
+        app::{channel::Record, App, ChannelConfig},
         internal::time::sleep::{MockSleep, SleepTrait},
     };
 
@@ -292,13 +307,15 @@ mod tests {
         let mut producer = channel.producer();
 
         assert_eq!(future.poll_unpin(&mut cx), Poll::Pending);
-        producer.produce(&mut vec![0u32]).await;
+        producer.produce(&mut vec![Record::from_value(vec![0u8])]).await;
 
         assert_eq!(future.poll_unpin(&mut cx), Poll::Pending);
         mock_sleep.force_complete();
 
-        producer.produce(&mut vec![1u32]).await;
+        producer.produce(&mut vec![Record::from_value(vec![1u8])]).await;
 
-        assert_eq!(future.poll_unpin(&mut cx), Poll::Ready(vec![0u32, 1u32]));
+        let mut expected = vec![Record::from_value(vec![0u8]), Record::from_value(vec![1u8])];
+        expected[1].offset = 1;
+        assert_eq!(future.poll_unpin(&mut cx), Poll::Ready(expected));
     }
 }
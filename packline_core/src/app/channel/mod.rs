@@ -1,13 +1,21 @@
-pub use channel::Channel;
+pub use batch::{RecordBatch, MAX_BATCH_SIZE};
+pub use channel::{consumer_group_id, Channel};
+pub use compression::Compression;
+pub use record::Record;
 
+mod batch;
 #[allow(clippy::module_inception)]
 mod channel;
+mod compression;
 pub mod consumer;
 pub mod producer;
+mod record;
 pub mod storage;
 
 #[cfg(test)]
 mod tests {
+    use std::time::Duration;
+
     use super::*;
 
     #[tokio::test]
@@ -22,11 +30,161 @@ mod tests {
         let consumer1 = channel.consumer(CONSUMER_ID1);
         let consumer2 = channel.consumer(CONSUMER_ID2);
 
-        producer.produce(&mut vec![1, 2, 3, 4]).await;
-        assert_eq!(consumer1.consume().await, vec![1, 2, 3, 4]);
+        producer.produce(&mut records(&[1, 2, 3, 4])).await;
+        assert_eq!(values(consumer1.consume().await), vec![1, 2, 3, 4]);
+
+        producer.produce(&mut records(&[5, 6])).await;
+        assert_eq!(values(consumer1.consume().await), vec![5, 6]);
+        assert_eq!(values(consumer2.consume().await), vec![1, 2, 3, 4, 5, 6]);
+    }
+
+    #[tokio::test]
+    async fn test_consumed_records_carry_offsets() {
+        let app = &mut crate::app::App::new();
+        let channel = Channel::new(app.clone());
+
+        channel.producer().produce(&mut records(&[7, 8, 9])).await;
+
+        let consumed = channel.consumer(0).consume().await;
+        assert_eq!(vec![0, 1, 2], consumed.iter().map(|r| r.offset).collect::<Vec<_>>());
+    }
+
+    #[tokio::test]
+    async fn test_group_consumers_share_offsets() {
+        let app = &mut crate::app::App::new();
+        let channel = Channel::new(app.clone());
+
+        channel.producer().produce(&mut records(&[1, 2])).await;
+        assert_eq!(values(channel.group_consumer("billing").consume().await), vec![1, 2]);
+
+        channel.producer().produce(&mut records(&[3])).await;
+        assert_eq!(values(channel.group_consumer("billing").consume().await), vec![3]);
+        assert_eq!(values(channel.group_consumer("audit").consume().await), vec![1, 2, 3]);
+    }
+
+    #[tokio::test]
+    async fn test_commit_offset() {
+        let app = &mut crate::app::App::new();
+        let channel = Channel::new(app.clone());
+        let group = consumer_group_id("billing");
+
+        channel.producer().produce(&mut records(&[1, 2, 3])).await;
+        channel.consumer(group).consume().await;
+
+        assert_eq!((None, 3), channel.offsets(group).await);
+        assert!(channel.commit_offset(group, 2).await.is_ok());
+        assert_eq!((Some(2), 3), channel.offsets(group).await);
+        assert!(channel.commit_offset(group, 4).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_seek() {
+        let app = &mut crate::app::App::new();
+        let channel = Channel::new(app.clone());
+        let group = consumer_group_id("billing");
+
+        channel.producer().produce(&mut records(&[1, 2, 3])).await;
+        assert_eq!(3, channel.end_offset());
+
+        assert!(channel.seek(group, 3).await.is_ok());
+        channel.producer().produce(&mut records(&[4])).await;
+        assert_eq!(values(channel.consumer(group).consume().await), vec![4]);
+
+        assert!(channel.seek(group, 1).await.is_ok());
+        assert_eq!(values(channel.consumer(group).consume().await), vec![2, 3, 4]);
+
+        assert!(channel.seek(group, 5).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_read_does_not_move_consumers() {
+        let app = &mut crate::app::App::new();
+        let channel = Channel::new(app.clone());
+
+        channel.producer().produce(&mut records(&[1, 2, 3])).await;
+
+        assert_eq!(values(channel.read(1, 10)), vec![2, 3]);
+        assert!(channel.read(3, 10).is_empty());
+        assert_eq!(values(channel.consumer(0).consume().await), vec![1, 2, 3]);
+    }
+
+    #[tokio::test]
+    async fn test_registered_groups_are_named() {
+        let app = &mut crate::app::App::new();
+        let channel = Channel::new(app.clone());
+
+        channel.group_consumer("billing");
+        channel.consumer(7);
+
+        let mut groups = channel.groups().await;
+        groups.sort();
+        assert_eq!(vec![7, consumer_group_id("billing")], groups);
+        assert_eq!(
+            Some("billing".to_string()),
+            channel.group_name(consumer_group_id("billing"))
+        );
+        assert_eq!(None, channel.group_name(7));
+    }
+
+    #[tokio::test]
+    async fn test_private_consumers_have_their_own_offsets() {
+        let app = &mut crate::app::App::new();
+        let channel = Channel::new(app.clone());
+
+        channel.producer().produce(&mut records(&[1, 2, 3])).await;
+
+        let first = channel.private_consumer(0).with_timeout(Duration::from_millis(10));
+        let second = channel.private_consumer(2).with_timeout(Duration::from_millis(10));
+        assert_eq!(values(first.consume().await), vec![1, 2, 3]);
+        assert_eq!(values(second.consume().await), vec![3]);
+
+        // Producing wakes private consumers waiting for records.
+        let waiting = tokio::spawn(async move { values(first.consume().await) });
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        channel.producer().produce(&mut records(&[4])).await;
+        let consumed = tokio::time::timeout(Duration::from_secs(1), waiting).await;
+        assert_eq!(consumed.unwrap().unwrap(), vec![4]);
+
+        assert!(channel.groups().await.is_empty());
+        drop(second);
+        assert!(channel.private_handlers().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_produce_batch() {
+        let app = &mut crate::app::App::new();
+        let channel = Channel::new(app.clone());
+
+        let batch = RecordBatch::compress(&records(&[2, 3, 4]), Compression::Lz4).unwrap();
+        channel.producer().produce(&mut records(&[1])).await;
+        channel.producer().produce_batch(batch).await.unwrap();
+        channel.producer().produce(&mut records(&[5])).await;
+
+        let consumed = channel.consumer(0).consume().await;
+        assert_eq!(values(consumed.clone()), vec![1, 2, 3, 4, 5]);
+        assert_eq!(
+            vec![0, 1, 2, 3, 4],
+            consumed.iter().map(|r| r.offset).collect::<Vec<_>>()
+        );
+        assert_eq!(values(channel.read(2, 2)), vec![3, 4]);
+
+        let mut corrupted = RecordBatch::compress(&records(&[6]), Compression::None).unwrap();
+        corrupted.payload[8] ^= 1;
+        assert!(channel.producer().produce_batch(corrupted).await.is_err());
+        assert_eq!(5, channel.end_offset());
+    }
+
+    #[test]
+    fn test_consumer_group_id_is_stable() {
+        assert_eq!(consumer_group_id("billing"), consumer_group_id("billing"));
+        assert_ne!(consumer_group_id("billing"), consumer_group_id("audit"));
+    }
+
+    fn records(values: &[u8]) -> Vec<Record> {
+        values.iter().map(|v| Record::from_value(vec![*v])).collect()
+    }
 
-        producer.produce(&mut vec![5, 6]).await;
-        assert_eq!(consumer1.consume().await, vec![5, 6]);
-        assert_eq!(consumer2.consume().await, vec![1, 2, 3, 4, 5, 6]);
+    fn values(records: Vec<Record>) -> Vec<u8> {
+        records.into_iter().map(|r| r.value[0]).collect()
     }
 }
@@ -1,21 +1,51 @@
+use std::io::Error;
+
+use super::batch::RecordBatch;
+use super::record::Record;
 use super::Channel;
 
 pub struct Producer {
     channel: Channel,
 }
 
-impl<'a> Producer {
+impl Producer {
     pub(crate) fn new(channel: Channel) -> Self {
         Producer { channel }
     }
 
-    pub async fn produce(&mut self, data: &mut Vec<u32>) {
+    pub async fn produce(&mut self, data: &mut Vec<Record>) {
+        self.channel.metrics().produced(data);
         self.channel.consumer_strategy().as_ref().unwrap().produce(data);
+        self.wake_consumers().await;
+    }
+
+    /// Stores `batch` compressed, as it was produced. The batch is rejected when its records can't
+    /// be read back, see [`RecordBatch::records`].
+    pub async fn produce_batch(&mut self, batch: RecordBatch) -> Result<(), Error> {
+        let records = batch.records()?;
+        let size = records.iter().map(|r| r.key.len() + r.value.len()).sum();
+
+        self.channel.metrics().produced(&records);
+        self.channel
+            .consumer_strategy()
+            .as_ref()
+            .unwrap()
+            .produce_batch(batch, size);
+        self.wake_consumers().await;
+
+        Ok(())
+    }
+
+    async fn wake_consumers(&self) {
         let consumer_group_handlers = self.channel.consumer_group_handlers();
         let guard = consumer_group_handlers.read().await;
 
-        for (_, consumer_group_handler) in guard.iter() {
+        for consumer_group_handler in guard.values() {
             consumer_group_handler.waker().wake();
         }
+
+        for private_handler in self.channel.private_handlers() {
+            private_handler.waker().wake();
+        }
     }
 }
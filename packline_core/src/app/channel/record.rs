@@ -0,0 +1,58 @@
+/// A single message stored in a channel.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Record {
+    /// Position of the record in its channel. Assigned by the channel when the record is stored,
+    /// any value set by the producer is overwritten.
+    pub offset: u64,
+
+    /// Key of the record, empty when the record has none.
+    pub key: Vec<u8>,
+    pub value: Vec<u8>,
+
+    /// CRC32C of the key and value, see [`Record::checksum`]. Computed when the record is created
+    /// and kept along with it, so that corruption of the stored bytes can be told apart.
+    pub crc: u32,
+}
+
+impl Record {
+    pub fn new<K: Into<Vec<u8>>, V: Into<Vec<u8>>>(key: K, value: V) -> Record {
+        let key = key.into();
+        let value = value.into();
+
+        Record {
+            offset: 0,
+            crc: Record::checksum(&key, &value),
+            key,
+            value,
+        }
+    }
+
+    pub fn from_value<V: Into<Vec<u8>>>(value: V) -> Record {
+        Record::new(vec![], value)
+    }
+
+    /// CRC32C of a key and a value, each prefixed with its big-endian `u32` length. Offsets are left
+    /// out, as they are assigned by the channel.
+    pub fn checksum(key: &[u8], value: &[u8]) -> u32 {
+        let crc = crc32c::crc32c(&(key.len() as u32).to_be_bytes());
+        let crc = crc32c::crc32c_append(crc, key);
+        let crc = crc32c::crc32c_append(crc, &(value.len() as u32).to_be_bytes());
+        crc32c::crc32c_append(crc, value)
+    }
+
+    /// Number of bytes covered by the checksum of the record.
+    pub fn checksum_len(&self) -> usize {
+        8 + self.key.len() + self.value.len()
+    }
+
+    /// Tells whether the key and value still match the checksum of the record.
+    pub fn is_valid(&self) -> bool {
+        self.crc == Record::checksum(&self.key, &self.value)
+    }
+}
+
+impl Default for Record {
+    fn default() -> Self {
+        Record::new(vec![], vec![])
+    }
+}
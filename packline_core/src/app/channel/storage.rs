@@ -1,6 +1,7 @@
 use spin::Mutex;
-use std::convert::TryInto;
 
+use super::batch::RecordBatch;
+use super::record::Record;
 use super::Channel;
 
 pub(crate) trait ChannelStorage: Send + Sync {
@@ -8,53 +9,260 @@ pub(crate) trait ChannelStorage: Send + Sync {
     where
         Self: Sized;
 
-    fn enqueue(&self, elements: &mut Vec<u32>);
+    /// Stores `elements`, assigning them the offsets following the last stored record.
+    fn enqueue(&self, elements: &mut Vec<Record>);
+
+    /// Stores `batch` as is, its records getting the offsets following the last stored record.
+    /// `size` is the key and value bytes of its records.
+    fn enqueue_batch(&self, batch: RecordBatch, size: usize);
+
+    #[allow(dead_code)]
     fn remove(&self, count: usize);
-    fn peek(&self, offset: usize, count: usize) -> Vec<u32>;
+    fn peek(&self, offset: usize, count: usize) -> Vec<Record>;
+
+    /// Offset the next stored record will get.
+    fn end_offset(&self) -> usize;
+
+    /// Offset of the first record still kept.
+    fn start_offset(&self) -> usize;
+
+    /// Key and value bytes of the records still kept.
+    fn size(&self) -> usize;
+
+    /// Returns the offset of the first record still kept and the entries holding the kept records.
+    /// A batch whose first records are no longer kept is returned as its remaining records.
+    fn entries(&self) -> (usize, Vec<Entry>);
+
+    /// Replaces the stored records with the ones of `entries`, the first of them being at
+    /// `start_offset`.
+    fn restore(&self, start_offset: usize, entries: Vec<Entry>);
+}
+
+/// Records stored together, either a single record or a batch stored as it was produced.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) enum Entry {
+    Record(Record),
+    Batch(RecordBatch),
 }
 
 pub struct VecStorage {
-    data: Mutex<Vec<u32>>,
+    data: Mutex<VecStorageData>,
+}
+
+struct VecStorageData {
+    /// Offset of the first record still kept.
+    start_offset: usize,
+    end_offset: usize,
+
+    /// Entries holding the kept records, by offset. The first one may start before `start_offset`
+    /// when only part of a batch was removed.
+    entries: Vec<StoredEntry>,
+
+    /// Key and value bytes of `entries`.
+    size: usize,
+}
+
+struct StoredEntry {
+    /// Offset of the first record of the entry.
+    offset: usize,
+    len: usize,
+
+    /// Key and value bytes of the records of the entry.
+    size: usize,
+    entry: Entry,
+}
+
+impl StoredEntry {
+    fn new(offset: usize, entry: Entry) -> StoredEntry {
+        match entry {
+            Entry::Record(mut record) => {
+                record.offset = offset as u64;
+                StoredEntry {
+                    offset,
+                    len: 1,
+                    size: record_size(&record),
+                    entry: Entry::Record(record),
+                }
+            }
+            Entry::Batch(batch) => {
+                let size = batch_size(&batch);
+                StoredEntry::batch(offset, batch, size)
+            }
+        }
+    }
+
+    fn batch(offset: usize, batch: RecordBatch, size: usize) -> StoredEntry {
+        StoredEntry {
+            offset,
+            len: batch.count as usize,
+            size,
+            entry: Entry::Batch(batch),
+        }
+    }
+
+    fn end_offset(&self) -> usize {
+        self.offset + self.len
+    }
+
+    /// Returns the records of the entry, with their offsets.
+    fn records(&self) -> Vec<Record> {
+        match &self.entry {
+            Entry::Record(record) => vec![record.clone()],
+            Entry::Batch(batch) => {
+                // Batches are checked before being stored.
+                let mut records = batch.records().expect("stored batch is valid");
+                for (i, record) in records.iter_mut().enumerate() {
+                    record.offset = (self.offset + i) as u64;
+                }
+                records
+            }
+        }
+    }
 }
 
 impl ChannelStorage for VecStorage {
     fn new(_app: crate::app::App, _channel: Channel) -> Self {
         VecStorage {
-            data: Mutex::new(Vec::new()),
+            data: Mutex::new(VecStorageData {
+                start_offset: 0,
+                end_offset: 0,
+                entries: Vec::new(),
+                size: 0,
+            }),
         }
     }
 
-    fn enqueue(&self, elements: &mut Vec<u32>) {
+    fn enqueue(&self, elements: &mut Vec<Record>) {
         let mut guard = self.data.lock();
-        guard.append(elements);
+
+        for element in elements.iter_mut() {
+            element.offset = guard.end_offset as u64;
+            guard.end_offset += 1;
+            guard.size += record_size(element);
+        }
+
+        let entries = elements.drain(..).map(|record| StoredEntry {
+            offset: record.offset as usize,
+            len: 1,
+            size: record_size(&record),
+            entry: Entry::Record(record),
+        });
+        guard.entries.extend(entries);
+    }
+
+    fn enqueue_batch(&self, batch: RecordBatch, size: usize) {
+        if batch.count == 0 {
+            return;
+        }
+
+        let mut guard = self.data.lock();
+
+        let entry = StoredEntry::batch(guard.end_offset, batch, size);
+        guard.end_offset = entry.end_offset();
+        guard.size += entry.size;
+        guard.entries.push(entry);
     }
 
     fn remove(&self, count: usize) {
         let mut guard = self.data.lock();
-        guard.drain(0..count);
+
+        let start_offset = (guard.start_offset + count).min(guard.end_offset);
+        let removed = guard
+            .entries
+            .partition_point(|entry| entry.end_offset() <= start_offset);
+        let removed_size = guard.entries.drain(0..removed).map(|entry| entry.size).sum::<usize>();
+        guard.size -= removed_size;
+        guard.start_offset = start_offset;
     }
 
-    fn peek(&self, offset: usize, count: usize) -> Vec<u32> {
+    fn peek(&self, offset: usize, count: usize) -> Vec<Record> {
         let guard = self.data.lock();
 
-        if offset > guard.len() {
+        let start = offset.max(guard.start_offset);
+        let end = start.saturating_add(count).min(guard.end_offset);
+        if start >= end {
             return vec![];
         }
 
-        let mut count = count;
-        if offset + count > guard.len() {
-            count = guard.len() - offset;
-        }
+        let first = guard.entries.partition_point(|entry| entry.end_offset() <= start);
+        guard.entries[first..]
+            .iter()
+            .take_while(|entry| entry.offset < end)
+            .flat_map(StoredEntry::records)
+            .filter(|record| (start..end).contains(&(record.offset as usize)))
+            .collect()
+    }
+
+    fn end_offset(&self) -> usize {
+        self.data.lock().end_offset
+    }
+
+    fn start_offset(&self) -> usize {
+        self.data.lock().start_offset
+    }
+
+    fn size(&self) -> usize {
+        self.data.lock().size
+    }
+
+    fn entries(&self) -> (usize, Vec<Entry>) {
+        let guard = self.data.lock();
+
+        let entries = guard
+            .entries
+            .iter()
+            .flat_map(|entry| match entry.offset < guard.start_offset {
+                true => entry
+                    .records()
+                    .into_iter()
+                    .filter(|record| record.offset as usize >= guard.start_offset)
+                    .map(Entry::Record)
+                    .collect(),
+                false => vec![entry.entry.clone()],
+            })
+            .collect();
+
+        (guard.start_offset, entries)
+    }
+
+    fn restore(&self, start_offset: usize, entries: Vec<Entry>) {
+        let mut offset = start_offset;
+        let mut size = 0;
+        let entries = entries
+            .into_iter()
+            .map(|entry| {
+                let entry = StoredEntry::new(offset, entry);
+                offset = entry.end_offset();
+                size += entry.size;
+                entry
+            })
+            .collect();
 
-        guard[offset..offset + count].try_into().unwrap()
+        *self.data.lock() = VecStorageData {
+            start_offset,
+            end_offset: offset,
+            entries,
+            size,
+        };
     }
 }
 
+fn record_size(record: &Record) -> usize {
+    record.key.len() + record.value.len()
+}
+
+fn batch_size(batch: &RecordBatch) -> usize {
+    batch
+        .records()
+        .map(|records| records.iter().map(record_size).sum())
+        .unwrap_or(0)
+}
+
 #[cfg(test)]
 mod tests {
     use crate::app::{App, ChannelConfig};
 
-    use super::{ChannelStorage, VecStorage};
+    use super::{ChannelStorage, Record, VecStorage};
 
     #[tokio::test]
     async fn test_vec_storage() {
@@ -77,10 +285,33 @@ mod tests {
 
         assert_eq!(storage.peek(0, 1), vec![]);
 
-        storage.enqueue(&mut vec![0u32]);
-        assert_eq!(storage.peek(0, 1), vec![0u32]);
+        storage.enqueue(&mut vec![Record::from_value(vec![0u8])]);
+        assert_eq!(storage.peek(0, 1), vec![Record::from_value(vec![0u8])]);
 
         storage.remove(1);
         assert_eq!(storage.peek(0, 1), vec![]);
     }
+
+    #[tokio::test]
+    async fn test_vec_storage_assigns_offsets() {
+        let app = App::new();
+        let _ = app
+            .create_channel(ChannelConfig {
+                name: "test_topic".to_string(),
+                partitions: 1,
+            })
+            .await;
+
+        let channel = app.get_channel(&("test_topic".to_string(), 1)).await.unwrap();
+        let storage = VecStorage::new(app, channel);
+
+        storage.enqueue(&mut vec![Record::from_value("a"), Record::from_value("b")]);
+        storage.remove(1);
+        storage.enqueue(&mut vec![Record::from_value("c")]);
+
+        let records = storage.peek(1, 10);
+        assert_eq!(vec![1, 2], records.iter().map(|r| r.offset).collect::<Vec<_>>());
+        assert_eq!(b"c".to_vec(), storage.peek(2, 1)[0].value);
+        assert_eq!(3, storage.end_offset());
+    }
 }
@@ -0,0 +1,84 @@
+//! Liveness and readiness of the broker, as reported to orchestrators by probes.
+//!
+//! The broker is live as long as its runtime runs tasks. It is ready once every check required
+//! through [`Health::require`] passed, and until it starts shutting down. Connectors pass
+//! [`LISTENERS`] once bound, the broker passes the other checks as it starts.
+
+use std::collections::BTreeMap;
+use std::sync::Mutex;
+use std::time::Duration;
+
+/// Passed once the connectors bound their listeners.
+pub const LISTENERS: &str = "listeners";
+
+/// Passed once the persisted channels were restored.
+pub const STORAGE: &str = "storage";
+
+/// Passed once the configured topics exist.
+pub const TOPICS: &str = "topics";
+
+/// Time a task spawned by the liveness check is given to run.
+const LIVENESS_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// Readiness checks of an app, by name.
+#[derive(Default)]
+pub struct Health {
+    checks: Mutex<BTreeMap<String, bool>>,
+}
+
+impl Health {
+    /// Makes the app unready until `check` passes. Checks that already passed are kept passed.
+    pub fn require(&self, check: &str) {
+        self.checks.lock().unwrap().entry(check.to_string()).or_insert(false);
+    }
+
+    pub fn pass(&self, check: &str) {
+        self.checks.lock().unwrap().insert(check.to_string(), true);
+    }
+
+    /// Returns the required checks that didn't pass yet, sorted by name.
+    pub fn pending(&self) -> Vec<String> {
+        self.checks
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|(_, passed)| !**passed)
+            .map(|(check, _)| check.clone())
+            .collect()
+    }
+}
+
+/// Returns whether the current runtime still runs the tasks spawned on it.
+pub async fn runtime_responsive() -> bool {
+    matches!(
+        tokio::time::timeout(LIVENESS_TIMEOUT, tokio::spawn(async {})).await,
+        Ok(Ok(()))
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::app::App;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn test_readiness() {
+        let app = App::new();
+        assert!(app.pending_checks().is_empty());
+
+        app.health().pass(LISTENERS);
+        app.health().require(LISTENERS);
+        app.health().require(STORAGE);
+        app.health().require(TOPICS);
+        assert_eq!(vec![STORAGE, TOPICS], app.pending_checks());
+
+        app.health().pass(STORAGE);
+        app.health().pass(TOPICS);
+        assert!(app.pending_checks().is_empty());
+
+        app.shutdown();
+        assert_eq!(vec!["shutdown"], app.pending_checks());
+        assert!(runtime_responsive().await);
+    }
+}
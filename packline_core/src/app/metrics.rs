@@ -0,0 +1,394 @@
+//! Broker metrics, exported in the Prometheus text format.
+//!
+//! Connectors update the [`Metrics`] of the app as connections come and go and requests are
+//! handled, while channels count the records and bytes produced to and consumed from them in their
+//! [`ChannelMetrics`]. Consumer group lag and storage sizes are read from the channels when the
+//! metrics are exported by [`App::export_metrics`], so that they are never stale.
+
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::sync::atomic::{AtomicI64, AtomicU64, Ordering};
+use std::sync::{Arc, RwLock};
+use std::time::Duration;
+
+use super::channel::Record;
+use super::App;
+
+/// Upper bounds, in seconds, of the request duration histogram buckets.
+const REQUEST_DURATION_BUCKETS: [f64; 14] = [
+    0.0001, 0.00025, 0.0005, 0.001, 0.0025, 0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5,
+];
+
+/// Metrics of the connections and requests handled by the connectors of an app.
+#[derive(Default)]
+pub struct Metrics {
+    connections: Family<Gauge>,
+    request_durations: Family<Histogram>,
+}
+
+/// Records and bytes that went through a channel partition since it was created.
+#[derive(Default)]
+pub struct ChannelMetrics {
+    produced_records: AtomicU64,
+    produced_bytes: AtomicU64,
+    consumed_records: AtomicU64,
+    consumed_bytes: AtomicU64,
+}
+
+/// Counts a connection as active until dropped, see [`Metrics::connection_opened`].
+pub struct ConnectionGuard {
+    gauge: Arc<Gauge>,
+}
+
+/// Values of a metric, one per set of label values.
+struct Family<M> {
+    values: RwLock<BTreeMap<Vec<String>, Arc<M>>>,
+}
+
+#[derive(Default)]
+struct Gauge(AtomicI64);
+
+struct Histogram {
+    /// Observations falling in each bucket of [`REQUEST_DURATION_BUCKETS`], not cumulated.
+    buckets: [AtomicU64; REQUEST_DURATION_BUCKETS.len()],
+    count: AtomicU64,
+    sum_nanos: AtomicU64,
+}
+
+impl Metrics {
+    /// Counts a connection accepted by `connector` as active until the returned guard is dropped.
+    pub fn connection_opened(&self, connector: &str) -> ConnectionGuard {
+        let gauge = self.connections.get(&[connector]);
+        gauge.0.fetch_add(1, Ordering::Relaxed);
+        ConnectionGuard { gauge }
+    }
+
+    /// Records how long a `protocol` request for `route` took to be handled.
+    pub fn observe_request(&self, protocol: &str, route: &str, duration: Duration) {
+        self.request_durations.get(&[protocol, route]).observe(duration);
+    }
+}
+
+impl ChannelMetrics {
+    pub fn produced(&self, records: &[Record]) {
+        self.produced_records.fetch_add(records.len() as u64, Ordering::Relaxed);
+        self.produced_bytes.fetch_add(size(records), Ordering::Relaxed);
+    }
+
+    pub fn consumed(&self, records: &[Record]) {
+        self.consumed_records.fetch_add(records.len() as u64, Ordering::Relaxed);
+        self.consumed_bytes.fetch_add(size(records), Ordering::Relaxed);
+    }
+}
+
+impl Drop for ConnectionGuard {
+    fn drop(&mut self) {
+        self.gauge.0.fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
+impl<M: Default> Family<M> {
+    fn get(&self, labels: &[&str]) -> Arc<M> {
+        let key = labels.iter().map(|label| label.to_string()).collect::<Vec<_>>();
+        if let Some(value) = self.values.read().unwrap().get(&key) {
+            return value.clone();
+        }
+
+        self.values.write().unwrap().entry(key).or_default().clone()
+    }
+
+    fn snapshot(&self) -> Vec<(Vec<String>, Arc<M>)> {
+        self.values
+            .read()
+            .unwrap()
+            .iter()
+            .map(|(labels, value)| (labels.clone(), value.clone()))
+            .collect()
+    }
+}
+
+impl<M> Default for Family<M> {
+    fn default() -> Self {
+        Family {
+            values: RwLock::new(BTreeMap::new()),
+        }
+    }
+}
+
+impl Histogram {
+    fn observe(&self, duration: Duration) {
+        let seconds = duration.as_secs_f64();
+        if let Some(bucket) = REQUEST_DURATION_BUCKETS.iter().position(|bound| seconds <= *bound) {
+            self.buckets[bucket].fetch_add(1, Ordering::Relaxed);
+        }
+
+        self.count.fetch_add(1, Ordering::Relaxed);
+        self.sum_nanos.fetch_add(duration.as_nanos() as u64, Ordering::Relaxed);
+    }
+}
+
+impl Default for Histogram {
+    fn default() -> Self {
+        Histogram {
+            buckets: Default::default(),
+            count: AtomicU64::new(0),
+            sum_nanos: AtomicU64::new(0),
+        }
+    }
+}
+
+type ChannelCounter = fn(&ChannelMetrics) -> &AtomicU64;
+
+pub(crate) async fn export(app: &App) -> String {
+    let mut out = Exporter::default();
+
+    let mut partitions = vec![];
+    for channel in app.list_channels().await {
+        for partition in 1..=channel.partitions {
+            if let Some(handle) = app.get_channel(&(channel.name.clone(), partition)).await {
+                partitions.push((channel.name.clone(), partition.to_string(), handle));
+            }
+        }
+    }
+
+    let channel_counters: [(&str, &str, ChannelCounter); 4] = [
+        (
+            "packline_records_produced_total",
+            "Records produced to a channel partition.",
+            |metrics| &metrics.produced_records,
+        ),
+        (
+            "packline_bytes_produced_total",
+            "Key and value bytes produced to a channel partition.",
+            |metrics| &metrics.produced_bytes,
+        ),
+        (
+            "packline_records_consumed_total",
+            "Records consumed from a channel partition.",
+            |metrics| &metrics.consumed_records,
+        ),
+        (
+            "packline_bytes_consumed_total",
+            "Key and value bytes consumed from a channel partition.",
+            |metrics| &metrics.consumed_bytes,
+        ),
+    ];
+    for (name, help, counter) in channel_counters {
+        out.header(name, help, "counter");
+        for (channel, partition, handle) in &partitions {
+            let value = counter(&handle.metrics()).load(Ordering::Relaxed);
+            out.sample(name, &[("channel", channel), ("partition", partition)], value);
+        }
+    }
+
+    out.header(
+        "packline_storage_records",
+        "Records stored by a channel partition.",
+        "gauge",
+    );
+    for (channel, partition, handle) in &partitions {
+        let records = handle.end_offset() - handle.start_offset();
+        out.sample(
+            "packline_storage_records",
+            &[("channel", channel), ("partition", partition)],
+            records,
+        );
+    }
+
+    out.header(
+        "packline_storage_bytes",
+        "Key and value bytes stored by a channel partition.",
+        "gauge",
+    );
+    for (channel, partition, handle) in &partitions {
+        let bytes = handle.storage().map(|storage| storage.size()).unwrap_or(0);
+        out.sample(
+            "packline_storage_bytes",
+            &[("channel", channel), ("partition", partition)],
+            bytes,
+        );
+    }
+
+    out.header(
+        "packline_consumer_group_lag",
+        "Records of a channel partition a consumer group has yet to consume.",
+        "gauge",
+    );
+    for (channel, partition, handle) in &partitions {
+        let end_offset = handle.end_offset();
+
+        let mut groups = vec![];
+        for id in handle.groups().await {
+            let (_, position) = handle.offsets(id).await;
+            let group = handle.group_name(id).unwrap_or_else(|| format!("#{:032x}", id));
+            groups.push((group, end_offset.saturating_sub(position)));
+        }
+        groups.sort();
+
+        for (group, lag) in groups {
+            out.sample(
+                "packline_consumer_group_lag",
+                &[("channel", channel), ("partition", partition), ("group", &group)],
+                lag,
+            );
+        }
+    }
+
+    let metrics = app.metrics();
+
+    out.header(
+        "packline_connections_active",
+        "Connections currently open on a connector.",
+        "gauge",
+    );
+    for (labels, gauge) in metrics.connections.snapshot() {
+        out.sample(
+            "packline_connections_active",
+            &[("connector", &labels[0])],
+            gauge.0.load(Ordering::Relaxed),
+        );
+    }
+
+    out.header(
+        "packline_request_duration_seconds",
+        "Time taken to handle a request, per protocol and route.",
+        "histogram",
+    );
+    for (labels, histogram) in metrics.request_durations.snapshot() {
+        let labels = [("protocol", labels[0].as_str()), ("route", labels[1].as_str())];
+
+        let mut cumulated = 0;
+        for (bound, bucket) in REQUEST_DURATION_BUCKETS.iter().zip(&histogram.buckets) {
+            cumulated += bucket.load(Ordering::Relaxed);
+            let le = bound.to_string();
+            out.sample(
+                "packline_request_duration_seconds_bucket",
+                &[labels[0], labels[1], ("le", &le)],
+                cumulated,
+            );
+        }
+
+        let count = histogram.count.load(Ordering::Relaxed);
+        out.sample(
+            "packline_request_duration_seconds_bucket",
+            &[labels[0], labels[1], ("le", "+Inf")],
+            count,
+        );
+        out.sample(
+            "packline_request_duration_seconds_sum",
+            &labels,
+            histogram.sum_nanos.load(Ordering::Relaxed) as f64 / 1e9,
+        );
+        out.sample("packline_request_duration_seconds_count", &labels, count);
+    }
+
+    out.text
+}
+
+#[derive(Default)]
+struct Exporter {
+    text: String,
+}
+
+impl Exporter {
+    fn header(&mut self, name: &str, help: &str, kind: &str) {
+        let _ = writeln!(self.text, "# HELP {} {}", name, help);
+        let _ = writeln!(self.text, "# TYPE {} {}", name, kind);
+    }
+
+    fn sample<V: std::fmt::Display>(&mut self, name: &str, labels: &[(&str, &str)], value: V) {
+        let labels = labels
+            .iter()
+            .map(|(label, value)| format!("{}=\"{}\"", label, escape(value)))
+            .collect::<Vec<_>>()
+            .join(",");
+
+        let _ = writeln!(self.text, "{}{{{}}} {}", name, labels, value);
+    }
+}
+
+fn escape(value: &str) -> String {
+    value.replace('\\', "\\\\").replace('"', "\\\"").replace('\n', "\\n")
+}
+
+fn size(records: &[Record]) -> u64 {
+    records
+        .iter()
+        .map(|record| (record.key.len() + record.value.len()) as u64)
+        .sum()
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::app::ChannelConfig;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn test_export_channel_metrics() {
+        let app = App::new();
+        app.create_channel(ChannelConfig {
+            name: "orders".to_string(),
+            partitions: 1,
+        })
+        .await
+        .unwrap();
+
+        let channel = app.get_channel(&("orders".to_string(), 1)).await.unwrap();
+        channel
+            .producer()
+            .produce(&mut vec![
+                Record::new("k", "abc"),
+                Record::from_value("de"),
+                Record::from_value("f"),
+            ])
+            .await;
+        channel.group_consumer("billing").consume().await;
+        channel.register_group("audit");
+        channel.seek(channel.register_group("audit"), 1).await.unwrap();
+
+        let text = app.export_metrics().await;
+        for line in [
+            "# TYPE packline_records_produced_total counter",
+            "packline_records_produced_total{channel=\"orders\",partition=\"1\"} 3",
+            "packline_bytes_produced_total{channel=\"orders\",partition=\"1\"} 7",
+            "packline_records_consumed_total{channel=\"orders\",partition=\"1\"} 3",
+            "packline_storage_records{channel=\"orders\",partition=\"1\"} 3",
+            "packline_storage_bytes{channel=\"orders\",partition=\"1\"} 7",
+            "packline_consumer_group_lag{channel=\"orders\",partition=\"1\",group=\"audit\"} 2",
+            "packline_consumer_group_lag{channel=\"orders\",partition=\"1\",group=\"billing\"} 0",
+        ] {
+            assert!(text.lines().any(|l| l == line), "missing {:?} in\n{}", line, text);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_export_connection_and_request_metrics() {
+        let app = App::new();
+
+        let first = app.metrics().connection_opened("flow");
+        let _second = app.metrics().connection_opened("flow");
+        drop(first);
+
+        app.metrics().observe_request("flow", "6", Duration::from_micros(300));
+        app.metrics().observe_request("flow", "6", Duration::from_secs(3));
+
+        let text = app.export_metrics().await;
+        for line in [
+            "packline_connections_active{connector=\"flow\"} 1",
+            "packline_request_duration_seconds_bucket{protocol=\"flow\",route=\"6\",le=\"0.00025\"} 0",
+            "packline_request_duration_seconds_bucket{protocol=\"flow\",route=\"6\",le=\"0.0005\"} 1",
+            "packline_request_duration_seconds_bucket{protocol=\"flow\",route=\"6\",le=\"2.5\"} 1",
+            "packline_request_duration_seconds_bucket{protocol=\"flow\",route=\"6\",le=\"+Inf\"} 2",
+            "packline_request_duration_seconds_sum{protocol=\"flow\",route=\"6\"} 3.0003",
+            "packline_request_duration_seconds_count{protocol=\"flow\",route=\"6\"} 2",
+        ] {
+            assert!(text.lines().any(|l| l == line), "missing {:?} in\n{}", line, text);
+        }
+    }
+
+    #[test]
+    fn test_escape_label_values() {
+        assert_eq!("a\\\"b\\\\c\\nd", escape("a\"b\\c\nd"));
+    }
+}
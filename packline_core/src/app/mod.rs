@@ -1,10 +1,22 @@
+use std::io::Error;
+use std::path::Path;
+use std::sync::RwLock as StdRwLock;
 use std::{collections::HashMap, sync::Arc};
 
-use tokio::sync::RwLock;
+use tokio::sync::{watch, RwLock};
 
+use self::acl::{Authorizer, Operation, Resource};
+use self::binding::{Binding, BindingError, BindingTable, ExchangeKind};
 use self::channel::Channel;
+use self::health::Health;
+use self::metrics::Metrics;
 
+pub mod acl;
+pub mod binding;
 pub mod channel;
+pub mod health;
+pub mod metrics;
+pub mod snapshot;
 
 /// Handle for packline core functions.
 #[derive(Clone)]
@@ -16,6 +28,14 @@ type ChannelIdentifier = (String, u16);
 
 struct Inner {
     channels: RwLock<HashMap<ChannelIdentifier, Channel>>,
+    authorizer: Option<Authorizer>,
+    bindings: StdRwLock<BindingTable>,
+    metrics: Metrics,
+    health: Health,
+
+    /// Set once the app is shutting down. The sender is kept here so that receivers never see it
+    /// closed while the app is alive.
+    shutdown: watch::Sender<bool>,
 }
 
 pub struct ChannelConfig {
@@ -34,13 +54,49 @@ impl App {
         App {
             inner: Arc::new(Inner {
                 channels: Default::default(),
+                authorizer: None,
+                bindings: StdRwLock::new(BindingTable::new()),
+                metrics: Metrics::default(),
+                health: Health::default(),
+                shutdown: watch::Sender::new(false),
             }),
         }
     }
 
+    /// Creates an app checking every operation against `authorizer`. Apps created through
+    /// [`App::new`] allow everything.
+    pub fn with_authorizer(authorizer: Authorizer) -> App {
+        App {
+            inner: Arc::new(Inner {
+                channels: Default::default(),
+                authorizer: Some(authorizer),
+                bindings: StdRwLock::new(BindingTable::new()),
+                metrics: Metrics::default(),
+                health: Health::default(),
+                shutdown: watch::Sender::new(false),
+            }),
+        }
+    }
+
+    pub fn authorizer(&self) -> Option<&Authorizer> {
+        self.inner.authorizer.as_ref()
+    }
+
+    pub fn authorize(&self, principal: &str, resource: &Resource, operation: Operation) -> bool {
+        match &self.inner.authorizer {
+            Some(authorizer) => authorizer.authorize(principal, resource, operation),
+            None => true,
+        }
+    }
+
+    /// Creates the partitions of a channel. Fails if a channel with the same name already exists.
     pub async fn create_channel(&self, config: ChannelConfig) -> Result<ChannelMetadata, ()> {
         let mut guard = self.inner.channels.write().await;
 
+        if guard.contains_key(&(config.name.clone(), 1)) {
+            return Err(());
+        }
+
         let channels =
             (1..=config.partitions).fold(Vec::with_capacity(config.partitions.into()), |mut acc, partition| {
                 let channel = Channel::new(self.clone());
@@ -54,10 +110,128 @@ impl App {
         Ok(ChannelMetadata { channels })
     }
 
+    /// Deletes every partition of a channel, returning whether it existed. Producers and consumers
+    /// already holding a partition keep it until they're dropped.
+    pub async fn delete_channel(&self, name: &str) -> bool {
+        let mut guard = self.inner.channels.write().await;
+
+        let length = guard.len();
+        guard.retain(|(channel, _), _| channel != name);
+        guard.len() != length
+    }
+
     pub async fn get_channel(&self, identifier: &ChannelIdentifier) -> Option<Channel> {
         let guard = self.inner.channels.read().await;
         guard.get(identifier).cloned()
     }
+
+    /// Lists the existing channels with their partition count, sorted by name.
+    pub async fn list_channels(&self) -> Vec<ChannelConfig> {
+        let guard = self.inner.channels.read().await;
+
+        let mut partitions: HashMap<&String, u16> = HashMap::new();
+        for (name, _) in guard.keys() {
+            *partitions.entry(name).or_default() += 1;
+        }
+
+        let mut channels = partitions
+            .into_iter()
+            .map(|(name, partitions)| ChannelConfig {
+                name: name.clone(),
+                partitions,
+            })
+            .collect::<Vec<_>>();
+
+        channels.sort_by(|a, b| a.name.cmp(&b.name));
+        channels
+    }
+
+    /// Declares an exchange, returning whether it didn't exist. Fails when an exchange of another
+    /// kind has the same name.
+    pub fn declare_exchange(&self, name: &str, kind: ExchangeKind) -> Result<bool, BindingError> {
+        self.inner.bindings.write().unwrap().declare_exchange(name, kind)
+    }
+
+    pub fn exchange(&self, name: &str) -> Option<ExchangeKind> {
+        self.inner.bindings.read().unwrap().exchange(name)
+    }
+
+    /// Deletes an exchange and its bindings, returning whether it existed.
+    pub fn delete_exchange(&self, name: &str) -> bool {
+        self.inner.bindings.write().unwrap().delete_exchange(name)
+    }
+
+    /// Adds a binding, returning whether it didn't exist. Fails when its exchange doesn't exist.
+    pub fn bind(&self, binding: Binding) -> Result<bool, BindingError> {
+        self.inner.bindings.write().unwrap().bind(binding)
+    }
+
+    /// Removes a binding, returning whether it existed.
+    pub fn unbind(&self, binding: &Binding) -> bool {
+        self.inner.bindings.write().unwrap().unbind(binding)
+    }
+
+    pub fn bindings(&self) -> Vec<Binding> {
+        self.inner.bindings.read().unwrap().bindings()
+    }
+
+    /// Returns the channels a message published to `exchange` with `routing_key` is routed to, or
+    /// `None` when the exchange doesn't exist. See [`binding`] for the routing rules.
+    pub fn route(&self, exchange: &str, routing_key: &str) -> Option<Vec<String>> {
+        self.inner.bindings.read().unwrap().route(exchange, routing_key)
+    }
+
+    /// Writes the records of every channel and the offsets of their consumer groups to `dir`, see
+    /// [`snapshot`].
+    pub async fn persist<P: AsRef<Path>>(&self, dir: P) -> Result<(), Error> {
+        snapshot::persist(self, dir.as_ref()).await
+    }
+
+    /// Loads the channels persisted to `dir`, if any. Channels are created as needed, the records
+    /// and consumer group offsets of existing ones are replaced.
+    pub async fn restore<P: AsRef<Path>>(&self, dir: P) -> Result<(), Error> {
+        snapshot::restore(self, dir.as_ref()).await
+    }
+
+    pub fn metrics(&self) -> &Metrics {
+        &self.inner.metrics
+    }
+
+    /// Renders the metrics of the app and of every channel in the Prometheus text format, see
+    /// [`metrics`].
+    pub async fn export_metrics(&self) -> String {
+        metrics::export(self).await
+    }
+
+    pub fn health(&self) -> &Health {
+        &self.inner.health
+    }
+
+    /// Returns why the app isn't ready to serve clients: the readiness checks that didn't pass
+    /// yet, or `shutdown` once it is shutting down. Empty when ready, see [`health`].
+    pub fn pending_checks(&self) -> Vec<String> {
+        if self.is_shutting_down() {
+            return vec!["shutdown".to_string()];
+        }
+
+        self.inner.health.pending()
+    }
+
+    /// Tells connections to finish: they stop reading requests, flush the responses in flight and
+    /// close.
+    pub fn shutdown(&self) {
+        self.inner.shutdown.send_replace(true);
+    }
+
+    pub fn is_shutting_down(&self) -> bool {
+        *self.inner.shutdown.borrow()
+    }
+
+    /// Resolves once [`App::shutdown`] was called.
+    pub async fn shutting_down(&self) {
+        let mut receiver = self.inner.shutdown.subscribe();
+        let _ = receiver.wait_for(|shutdown| *shutdown).await;
+    }
 }
 
 impl Default for App {
@@ -68,6 +242,7 @@ impl Default for App {
 
 #[cfg(test)]
 mod tests {
+    use super::acl::{Authorizer, Operation, Resource, ANONYMOUS};
     use super::{App, ChannelConfig};
 
     #[tokio::test]
@@ -84,6 +259,74 @@ mod tests {
         assert_eq!(3, result.unwrap().channels.len());
     }
 
+    #[test]
+    fn test_authorize_without_authorizer_allows_everything() {
+        let app = App::new();
+
+        assert!(app.authorize(ANONYMOUS, &Resource::cluster(), Operation::Delete));
+    }
+
+    #[test]
+    fn test_authorize_with_authorizer() {
+        let app = App::with_authorizer(Authorizer::new());
+
+        assert!(!app.authorize(ANONYMOUS, &Resource::topic("orders"), Operation::Consume));
+    }
+
+    #[tokio::test]
+    async fn test_create_existing_channel_fails() {
+        let app = App::new();
+        let config = || ChannelConfig {
+            partitions: 1,
+            name: "testing_channel".to_string(),
+        };
+
+        assert!(app.create_channel(config()).await.is_ok());
+        assert!(app.create_channel(config()).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_list_channels() {
+        let app = App::new();
+        for (name, partitions) in [("b_channel", 2), ("a_channel", 1)] {
+            let _ = app
+                .create_channel(ChannelConfig {
+                    partitions,
+                    name: name.to_string(),
+                })
+                .await;
+        }
+
+        let channels = app.list_channels().await;
+
+        assert_eq!(
+            vec![("a_channel".to_string(), 1), ("b_channel".to_string(), 2)],
+            channels.into_iter().map(|c| (c.name, c.partitions)).collect::<Vec<_>>()
+        );
+    }
+
+    #[tokio::test]
+    async fn test_delete_channel() {
+        let app = App::new();
+        for name in ["orders", "audit"] {
+            app.create_channel(ChannelConfig {
+                name: name.to_string(),
+                partitions: 2,
+            })
+            .await
+            .unwrap();
+        }
+
+        assert!(app.delete_channel("orders").await);
+        assert!(!app.delete_channel("orders").await);
+
+        assert!(app.get_channel(&("orders".to_string(), 2)).await.is_none());
+        assert_eq!(
+            vec!["audit"],
+            app.list_channels().await.iter().map(|c| &c.name).collect::<Vec<_>>()
+        );
+    }
+
     #[tokio::test]
     async fn test_get_channel_return_some() {
         let name = "testing_channel".to_string();
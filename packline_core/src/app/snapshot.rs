@@ -0,0 +1,416 @@
+//! Snapshots of the channels of an app, so that a broker can be stopped and started again without
+//! losing them.
+//!
+//! Channels are kept in memory while the broker runs. [`App::persist`] writes the records of every
+//! partition and the offsets of its consumer groups to a single JSON file, [`SNAPSHOT_FILE`], in
+//! the storage directory, and [`App::restore`] loads it back. Keys and values are base64 encoded and
+//! kept with their checksum, which the restore checks. Batches produced compressed are kept as such,
+//! their payload base64 encoded. Consumer groups are identified by their id,
+//! along with their name when it was registered.
+
+use std::collections::HashMap;
+use std::io::{Error, ErrorKind};
+use std::path::Path;
+
+use base64::engine::general_purpose::STANDARD as BASE64;
+use base64::Engine;
+use serde::{Deserialize, Serialize};
+
+use super::channel::storage::Entry;
+use super::channel::{Channel, Compression, Record, RecordBatch};
+use super::{App, ChannelConfig};
+
+/// Name of the snapshot file in the storage directory.
+pub const SNAPSHOT_FILE: &str = "channels.json";
+
+#[derive(Serialize, Deserialize)]
+struct Snapshot {
+    channels: Vec<PartitionSnapshot>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct PartitionSnapshot {
+    name: String,
+    partition: u16,
+
+    /// Offset of the first record, the following ones have consecutive offsets.
+    start_offset: u64,
+    records: Vec<EntrySnapshot>,
+    groups: Vec<GroupSnapshot>,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(untagged)]
+enum EntrySnapshot {
+    Record(RecordSnapshot),
+    Batch(BatchSnapshot),
+}
+
+#[derive(Serialize, Deserialize)]
+struct RecordSnapshot {
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    key: String,
+    value: String,
+
+    /// Checksum kept with the record, missing from snapshots taken before records had one.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    crc: Option<u32>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct BatchSnapshot {
+    compression: u8,
+    count: u32,
+    payload: String,
+}
+
+#[derive(Serialize, Deserialize)]
+struct GroupSnapshot {
+    /// Consumer group id, as 32 hexadecimal digits.
+    id: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    name: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    committed: Option<u64>,
+    position: u64,
+}
+
+pub(crate) async fn persist(app: &App, dir: &Path) -> Result<(), Error> {
+    let channels = app
+        .inner
+        .channels
+        .read()
+        .await
+        .iter()
+        .map(|((name, partition), channel)| (name.clone(), *partition, channel.clone()))
+        .collect::<Vec<_>>();
+
+    let mut snapshot = Snapshot {
+        channels: Vec::with_capacity(channels.len()),
+    };
+    for (name, partition, channel) in channels {
+        snapshot
+            .channels
+            .push(PartitionSnapshot::capture(name, partition, &channel).await);
+    }
+
+    snapshot
+        .channels
+        .sort_by(|a, b| (&a.name, a.partition).cmp(&(&b.name, b.partition)));
+
+    let content = serde_json::to_vec(&snapshot)?;
+
+    // Written aside first so that a crash never leaves a truncated snapshot behind.
+    std::fs::create_dir_all(dir)?;
+    let path = dir.join(SNAPSHOT_FILE);
+    let tmp_path = path.with_extension("tmp");
+    std::fs::write(&tmp_path, content)?;
+    std::fs::rename(tmp_path, path)
+}
+
+pub(crate) async fn restore(app: &App, dir: &Path) -> Result<(), Error> {
+    let path = dir.join(SNAPSHOT_FILE);
+    let content = match std::fs::read(&path) {
+        Ok(content) => content,
+        Err(e) if e.kind() == ErrorKind::NotFound => return Ok(()),
+        Err(e) => return Err(e),
+    };
+
+    let snapshot: Snapshot = serde_json::from_slice(&content).map_err(|e| invalid(&path, &e.to_string()))?;
+
+    let mut partitions: HashMap<&str, u16> = HashMap::new();
+    for channel in &snapshot.channels {
+        let count = partitions.entry(&channel.name).or_default();
+        *count = (*count).max(channel.partition);
+    }
+
+    for (name, partitions) in partitions {
+        // Channels that already exist are kept as they are.
+        let _ = app
+            .create_channel(ChannelConfig {
+                name: name.to_string(),
+                partitions,
+            })
+            .await;
+    }
+
+    for partition in snapshot.channels {
+        let channel = app
+            .get_channel(&(partition.name.clone(), partition.partition))
+            .await
+            .ok_or_else(|| {
+                invalid(
+                    &path,
+                    &format!("channel {} has no partition {}", partition.name, partition.partition),
+                )
+            })?;
+
+        partition.apply(&channel).await.map_err(|e| invalid(&path, &e))?;
+    }
+
+    Ok(())
+}
+
+impl PartitionSnapshot {
+    async fn capture(name: String, partition: u16, channel: &Channel) -> PartitionSnapshot {
+        let (start_offset, entries) = match channel.storage() {
+            Some(storage) => storage.entries(),
+            None => (0, vec![]),
+        };
+
+        let handlers = channel.consumer_group_handlers();
+        let mut groups = handlers
+            .read()
+            .await
+            .iter()
+            .map(|(id, handler)| GroupSnapshot {
+                id: format!("{:032x}", id),
+                name: channel.group_name(*id),
+                committed: handler.committed(),
+                position: handler.position(),
+            })
+            .collect::<Vec<_>>();
+
+        groups.sort_by(|a, b| a.id.cmp(&b.id));
+
+        PartitionSnapshot {
+            name,
+            partition,
+            start_offset: start_offset as u64,
+            records: entries
+                .into_iter()
+                .map(|entry| match entry {
+                    Entry::Record(record) => EntrySnapshot::Record(RecordSnapshot {
+                        key: BASE64.encode(record.key),
+                        value: BASE64.encode(record.value),
+                        crc: Some(record.crc),
+                    }),
+                    Entry::Batch(batch) => EntrySnapshot::Batch(BatchSnapshot {
+                        compression: batch.compression as u8,
+                        count: batch.count,
+                        payload: BASE64.encode(batch.payload),
+                    }),
+                })
+                .collect(),
+            groups,
+        }
+    }
+
+    /// Replaces the records of `channel` and moves its consumer groups to the snapshot offsets.
+    async fn apply(self, channel: &Channel) -> Result<(), String> {
+        let mut offset = self.start_offset;
+        let mut entries = Vec::with_capacity(self.records.len());
+        for snapshot in self.records {
+            let entry = match snapshot {
+                EntrySnapshot::Record(snapshot) => {
+                    let record = Record::new(
+                        BASE64
+                            .decode(snapshot.key)
+                            .map_err(|e| format!("invalid record key: {}", e))?,
+                        BASE64
+                            .decode(snapshot.value)
+                            .map_err(|e| format!("invalid record value: {}", e))?,
+                    );
+
+                    match snapshot.crc {
+                        Some(crc) if crc != record.crc => {
+                            return Err(format!(
+                                "checksum mismatch for record {} of channel {} partition {}",
+                                offset, self.name, self.partition
+                            ))
+                        }
+                        _ => Entry::Record(record),
+                    }
+                }
+                EntrySnapshot::Batch(snapshot) => {
+                    let batch = RecordBatch {
+                        compression: Compression::try_from(snapshot.compression).map_err(|e| e.to_string())?,
+                        count: snapshot.count,
+                        payload: BASE64
+                            .decode(snapshot.payload)
+                            .map_err(|e| format!("invalid batch payload: {}", e))?,
+                    };
+
+                    batch.records().map_err(|e| {
+                        format!(
+                            "invalid batch at record {} of channel {} partition {}: {}",
+                            offset, self.name, self.partition, e
+                        )
+                    })?;
+                    Entry::Batch(batch)
+                }
+            };
+
+            offset += match &entry {
+                Entry::Record(_) => 1,
+                Entry::Batch(batch) => batch.count as u64,
+            };
+            entries.push(entry);
+        }
+
+        if let Some(storage) = channel.storage() {
+            storage.restore(self.start_offset as usize, entries);
+        }
+
+        for group in self.groups {
+            let id = u128::from_str_radix(&group.id, 16).map_err(|_| format!("invalid group id {:?}", group.id))?;
+
+            if let Some(name) = &group.name {
+                channel.register_group(name);
+            }
+
+            let handler = channel.consumer_group_handler(id).await;
+            handler.seek(group.position);
+            if let Some(committed) = group.committed {
+                handler.commit(committed);
+            }
+        }
+
+        Ok(())
+    }
+}
+
+fn invalid(path: &Path, message: &str) -> Error {
+    Error::new(
+        ErrorKind::InvalidData,
+        format!("invalid snapshot {}: {}", path.display(), message),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::app::channel::consumer_group_id;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn test_persist_and_restore() {
+        let dir = tempfile::tempdir().unwrap();
+
+        let app = App::new();
+        app.create_channel(ChannelConfig {
+            name: "orders".to_string(),
+            partitions: 2,
+        })
+        .await
+        .unwrap();
+
+        let channel = app.get_channel(&("orders".to_string(), 1)).await.unwrap();
+        channel
+            .producer()
+            .produce(&mut vec![
+                Record::from_value("a"),
+                Record::new("k", "b"),
+                Record::from_value("c"),
+            ])
+            .await;
+        channel.storage().unwrap().remove(1);
+
+        let group = consumer_group_id("billing");
+        channel.group_consumer("billing").consume().await;
+        channel.commit_offset(group, 2).await.unwrap();
+
+        app.persist(dir.path()).await.unwrap();
+
+        let restored = App::new();
+        restored.restore(dir.path()).await.unwrap();
+
+        let names = restored.list_channels().await;
+        assert_eq!(1, names.len());
+        assert_eq!(2, names[0].partitions);
+
+        let channel = restored.get_channel(&("orders".to_string(), 1)).await.unwrap();
+        assert_eq!(3, channel.end_offset());
+
+        let records = channel.read(0, 10);
+        assert_eq!(vec![1, 2], records.iter().map(|r| r.offset).collect::<Vec<_>>());
+        assert_eq!(b"k".to_vec(), records[0].key);
+        assert_eq!(b"c".to_vec(), records[1].value);
+        assert_eq!((Some(2), 3), channel.offsets(group).await);
+        assert_eq!(Some("billing".to_string()), channel.group_name(group));
+
+        channel.producer().produce(&mut vec![Record::from_value("d")]).await;
+        assert_eq!(3, channel.read(3, 1)[0].offset);
+    }
+
+    #[tokio::test]
+    async fn test_persist_and_restore_batches() {
+        let dir = tempfile::tempdir().unwrap();
+
+        let app = App::new();
+        app.create_channel(ChannelConfig {
+            name: "orders".to_string(),
+            partitions: 1,
+        })
+        .await
+        .unwrap();
+
+        let channel = app.get_channel(&("orders".to_string(), 1)).await.unwrap();
+        let values = ["a", "b", "c"].map(Record::from_value);
+        for compression in [Compression::Lz4, Compression::Zstd] {
+            let batch = RecordBatch::compress(&values, compression).unwrap();
+            channel.producer().produce_batch(batch).await.unwrap();
+        }
+        channel.storage().unwrap().remove(1);
+
+        app.persist(dir.path()).await.unwrap();
+
+        let restored = App::new();
+        restored.restore(dir.path()).await.unwrap();
+
+        let channel = restored.get_channel(&("orders".to_string(), 1)).await.unwrap();
+        let (start_offset, entries) = channel.storage().unwrap().entries();
+        assert_eq!(1, start_offset);
+        assert_eq!(
+            Entry::Batch(RecordBatch::compress(&values, Compression::Zstd).unwrap()),
+            entries[2]
+        );
+
+        let records = channel.read(0, 10);
+        assert_eq!(
+            vec![1, 2, 3, 4, 5],
+            records.iter().map(|r| r.offset).collect::<Vec<_>>()
+        );
+        assert_eq!(
+            vec!["b", "c", "a", "b", "c"],
+            records
+                .iter()
+                .map(|r| std::str::from_utf8(&r.value).unwrap())
+                .collect::<Vec<_>>()
+        );
+    }
+
+    #[tokio::test]
+    async fn test_restore_without_snapshot() {
+        let dir = tempfile::tempdir().unwrap();
+
+        let app = App::new();
+        assert!(app.restore(dir.path().join("missing")).await.is_ok());
+        assert!(app.list_channels().await.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_restore_rejects_invalid_snapshot() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(dir.path().join(SNAPSHOT_FILE), "{\"channels\": 1}").unwrap();
+
+        let error = App::new().restore(dir.path()).await.unwrap_err();
+        assert_eq!(ErrorKind::InvalidData, error.kind());
+    }
+
+    #[tokio::test]
+    async fn test_restore_rejects_corrupted_records() {
+        let dir = tempfile::tempdir().unwrap();
+        let snapshot = format!(
+            "{{\"channels\": [{{\"name\": \"orders\", \"partition\": 1, \"start_offset\": 0, \
+             \"records\": [{{\"value\": \"{}\", \"crc\": {}}}], \"groups\": []}}]}}",
+            BASE64.encode("b"),
+            Record::from_value("a").crc
+        );
+        std::fs::write(dir.path().join(SNAPSHOT_FILE), snapshot).unwrap();
+
+        let error = App::new().restore(dir.path()).await.unwrap_err();
+        assert_eq!(ErrorKind::InvalidData, error.kind());
+        assert!(error.to_string().contains("checksum mismatch"));
+    }
+}
@@ -0,0 +1,691 @@
+use std::collections::HashMap;
+use std::convert::Infallible;
+use std::io::Error;
+use std::time::Duration;
+
+use async_trait::async_trait;
+use base64::engine::general_purpose::STANDARD as BASE64;
+use base64::Engine;
+use futures::{future::Fuse, select, FutureExt};
+use http_body_util::{BodyExt, Full, Limited};
+use hyper::body::{Bytes, Incoming};
+use hyper::header::CONTENT_TYPE;
+use hyper::server::conn::http1;
+use hyper::service::service_fn;
+use hyper::{Method, Request, Response, StatusCode};
+use hyper_util::rt::TokioIo;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use tokio::runtime::Handle;
+use tokio::sync::oneshot::Receiver;
+use tracing::{debug, warn};
+
+use super::tls::{self, HANDSHAKE_TIMEOUT};
+use super::{App, Connector, TCPListenerConfig, TCPStream, TLSConfig, ACCEPT_ERROR_DELAY};
+use crate::app::acl::{Operation, Resource, ANONYMOUS};
+use crate::app::channel::{consumer_group_id, Channel, Record};
+use crate::app::health::LISTENERS;
+use crate::app::ChannelConfig;
+
+const MAX_BODY_SIZE: usize = 16 * 1024 * 1024;
+const DEFAULT_POLL_TIMEOUT: Duration = Duration::from_millis(1000);
+const MAX_POLL_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Time records are collected for once the first one arrives during a long-poll.
+const POLL_LINGER: Duration = Duration::from_millis(10);
+
+/// Serves produce, consume and topic administration over HTTP, for tools that can't speak the
+/// flow protocol.
+///
+/// | Method | Path | |
+/// |---|---|---|
+/// | `GET` | `/topics` | Lists topics. |
+/// | `POST` | `/topics` | Creates a topic from `{"name": ..., "partitions": ...}`. |
+/// | `POST` | `/topics/{topic}/records` | Produces records. |
+/// | `GET` | `/topics/{topic}/groups/{group}/records` | Long-polls records for a consumer group. |
+/// | `GET` | `/topics/{topic}/groups/{group}/offsets` | Returns the group offsets. |
+/// | `POST` | `/topics/{topic}/groups/{group}/offsets` | Commits `{"offset": ...}` for the group. |
+///
+/// Records are produced either as a JSON body, `{"records": [{"key": "...", "value": ...}]}`, or
+/// as a raw body holding a single record value with its key in the `key` query parameter. JSON
+/// values are stored as their serialized bytes, and `value_base64` can be used instead of `value`
+/// for binary data.
+///
+/// Consumed records are returned as `{"records": [{"offset": ..., "key": ..., "value": ...}]}`.
+/// Values that aren't JSON, or all values when `format=binary` is given, are returned base64
+/// encoded as `value_base64`. The `timeout_ms` query parameter bounds how long the request waits
+/// for records, an empty list is returned when none arrived.
+pub struct HTTPConnector {
+    config: TCPListenerConfig,
+}
+
+impl HTTPConnector {
+    pub fn new(config: TCPListenerConfig) -> HTTPConnector {
+        HTTPConnector { config }
+    }
+}
+
+#[async_trait]
+impl Connector for HTTPConnector {
+    async fn run(&mut self, app: &mut App, handle: Handle, mut signal: &mut Fuse<Receiver<bool>>) -> Result<(), Error> {
+        debug!("Running HTTPConnector");
+
+        let acceptor = self.config.tls.as_ref().map(TLSConfig::acceptor).transpose()?;
+        let listener = self.config.bind()?;
+        app.health().pass(LISTENERS);
+
+        loop {
+            let accept_fuse = listener.accept().fuse();
+            tokio::pin!(accept_fuse);
+
+            let res = select! {
+                _ = signal => return Ok(()),
+                conn = accept_fuse => conn,
+            };
+
+            let (stream, addr) = match res {
+                Ok(conn) => conn,
+                Err(e) => {
+                    warn!("Failed to accept connection: {}", e);
+                    tokio::time::sleep(ACCEPT_ERROR_DELAY).await;
+                    continue;
+                }
+            };
+
+            if let Err(e) = self.config.configure(&stream) {
+                warn!("Failed to configure connection from {}: {}", addr, e);
+            }
+
+            let app = app.clone();
+            let acceptor = acceptor.clone();
+            let connection = app.metrics().connection_opened("http");
+
+            handle.spawn(async move {
+                let _connection = connection;
+                let stream = match acceptor {
+                    Some(acceptor) => match tls::handshake(acceptor, stream, HANDSHAKE_TIMEOUT).await {
+                        Ok(stream) => TCPStream::Tls(Box::new(stream)),
+                        Err(e) => {
+                            warn!("TLS handshake with {} failed: {}", addr, e);
+                            return;
+                        }
+                    },
+                    None => TCPStream::Plain(stream),
+                };
+
+                let service = service_fn(move |request| handle_request(app.clone(), request));
+                if let Err(e) = http1::Builder::new()
+                    .serve_connection(TokioIo::new(stream), service)
+                    .await
+                {
+                    debug!("HTTP connection from {} failed: {}", addr, e);
+                }
+            });
+        }
+    }
+}
+
+#[derive(Serialize)]
+struct TopicsResponse {
+    topics: Vec<TopicResponse>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct TopicResponse {
+    name: String,
+    partitions: u16,
+}
+
+#[derive(Deserialize)]
+struct CreateTopicRequest {
+    name: String,
+
+    #[serde(default = "default_partitions")]
+    partitions: u16,
+}
+
+fn default_partitions() -> u16 {
+    1
+}
+
+#[derive(Deserialize)]
+struct ProduceRequest {
+    records: Vec<ProduceRecord>,
+}
+
+#[derive(Deserialize)]
+struct ProduceRecord {
+    key: Option<String>,
+    value: Option<Value>,
+    value_base64: Option<String>,
+}
+
+#[derive(Serialize)]
+struct ProduceResponse {
+    count: usize,
+}
+
+#[derive(Serialize)]
+struct ConsumeResponse {
+    records: Vec<ConsumedRecord>,
+}
+
+#[derive(Serialize)]
+struct ConsumedRecord {
+    offset: u64,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    key: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    key_base64: Option<String>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    value: Option<Value>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    value_base64: Option<String>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct CommitRequest {
+    offset: u64,
+}
+
+#[derive(Serialize)]
+struct OffsetsResponse {
+    committed: Option<u64>,
+    position: u64,
+}
+
+#[derive(Serialize)]
+struct ErrorResponse {
+    error: String,
+}
+
+/// Error answered to a request, with the status code to send.
+struct HTTPError(StatusCode, String);
+
+type HTTPResult = Result<Response<Full<Bytes>>, HTTPError>;
+
+async fn handle_request(app: App, request: Request<Incoming>) -> Result<Response<Full<Bytes>>, Infallible> {
+    let path = request.uri().path().to_string();
+    let segments = path
+        .trim_matches('/')
+        .split('/')
+        .map(percent_decode)
+        .collect::<Vec<_>>();
+    let segments = segments.iter().map(String::as_str).collect::<Vec<_>>();
+
+    debug!("HTTP {} {}", request.method(), path);
+
+    let result = match (request.method().clone(), segments.as_slice()) {
+        (Method::GET, ["topics"]) => list_topics(&app).await,
+        (Method::POST, ["topics"]) => create_topic(&app, request).await,
+        (Method::POST, ["topics", topic, "records"]) => produce(&app, topic, request).await,
+        (Method::GET, ["topics", topic, "groups", group, "records"]) => consume(&app, topic, group, request).await,
+        (Method::GET, ["topics", topic, "groups", group, "offsets"]) => offsets(&app, topic, group).await,
+        (Method::POST, ["topics", topic, "groups", group, "offsets"]) => commit(&app, topic, group, request).await,
+        _ => Err(HTTPError(StatusCode::NOT_FOUND, format!("no route for {}", path))),
+    };
+
+    Ok(result.unwrap_or_else(|HTTPError(status, error)| json_response(status, &ErrorResponse { error })))
+}
+
+async fn list_topics(app: &App) -> HTTPResult {
+    let topics = app
+        .list_channels()
+        .await
+        .into_iter()
+        .filter(|channel| app.authorize(ANONYMOUS, &Resource::topic(&channel.name), Operation::Describe))
+        .map(|channel| TopicResponse {
+            name: channel.name,
+            partitions: channel.partitions,
+        })
+        .collect();
+
+    Ok(json_response(StatusCode::OK, &TopicsResponse { topics }))
+}
+
+async fn create_topic(app: &App, request: Request<Incoming>) -> HTTPResult {
+    let create: CreateTopicRequest = read_json(request).await?;
+    authorize(app, Resource::topic(&create.name), Operation::Create)?;
+
+    if create.name.is_empty() || create.partitions == 0 {
+        return Err(bad_request("topics need a name and at least one partition"));
+    }
+
+    let config = ChannelConfig {
+        name: create.name.clone(),
+        partitions: create.partitions,
+    };
+
+    match app.create_channel(config).await {
+        Ok(_) => Ok(json_response(
+            StatusCode::CREATED,
+            &TopicResponse {
+                name: create.name,
+                partitions: create.partitions,
+            },
+        )),
+        Err(_) => Err(HTTPError(
+            StatusCode::CONFLICT,
+            format!("topic {} already exists", create.name),
+        )),
+    }
+}
+
+async fn produce(app: &App, topic: &str, request: Request<Incoming>) -> HTTPResult {
+    authorize(app, Resource::topic(topic), Operation::Produce)?;
+    let channel = get_channel(app, topic).await?;
+
+    let mut records = if is_json(&request) {
+        let produce: ProduceRequest = read_json(request).await?;
+        produce
+            .records
+            .into_iter()
+            .map(ProduceRecord::into_record)
+            .collect::<Result<Vec<_>, _>>()?
+    } else {
+        let key = query(&request).remove("key").unwrap_or_default();
+        vec![Record::new(key, read_body(request).await?.to_vec())]
+    };
+
+    let count = records.len();
+    channel.producer().produce(&mut records).await;
+
+    Ok(json_response(StatusCode::OK, &ProduceResponse { count }))
+}
+
+async fn consume(app: &App, topic: &str, group: &str, request: Request<Incoming>) -> HTTPResult {
+    authorize(app, Resource::topic(topic), Operation::Consume)?;
+    authorize(app, Resource::consumer_group(group), Operation::Consume)?;
+    let channel = get_channel(app, topic).await?;
+
+    let query = query(&request);
+    let timeout = match query.get("timeout_ms") {
+        Some(timeout) => Duration::from_millis(
+            timeout
+                .parse()
+                .map_err(|_| bad_request("timeout_ms must be a number"))?,
+        ),
+        None => DEFAULT_POLL_TIMEOUT,
+    }
+    .min(MAX_POLL_TIMEOUT);
+    let binary = query.get("format").map(String::as_str) == Some("binary");
+
+    // The consumer hands records over only once its own timeout elapsed, which is shorter than the
+    // outer one. Records it collected are thus always returned rather than dropped by the timeout.
+    let consumer = channel.group_consumer(group).with_timeout(POLL_LINGER.min(timeout));
+    let records = tokio::time::timeout(timeout, consumer.consume())
+        .await
+        .unwrap_or_default();
+
+    let records = records
+        .into_iter()
+        .map(|record| ConsumedRecord::new(record, binary))
+        .collect();
+
+    Ok(json_response(StatusCode::OK, &ConsumeResponse { records }))
+}
+
+async fn offsets(app: &App, topic: &str, group: &str) -> HTTPResult {
+    authorize(app, Resource::topic(topic), Operation::Describe)?;
+    authorize(app, Resource::consumer_group(group), Operation::Describe)?;
+    let channel = get_channel(app, topic).await?;
+
+    let (committed, position) = channel.offsets(consumer_group_id(group)).await;
+    Ok(json_response(StatusCode::OK, &OffsetsResponse { committed, position }))
+}
+
+async fn commit(app: &App, topic: &str, group: &str, request: Request<Incoming>) -> HTTPResult {
+    authorize(app, Resource::topic(topic), Operation::Consume)?;
+    authorize(app, Resource::consumer_group(group), Operation::Consume)?;
+    let channel = get_channel(app, topic).await?;
+
+    let commit: CommitRequest = read_json(request).await?;
+    channel
+        .commit_offset(channel.register_group(group), commit.offset)
+        .await
+        .map_err(|_| bad_request(&format!("offset {} is past the end of {}", commit.offset, topic)))?;
+
+    Ok(Response::builder()
+        .status(StatusCode::NO_CONTENT)
+        .body(Full::new(Bytes::new()))
+        .unwrap())
+}
+
+impl ProduceRecord {
+    fn into_record(self) -> Result<Record, HTTPError> {
+        let value = match (self.value, self.value_base64) {
+            (Some(value), None) => value.to_string().into_bytes(),
+            (None, Some(value)) => BASE64
+                .decode(value)
+                .map_err(|e| bad_request(&format!("invalid value_base64: {}", e)))?,
+            _ => return Err(bad_request("records need exactly one of value or value_base64")),
+        };
+
+        Ok(Record::new(self.key.unwrap_or_default(), value))
+    }
+}
+
+impl ConsumedRecord {
+    fn new(record: Record, binary: bool) -> ConsumedRecord {
+        let (key, key_base64) = match String::from_utf8(record.key) {
+            Ok(key) if key.is_empty() => (None, None),
+            Ok(key) => (Some(key), None),
+            Err(e) => (None, Some(BASE64.encode(e.into_bytes()))),
+        };
+
+        let value = if binary {
+            None
+        } else {
+            serde_json::from_slice(&record.value).ok()
+        };
+        let value_base64 = match value {
+            Some(_) => None,
+            None => Some(BASE64.encode(&record.value)),
+        };
+
+        ConsumedRecord {
+            offset: record.offset,
+            key,
+            key_base64,
+            value,
+            value_base64,
+        }
+    }
+}
+
+fn authorize(app: &App, resource: Resource, operation: Operation) -> Result<(), HTTPError> {
+    if app.authorize(ANONYMOUS, &resource, operation) {
+        return Ok(());
+    }
+
+    Err(HTTPError(
+        StatusCode::FORBIDDEN,
+        format!("not allowed to {} {}", operation, resource.name),
+    ))
+}
+
+async fn get_channel(app: &App, topic: &str) -> Result<Channel, HTTPError> {
+    app.get_channel(&(topic.to_string(), 1))
+        .await
+        .ok_or_else(|| HTTPError(StatusCode::NOT_FOUND, format!("unknown topic {}", topic)))
+}
+
+fn is_json(request: &Request<Incoming>) -> bool {
+    request
+        .headers()
+        .get(CONTENT_TYPE)
+        .and_then(|content_type| content_type.to_str().ok())
+        .is_some_and(|content_type| content_type.starts_with("application/json"))
+}
+
+async fn read_body(request: Request<Incoming>) -> Result<Bytes, HTTPError> {
+    Limited::new(request.into_body(), MAX_BODY_SIZE)
+        .collect()
+        .await
+        .map(|body| body.to_bytes())
+        .map_err(|e| bad_request(&format!("failed to read body: {}", e)))
+}
+
+async fn read_json<T: for<'de> Deserialize<'de>>(request: Request<Incoming>) -> Result<T, HTTPError> {
+    let body = read_body(request).await?;
+    serde_json::from_slice(&body).map_err(|e| bad_request(&format!("invalid JSON body: {}", e)))
+}
+
+fn query(request: &Request<Incoming>) -> HashMap<String, String> {
+    request
+        .uri()
+        .query()
+        .unwrap_or_default()
+        .split('&')
+        .filter(|pair| !pair.is_empty())
+        .map(|pair| match pair.split_once('=') {
+            Some((name, value)) => (percent_decode(name), percent_decode(value)),
+            None => (percent_decode(pair), String::new()),
+        })
+        .collect()
+}
+
+fn percent_decode(value: &str) -> String {
+    let bytes = value.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+
+    let mut i = 0;
+    while i < bytes.len() {
+        let hex = bytes.get(i + 1..i + 3).and_then(|hex| std::str::from_utf8(hex).ok());
+        match (bytes[i], hex.and_then(|hex| u8::from_str_radix(hex, 16).ok())) {
+            (b'%', Some(byte)) => {
+                decoded.push(byte);
+                i += 3;
+            }
+            (b'+', _) => {
+                decoded.push(b' ');
+                i += 1;
+            }
+            (byte, _) => {
+                decoded.push(byte);
+                i += 1;
+            }
+        }
+    }
+
+    String::from_utf8_lossy(&decoded).into_owned()
+}
+
+fn json_response<T: Serialize>(status: StatusCode, body: &T) -> Response<Full<Bytes>> {
+    Response::builder()
+        .status(status)
+        .header(CONTENT_TYPE, "application/json")
+        .body(Full::new(Bytes::from(serde_json::to_vec(body).unwrap())))
+        .unwrap()
+}
+
+fn bad_request(message: &str) -> HTTPError {
+    HTTPError(StatusCode::BAD_REQUEST, message.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::SocketAddr;
+
+    use futures::FutureExt;
+    use serde_json::{json, Value};
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::TcpStream;
+
+    use super::*;
+    use crate::app::acl::Authorizer;
+
+    async fn start() -> (SocketAddr, tokio::sync::oneshot::Sender<bool>) {
+        start_with(App::new()).await
+    }
+
+    async fn start_with(mut app: App) -> (SocketAddr, tokio::sync::oneshot::Sender<bool>) {
+        let port = std::net::TcpListener::bind("127.0.0.1:0")
+            .unwrap()
+            .local_addr()
+            .unwrap()
+            .port();
+        let config = TCPListenerConfig {
+            port,
+            ..Default::default()
+        };
+        let address = config.socket_addr();
+
+        let mut connector = HTTPConnector::new(config);
+        let (tx, rx) = tokio::sync::oneshot::channel();
+
+        tokio::spawn(async move {
+            let _ = connector.run(&mut app, Handle::current(), &mut rx.fuse()).await;
+        });
+
+        while TcpStream::connect(address).await.is_err() {
+            tokio::task::yield_now().await;
+        }
+
+        (address, tx)
+    }
+
+    async fn request(address: SocketAddr, method: &str, path: &str, content_type: &str, body: &[u8]) -> (u16, Value) {
+        let mut stream = TcpStream::connect(address).await.unwrap();
+        let head = format!(
+            "{} {} HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\nContent-Type: {}\r\nContent-Length: {}\r\n\r\n",
+            method,
+            path,
+            content_type,
+            body.len()
+        );
+        stream.write_all(head.as_bytes()).await.unwrap();
+        stream.write_all(body).await.unwrap();
+
+        let mut response = String::new();
+        stream.read_to_string(&mut response).await.unwrap();
+
+        let status = response[9..12].parse().unwrap();
+        let body = response.split_once("\r\n\r\n").unwrap().1;
+
+        (status, serde_json::from_str(body).unwrap_or(Value::Null))
+    }
+
+    async fn post_json(address: SocketAddr, path: &str, body: Value) -> (u16, Value) {
+        request(address, "POST", path, "application/json", body.to_string().as_bytes()).await
+    }
+
+    async fn get(address: SocketAddr, path: &str) -> (u16, Value) {
+        request(address, "GET", path, "application/json", b"").await
+    }
+
+    #[tokio::test]
+    async fn test_create_and_list_topics() {
+        let (address, _shutdown) = start().await;
+
+        let (status, _) = post_json(address, "/topics", json!({"name": "orders", "partitions": 2})).await;
+        assert_eq!(201, status);
+
+        let (status, _) = post_json(address, "/topics", json!({"name": "orders"})).await;
+        assert_eq!(409, status);
+
+        let (status, body) = get(address, "/topics").await;
+        assert_eq!(200, status);
+        assert_eq!(json!({"topics": [{"name": "orders", "partitions": 2}]}), body);
+    }
+
+    #[tokio::test]
+    async fn test_produce_and_consume_records() {
+        let (address, _shutdown) = start().await;
+        post_json(address, "/topics", json!({"name": "orders"})).await;
+
+        let records = json!({"records": [{"key": "a", "value": {"id": 1}}, {"value_base64": "AAE="}]});
+        let (status, body) = post_json(address, "/topics/orders/records", records).await;
+        assert_eq!(200, status);
+        assert_eq!(json!({"count": 2}), body);
+
+        let (status, _) = request(
+            address,
+            "POST",
+            "/topics/orders/records?key=raw%20key",
+            "text/plain",
+            b"hi",
+        )
+        .await;
+        assert_eq!(200, status);
+
+        let (status, body) = get(address, "/topics/orders/groups/billing/records?timeout_ms=500").await;
+        assert_eq!(200, status);
+        assert_eq!(
+            json!({"records": [
+                {"offset": 0, "key": "a", "value": {"id": 1}},
+                {"offset": 1, "value_base64": "AAE="},
+                {"offset": 2, "key": "raw key", "value_base64": "aGk="},
+            ]}),
+            body
+        );
+
+        let (status, body) = get(address, "/topics/orders/groups/billing/records?timeout_ms=50").await;
+        assert_eq!(200, status);
+        assert_eq!(json!({"records": []}), body);
+    }
+
+    #[tokio::test]
+    async fn test_long_poll_returns_records_produced_while_waiting() {
+        let (address, _shutdown) = start().await;
+        post_json(address, "/topics", json!({"name": "orders"})).await;
+
+        let poll = tokio::spawn(get(address, "/topics/orders/groups/billing/records?timeout_ms=5000"));
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        post_json(address, "/topics/orders/records", json!({"records": [{"value": 1}]})).await;
+
+        let (status, body) = tokio::time::timeout(Duration::from_secs(2), poll)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(200, status);
+        assert_eq!(json!({"records": [{"offset": 0, "value": 1}]}), body);
+    }
+
+    #[tokio::test]
+    async fn test_commit_offsets() {
+        let (address, _shutdown) = start().await;
+        post_json(address, "/topics", json!({"name": "orders"})).await;
+        post_json(address, "/topics/orders/records", json!({"records": [{"value": 1}]})).await;
+
+        let (status, _) = post_json(address, "/topics/orders/groups/billing/offsets", json!({"offset": 1})).await;
+        assert_eq!(204, status);
+
+        let (status, _) = post_json(address, "/topics/orders/groups/billing/offsets", json!({"offset": 5})).await;
+        assert_eq!(400, status);
+
+        let (status, body) = get(address, "/topics/orders/groups/billing/offsets").await;
+        assert_eq!(200, status);
+        assert_eq!(json!({"committed": 1, "position": 0}), body);
+    }
+
+    #[tokio::test]
+    async fn test_topics_are_described_only_when_allowed() {
+        let authorizer = Authorizer::new();
+        for rule in [
+            "allow * topic:* create",
+            "allow * topic:orders describe",
+            "allow * group:billing describe",
+        ] {
+            authorizer.add_rule(rule.parse().unwrap()).unwrap();
+        }
+        let (address, _shutdown) = start_with(App::with_authorizer(authorizer)).await;
+
+        for name in ["orders", "payments"] {
+            let (status, _) = post_json(address, "/topics", json!({ "name": name })).await;
+            assert_eq!(201, status);
+        }
+
+        let (status, body) = get(address, "/topics").await;
+        assert_eq!(200, status);
+        assert_eq!(json!({"topics": [{"name": "orders", "partitions": 1}]}), body);
+
+        let (status, _) = get(address, "/topics/orders/groups/billing/offsets").await;
+        assert_eq!(200, status);
+
+        let (status, body) = get(address, "/topics/payments/groups/billing/offsets").await;
+        assert_eq!(403, status);
+        assert_eq!(json!({"error": "not allowed to describe payments"}), body);
+    }
+
+    #[tokio::test]
+    async fn test_unknown_topic_and_route() {
+        let (address, _shutdown) = start().await;
+
+        let (status, body) = post_json(address, "/topics/missing/records", json!({"records": []})).await;
+        assert_eq!(404, status);
+        assert_eq!(json!({"error": "unknown topic missing"}), body);
+
+        let (status, _) = get(address, "/nothing").await;
+        assert_eq!(404, status);
+    }
+
+    #[test]
+    fn test_percent_decode() {
+        assert_eq!("raw key", percent_decode("raw%20key"));
+        assert_eq!("a b", percent_decode("a+b"));
+        assert_eq!("100%", percent_decode("100%"));
+    }
+}
@@ -0,0 +1,226 @@
+use std::convert::Infallible;
+use std::io::Error;
+
+use async_trait::async_trait;
+use futures::{future::Fuse, select, FutureExt};
+use http_body_util::Full;
+use hyper::body::{Bytes, Incoming};
+use hyper::header::CONTENT_TYPE;
+use hyper::server::conn::http1;
+use hyper::service::service_fn;
+use hyper::{Method, Request, Response, StatusCode};
+use hyper_util::rt::TokioIo;
+use tokio::runtime::Handle;
+use tokio::sync::oneshot::Receiver;
+use tracing::{debug, warn};
+
+use super::tls::{self, HANDSHAKE_TIMEOUT};
+use super::{App, Connector, TCPListenerConfig, TCPStream, TLSConfig, ACCEPT_ERROR_DELAY};
+use crate::app::health::runtime_responsive;
+
+/// Content type of the Prometheus text exposition format.
+const METRICS_CONTENT_TYPE: &str = "text/plain; version=0.0.4; charset=utf-8";
+
+/// Serves the metrics of the app on `GET /metrics`, in the Prometheus text format, see
+/// [`crate::app::metrics`], along with its health probes.
+///
+/// `GET /health/live` answers `200` while the runtime runs tasks. `GET /health/ready` answers `200`
+/// once every readiness check passed, `503` with the pending ones otherwise, see
+/// [`crate::app::health`].
+pub struct MetricsConnector {
+    config: TCPListenerConfig,
+}
+
+impl MetricsConnector {
+    pub fn new(config: TCPListenerConfig) -> MetricsConnector {
+        MetricsConnector { config }
+    }
+}
+
+#[async_trait]
+impl Connector for MetricsConnector {
+    async fn run(&mut self, app: &mut App, handle: Handle, mut signal: &mut Fuse<Receiver<bool>>) -> Result<(), Error> {
+        debug!("Running MetricsConnector");
+
+        let acceptor = self.config.tls.as_ref().map(TLSConfig::acceptor).transpose()?;
+        let listener = self.config.bind()?;
+
+        loop {
+            let accept_fuse = listener.accept().fuse();
+            tokio::pin!(accept_fuse);
+
+            let res = select! {
+                _ = signal => return Ok(()),
+                conn = accept_fuse => conn,
+            };
+
+            let (stream, addr) = match res {
+                Ok(conn) => conn,
+                Err(e) => {
+                    warn!("Failed to accept connection: {}", e);
+                    tokio::time::sleep(ACCEPT_ERROR_DELAY).await;
+                    continue;
+                }
+            };
+
+            let app = app.clone();
+            let acceptor = acceptor.clone();
+
+            handle.spawn(async move {
+                let stream = match acceptor {
+                    Some(acceptor) => match tls::handshake(acceptor, stream, HANDSHAKE_TIMEOUT).await {
+                        Ok(stream) => TCPStream::Tls(Box::new(stream)),
+                        Err(e) => {
+                            warn!("TLS handshake with {} failed: {}", addr, e);
+                            return;
+                        }
+                    },
+                    None => TCPStream::Plain(stream),
+                };
+
+                let service = service_fn(move |request| handle_request(app.clone(), request));
+                if let Err(e) = http1::Builder::new()
+                    .serve_connection(TokioIo::new(stream), service)
+                    .await
+                {
+                    debug!("Metrics connection from {} failed: {}", addr, e);
+                }
+            });
+        }
+    }
+}
+
+async fn handle_request(app: App, request: Request<Incoming>) -> Result<Response<Full<Bytes>>, Infallible> {
+    let response = match (request.method(), request.uri().path()) {
+        (&Method::GET, "/metrics") => Response::builder()
+            .status(StatusCode::OK)
+            .header(CONTENT_TYPE, METRICS_CONTENT_TYPE)
+            .body(Full::new(Bytes::from(app.export_metrics().await))),
+        (&Method::GET, "/health/live") => match runtime_responsive().await {
+            true => text_response(StatusCode::OK, "live\n".to_string()),
+            false => text_response(StatusCode::SERVICE_UNAVAILABLE, "runtime unresponsive\n".to_string()),
+        },
+        (&Method::GET, "/health/ready") => {
+            let pending = app.pending_checks();
+            match pending.is_empty() {
+                true => text_response(StatusCode::OK, "ready\n".to_string()),
+                false => text_response(
+                    StatusCode::SERVICE_UNAVAILABLE,
+                    format!("not ready: {}\n", pending.join(", ")),
+                ),
+            }
+        }
+        _ => Response::builder()
+            .status(StatusCode::NOT_FOUND)
+            .body(Full::new(Bytes::from_static(b"not found\n"))),
+    };
+
+    Ok(response.unwrap())
+}
+
+fn text_response(status: StatusCode, body: String) -> Result<Response<Full<Bytes>>, hyper::http::Error> {
+    Response::builder()
+        .status(status)
+        .header(CONTENT_TYPE, "text/plain; charset=utf-8")
+        .body(Full::new(Bytes::from(body)))
+}
+
+#[cfg(test)]
+mod tests {
+    use futures::FutureExt;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::TcpStream;
+
+    use crate::app::health::STORAGE;
+    use crate::app::ChannelConfig;
+
+    use super::*;
+
+    async fn get(address: std::net::SocketAddr, path: &str) -> String {
+        let mut stream = TcpStream::connect(address).await.unwrap();
+        let head = format!("GET {} HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\r\n", path);
+        stream.write_all(head.as_bytes()).await.unwrap();
+
+        let mut response = String::new();
+        stream.read_to_string(&mut response).await.unwrap();
+        response
+    }
+
+    #[tokio::test]
+    async fn test_serves_metrics() {
+        let port = std::net::TcpListener::bind("127.0.0.1:0")
+            .unwrap()
+            .local_addr()
+            .unwrap()
+            .port();
+        let config = TCPListenerConfig {
+            port,
+            ..Default::default()
+        };
+        let address = config.socket_addr();
+
+        let mut app = App::new();
+        app.create_channel(ChannelConfig {
+            name: "orders".to_string(),
+            partitions: 1,
+        })
+        .await
+        .unwrap();
+
+        let mut connector = MetricsConnector::new(config);
+        let (_tx, rx) = tokio::sync::oneshot::channel();
+        tokio::spawn(async move {
+            let _ = connector.run(&mut app, Handle::current(), &mut rx.fuse()).await;
+        });
+
+        while TcpStream::connect(address).await.is_err() {
+            tokio::task::yield_now().await;
+        }
+
+        let response = get(address, "/metrics").await;
+        assert!(response.starts_with("HTTP/1.1 200"));
+        assert!(response.contains(METRICS_CONTENT_TYPE));
+        assert!(response.contains("packline_records_produced_total{channel=\"orders\",partition=\"1\"} 0\n"));
+
+        assert!(get(address, "/topics").await.starts_with("HTTP/1.1 404"));
+    }
+
+    #[tokio::test]
+    async fn test_serves_health_probes() {
+        let port = std::net::TcpListener::bind("127.0.0.1:0")
+            .unwrap()
+            .local_addr()
+            .unwrap()
+            .port();
+        let config = TCPListenerConfig {
+            port,
+            ..Default::default()
+        };
+        let address = config.socket_addr();
+
+        let app = App::new();
+        app.health().require(STORAGE);
+
+        let mut connector = MetricsConnector::new(config);
+        let (_tx, rx) = tokio::sync::oneshot::channel();
+        let mut connector_app = app.clone();
+        tokio::spawn(async move {
+            let _ = connector
+                .run(&mut connector_app, Handle::current(), &mut rx.fuse())
+                .await;
+        });
+
+        while TcpStream::connect(address).await.is_err() {
+            tokio::task::yield_now().await;
+        }
+
+        assert!(get(address, "/health/live").await.starts_with("HTTP/1.1 200"));
+
+        let response = get(address, "/health/ready").await;
+        assert!(response.starts_with("HTTP/1.1 503"));
+        assert!(response.ends_with("not ready: storage\n"));
+
+        app.health().pass(STORAGE);
+        assert!(get(address, "/health/ready").await.starts_with("HTTP/1.1 200"));
+    }
+}
@@ -1,14 +1,26 @@
+use std::io::Error;
+
 use async_trait::async_trait;
 use futures::future::Fuse;
+pub use http::HTTPConnector;
+pub use metrics::MetricsConnector;
 pub use tcp::*;
+pub use tls::TLSConfig;
 use tokio::runtime::Handle;
 use tokio::sync::oneshot::Receiver;
+#[cfg(unix)]
+pub use unix::*;
 
 use crate::app::App;
 
+pub mod http;
+pub mod metrics;
 pub mod tcp;
+pub mod tls;
+#[cfg(unix)]
+pub mod unix;
 
 #[async_trait]
 pub trait Connector: Send {
-    async fn run(&mut self, app: &mut App, handle: Handle, mut signal: &mut Fuse<Receiver<bool>>);
+    async fn run(&mut self, app: &mut App, handle: Handle, mut signal: &mut Fuse<Receiver<bool>>) -> Result<(), Error>;
 }
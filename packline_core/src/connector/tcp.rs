@@ -1,18 +1,39 @@
 use std::io::Error;
 use std::net::{IpAddr, Ipv4Addr, SocketAddr};
+use std::pin::Pin;
+use std::sync::Arc;
+use std::task::{Context, Poll};
+use std::time::Duration;
 
 use async_trait::async_trait;
+use futures::future::select_all;
 use futures::{future::Fuse, select, FutureExt};
-use tokio::net::{TcpListener, TcpStream};
+use socket2::{SockRef, TcpKeepalive};
+use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
+use tokio::net::{TcpListener, TcpSocket, TcpStream};
 use tokio::runtime::Handle;
 use tokio::sync::oneshot::Receiver;
-use tracing::debug;
+use tokio::task::JoinSet;
+use tokio_rustls::server::TlsStream;
+use tokio_rustls::TlsAcceptor;
+use tracing::{debug, info, warn};
 
-use super::{App, Connector};
+use super::tls::{self, HANDSHAKE_TIMEOUT};
+use super::{App, Connector, TLSConfig};
+use crate::app::health::LISTENERS;
+
+/// Pause after a failed accept, so that running out of file descriptors doesn't turn the accept
+/// loop into a busy loop.
+pub(crate) const ACCEPT_ERROR_DELAY: Duration = Duration::from_millis(100);
 
 #[async_trait]
-pub trait TCPConnectorHandler: Send {
-    fn handle_connection(&self, conn: (TcpStream, SocketAddr)) -> Box<dyn TCPConnectionHandler>;
+pub trait TCPConnectorHandler: Send + Sync {
+    fn handle_connection(&self, conn: (TCPStream, SocketAddr)) -> Box<dyn TCPConnectionHandler>;
+
+    /// Protocol served, used to label the connection metrics.
+    fn name(&self) -> &'static str {
+        "tcp"
+    }
 }
 
 #[async_trait]
@@ -20,43 +41,389 @@ pub trait TCPConnectionHandler: Send {
     async fn handle(&mut self) -> Result<(), Error>;
 }
 
+/// Connection accepted by a [`TCPConnector`], either plaintext or wrapped in TLS when the listener
+/// has a [`TLSConfig`].
+#[derive(Debug)]
+pub enum TCPStream {
+    Plain(TcpStream),
+    Tls(Box<TlsStream<TcpStream>>),
+}
+
+impl TCPStream {
+    pub fn local_addr(&self) -> Result<SocketAddr, Error> {
+        match self {
+            TCPStream::Plain(stream) => stream.local_addr(),
+            TCPStream::Tls(stream) => stream.get_ref().0.local_addr(),
+        }
+    }
+}
+
+impl AsyncRead for TCPStream {
+    fn poll_read(self: Pin<&mut Self>, cx: &mut Context<'_>, buf: &mut ReadBuf<'_>) -> Poll<Result<(), Error>> {
+        match self.get_mut() {
+            TCPStream::Plain(stream) => Pin::new(stream).poll_read(cx, buf),
+            TCPStream::Tls(stream) => Pin::new(stream).poll_read(cx, buf),
+        }
+    }
+}
+
+impl AsyncWrite for TCPStream {
+    fn poll_write(self: Pin<&mut Self>, cx: &mut Context<'_>, buf: &[u8]) -> Poll<Result<usize, Error>> {
+        match self.get_mut() {
+            TCPStream::Plain(stream) => Pin::new(stream).poll_write(cx, buf),
+            TCPStream::Tls(stream) => Pin::new(stream).poll_write(cx, buf),
+        }
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Error>> {
+        match self.get_mut() {
+            TCPStream::Plain(stream) => Pin::new(stream).poll_flush(cx),
+            TCPStream::Tls(stream) => Pin::new(stream).poll_flush(cx),
+        }
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Error>> {
+        match self.get_mut() {
+            TCPStream::Plain(stream) => Pin::new(stream).poll_shutdown(cx),
+            TCPStream::Tls(stream) => Pin::new(stream).poll_shutdown(cx),
+        }
+    }
+}
+
+/// Address and socket options of a single TCP listener.
+#[derive(Clone, Debug)]
+pub struct TCPListenerConfig {
+    pub address: IpAddr,
+    pub port: u16,
+
+    /// Accept only IPv6 connections when bound to an IPv6 address, instead of dual-stack.
+    pub ipv6_only: bool,
+    pub nodelay: bool,
+
+    /// Idle time before keepalive probes are sent on accepted connections, disabled when `None`.
+    pub keepalive: Option<Duration>,
+    pub backlog: u32,
+
+    /// Serves connections over TLS when set.
+    pub tls: Option<TLSConfig>,
+}
+
+impl TCPListenerConfig {
+    pub fn socket_addr(&self) -> SocketAddr {
+        SocketAddr::new(self.address, self.port)
+    }
+
+    pub(crate) fn bind(&self) -> Result<TcpListener, Error> {
+        let address = self.socket_addr();
+
+        let socket = match address {
+            SocketAddr::V4(_) => TcpSocket::new_v4()?,
+            SocketAddr::V6(_) => {
+                let socket = TcpSocket::new_v6()?;
+                SockRef::from(&socket).set_only_v6(self.ipv6_only)?;
+                socket
+            }
+        };
+
+        socket.set_reuseaddr(true)?;
+        socket
+            .bind(address)
+            .map_err(|e| Error::new(e.kind(), format!("failed to bind {}: {}", address, e)))?;
+
+        socket.listen(self.backlog)
+    }
+
+    pub(crate) fn configure(&self, stream: &TcpStream) -> Result<(), Error> {
+        stream.set_nodelay(self.nodelay)?;
+
+        if let Some(keepalive) = self.keepalive {
+            SockRef::from(stream).set_tcp_keepalive(&TcpKeepalive::new().with_time(keepalive))?;
+        }
+
+        Ok(())
+    }
+}
+
+impl Default for TCPListenerConfig {
+    fn default() -> Self {
+        TCPListenerConfig {
+            address: IpAddr::V4(Ipv4Addr::new(127, 0, 0, 1)), // localhost:1883
+            port: 1883,
+            ipv6_only: false,
+            nodelay: true,
+            keepalive: None,
+            backlog: 1024,
+            tls: None,
+        }
+    }
+}
+
+struct TCPListener {
+    config: TCPListenerConfig,
+    handler: Arc<dyn TCPConnectorHandler>,
+
+    /// Socket bound ahead of [`TCPConnector::run`] by [`TCPConnector::bind`].
+    bound: Option<TcpListener>,
+}
+
+/// Accepts TCP connections on one or more listeners, each handing its connections to its own
+/// protocol handler.
+///
+/// When signaled, the connector stops accepting, tells the app it is shutting down so that live
+/// connections finish, and returns once they all did. Callers bound the wait by dropping the
+/// future, which aborts the remaining connections.
 pub struct TCPConnector {
-    handler: Box<dyn TCPConnectorHandler>,
+    listeners: Vec<TCPListener>,
 }
 
 impl TCPConnector {
     pub fn new(handler: Box<dyn TCPConnectorHandler>) -> TCPConnector {
-        TCPConnector { handler }
+        TCPConnector::with_config(TCPListenerConfig::default(), handler)
+    }
+
+    pub fn with_config(config: TCPListenerConfig, handler: Box<dyn TCPConnectorHandler>) -> TCPConnector {
+        TCPConnector {
+            listeners: vec![TCPListener {
+                config,
+                handler: Arc::from(handler),
+                bound: None,
+            }],
+        }
+    }
+
+    /// Adds another listener, accepting connections alongside the existing ones.
+    pub fn listener(mut self, config: TCPListenerConfig, handler: Box<dyn TCPConnectorHandler>) -> TCPConnector {
+        self.listeners.push(TCPListener {
+            config,
+            handler: Arc::from(handler),
+            bound: None,
+        });
+        self
+    }
+
+    /// Binds the listeners that aren't bound yet and returns the local address of every listener,
+    /// in the order they were added. Listeners on port `0` get an ephemeral port, reported here.
+    ///
+    /// [`Connector::run`] binds them itself when this wasn't called.
+    pub fn bind(&mut self) -> Result<Vec<SocketAddr>, Error> {
+        self.listeners
+            .iter_mut()
+            .map(|listener| {
+                let bound = match listener.bound.take() {
+                    Some(bound) => bound,
+                    None => listener.config.bind()?,
+                };
+                let address = bound.local_addr();
+                listener.bound = Some(bound);
+                address
+            })
+            .collect()
     }
 }
 
 #[async_trait]
 impl Connector for TCPConnector {
-    async fn run(&mut self, _: &mut App, handle: Handle, mut signal: &mut Fuse<Receiver<bool>>) {
+    async fn run(&mut self, app: &mut App, handle: Handle, mut signal: &mut Fuse<Receiver<bool>>) -> Result<(), Error> {
         debug!("Running TCPConnector");
 
-        let address = SocketAddr::new(IpAddr::V4(Ipv4Addr::new(127, 0, 0, 1)), 1883); // localhost:1883
-        let listener = TcpListener::bind(address).await.unwrap();
+        let acceptors = self
+            .listeners
+            .iter()
+            .map(|listener| listener.config.tls.as_ref().map(TLSConfig::acceptor).transpose())
+            .collect::<Result<Vec<Option<TlsAcceptor>>, Error>>()?;
+
+        self.bind()?;
+        let listeners = self
+            .listeners
+            .iter_mut()
+            .filter_map(|listener| listener.bound.take())
+            .collect::<Vec<TcpListener>>();
+        app.health().pass(LISTENERS);
+
+        let mut connections = JoinSet::new();
 
         loop {
-            let accept_fuse = listener.accept().fuse();
+            while connections.try_join_next().is_some() {}
+
+            let accept_fuse = select_all(listeners.iter().map(|listener| listener.accept().boxed())).fuse();
             tokio::pin!(accept_fuse);
 
-            let res: Result<(TcpStream, SocketAddr), Error> = select! {
-                _ = signal => return,
-                conn = accept_fuse => conn,
-                complete => break,
+            let (res, index): (Result<(TcpStream, SocketAddr), Error>, usize) = select! {
+                _ = signal => break,
+                (conn, index, _) = accept_fuse => (conn, index),
             };
 
-            if let Ok(conn) = res {
-                let mut conn_handler = self.handler.handle_connection(conn);
+            match res {
+                Ok(conn) => {
+                    let listener = &self.listeners[index];
+                    if let Err(e) = listener.config.configure(&conn.0) {
+                        warn!("Failed to configure connection from {}: {}", conn.1, e);
+                    }
+
+                    let handler = listener.handler.clone();
+                    let acceptor = acceptors[index].clone();
+                    let connection = app.metrics().connection_opened(handler.name());
+
+                    connections.spawn_on(
+                        async move {
+                            let _connection = connection;
+                            let (stream, addr) = conn;
+                            let stream = match acceptor {
+                                Some(acceptor) => match tls::handshake(acceptor, stream, HANDSHAKE_TIMEOUT).await {
+                                    Ok(stream) => TCPStream::Tls(Box::new(stream)),
+                                    Err(e) => {
+                                        warn!("TLS handshake with {} failed: {}", addr, e);
+                                        return;
+                                    }
+                                },
+                                None => TCPStream::Plain(stream),
+                            };
 
-                handle.spawn(async move {
-                    let _ = conn_handler.handle().await.map_err(|e| println!("{:#?}", e));
-                });
+                            let mut conn_handler = handler.handle_connection((stream, addr));
+                            let _ = conn_handler.handle().await.map_err(|e| println!("{:#?}", e));
+                        },
+                        &handle,
+                    );
+                }
+                Err(e) => {
+                    warn!("Failed to accept connection: {}", e);
+                    tokio::time::sleep(ACCEPT_ERROR_DELAY).await;
+                }
             }
 
             tokio::task::yield_now().await;
         }
+
+        drop(listeners);
+        app.shutdown();
+
+        info!("Waiting for {} connections to finish", connections.len());
+        while connections.join_next().await.is_some() {}
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use futures::FutureExt;
+
+    use super::*;
+
+    struct NoopHandler;
+
+    impl TCPConnectorHandler for NoopHandler {
+        fn handle_connection(&self, _: (TCPStream, SocketAddr)) -> Box<dyn TCPConnectionHandler> {
+            unreachable!()
+        }
+    }
+
+    /// Writes a farewell once the app shuts down, then closes the connection.
+    struct FarewellHandler {
+        app: App,
+    }
+
+    struct FarewellConnection {
+        app: App,
+        stream: TCPStream,
+    }
+
+    impl TCPConnectorHandler for FarewellHandler {
+        fn handle_connection(&self, conn: (TCPStream, SocketAddr)) -> Box<dyn TCPConnectionHandler> {
+            Box::new(FarewellConnection {
+                app: self.app.clone(),
+                stream: conn.0,
+            })
+        }
+    }
+
+    #[async_trait]
+    impl TCPConnectionHandler for FarewellConnection {
+        async fn handle(&mut self) -> Result<(), Error> {
+            use tokio::io::AsyncWriteExt;
+
+            self.app.shutting_down().await;
+            self.stream.write_all(b"bye").await?;
+            self.stream.shutdown().await
+        }
+    }
+
+    #[tokio::test]
+    async fn test_run_drains_connections_on_signal() {
+        use tokio::io::AsyncReadExt;
+
+        let port = std::net::TcpListener::bind("127.0.0.1:0")
+            .unwrap()
+            .local_addr()
+            .unwrap()
+            .port();
+        let config = TCPListenerConfig {
+            port,
+            ..Default::default()
+        };
+
+        let mut app = App::new();
+        let mut connector = TCPConnector::with_config(config.clone(), Box::new(FarewellHandler { app: app.clone() }));
+        let (tx, rx) = tokio::sync::oneshot::channel();
+
+        let run = tokio::spawn(async move {
+            connector
+                .run(&mut app, Handle::current(), &mut rx.fuse())
+                .await
+                .map(|_| app)
+        });
+
+        let mut stream = loop {
+            match TcpStream::connect(config.socket_addr()).await {
+                Ok(stream) => break stream,
+                Err(_) => tokio::time::sleep(Duration::from_millis(10)).await,
+            }
+        };
+
+        // Lets the connector hand the connection to its handler before being signaled.
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        tx.send(true).unwrap();
+
+        let mut farewell = vec![];
+        stream.read_to_end(&mut farewell).await.unwrap();
+        assert_eq!(b"bye".to_vec(), farewell);
+
+        let app = run.await.unwrap().unwrap();
+        assert!(app.is_shutting_down());
+        assert!(TcpStream::connect(config.socket_addr()).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_run_returns_bind_error() {
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let config = TCPListenerConfig {
+            port: listener.local_addr().unwrap().port(),
+            ..Default::default()
+        };
+
+        let mut connector = TCPConnector::with_config(config, Box::new(NoopHandler));
+        let (_tx, rx) = tokio::sync::oneshot::channel();
+
+        let result = connector.run(&mut App::new(), Handle::current(), &mut rx.fuse()).await;
+
+        assert!(result.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_bind_reports_ephemeral_ports() {
+        let config = TCPListenerConfig {
+            port: 0,
+            ..Default::default()
+        };
+
+        let mut connector =
+            TCPConnector::with_config(config.clone(), Box::new(NoopHandler)).listener(config, Box::new(NoopHandler));
+        let addresses = connector.bind().unwrap();
+
+        assert_eq!(2, addresses.len());
+        assert_ne!(0, addresses[0].port());
+        assert_ne!(addresses[0], addresses[1]);
+        assert_eq!(addresses, connector.bind().unwrap());
+        assert!(TcpStream::connect(addresses[0]).await.is_ok());
     }
 }
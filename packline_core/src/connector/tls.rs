@@ -0,0 +1,258 @@
+use std::io::{Error, ErrorKind};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Duration;
+
+use tokio::net::TcpStream;
+use tokio_rustls::rustls::crypto::ring::default_provider;
+use tokio_rustls::rustls::pki_types::pem::PemObject;
+use tokio_rustls::rustls::pki_types::{CertificateDer, PrivateKeyDer};
+use tokio_rustls::rustls::server::WebPkiClientVerifier;
+use tokio_rustls::rustls::{RootCertStore, ServerConfig};
+use tokio_rustls::server::TlsStream;
+use tokio_rustls::TlsAcceptor;
+
+/// Time a client has to complete the TLS handshake before its connection is dropped.
+pub const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Certificate files used by a TLS listener.
+#[derive(Clone, Debug)]
+pub struct TLSConfig {
+    /// PEM file with the certificate chain presented to clients.
+    pub cert_path: PathBuf,
+    pub key_path: PathBuf,
+
+    /// PEM file with the CAs trusted to sign client certificates. When set, clients must present
+    /// a certificate signed by one of them (mutual TLS).
+    pub client_ca_path: Option<PathBuf>,
+}
+
+impl TLSConfig {
+    pub fn acceptor(&self) -> Result<TlsAcceptor, Error> {
+        let provider = Arc::new(default_provider());
+
+        let certs = load_certs(&self.cert_path)?;
+        let key = PrivateKeyDer::from_pem_file(&self.key_path).map_err(|e| invalid_file(&self.key_path, e))?;
+
+        let builder = ServerConfig::builder_with_provider(provider.clone())
+            .with_safe_default_protocol_versions()
+            .map_err(tls_error)?;
+
+        let builder = match &self.client_ca_path {
+            Some(path) => {
+                let mut roots = RootCertStore::empty();
+                for cert in load_certs(path)? {
+                    roots.add(cert).map_err(tls_error)?;
+                }
+
+                let verifier = WebPkiClientVerifier::builder_with_provider(Arc::new(roots), provider)
+                    .build()
+                    .map_err(|e| Error::new(ErrorKind::InvalidInput, e))?;
+                builder.with_client_cert_verifier(verifier)
+            }
+            None => builder.with_no_client_auth(),
+        };
+
+        let config = builder.with_single_cert(certs, key).map_err(tls_error)?;
+        Ok(TlsAcceptor::from(Arc::new(config)))
+    }
+}
+
+/// Runs the server side of the TLS handshake on `stream`, failing with [`ErrorKind::TimedOut`] when
+/// the client doesn't complete it within `timeout`.
+pub(crate) async fn handshake(
+    acceptor: TlsAcceptor,
+    stream: TcpStream,
+    timeout: Duration,
+) -> Result<TlsStream<TcpStream>, Error> {
+    match tokio::time::timeout(timeout, acceptor.accept(stream)).await {
+        Ok(result) => result,
+        Err(_) => Err(Error::new(ErrorKind::TimedOut, "handshake timed out")),
+    }
+}
+
+fn load_certs(path: &Path) -> Result<Vec<CertificateDer<'static>>, Error> {
+    CertificateDer::pem_file_iter(path)
+        .and_then(|certs| certs.collect::<Result<Vec<_>, _>>())
+        .map_err(|e| invalid_file(path, e))
+}
+
+fn invalid_file(path: &Path, e: impl std::fmt::Display) -> Error {
+    Error::new(
+        ErrorKind::InvalidInput,
+        format!("failed to load {}: {}", path.display(), e),
+    )
+}
+
+fn tls_error(e: tokio_rustls::rustls::Error) -> Error {
+    Error::new(ErrorKind::InvalidInput, e)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::convert::TryFrom;
+    use std::fs;
+
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::{TcpListener, TcpStream};
+    use tokio_rustls::rustls::pki_types::ServerName;
+    use tokio_rustls::rustls::ClientConfig;
+    use tokio_rustls::TlsConnector;
+
+    use super::*;
+
+    struct Certificates {
+        dir: tempfile::TempDir,
+        ca: rcgen::Certificate,
+        ca_params: rcgen::CertificateParams,
+        ca_key: rcgen::KeyPair,
+    }
+
+    impl Certificates {
+        fn new() -> Certificates {
+            let mut params = rcgen::CertificateParams::new(vec![]).unwrap();
+            params.is_ca = rcgen::IsCa::Ca(rcgen::BasicConstraints::Unconstrained);
+
+            let ca_key = rcgen::KeyPair::generate().unwrap();
+            let ca = params.self_signed(&ca_key).unwrap();
+
+            let dir = tempfile::tempdir().unwrap();
+            fs::write(dir.path().join("ca.pem"), ca.pem()).unwrap();
+
+            Certificates {
+                dir,
+                ca,
+                ca_params: params,
+                ca_key,
+            }
+        }
+
+        fn issue(&self, name: &str) -> (PathBuf, PathBuf) {
+            let params = rcgen::CertificateParams::new(vec!["localhost".to_string()]).unwrap();
+            let key = rcgen::KeyPair::generate().unwrap();
+            let issuer = rcgen::Issuer::new(self.ca_params.clone(), &self.ca_key);
+            let cert = params.signed_by(&key, &issuer).unwrap();
+
+            let cert_path = self.dir.path().join(format!("{}.pem", name));
+            let key_path = self.dir.path().join(format!("{}.key", name));
+            fs::write(&cert_path, cert.pem()).unwrap();
+            fs::write(&key_path, key.serialize_pem()).unwrap();
+
+            (cert_path, key_path)
+        }
+
+        fn ca_path(&self) -> PathBuf {
+            self.dir.path().join("ca.pem")
+        }
+
+        fn client_config(&self, client_cert: Option<(PathBuf, PathBuf)>) -> ClientConfig {
+            let mut roots = RootCertStore::empty();
+            roots.add(self.ca.der().clone()).unwrap();
+
+            let builder = ClientConfig::builder_with_provider(Arc::new(default_provider()))
+                .with_safe_default_protocol_versions()
+                .unwrap()
+                .with_root_certificates(roots);
+
+            match client_cert {
+                Some((cert, key)) => builder
+                    .with_client_auth_cert(load_certs(&cert).unwrap(), PrivateKeyDer::from_pem_file(key).unwrap())
+                    .unwrap(),
+                None => builder.with_no_client_auth(),
+            }
+        }
+    }
+
+    async fn exchange(acceptor: TlsAcceptor, client: ClientConfig) -> Result<Vec<u8>, Error> {
+        let listener = TcpListener::bind("127.0.0.1:0").await?;
+        let address = listener.local_addr()?;
+
+        tokio::spawn(async move {
+            let (stream, _) = listener.accept().await.unwrap();
+            if let Ok(mut stream) = acceptor.accept(stream).await {
+                let _ = stream.write_all(b"packline").await;
+                let _ = stream.shutdown().await;
+            }
+        });
+
+        let stream = TcpStream::connect(address).await?;
+        let mut stream = TlsConnector::from(Arc::new(client))
+            .connect(ServerName::try_from("localhost").unwrap(), stream)
+            .await?;
+
+        let mut buf = Vec::new();
+        stream.read_to_end(&mut buf).await?;
+
+        Ok(buf)
+    }
+
+    #[tokio::test]
+    async fn test_tls_acceptor() {
+        let certificates = Certificates::new();
+        let (cert_path, key_path) = certificates.issue("server");
+
+        let config = TLSConfig {
+            cert_path,
+            key_path,
+            client_ca_path: None,
+        };
+
+        let result = exchange(config.acceptor().unwrap(), certificates.client_config(None)).await;
+        assert_eq!(b"packline".to_vec(), result.unwrap());
+    }
+
+    #[tokio::test]
+    async fn test_handshake_times_out() {
+        let certificates = Certificates::new();
+        let (cert_path, key_path) = certificates.issue("server");
+        let acceptor = TLSConfig {
+            cert_path,
+            key_path,
+            client_ca_path: None,
+        }
+        .acceptor()
+        .unwrap();
+
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let _client = TcpStream::connect(listener.local_addr().unwrap()).await.unwrap();
+        let (stream, _) = listener.accept().await.unwrap();
+
+        // The client never starts the handshake.
+        let result = handshake(acceptor, stream, Duration::from_millis(50)).await;
+        assert_eq!(ErrorKind::TimedOut, result.unwrap_err().kind());
+    }
+
+    #[tokio::test]
+    async fn test_tls_acceptor_requires_client_certificate() {
+        let certificates = Certificates::new();
+        let (cert_path, key_path) = certificates.issue("server");
+        let client_cert = certificates.issue("client");
+
+        let config = TLSConfig {
+            cert_path,
+            key_path,
+            client_ca_path: Some(certificates.ca_path()),
+        };
+
+        let result = exchange(config.acceptor().unwrap(), certificates.client_config(None)).await;
+        assert!(result.is_err());
+
+        let result = exchange(
+            config.acceptor().unwrap(),
+            certificates.client_config(Some(client_cert)),
+        )
+        .await;
+        assert_eq!(b"packline".to_vec(), result.unwrap());
+    }
+
+    #[test]
+    fn test_tls_acceptor_missing_files() {
+        let config = TLSConfig {
+            cert_path: PathBuf::from("/nonexistent/cert.pem"),
+            key_path: PathBuf::from("/nonexistent/key.pem"),
+            client_ca_path: None,
+        };
+
+        assert!(config.acceptor().is_err());
+    }
+}
@@ -0,0 +1,191 @@
+use std::io::{Error, ErrorKind};
+use std::os::unix::fs::FileTypeExt;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use async_trait::async_trait;
+use futures::{future::Fuse, select, FutureExt};
+use tokio::net::unix::SocketAddr;
+use tokio::net::{UnixListener, UnixStream};
+use tokio::runtime::Handle;
+use tokio::sync::oneshot::Receiver;
+use tokio::task::JoinSet;
+use tracing::{debug, info, warn};
+
+use super::{App, Connector, TCPConnectionHandler, ACCEPT_ERROR_DELAY};
+use crate::app::health::LISTENERS;
+
+#[async_trait]
+pub trait UnixConnectorHandler: Send + Sync {
+    fn handle_connection(&self, conn: (UnixStream, SocketAddr)) -> Box<dyn TCPConnectionHandler>;
+
+    /// Protocol served, used to label the connection metrics.
+    fn name(&self) -> &'static str {
+        "unix"
+    }
+}
+
+/// Accepts connections on a Unix domain socket, for clients running on the same host.
+pub struct UnixConnector {
+    path: PathBuf,
+    handler: Arc<dyn UnixConnectorHandler>,
+}
+
+impl UnixConnector {
+    pub fn new<P: AsRef<Path>>(path: P, handler: Box<dyn UnixConnectorHandler>) -> UnixConnector {
+        UnixConnector {
+            path: path.as_ref().to_path_buf(),
+            handler: Arc::from(handler),
+        }
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    fn bind(&self) -> Result<UnixListener, Error> {
+        // A socket file left behind by a previous run would make the bind fail. Anything that isn't
+        // a socket is left alone.
+        match std::fs::symlink_metadata(&self.path) {
+            Ok(metadata) if metadata.file_type().is_socket() => std::fs::remove_file(&self.path)?,
+            Ok(_) => {
+                return Err(Error::new(
+                    ErrorKind::AlreadyExists,
+                    format!("{} exists and is not a socket", self.path.display()),
+                ))
+            }
+            Err(e) if e.kind() == ErrorKind::NotFound => {}
+            Err(e) => return Err(e),
+        }
+
+        UnixListener::bind(&self.path)
+            .map_err(|e| Error::new(e.kind(), format!("failed to bind {}: {}", self.path.display(), e)))
+    }
+}
+
+#[async_trait]
+impl Connector for UnixConnector {
+    async fn run(&mut self, app: &mut App, handle: Handle, mut signal: &mut Fuse<Receiver<bool>>) -> Result<(), Error> {
+        debug!("Running UnixConnector on {}", self.path.display());
+
+        let listener = self.bind()?;
+        app.health().pass(LISTENERS);
+        let mut connections = JoinSet::new();
+
+        loop {
+            while connections.try_join_next().is_some() {}
+
+            let accept_fuse = listener.accept().fuse();
+            tokio::pin!(accept_fuse);
+
+            let res = select! {
+                _ = signal => break,
+                conn = accept_fuse => conn,
+            };
+
+            match res {
+                Ok(conn) => {
+                    let handler = self.handler.clone();
+                    let connection = app.metrics().connection_opened(handler.name());
+
+                    connections.spawn_on(
+                        async move {
+                            let _connection = connection;
+                            let mut conn_handler = handler.handle_connection(conn);
+                            if let Err(e) = conn_handler.handle().await {
+                                debug!("Unix socket connection failed: {}", e);
+                            }
+                        },
+                        &handle,
+                    );
+                }
+                Err(e) => {
+                    warn!("Failed to accept connection: {}", e);
+                    tokio::time::sleep(ACCEPT_ERROR_DELAY).await;
+                }
+            }
+
+            tokio::task::yield_now().await;
+        }
+
+        drop(listener);
+        let _ = std::fs::remove_file(&self.path);
+        app.shutdown();
+
+        info!("Waiting for {} connections to finish", connections.len());
+        while connections.join_next().await.is_some() {}
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use futures::FutureExt;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    use super::*;
+
+    struct EchoHandler;
+
+    struct EchoConnectionHandler {
+        stream: UnixStream,
+    }
+
+    impl UnixConnectorHandler for EchoHandler {
+        fn handle_connection(&self, conn: (UnixStream, SocketAddr)) -> Box<dyn TCPConnectionHandler> {
+            Box::new(EchoConnectionHandler { stream: conn.0 })
+        }
+    }
+
+    #[async_trait]
+    impl TCPConnectionHandler for EchoConnectionHandler {
+        async fn handle(&mut self) -> Result<(), Error> {
+            let mut buf = [0u8; 4];
+            self.stream.read_exact(&mut buf).await?;
+            self.stream.write_all(&buf).await
+        }
+    }
+
+    #[tokio::test]
+    async fn test_accepts_connections_and_removes_socket_on_shutdown() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("packline.sock");
+
+        let mut connector = UnixConnector::new(&path, Box::new(EchoHandler));
+        let (tx, rx) = tokio::sync::oneshot::channel();
+
+        let task = tokio::spawn(async move { connector.run(&mut App::new(), Handle::current(), &mut rx.fuse()).await });
+
+        let mut stream = loop {
+            match UnixStream::connect(&path).await {
+                Ok(stream) => break stream,
+                Err(_) => tokio::task::yield_now().await,
+            }
+        };
+
+        stream.write_all(b"ping").await.unwrap();
+        let mut buf = [0u8; 4];
+        stream.read_exact(&mut buf).await.unwrap();
+        assert_eq!(b"ping", &buf);
+
+        tx.send(true).unwrap();
+        assert!(task.await.unwrap().is_ok());
+        assert!(!path.exists());
+    }
+
+    #[tokio::test]
+    async fn test_run_refuses_to_replace_regular_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("packline.sock");
+        std::fs::write(&path, b"").unwrap();
+
+        let mut connector = UnixConnector::new(&path, Box::new(EchoHandler));
+        let (_tx, rx) = tokio::sync::oneshot::channel();
+
+        let result = connector.run(&mut App::new(), Handle::current(), &mut rx.fuse()).await;
+
+        assert_eq!(ErrorKind::AlreadyExists, result.err().unwrap().kind());
+        assert!(path.exists());
+    }
+}
@@ -23,6 +23,7 @@ impl SleepTrait for TokioSleep {
     }
 }
 
+#[allow(dead_code)]
 #[derive(Clone)]
 pub struct MockSleep {
     inner: Arc<Inner>,
@@ -41,6 +42,7 @@ pub struct Inner {
     elapsed: Mutex<Duration>,
 }
 
+#[allow(dead_code)]
 impl Inner {
     fn poll(&self, _: &mut Context<'_>) -> Poll<()> {
         if self.is_elapsed() {
@@ -0,0 +1,153 @@
+use std::collections::HashMap;
+use std::io::{Error, ErrorKind};
+use std::path::Path;
+
+use rand::{thread_rng, RngCore};
+use subtle::ConstantTimeEq;
+
+use super::scram;
+
+pub const DEFAULT_ITERATIONS: u32 = 4096;
+pub(crate) const SALT_LENGTH: usize = 16;
+
+/// Keys derived from a user password. Passwords themselves are never kept in memory.
+#[derive(Clone)]
+pub struct ScramCredential {
+    pub salt: Vec<u8>,
+    pub iterations: u32,
+    pub stored_key: Vec<u8>,
+    pub server_key: Vec<u8>,
+}
+
+impl ScramCredential {
+    /// Derives the keys of `password`. The key derivation is slow on purpose, so it runs on the
+    /// blocking thread pool rather than on the async runtime.
+    pub async fn new(password: &str, salt: Vec<u8>, iterations: u32) -> ScramCredential {
+        let password = password.to_string();
+        let (salt, salted_password) = blocking(move || {
+            let salted_password = scram::salted_password(&password, &salt, iterations);
+            (salt, salted_password)
+        })
+        .await;
+
+        ScramCredential {
+            stored_key: scram::stored_key(&scram::client_key(&salted_password)),
+            server_key: scram::server_key(&salted_password),
+            salt,
+            iterations,
+        }
+    }
+
+    /// Tells whether `password` is the one the credential was derived from, deriving its keys on
+    /// the blocking thread pool.
+    pub async fn verify(&self, password: &str) -> bool {
+        let password = password.to_string();
+        let salt = self.salt.clone();
+        let iterations = self.iterations;
+        let salted_password = blocking(move || scram::salted_password(&password, &salt, iterations)).await;
+
+        scram::stored_key(&scram::client_key(&salted_password))
+            .ct_eq(&self.stored_key)
+            .into()
+    }
+}
+
+async fn blocking<T: Send + 'static>(f: impl FnOnce() -> T + Send + 'static) -> T {
+    match tokio::task::spawn_blocking(f).await {
+        Ok(result) => result,
+        Err(e) => std::panic::resume_unwind(e.into_panic()),
+    }
+}
+
+/// Users allowed to authenticate with the broker.
+#[derive(Clone, Default)]
+pub struct CredentialStore {
+    credentials: HashMap<String, ScramCredential>,
+}
+
+impl CredentialStore {
+    pub fn new() -> CredentialStore {
+        CredentialStore::default()
+    }
+
+    /// Loads users from a file with one `username:password` entry per line. Empty lines and lines
+    /// starting with `#` are skipped.
+    pub async fn from_file<P: AsRef<Path>>(path: P) -> Result<CredentialStore, Error> {
+        let content = tokio::fs::read_to_string(path.as_ref()).await?;
+        let mut store = CredentialStore::new();
+
+        for (number, line) in content.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            match line.split_once(':') {
+                Some((username, password)) if !username.is_empty() => store.add_user(username, password).await,
+                _ => {
+                    return Err(Error::new(
+                        ErrorKind::InvalidData,
+                        format!("invalid credential entry at {}:{}", path.as_ref().display(), number + 1),
+                    ))
+                }
+            }
+        }
+
+        Ok(store)
+    }
+
+    pub async fn add_user(&mut self, username: &str, password: &str) {
+        let mut salt = vec![0u8; SALT_LENGTH];
+        thread_rng().fill_bytes(&mut salt);
+
+        self.credentials.insert(
+            username.to_string(),
+            ScramCredential::new(password, salt, DEFAULT_ITERATIONS).await,
+        );
+    }
+
+    pub fn get(&self, username: &str) -> Option<&ScramCredential> {
+        self.credentials.get(username)
+    }
+
+    /// Tells whether `username` is a known user and `password` its password. The password of an
+    /// unknown user is derived all the same, so that it takes as long as a wrong password.
+    pub async fn verify(&self, username: &str, password: &str) -> bool {
+        match self.get(username) {
+            Some(credential) => credential.verify(password).await,
+            None => {
+                let password = password.to_string();
+                blocking(move || scram::salted_password(&password, &[0u8; SALT_LENGTH], DEFAULT_ITERATIONS)).await;
+                false
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::io::Write;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn test_credential_store_from_file() {
+        let mut file = tempfile::NamedTempFile::new().unwrap();
+        writeln!(file, "# packline users\n\nalice:secret\nbob:with:colon").unwrap();
+
+        let store = CredentialStore::from_file(file.path()).await.unwrap();
+
+        assert!(store.verify("alice", "secret").await);
+        assert!(!store.verify("alice", "wrong").await);
+        assert!(store.verify("bob", "with:colon").await);
+        assert!(!store.verify("carol", "secret").await);
+    }
+
+    #[tokio::test]
+    async fn test_credential_store_rejects_invalid_entries() {
+        let mut file = tempfile::NamedTempFile::new().unwrap();
+        writeln!(file, "alice").unwrap();
+
+        assert!(CredentialStore::from_file(file.path()).await.is_err());
+    }
+}
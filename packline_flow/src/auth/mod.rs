@@ -0,0 +1,170 @@
+use std::fmt::{Display, Formatter};
+use std::path::Path;
+
+pub use credentials::{CredentialStore, ScramCredential};
+
+use rand::{thread_rng, RngCore};
+
+use self::scram::ScramServer;
+
+pub mod credentials;
+pub mod plain;
+pub mod scram;
+
+pub const PLAIN: &str = "PLAIN";
+pub const SCRAM_SHA_256: &str = "SCRAM-SHA-256";
+
+const SECRET_LENGTH: usize = 32;
+
+#[derive(Debug)]
+pub enum AuthError {
+    UnsupportedMechanism(String),
+    InvalidCredentials,
+    InvalidServerSignature,
+    Malformed(String),
+}
+
+impl Display for AuthError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            AuthError::UnsupportedMechanism(mechanism) => write!(f, "unsupported mechanism {}", mechanism),
+            AuthError::InvalidCredentials => write!(f, "invalid credentials"),
+            AuthError::InvalidServerSignature => write!(f, "invalid server signature"),
+            AuthError::Malformed(reason) => write!(f, "malformed authentication message: {}", reason),
+        }
+    }
+}
+
+impl std::error::Error for AuthError {}
+
+/// Result of a single authentication step on the broker.
+pub enum AuthStep {
+    /// The mechanism needs another message from the client, answering the given payload.
+    Continue(Vec<u8>),
+
+    /// The client authenticated as the given user.
+    Done(String, Vec<u8>),
+}
+
+/// Verifies client credentials against a [`CredentialStore`]. Clones share the secret unknown
+/// users' SCRAM salts are derived from, so they answer the same way.
+#[derive(Clone)]
+pub struct Authenticator {
+    credentials: CredentialStore,
+    secret: Vec<u8>,
+}
+
+/// Authentication progress of a single connection.
+#[derive(Default)]
+pub struct AuthSession {
+    scram: Option<ScramServer>,
+}
+
+impl Authenticator {
+    pub fn new(credentials: CredentialStore) -> Authenticator {
+        let mut secret = vec![0u8; SECRET_LENGTH];
+        thread_rng().fill_bytes(&mut secret);
+
+        Authenticator { credentials, secret }
+    }
+
+    pub async fn from_file<P: AsRef<Path>>(path: P) -> Result<Authenticator, std::io::Error> {
+        Ok(Authenticator::new(CredentialStore::from_file(path).await?))
+    }
+
+    pub async fn step(
+        &self,
+        session: &mut AuthSession,
+        mechanism: &str,
+        payload: &[u8],
+    ) -> Result<AuthStep, AuthError> {
+        match mechanism {
+            PLAIN => {
+                let (username, password) = plain::decode(payload)?;
+
+                if self.credentials.verify(&username, &password).await {
+                    Ok(AuthStep::Done(username, vec![]))
+                } else {
+                    Err(AuthError::InvalidCredentials)
+                }
+            }
+            SCRAM_SHA_256 => match session.scram.take() {
+                None => {
+                    let (server, server_first) = ScramServer::start(payload, &self.secret, |username| {
+                        self.credentials.get(username).cloned()
+                    })?;
+                    session.scram = Some(server);
+
+                    Ok(AuthStep::Continue(server_first))
+                }
+                Some(server) => {
+                    let (username, server_final) = server.finish(payload)?;
+                    Ok(AuthStep::Done(username, server_final))
+                }
+            },
+            _ => Err(AuthError::UnsupportedMechanism(mechanism.to_string())),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::scram::ScramClient;
+    use super::*;
+
+    async fn authenticator() -> Authenticator {
+        let mut credentials = CredentialStore::new();
+        credentials.add_user("alice", "secret").await;
+
+        Authenticator::new(credentials)
+    }
+
+    #[tokio::test]
+    async fn test_authenticator_plain() {
+        let authenticator = authenticator().await;
+        let mut session = AuthSession::default();
+
+        let result = authenticator
+            .step(&mut session, PLAIN, &plain::encode("alice", "secret"))
+            .await;
+        assert!(matches!(result, Ok(AuthStep::Done(username, _)) if username == "alice"));
+
+        let result = authenticator
+            .step(&mut session, PLAIN, &plain::encode("alice", "wrong"))
+            .await;
+        assert!(matches!(result, Err(AuthError::InvalidCredentials)));
+    }
+
+    #[tokio::test]
+    async fn test_authenticator_scram() {
+        let authenticator = authenticator().await;
+        let mut session = AuthSession::default();
+        let mut client = ScramClient::new("alice", "secret");
+
+        let server_first = match authenticator
+            .step(&mut session, SCRAM_SHA_256, &client.client_first())
+            .await
+        {
+            Ok(AuthStep::Continue(server_first)) => server_first,
+            _ => panic!("expected server-first message"),
+        };
+
+        let client_final = client.client_final(&server_first).unwrap();
+        match authenticator.step(&mut session, SCRAM_SHA_256, &client_final).await {
+            Ok(AuthStep::Done(username, server_final)) => {
+                assert_eq!("alice", username);
+                assert!(client.verify_server_final(&server_final).is_ok());
+            }
+            _ => panic!("expected authentication to succeed"),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_authenticator_unsupported_mechanism() {
+        let result = authenticator()
+            .await
+            .step(&mut AuthSession::default(), "GSSAPI", &[])
+            .await;
+        assert!(matches!(result, Err(AuthError::UnsupportedMechanism(_))));
+    }
+}
@@ -0,0 +1,38 @@
+//! PLAIN mechanism as described in RFC 4616.
+
+use super::AuthError;
+
+pub fn encode(username: &str, password: &str) -> Vec<u8> {
+    format!("\0{}\0{}", username, password).into_bytes()
+}
+
+/// Splits a PLAIN message into username and password. Authorization identities other than the
+/// authenticated user aren't supported.
+pub fn decode(payload: &[u8]) -> Result<(String, String), AuthError> {
+    let message = std::str::from_utf8(payload).map_err(|e| AuthError::Malformed(e.to_string()))?;
+    let mut parts = message.splitn(3, '\0');
+
+    match (parts.next(), parts.next(), parts.next()) {
+        (Some(authzid), Some(username), Some(password)) if authzid.is_empty() || authzid == username => {
+            Ok((username.to_string(), password.to_string()))
+        }
+        _ => Err(AuthError::Malformed("invalid PLAIN message".to_string())),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_plain_round_trip() {
+        let payload = encode("alice", "secret");
+        assert_eq!(("alice".to_string(), "secret".to_string()), decode(&payload).unwrap());
+    }
+
+    #[test]
+    fn test_plain_rejects_other_authorization_identity() {
+        assert!(decode(b"bob\0alice\0secret").is_err());
+        assert!(decode(b"alice").is_err());
+    }
+}
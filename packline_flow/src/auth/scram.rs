@@ -0,0 +1,344 @@
+//! SCRAM-SHA-256 exchange as described in RFC 5802 and RFC 7677, without channel binding.
+
+use base64::engine::general_purpose::STANDARD as BASE64;
+use base64::Engine;
+use hmac::{Hmac, Mac};
+use rand::distributions::Alphanumeric;
+use rand::{thread_rng, Rng};
+use sha2::{Digest, Sha256};
+use subtle::ConstantTimeEq;
+
+use super::credentials::{ScramCredential, DEFAULT_ITERATIONS, SALT_LENGTH};
+use super::AuthError;
+
+type HmacSha256 = Hmac<Sha256>;
+
+const NONCE_LENGTH: usize = 24;
+const GS2_HEADER: &str = "n,,";
+
+fn fake_salt(secret: &[u8], username: &str) -> Vec<u8> {
+    let mut salt = hmac(secret, username.as_bytes());
+    salt.truncate(SALT_LENGTH);
+    salt
+}
+
+pub(crate) fn hmac(key: &[u8], data: &[u8]) -> Vec<u8> {
+    let mut mac = HmacSha256::new_from_slice(key).expect("HMAC accepts keys of any size");
+    mac.update(data);
+    mac.finalize().into_bytes().to_vec()
+}
+
+pub(crate) fn salted_password(password: &str, salt: &[u8], iterations: u32) -> [u8; 32] {
+    let mut result = [0u8; 32];
+    pbkdf2::pbkdf2_hmac::<Sha256>(password.as_bytes(), salt, iterations, &mut result);
+    result
+}
+
+pub(crate) fn client_key(salted_password: &[u8]) -> Vec<u8> {
+    hmac(salted_password, b"Client Key")
+}
+
+pub(crate) fn server_key(salted_password: &[u8]) -> Vec<u8> {
+    hmac(salted_password, b"Server Key")
+}
+
+pub(crate) fn stored_key(client_key: &[u8]) -> Vec<u8> {
+    Sha256::digest(client_key).to_vec()
+}
+
+fn nonce() -> String {
+    thread_rng()
+        .sample_iter(&Alphanumeric)
+        .take(NONCE_LENGTH)
+        .map(char::from)
+        .collect()
+}
+
+fn escape_username(username: &str) -> String {
+    username.replace('=', "=3D").replace(',', "=2C")
+}
+
+fn unescape_username(username: &str) -> String {
+    username.replace("=2C", ",").replace("=3D", "=")
+}
+
+/// Looks up the value of a `key=value` attribute in a comma separated SCRAM message.
+fn attribute(message: &str, key: char) -> Result<&str, AuthError> {
+    message
+        .split(',')
+        .find_map(|attribute| {
+            let mut chars = attribute.chars();
+            match (chars.next(), chars.next()) {
+                (Some(k), Some('=')) if k == key => Some(&attribute[2..]),
+                _ => None,
+            }
+        })
+        .ok_or_else(|| AuthError::Malformed(format!("missing attribute {}", key)))
+}
+
+fn decode(value: &str) -> Result<Vec<u8>, AuthError> {
+    BASE64.decode(value).map_err(|e| AuthError::Malformed(e.to_string()))
+}
+
+fn utf8(payload: &[u8]) -> Result<&str, AuthError> {
+    std::str::from_utf8(payload).map_err(|e| AuthError::Malformed(e.to_string()))
+}
+
+/// Broker side of a SCRAM exchange.
+pub struct ScramServer {
+    username: String,
+    client_first_bare: String,
+    server_first: String,
+    nonce: String,
+    credential: Option<ScramCredential>,
+}
+
+impl ScramServer {
+    /// Handles the client-first message. The exchange runs even for unknown users, so that a
+    /// client can't tell them apart from a wrong password: they get a salt derived from the
+    /// username under the broker's `secret`, which stays the same across attempts like a real one.
+    pub fn start<F>(client_first: &[u8], secret: &[u8], lookup: F) -> Result<(ScramServer, Vec<u8>), AuthError>
+    where
+        F: FnOnce(&str) -> Option<ScramCredential>,
+    {
+        let client_first = utf8(client_first)?;
+        let client_first_bare = client_first
+            .strip_prefix(GS2_HEADER)
+            .ok_or_else(|| AuthError::Malformed("unsupported GS2 header".to_string()))?;
+
+        let username = unescape_username(attribute(client_first_bare, 'n')?);
+        let client_nonce = attribute(client_first_bare, 'r')?;
+
+        let credential = lookup(&username);
+        let (salt, iterations) = match &credential {
+            Some(credential) => (credential.salt.clone(), credential.iterations),
+            None => (fake_salt(secret, &username), DEFAULT_ITERATIONS),
+        };
+
+        let nonce = format!("{}{}", client_nonce, self::nonce());
+        let server_first = format!("r={},s={},i={}", nonce, BASE64.encode(salt), iterations);
+
+        Ok((
+            ScramServer {
+                username,
+                client_first_bare: client_first_bare.to_string(),
+                server_first: server_first.clone(),
+                nonce,
+                credential,
+            },
+            server_first.into_bytes(),
+        ))
+    }
+
+    /// Verifies the client proof, returning the authenticated username and the server-final message.
+    pub fn finish(self, client_final: &[u8]) -> Result<(String, Vec<u8>), AuthError> {
+        let client_final = utf8(client_final)?;
+
+        let proof_index = client_final
+            .rfind(",p=")
+            .ok_or_else(|| AuthError::Malformed("missing client proof".to_string()))?;
+        let client_final_without_proof = &client_final[..proof_index];
+        let proof = decode(&client_final[proof_index + 3..])?;
+
+        if attribute(client_final_without_proof, 'c')? != BASE64.encode(GS2_HEADER) {
+            return Err(AuthError::Malformed("unsupported channel binding".to_string()));
+        }
+
+        if attribute(client_final_without_proof, 'r')? != self.nonce {
+            return Err(AuthError::InvalidCredentials);
+        }
+
+        let credential = self.credential.ok_or(AuthError::InvalidCredentials)?;
+
+        let auth_message = format!(
+            "{},{},{}",
+            self.client_first_bare, self.server_first, client_final_without_proof
+        );
+        let client_signature = hmac(&credential.stored_key, auth_message.as_bytes());
+
+        if proof.len() != client_signature.len() {
+            return Err(AuthError::InvalidCredentials);
+        }
+
+        let client_key: Vec<u8> = proof.iter().zip(client_signature).map(|(a, b)| a ^ b).collect();
+        if !bool::from(stored_key(&client_key).ct_eq(&credential.stored_key)) {
+            return Err(AuthError::InvalidCredentials);
+        }
+
+        let server_signature = hmac(&credential.server_key, auth_message.as_bytes());
+        let server_final = format!("v={}", BASE64.encode(server_signature));
+
+        Ok((self.username, server_final.into_bytes()))
+    }
+}
+
+/// Client side of a SCRAM exchange.
+pub struct ScramClient {
+    password: String,
+    client_first_bare: String,
+    nonce: String,
+    server_signature: Option<Vec<u8>>,
+}
+
+impl ScramClient {
+    pub fn new(username: &str, password: &str) -> ScramClient {
+        ScramClient::with_nonce(username, password, nonce())
+    }
+
+    fn with_nonce(username: &str, password: &str, nonce: String) -> ScramClient {
+        ScramClient {
+            password: password.to_string(),
+            client_first_bare: format!("n={},r={}", escape_username(username), nonce),
+            nonce,
+            server_signature: None,
+        }
+    }
+
+    pub fn client_first(&self) -> Vec<u8> {
+        format!("{}{}", GS2_HEADER, self.client_first_bare).into_bytes()
+    }
+
+    /// Computes the client-final message answering the broker's server-first message.
+    pub fn client_final(&mut self, server_first: &[u8]) -> Result<Vec<u8>, AuthError> {
+        let server_first = utf8(server_first)?;
+
+        let nonce = attribute(server_first, 'r')?;
+        if !nonce.starts_with(&self.nonce) {
+            return Err(AuthError::Malformed(
+                "server nonce doesn't extend the client nonce".to_string(),
+            ));
+        }
+
+        let salt = decode(attribute(server_first, 's')?)?;
+        let iterations = attribute(server_first, 'i')?
+            .parse::<u32>()
+            .map_err(|e| AuthError::Malformed(e.to_string()))?;
+
+        let salted_password = salted_password(&self.password, &salt, iterations);
+        let client_key = client_key(&salted_password);
+
+        let client_final_without_proof = format!("c={},r={}", BASE64.encode(GS2_HEADER), nonce);
+        let auth_message = format!(
+            "{},{},{}",
+            self.client_first_bare, server_first, client_final_without_proof
+        );
+
+        let client_signature = hmac(&stored_key(&client_key), auth_message.as_bytes());
+        let proof: Vec<u8> = client_key.iter().zip(client_signature).map(|(a, b)| a ^ b).collect();
+
+        self.server_signature = Some(hmac(&server_key(&salted_password), auth_message.as_bytes()));
+
+        Ok(format!("{},p={}", client_final_without_proof, BASE64.encode(proof)).into_bytes())
+    }
+
+    /// Checks the broker's signature, proving it knows the credentials too.
+    pub fn verify_server_final(&self, server_final: &[u8]) -> Result<(), AuthError> {
+        let signature = decode(attribute(utf8(server_final)?, 'v')?)?;
+
+        match &self.server_signature {
+            Some(expected) if bool::from(expected.ct_eq(&signature)) => Ok(()),
+            _ => Err(AuthError::InvalidServerSignature),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const SECRET: &[u8] = b"broker secret";
+
+    async fn credential(password: &str, salt: &[u8]) -> ScramCredential {
+        ScramCredential::new(password, salt.to_vec(), 4096).await
+    }
+
+    fn salt(server_first: &[u8]) -> String {
+        attribute(utf8(server_first).unwrap(), 's').unwrap().to_string()
+    }
+
+    #[test]
+    fn test_scram_client_rfc7677_vector() {
+        let mut client = ScramClient::with_nonce("user", "pencil", "rOprNGfwEbeRWgbNEkqO".to_string());
+        assert_eq!(b"n,,n=user,r=rOprNGfwEbeRWgbNEkqO".to_vec(), client.client_first());
+
+        let server_first = b"r=rOprNGfwEbeRWgbNEkqO%hvYDpWUa2RaTCAfuxFIlj)hNlF$k0,s=W22ZaJ0SNY7soEsUEjb6gQ==,i=4096";
+        let client_final = client.client_final(server_first).unwrap();
+
+        let expected = concat!(
+            "c=biws,r=rOprNGfwEbeRWgbNEkqO%hvYDpWUa2RaTCAfuxFIlj)hNlF$k0,",
+            "p=dHzbZapWIk4jUhN+Ute9ytag9zjfMHgsqmmiz7AndVQ="
+        );
+        assert_eq!(expected, String::from_utf8(client_final).unwrap());
+        assert!(client
+            .verify_server_final(b"v=6rriTRBi23WpRR/wtup+mMhUZUn/dB5nLTJRsjl95G4=")
+            .is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_scram_exchange() {
+        let credential = credential("pencil", b"packline").await;
+        let mut client = ScramClient::new("user", "pencil");
+
+        let (server, server_first) = ScramServer::start(&client.client_first(), SECRET, |username| {
+            assert_eq!("user", username);
+            Some(credential.clone())
+        })
+        .unwrap();
+
+        let client_final = client.client_final(&server_first).unwrap();
+        let (username, server_final) = server.finish(&client_final).unwrap();
+
+        assert_eq!("user", username);
+        assert!(client.verify_server_final(&server_final).is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_scram_exchange_wrong_password() {
+        let credential = credential("pencil", b"packline").await;
+        let mut client = ScramClient::new("user", "crayon");
+
+        let (server, server_first) = ScramServer::start(&client.client_first(), SECRET, |_| Some(credential)).unwrap();
+        let client_final = client.client_final(&server_first).unwrap();
+
+        assert!(matches!(
+            server.finish(&client_final),
+            Err(AuthError::InvalidCredentials)
+        ));
+    }
+
+    #[test]
+    fn test_scram_exchange_unknown_user() {
+        let mut client = ScramClient::new("nobody", "pencil");
+
+        let (server, server_first) = ScramServer::start(&client.client_first(), SECRET, |_| None).unwrap();
+        let client_final = client.client_final(&server_first).unwrap();
+
+        assert!(matches!(
+            server.finish(&client_final),
+            Err(AuthError::InvalidCredentials)
+        ));
+    }
+
+    #[test]
+    fn test_scram_unknown_user_salt_is_stable() {
+        let start = |username: &str, secret: &[u8]| {
+            let client = ScramClient::new(username, "pencil");
+            let (_, server_first) = ScramServer::start(&client.client_first(), secret, |_| None).unwrap();
+            salt(&server_first)
+        };
+
+        assert_eq!(start("nobody", SECRET), start("nobody", SECRET));
+        assert_ne!(start("nobody", SECRET), start("somebody", SECRET));
+        assert_ne!(start("nobody", SECRET), start("nobody", b"other secret"));
+        assert_eq!(SALT_LENGTH, decode(&start("nobody", SECRET)).unwrap().len());
+    }
+
+    #[test]
+    fn test_scram_username_escaping() {
+        let client = ScramClient::with_nonce("a=b,c", "pencil", "nonce".to_string());
+        assert_eq!(b"n,,n=a=3Db=2Cc,r=nonce".to_vec(), client.client_first());
+
+        let (server, _) = ScramServer::start(&client.client_first(), SECRET, |_| None).unwrap();
+        assert_eq!("a=b,c", server.username);
+    }
+}
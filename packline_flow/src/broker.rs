@@ -0,0 +1,204 @@
+//! Broker running in process, serving the flow protocol. Meant for tests that need a real broker:
+//! it listens on an ephemeral port by default, so that every test can start its own.
+//!
+//! ```no_run
+//! # async fn example() -> Result<(), std::io::Error> {
+//! use packline_flow::broker::Broker;
+//!
+//! let broker = Broker::builder().topic("orders", 2).start().await?;
+//! let address = broker.addr();
+//! // Connect clients to `address`...
+//! broker.shutdown().await
+//! # }
+//! ```
+
+use std::io::{Error, ErrorKind};
+use std::net::SocketAddr;
+
+use futures::FutureExt;
+use tokio::runtime::Handle;
+use tokio::sync::oneshot::Sender;
+use tokio::task::JoinHandle;
+
+use packline_core::app::{App, ChannelConfig};
+use packline_core::connector::{Connector, TCPConnector, TCPListenerConfig};
+
+use crate::auth::Authenticator;
+use crate::connector::FlowConnector;
+
+pub struct BrokerBuilder {
+    app: App,
+    listener: TCPListenerConfig,
+    authenticator: Option<Authenticator>,
+    topics: Vec<ChannelConfig>,
+}
+
+impl BrokerBuilder {
+    /// Serves this app instead of an empty one, such as an app with an authorizer.
+    pub fn app(mut self, app: App) -> BrokerBuilder {
+        self.app = app;
+        self
+    }
+
+    /// Listens with this config instead of on an ephemeral port of `127.0.0.1`.
+    pub fn listener(mut self, config: TCPListenerConfig) -> BrokerBuilder {
+        self.listener = config;
+        self
+    }
+
+    /// Requires clients to authenticate before any other request.
+    pub fn authenticator(mut self, authenticator: Authenticator) -> BrokerBuilder {
+        self.authenticator = Some(authenticator);
+        self
+    }
+
+    /// Creates a topic when the broker starts, unless the app already has it.
+    pub fn topic(mut self, name: &str, partitions: u16) -> BrokerBuilder {
+        self.topics.push(ChannelConfig {
+            name: name.to_string(),
+            partitions,
+        });
+        self
+    }
+
+    /// Creates the topics and binds the listener, then serves connections on a task of the current
+    /// runtime until the returned broker is shut down or dropped.
+    pub async fn start(self) -> Result<Broker, Error> {
+        let BrokerBuilder {
+            app,
+            listener,
+            authenticator,
+            topics,
+        } = self;
+
+        for topic in topics {
+            let name = topic.name.clone();
+            if app.get_channel(&(name.clone(), 1)).await.is_none() && app.create_channel(topic).await.is_err() {
+                return Err(Error::new(
+                    ErrorKind::InvalidInput,
+                    format!("failed to create topic {}", name),
+                ));
+            }
+        }
+
+        let mut handler = FlowConnector::new(app.clone());
+        if let Some(authenticator) = authenticator {
+            handler = handler.with_authenticator(authenticator);
+        }
+
+        let mut connector = TCPConnector::with_config(listener, Box::new(handler));
+        let addr = connector.bind()?[0];
+
+        let (signal, rx) = tokio::sync::oneshot::channel();
+        let task = {
+            let mut app = app.clone();
+            tokio::spawn(async move { connector.run(&mut app, Handle::current(), &mut rx.fuse()).await })
+        };
+
+        Ok(Broker {
+            app,
+            addr,
+            signal: Some(signal),
+            task: Some(task),
+        })
+    }
+}
+
+/// Broker started by a [`BrokerBuilder`]. Dropping it shuts it down in the background, use
+/// [`Broker::shutdown`] to wait for its connections to finish.
+pub struct Broker {
+    app: App,
+    addr: SocketAddr,
+    signal: Option<Sender<bool>>,
+    task: Option<JoinHandle<Result<(), Error>>>,
+}
+
+impl Broker {
+    pub fn builder() -> BrokerBuilder {
+        BrokerBuilder {
+            app: App::new(),
+            listener: TCPListenerConfig {
+                port: 0,
+                ..Default::default()
+            },
+            authenticator: None,
+            topics: vec![],
+        }
+    }
+
+    /// Address the broker listens on, with the port it was given when bound to an ephemeral one.
+    pub fn addr(&self) -> SocketAddr {
+        self.addr
+    }
+
+    pub fn app(&self) -> &App {
+        &self.app
+    }
+
+    /// Stops accepting connections and waits for the live ones to finish.
+    pub async fn shutdown(mut self) -> Result<(), Error> {
+        if let Some(signal) = self.signal.take() {
+            let _ = signal.send(true);
+        }
+
+        match self.task.take() {
+            Some(task) => task.await.map_err(Error::other)?,
+            None => Ok(()),
+        }
+    }
+}
+
+impl Drop for Broker {
+    fn drop(&mut self) {
+        if let Some(signal) = self.signal.take() {
+            let _ = signal.send(true);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use futures::{SinkExt, StreamExt};
+    use tokio::net::TcpStream;
+    use tokio_util::codec::Framed;
+
+    use crate::codec::FlowCodec;
+    use crate::messages::topic::ListTopicsRequestV1;
+    use crate::messages::{Message, Packet};
+
+    use super::*;
+
+    #[tokio::test]
+    async fn test_broker_serves_preloaded_topics() {
+        let first = Broker::builder().topic("orders", 2).start().await.unwrap();
+        let second = Broker::builder().start().await.unwrap();
+        assert_ne!(first.addr(), second.addr());
+        assert_eq!(2, first.app().list_channels().await[0].partitions);
+
+        let mut framed = Framed::new(TcpStream::connect(first.addr()).await.unwrap(), FlowCodec::new());
+        framed
+            .send(Packet::new(
+                (16, 1),
+                Message::ListTopicsRequestV1(ListTopicsRequestV1 {}),
+            ))
+            .await
+            .unwrap();
+        match framed.next().await.unwrap().unwrap().message {
+            Message::ListTopicsResponseV1(list) => assert_eq!("orders", list.topics[0].name),
+            message => panic!("unexpected response {:?}", message),
+        }
+
+        let address = first.addr();
+        first.shutdown().await.unwrap();
+        assert!(framed.next().await.is_none());
+        assert!(TcpStream::connect(address).await.is_err());
+
+        let address = second.addr();
+        let app = second.app().clone();
+        drop(second);
+        app.shutting_down().await;
+        while TcpStream::connect(address).await.is_ok() {
+            tokio::task::yield_now().await;
+        }
+    }
+}
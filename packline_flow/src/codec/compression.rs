@@ -0,0 +1,73 @@
+/// Compression codec applied to a packet payload. The codec is carried in the packet header flags,
+/// so every packet can be decoded independently of the connection settings. Produced batches can
+/// also be compressed as a whole, see [`ProduceBatchRequestV1`](crate::messages::produce::ProduceBatchRequestV1).
+pub use packline_core::app::channel::Compression;
+
+#[cfg(test)]
+mod test {
+    use bytes::BytesMut;
+    use tokio_util::codec::{Decoder, Encoder};
+
+    use super::*;
+    use crate::codec::FlowCodec;
+    use crate::messages::consume::ConsumeV1;
+    use crate::messages::record::RecordV1;
+    use crate::messages::{Message, Packet};
+    use crate::SizedSchema;
+
+    #[test]
+    fn test_codec_compresses_large_packets() {
+        let records = vec![
+            RecordV1 {
+                offset: 0,
+                key: vec![],
+                value: vec![42u8; 4],
+            };
+            1024
+        ];
+        let packet = Packet::new_stream_packet(
+            1,
+            (3, 1),
+            Message::ConsumeV1(ConsumeV1::new("testing_topic".to_string(), records.clone())),
+        );
+        let uncompressed_size = packet.size();
+
+        let mut codec = FlowCodec::with_compression(Compression::Zstd);
+        let mut buf = BytesMut::new();
+        codec.encode(packet, &mut buf).unwrap();
+
+        assert!(buf.len() < uncompressed_size);
+
+        let decoded = codec.decode(&mut buf).unwrap().unwrap();
+        assert_eq!(Compression::Zstd, decoded.compression);
+        assert!(buf.is_empty());
+
+        match decoded.message {
+            Message::ConsumeV1(consume) => assert_eq!(records, consume.records),
+            _ => panic!("unexpected message"),
+        }
+    }
+
+    #[test]
+    fn test_codec_skips_small_packets() {
+        let packet = Packet::new_stream_packet(
+            1,
+            (3, 1),
+            Message::ConsumeV1(ConsumeV1::new(
+                "testing_topic".to_string(),
+                vec![RecordV1 {
+                    offset: 0,
+                    key: vec![],
+                    value: vec![42u8],
+                }],
+            )),
+        );
+
+        let mut codec = FlowCodec::with_compression(Compression::Lz4);
+        let mut buf = BytesMut::new();
+        codec.encode(packet, &mut buf).unwrap();
+
+        let decoded = codec.decode(&mut buf).unwrap().unwrap();
+        assert_eq!(Compression::None, decoded.compression);
+    }
+}
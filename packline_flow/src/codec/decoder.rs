@@ -2,7 +2,6 @@ use bytes::BytesMut;
 use tokio_util::codec::Decoder;
 
 use crate::messages::{Message, Packet};
-use crate::DeserializableSchema;
 
 impl Decoder for super::FlowCodec {
     type Item = crate::messages::Packet;
@@ -11,8 +10,8 @@ impl Decoder for super::FlowCodec {
     fn decode(&mut self, src: &mut BytesMut) -> Result<Option<Self::Item>, Self::Error> {
         let (result, offset) = {
             let mut decoder = ByteDecoder::new(src);
-            let result = Packet::deserialize(&mut decoder).unwrap();
-            (result, decoder.offset)
+            let result = Packet::decode(&mut decoder, self.max_packet_size)?;
+            (result, decoder.offset())
         };
 
         if result.is_none() {
@@ -29,20 +28,62 @@ impl Decoder for super::FlowCodec {
     }
 }
 
+/// Bytes returned for fixed-size values read past the end of the input.
+const ZEROES: [u8; 8] = [0; 8];
+
+/// Reads values from a byte slice. Reading past the end of the input, or reading bytes that don't
+/// hold a valid value, doesn't panic: the decoder is marked as failed instead, see
+/// [`ByteDecoder::failed`], and the values read are meaningless.
 pub struct ByteDecoder<'a> {
     offset: usize,
     buf: &'a [u8],
+    failed: bool,
 }
 
 impl<'a> ByteDecoder<'a> {
-    fn new(src: &'a [u8]) -> ByteDecoder<'a> {
-        ByteDecoder { offset: 0, buf: src }
+    pub fn new(src: &'a [u8]) -> ByteDecoder<'a> {
+        ByteDecoder {
+            offset: 0,
+            buf: src,
+            failed: false,
+        }
     }
 
+    /// Returns the number of bytes read so far.
+    pub fn offset(&self) -> usize {
+        self.offset
+    }
+
+    /// Returns the next `size` bytes. Past the end of the input, the decoder fails and returns
+    /// zeroes for values of up to 8 bytes, nothing for larger ones.
     pub fn next(&mut self, size: usize) -> &[u8] {
-        let result = &self.buf[self.offset..self.offset + size];
-        self.offset += size;
-        result
+        match self.offset.checked_add(size) {
+            Some(end) if end <= self.buf.len() => {
+                let result = &self.buf[self.offset..end];
+                self.offset = end;
+                result
+            }
+            _ => {
+                self.fail();
+                self.offset = self.buf.len();
+                &ZEROES[..size.min(ZEROES.len())]
+            }
+        }
+    }
+
+    /// Returns the number of bytes left to read.
+    pub fn remaining(&self) -> usize {
+        self.buf.len() - self.offset
+    }
+
+    /// Marks the input as invalid.
+    pub fn fail(&mut self) {
+        self.failed = true;
+    }
+
+    /// Tells whether a value was read past the end of the input or from invalid bytes.
+    pub fn failed(&self) -> bool {
+        self.failed
     }
 
     pub fn len(&self) -> usize {
@@ -182,6 +223,65 @@ mod test {
         assert_eq!(vec![42i8, 42i8, 42i8, 42i8], result)
     }
 
+    #[test]
+    fn test_decode_past_the_end_fails() {
+        let buf = [0u8, 0u8, 0u8, 0u8, 0u8, 0u8, 0u8, 4u8, 42u8];
+        let mut decoder = ByteDecoder::new(&buf);
+        assert!(Vec::<i8>::deserialize(&mut decoder).unwrap().is_empty());
+        assert!(decoder.failed());
+
+        let buf = [0u8, 0u8, 0u8, 0u8, 0u8, 0u8, 0u8, 2u8, 0u8, 0u8, 0u8, 42u8, 0u8];
+        let mut decoder = ByteDecoder::new(&buf);
+        assert_eq!(vec![42u32], Vec::<u32>::deserialize(&mut decoder).unwrap());
+        assert!(decoder.failed());
+
+        let mut decoder = ByteDecoder::new(&buf[..2]);
+        assert_eq!(0, u32::deserialize(&mut decoder).unwrap());
+        assert!(decoder.failed());
+
+        let buf = [0xffu8; 9];
+        let mut decoder = ByteDecoder::new(&buf);
+        assert_eq!("", String::deserialize(&mut decoder).unwrap());
+        assert!(decoder.failed());
+    }
+
+    fn packet_bytes(size: i32, compression: u8, route: u16, payload: &[u8]) -> BytesMut {
+        let mut buf = BytesMut::new();
+        buf.extend_from_slice(&size.to_be_bytes());
+        buf.extend_from_slice(&[0, compression]);
+        buf.extend_from_slice(&route.to_be_bytes());
+        buf.extend_from_slice(&1u16.to_be_bytes());
+        buf.extend_from_slice(&1u32.to_be_bytes());
+        buf.extend_from_slice(payload);
+        buf
+    }
+
+    #[test]
+    fn test_decode_rejects_malformed_packets() {
+        let mut codec = crate::codec::FlowCodec::new();
+        codec.set_max_packet_size(1024);
+
+        let invalid = |codec: &mut crate::codec::FlowCodec, mut buf: BytesMut| {
+            let error = codec.decode(&mut buf).unwrap_err();
+            assert_eq!(std::io::ErrorKind::InvalidData, error.kind());
+        };
+
+        invalid(&mut codec, packet_bytes(4, 0, 26, &[]));
+        invalid(&mut codec, packet_bytes(-1, 0, 26, &[]));
+        invalid(&mut codec, packet_bytes(2048, 0, 26, &[]));
+
+        // A produce request whose payload is shorter than its topic name says.
+        invalid(&mut codec, packet_bytes(13, 0, 6, &[0, 0, 0]));
+
+        // A payload decompressing past the maximum packet size.
+        let bomb = crate::codec::Compression::Lz4.compress(&[0u8; 4096]).unwrap();
+        invalid(&mut codec, packet_bytes(10 + bomb.len() as i32, 1, 26, &bomb));
+
+        let mut buf = packet_bytes(10, 0, 26, &[]);
+        assert!(codec.decode(&mut buf).unwrap().is_some());
+        assert!(buf.is_empty());
+    }
+
     #[test]
     fn test_decode_struct_from_bytes() {
         #[derive(FlowDeserializable, FlowSized)]
@@ -1,6 +1,7 @@
-use bytes::BytesMut;
+use bytes::{BufMut, BytesMut};
 use tokio_util::codec::Encoder;
 
+use super::Compression;
 use crate::messages::Packet;
 use crate::{SerializableSchema, SizedSchema};
 
@@ -8,8 +9,25 @@ impl Encoder<Packet> for super::FlowCodec {
     type Error = std::io::Error;
 
     fn encode(&mut self, input: Packet, output: &mut BytesMut) -> Result<(), Self::Error> {
-        output.reserve(input.size());
-        input.serialize(output);
+        let compression = match input.compression {
+            Compression::None if input.message.size() >= self.compression_threshold => self.compression,
+            compression => compression,
+        };
+
+        if compression == Compression::None {
+            output.reserve(input.size());
+            input.serialize(output);
+
+            return Ok(());
+        }
+
+        let mut payload = BytesMut::with_capacity(input.message.size());
+        input.message.serialize(&mut payload);
+        let payload = compression.compress(&payload)?;
+
+        output.reserve(Packet::HEADER_SIZE + payload.len());
+        input.serialize_header(compression, payload.len(), output);
+        output.put_slice(&payload);
 
         Ok(())
     }
@@ -21,7 +39,6 @@ mod test {
     use crate::{FlowSerializable, FlowSized};
 
     mod flow {
-        pub use crate::codec;
         pub use crate::flow::*;
     }
 
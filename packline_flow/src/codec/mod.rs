@@ -1,12 +1,53 @@
+pub use compression::Compression;
+
+pub mod compression;
 pub mod decoder;
 pub mod encoder;
 
+/// Packets whose payload is smaller than this are sent uncompressed, as the codec overhead
+/// outweighs the savings.
+pub const DEFAULT_COMPRESSION_THRESHOLD: usize = 512;
+
+/// Largest packet accepted by default, length field excluded. Decompressed payloads are held to the
+/// same limit.
+pub const DEFAULT_MAX_PACKET_SIZE: usize = 16 * 1024 * 1024;
+
 #[cfg_attr(debug_assertions, derive(Debug))]
-pub struct FlowCodec;
+pub struct FlowCodec {
+    compression: Compression,
+    compression_threshold: usize,
+    max_packet_size: usize,
+}
 
 impl FlowCodec {
     pub fn new() -> FlowCodec {
-        FlowCodec {}
+        FlowCodec::with_compression(Compression::None)
+    }
+
+    pub fn with_compression(compression: Compression) -> FlowCodec {
+        FlowCodec {
+            compression,
+            compression_threshold: DEFAULT_COMPRESSION_THRESHOLD,
+            max_packet_size: DEFAULT_MAX_PACKET_SIZE,
+        }
+    }
+
+    pub fn compression(&self) -> Compression {
+        self.compression
+    }
+
+    /// Sets the codec used for packets that don't request a compression of their own.
+    pub fn set_compression(&mut self, compression: Compression) {
+        self.compression = compression;
+    }
+
+    pub fn set_compression_threshold(&mut self, threshold: usize) {
+        self.compression_threshold = threshold;
+    }
+
+    /// Sets the size past which received packets are rejected, before or after decompression.
+    pub fn set_max_packet_size(&mut self, max_packet_size: usize) {
+        self.max_packet_size = max_packet_size;
     }
 }
 
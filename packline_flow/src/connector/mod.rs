@@ -1,136 +1,1597 @@
+use std::collections::HashMap;
+use std::convert::TryFrom;
 use std::net::SocketAddr;
-use std::sync::Arc;
+use std::sync::{Arc, Mutex as StdMutex};
+use std::time::Instant;
 
 use async_trait::async_trait;
+use futures::future::{self, Either};
 use futures::stream::SplitSink;
 use futures::stream::StreamExt;
 use futures::SinkExt;
-use tokio::net::TcpStream;
+use tokio::io::{AsyncRead, AsyncWrite};
 use tokio::runtime::Handle;
 use tokio::sync::Mutex;
+use tokio::task::JoinHandle;
 use tokio_util::codec::Framed;
 use tracing::{debug, info};
 
-use packline_core::app::App;
-use packline_core::connector::{TCPConnectionHandler, TCPConnectorHandler};
+use packline_core::app::acl::{AclRule, Operation, Resource, ANONYMOUS};
+use packline_core::app::channel::{consumer_group_id, Channel, Record, RecordBatch};
+use packline_core::app::{App, ChannelConfig};
+#[cfg(unix)]
+use packline_core::connector::UnixConnectorHandler;
+use packline_core::connector::{TCPConnectionHandler, TCPConnectorHandler, TCPStream};
+#[cfg(unix)]
+use tokio::net::{unix::SocketAddr as UnixSocketAddr, UnixStream};
 
-use crate::codec::FlowCodec;
+use crate::auth::{AuthSession, AuthStep, Authenticator};
+use crate::codec::{Compression, FlowCodec};
+use crate::messages::acl::{AclEntryV1, ListAclsResponseV1};
+use crate::messages::authenticate::{AuthenticateRequestV1, AuthenticateResponseV1};
 use crate::messages::consume::ConsumeV1;
+use crate::messages::error::{self, ErrorResponseV1};
+use crate::messages::group::{
+    CommitOffsetRequestV1, DescribeGroupResponseV1, GroupListingV1, GroupOffsetV1, ListGroupsResponseV1,
+    ResetGroupOffsetsRequestV1, NO_COMMITTED_OFFSET, RESET_TO_EARLIEST, RESET_TO_LATEST, RESET_TO_OFFSET,
+};
+use crate::messages::health::HealthCheckResponseV1;
+use crate::messages::produce::{ProduceBatchRequestV1, ProduceRequestV2, ProduceResponseV1};
+use crate::messages::subscribe::{SubscribeTopicRequestV2, GROUP_POSITION};
+use crate::messages::topic::{
+    CreateTopicRequestV1, DescribeTopicResponseV1, ListTopicsResponseV1, PartitionMetadataV1, TopicMetadataV1,
+};
+use crate::messages::unsubscribe::{StreamCloseV1, UnsubscribeRequestV1};
 use crate::messages::Message;
 use crate::messages::Packet;
 
+pub use self::websocket::FlowWebSocketConnector;
+
+mod websocket;
+
 pub struct FlowConnector {
     pub app: App,
+
+    /// Requires clients to authenticate before any other request when set.
+    pub authenticator: Option<Arc<Authenticator>>,
 }
 
-pub struct FlowConnectionHandler {
+impl FlowConnector {
+    pub fn new(app: App) -> FlowConnector {
+        FlowConnector {
+            app,
+            authenticator: None,
+        }
+    }
+
+    pub fn with_authenticator(mut self, authenticator: Authenticator) -> FlowConnector {
+        self.authenticator = Some(Arc::new(authenticator));
+        self
+    }
+
+    /// Creates the handler running the flow protocol over `stream`. `peer` identifies the remote
+    /// end in logs.
+    pub fn connection_handler<S: FlowStream>(&self, stream: S, peer: String) -> FlowConnectionHandler<S> {
+        FlowConnectionHandler {
+            app: self.app.clone(),
+            peer,
+            stream: Some(stream),
+            authenticator: self.authenticator.clone(),
+            auth_session: AuthSession::default(),
+            principal: None,
+        }
+    }
+}
+
+/// Byte stream the flow protocol can run on, such as a TCP connection or a Unix domain socket.
+pub trait FlowStream: AsyncRead + AsyncWrite + Send + Sync + Unpin + 'static {}
+
+impl<T: AsyncRead + AsyncWrite + Send + Sync + Unpin + 'static> FlowStream for T {}
+
+pub struct FlowConnectionHandler<S: FlowStream> {
     app: App,
-    addr: SocketAddr,
-    stream: Option<TcpStream>,
+    peer: String,
+    stream: Option<S>,
+
+    authenticator: Option<Arc<Authenticator>>,
+    auth_session: AuthSession,
+    principal: Option<String>,
 }
 
 #[async_trait]
 impl TCPConnectorHandler for FlowConnector {
-    fn handle_connection(&self, conn: (TcpStream, SocketAddr)) -> Box<dyn TCPConnectionHandler> {
-        Box::new(FlowConnectionHandler {
-            app: self.app.clone(),
-            addr: conn.1,
-            stream: Some(conn.0),
-        })
+    fn handle_connection(&self, conn: (TCPStream, SocketAddr)) -> Box<dyn TCPConnectionHandler> {
+        Box::new(self.connection_handler(conn.0, conn.1.to_string()))
+    }
+
+    fn name(&self) -> &'static str {
+        "flow"
+    }
+}
+
+#[cfg(unix)]
+impl UnixConnectorHandler for FlowConnector {
+    fn handle_connection(&self, conn: (UnixStream, UnixSocketAddr)) -> Box<dyn TCPConnectionHandler> {
+        let peer = match conn.1.as_pathname() {
+            Some(path) => path.display().to_string(),
+            None => "unix socket peer".to_string(),
+        };
+
+        Box::new(self.connection_handler(conn.0, peer))
+    }
+
+    fn name(&self) -> &'static str {
+        "flow"
     }
 }
 
 #[cfg_attr(debug_assertions, derive(Debug))]
-struct ConnectionState {
-    sink: Mutex<SplitSink<Framed<TcpStream, FlowCodec>, Packet>>,
+struct ConnectionState<S> {
+    sink: Mutex<SplitSink<Framed<S, FlowCodec>, Packet>>,
+    subscriptions: StdMutex<HashMap<u32, JoinHandle<()>>>,
+}
+
+impl<S: FlowStream> ConnectionState<S> {
+    fn cancel_subscription(&self, stream_id: u32) -> bool {
+        let handle = self.subscriptions.lock().unwrap().remove(&stream_id);
+
+        match handle {
+            Some(handle) => {
+                handle.abort();
+                true
+            }
+            None => false,
+        }
+    }
+
+    fn cancel_all_subscriptions(&self) {
+        let mut guard = self.subscriptions.lock().unwrap();
+        for (_, handle) in guard.drain() {
+            handle.abort();
+        }
+    }
+
+    /// Cancels every subscription, telling the client its streams are closed, and flushes what is
+    /// left to write before closing the connection.
+    async fn close(&self) {
+        let stream_ids = {
+            let mut guard = self.subscriptions.lock().unwrap();
+            guard
+                .drain()
+                .map(|(stream_id, handle)| {
+                    handle.abort();
+                    stream_id
+                })
+                .collect::<Vec<_>>()
+        };
+
+        for stream_id in stream_ids {
+            self.close_stream(stream_id).await;
+        }
+
+        let mut sink = self.sink.lock().await;
+        if let Err(e) = sink.close().await {
+            debug!("Failed to close flow connection: {:?}", e);
+        }
+    }
+
+    async fn close_stream(&self, stream_id: u32) {
+        let packet = Packet::new_stream_packet(stream_id, (5, 1), Message::StreamCloseV1(StreamCloseV1 {}));
+
+        let mut sink = self.sink.lock().await;
+        let _ = sink.send(packet).await;
+    }
 }
 
 #[async_trait]
-impl TCPConnectionHandler for FlowConnectionHandler {
+impl<S: FlowStream> TCPConnectionHandler for FlowConnectionHandler<S> {
     async fn handle(&mut self) -> Result<(), std::io::Error> {
         let handle = Handle::current();
-        debug!("New Flow Connection: {}", self.addr);
+        debug!("New Flow Connection: {}", self.peer);
 
-        let framed = Framed::new(std::mem::replace(&mut self.stream, None).unwrap(), FlowCodec::new());
-        let (sink, mut stream) = framed.split();
+        let mut framed = Framed::new(self.stream.take().unwrap(), FlowCodec::new());
+        let pending = self.handshake(&mut framed).await?;
 
-        let rc_state = Arc::new(ConnectionState { sink: Mutex::new(sink) });
+        let (sink, stream) = framed.split();
+        let mut stream = futures::stream::iter(pending.map(Ok)).chain(stream);
+
+        let rc_state = Arc::new(ConnectionState {
+            sink: Mutex::new(sink),
+            subscriptions: StdMutex::new(HashMap::new()),
+        });
 
         handle.spawn(async {
             debug!("Starting connection stream handler");
         });
 
+        // Requests are handled one at a time, so the response of the last one read has already been
+        // written when the shutdown is noticed.
+        let app = self.app.clone();
+        let shutdown = app.shutting_down();
+        futures::pin_mut!(shutdown);
+
         loop {
-            let packet = stream.next().await;
+            let packet = match future::select(stream.next(), shutdown.as_mut()).await {
+                Either::Left((packet, _)) => packet,
+                Either::Right(_) => {
+                    debug!("Closing flow connection {} for shutdown", self.peer);
+                    rc_state.close().await;
+                    return Ok(());
+                }
+            };
+
             debug!(?packet);
             match packet {
                 None => break,
-                Some(r) => {
+                Some(Err(e)) => {
+                    debug!("Failed to read from flow connection: {:?}", e);
+                    break;
+                }
+                Some(Ok(packet)) => {
+                    let started = Instant::now();
+                    let (route, _) = packet.route();
+
                     let state = rc_state.clone();
-                    let packet = self.handle_packet(state.clone(), r.unwrap()).unwrap();
+                    let packet = self.handle_packet(state.clone(), packet).await?;
                     if let Some(packet) = packet {
                         let mut sink = state.sink.lock().await;
                         let result = sink.send(packet).await;
 
                         debug!("Wrote to stream; success={:?}", result.is_ok());
                     }
+
+                    app.metrics()
+                        .observe_request("flow", &route.to_string(), started.elapsed());
                 }
             }
         }
 
+        rc_state.cancel_all_subscriptions();
+
         debug!("Flow connection finished");
         Ok(())
     }
 }
 
-impl FlowConnectionHandler {
-    fn handle_packet(&self, state: Arc<ConnectionState>, packet: Packet) -> Result<Option<Packet>, std::io::Error> {
+impl<S: FlowStream> FlowConnectionHandler<S> {
+    /// Applies the connection settings requested by the client. Returns the first packet back when
+    /// the client skipped the connect request, so that it can be handled as a regular packet.
+    async fn handshake(&self, framed: &mut Framed<S, FlowCodec>) -> Result<Option<Packet>, std::io::Error> {
+        let packet = match framed.next().await {
+            Some(packet) => packet?,
+            None => return Ok(None),
+        };
+
+        let compression = match &packet.message {
+            Message::ConnectRequestV1(_) => Compression::None,
+            Message::ConnectRequestV2(connect) => Compression::try_from(connect.compression)?,
+            _ => return Ok(Some(packet)),
+        };
+        debug!("Flow connection {} using {:?} compression", self.peer, compression);
+
+        framed.codec_mut().set_compression(compression);
+        framed.send(packet).await?;
+
+        Ok(None)
+    }
+
+    async fn handle_packet(
+        &mut self,
+        state: Arc<ConnectionState<S>>,
+        packet: Packet,
+    ) -> Result<Option<Packet>, std::io::Error> {
         info!("handling packet {:?}", &packet.message);
+
+        if !self.is_authenticated() {
+            match &packet.message {
+                Message::ConnectRequestV1(_)
+                | Message::ConnectRequestV2(_)
+                | Message::AuthenticateRequestV1(_)
+                | Message::HealthCheckRequestV1(_) => {}
+                _ => {
+                    return Ok(Some(error_response(
+                        &packet,
+                        error::AUTHENTICATION_REQUIRED,
+                        "authentication required".to_string(),
+                    )))
+                }
+            }
+        }
+
         match &packet.message {
+            Message::AuthenticateRequestV1(authenticate) => {
+                Ok(Some(self.handle_authenticate_request(&packet, authenticate).await))
+            }
             Message::SubscribeTopicRequestV1(subscribe) => {
+                let subscribe = SubscribeTopicRequestV2::from(subscribe.clone());
+                if let Err(denied) = self.authorize_subscribe(&packet, &subscribe) {
+                    return Ok(Some(denied));
+                }
+
+                self.handle_subscribe_topic_request(state, packet.context_id, subscribe);
+                Ok(None)
+            }
+            Message::SubscribeTopicRequestV2(subscribe) => {
+                if let Err(denied) = self.authorize_subscribe(&packet, subscribe) {
+                    return Ok(Some(denied));
+                }
+
                 self.handle_subscribe_topic_request(state, packet.context_id, subscribe.clone());
                 Ok(None)
             }
+            Message::UnsubscribeRequestV1(unsubscribe) => {
+                self.handle_unsubscribe_request(state, unsubscribe).await;
+                Ok(Some(packet))
+            }
+            Message::ProduceRequestV1(produce) => {
+                let produce = ProduceRequestV2::from(produce.clone());
+                Ok(Some(self.handle_produce_request(&packet, &produce).await))
+            }
+            Message::ProduceRequestV2(produce) => Ok(Some(self.handle_produce_request(&packet, produce).await)),
+            Message::ProduceBatchRequestV1(produce) => {
+                Ok(Some(self.handle_produce_batch_request(&packet, produce).await))
+            }
+            Message::CreateTopicRequestV1(create) => {
+                Ok(Some(self.handle_create_topic_request(packet.clone(), create).await))
+            }
+            Message::CreateAclRequestV1(create) => Ok(Some(self.handle_acl_request(
+                packet.clone(),
+                create.acl.clone(),
+                Operation::Create,
+            ))),
+            Message::DeleteAclRequestV1(delete) => Ok(Some(self.handle_acl_request(
+                packet.clone(),
+                delete.acl.clone(),
+                Operation::Delete,
+            ))),
+            Message::ListAclsRequestV1(_) => Ok(Some(self.handle_list_acls_request(&packet))),
+            Message::ListTopicsRequestV1(_) => Ok(Some(self.handle_list_topics_request(&packet).await)),
+            Message::DescribeTopicRequestV1(describe) => {
+                Ok(Some(self.handle_describe_topic_request(&packet, &describe.name).await))
+            }
+            Message::DeleteTopicRequestV1(delete) => Ok(Some(
+                self.handle_delete_topic_request(packet.clone(), &delete.name).await,
+            )),
+            Message::ListGroupsRequestV1(_) => Ok(Some(self.handle_list_groups_request(&packet).await)),
+            Message::DescribeGroupRequestV1(describe) => {
+                Ok(Some(self.handle_describe_group_request(&packet, &describe.group).await))
+            }
+            Message::ResetGroupOffsetsRequestV1(reset) => {
+                Ok(Some(self.handle_reset_group_offsets_request(&packet, reset).await))
+            }
+            Message::HealthCheckRequestV1(_) => Ok(Some(self.handle_health_check_request(&packet))),
+            Message::CommitOffsetRequestV1(commit) => {
+                Ok(Some(self.handle_commit_offset_request(&packet, commit).await))
+            }
             _ => Ok(Some(packet)),
         }
     }
 
+    fn is_authenticated(&self) -> bool {
+        self.authenticator.is_none() || self.principal.is_some()
+    }
+
+    fn principal(&self) -> &str {
+        self.principal.as_deref().unwrap_or(ANONYMOUS)
+    }
+
+    /// Returns the error response to send back when the connection principal may not perform
+    /// `operation` on `resource`.
+    fn authorize(&self, packet: &Packet, resource: &Resource, operation: Operation) -> Result<(), Packet> {
+        if self.app.authorize(self.principal(), resource, operation) {
+            return Ok(());
+        }
+
+        debug!(
+            "Denied {} on {}:{} to {}",
+            operation,
+            resource.resource_type,
+            resource.name,
+            self.principal()
+        );
+        Err(error_response(
+            packet,
+            error::AUTHORIZATION_FAILED,
+            format!("{} is not allowed to {} {}", self.principal(), operation, resource.name),
+        ))
+    }
+
+    fn authorize_subscribe(&self, packet: &Packet, subscribe: &SubscribeTopicRequestV2) -> Result<(), Packet> {
+        self.authorize(packet, &Resource::topic(&subscribe.topic), Operation::Consume)?;
+
+        if !subscribe.consumer_group_id.is_empty() {
+            self.authorize(
+                packet,
+                &Resource::consumer_group(&subscribe.consumer_group_id),
+                Operation::Consume,
+            )?;
+        }
+
+        Ok(())
+    }
+
+    async fn handle_authenticate_request(&mut self, packet: &Packet, authenticate: &AuthenticateRequestV1) -> Packet {
+        let authenticator = match &self.authenticator {
+            Some(authenticator) => authenticator,
+            None => {
+                return error_response(
+                    packet,
+                    error::AUTHENTICATION_FAILED,
+                    "authentication is not enabled".to_string(),
+                )
+            }
+        };
+
+        let step = authenticator
+            .step(&mut self.auth_session, &authenticate.mechanism, &authenticate.payload)
+            .await;
+        let payload = match step {
+            Ok(AuthStep::Continue(payload)) => payload,
+            Ok(AuthStep::Done(principal, payload)) => {
+                info!("Flow connection {} authenticated as {}", self.peer, principal);
+                self.principal = Some(principal);
+                payload
+            }
+            Err(e) => {
+                debug!("Flow connection {} failed to authenticate: {}", self.peer, e);
+                self.auth_session = AuthSession::default();
+                return error_response(packet, error::AUTHENTICATION_FAILED, e.to_string());
+            }
+        };
+
+        packet.response(
+            (10, 1),
+            Message::AuthenticateResponseV1(AuthenticateResponseV1 { payload }),
+        )
+    }
+
+    async fn handle_produce_request(&self, packet: &Packet, produce: &ProduceRequestV2) -> Packet {
+        if !produce.is_valid() {
+            debug!("Rejecting corrupted batch for topic {}", produce.topic);
+            return error_response(
+                packet,
+                error::CORRUPT_BATCH,
+                format!("checksum mismatch on batch for topic {}", produce.topic),
+            );
+        }
+
+        if let Err(denied) = self.authorize(packet, &Resource::topic(&produce.topic), Operation::Produce) {
+            return denied;
+        }
+
+        let channel = self.app.get_channel(&(produce.topic.clone(), produce.partition)).await;
+
+        match channel {
+            Some(channel) => {
+                let mut records = produce.records.iter().cloned().map(Record::from).collect();
+                channel.producer().produce(&mut records).await;
+                packet.response((7, 1), Message::ProduceResponseV1(ProduceResponseV1 {}))
+            }
+            None => error_response(
+                packet,
+                error::UNKNOWN_TOPIC,
+                format!("unknown topic {} partition {}", produce.topic, produce.partition),
+            ),
+        }
+    }
+
+    /// Stores a batch compressed by the producer as is, once checked that its records can be read.
+    async fn handle_produce_batch_request(&self, packet: &Packet, produce: &ProduceBatchRequestV1) -> Packet {
+        if !produce.is_valid() {
+            debug!("Rejecting corrupted batch for topic {}", produce.topic);
+            return error_response(
+                packet,
+                error::CORRUPT_BATCH,
+                format!("checksum mismatch on batch for topic {}", produce.topic),
+            );
+        }
+
+        let batch = match Compression::try_from(produce.compression) {
+            Ok(compression) => RecordBatch {
+                compression,
+                count: produce.count,
+                payload: produce.payload.clone(),
+            },
+            Err(e) => return error_response(packet, error::INVALID_REQUEST, e.to_string()),
+        };
+
+        if let Err(denied) = self.authorize(packet, &Resource::topic(&produce.topic), Operation::Produce) {
+            return denied;
+        }
+
+        let channel = self.app.get_channel(&(produce.topic.clone(), 1u16)).await;
+
+        match channel {
+            Some(channel) => match channel.producer().produce_batch(batch).await {
+                Ok(_) => packet.response((7, 1), Message::ProduceResponseV1(ProduceResponseV1 {})),
+                Err(e) => error_response(
+                    packet,
+                    error::CORRUPT_BATCH,
+                    format!("invalid batch for topic {}: {}", produce.topic, e),
+                ),
+            },
+            None => error_response(packet, error::UNKNOWN_TOPIC, format!("unknown topic {}", produce.topic)),
+        }
+    }
+
+    async fn handle_create_topic_request(&self, packet: Packet, create: &CreateTopicRequestV1) -> Packet {
+        if let Err(denied) = self.authorize(&packet, &Resource::topic(&create.name), Operation::Create) {
+            return denied;
+        }
+
+        let config = ChannelConfig {
+            name: create.name.clone(),
+            partitions: create.partitions,
+        };
+
+        match self.app.create_channel(config).await {
+            Ok(_) => packet,
+            Err(_) => error_response(
+                &packet,
+                error::INVALID_REQUEST,
+                format!("failed to create topic {}", create.name),
+            ),
+        }
+    }
+
+    /// Adds or removes an ACL rule, depending on `operation`.
+    fn handle_acl_request(&self, packet: Packet, acl: AclEntryV1, operation: Operation) -> Packet {
+        if let Err(denied) = self.authorize(&packet, &Resource::cluster(), operation) {
+            return denied;
+        }
+
+        let authorizer = match self.app.authorizer() {
+            Some(authorizer) => authorizer,
+            None => {
+                return error_response(
+                    &packet,
+                    error::INVALID_REQUEST,
+                    "authorization is not enabled".to_string(),
+                )
+            }
+        };
+
+        let result = AclRule::try_from(acl).and_then(|rule| match operation {
+            Operation::Delete => authorizer.remove_rule(&rule),
+            _ => authorizer.add_rule(rule),
+        });
+
+        match result {
+            Ok(_) => packet,
+            Err(e) => error_response(&packet, error::INVALID_REQUEST, e.to_string()),
+        }
+    }
+
+    fn handle_list_acls_request(&self, packet: &Packet) -> Packet {
+        if let Err(denied) = self.authorize(packet, &Resource::cluster(), Operation::Describe) {
+            return denied;
+        }
+
+        let acls = self
+            .app
+            .authorizer()
+            .map(|authorizer| authorizer.rules().into_iter().map(AclEntryV1::from).collect())
+            .unwrap_or_default();
+
+        packet.response((15, 1), Message::ListAclsResponseV1(ListAclsResponseV1 { acls }))
+    }
+
+    /// Reports the readiness of the broker. Like its HTTP probes, it requires no authentication.
+    fn handle_health_check_request(&self, packet: &Packet) -> Packet {
+        let pending = self.app.pending_checks();
+        packet.response(
+            (27, 1),
+            Message::HealthCheckResponseV1(HealthCheckResponseV1 {
+                ready: pending.is_empty() as u8,
+                pending,
+            }),
+        )
+    }
+
+    /// Lists the topics the connection principal may describe.
+    async fn handle_list_topics_request(&self, packet: &Packet) -> Packet {
+        let mut topics = vec![];
+        for channel in self.app.list_channels().await {
+            if self
+                .app
+                .authorize(self.principal(), &Resource::topic(&channel.name), Operation::Describe)
+            {
+                topics.push(self.topic_metadata(&channel).await);
+            }
+        }
+
+        packet.response((17, 1), Message::ListTopicsResponseV1(ListTopicsResponseV1 { topics }))
+    }
+
+    async fn handle_describe_topic_request(&self, packet: &Packet, name: &str) -> Packet {
+        if let Err(denied) = self.authorize(packet, &Resource::topic(name), Operation::Describe) {
+            return denied;
+        }
+
+        match self.find_channel(name).await {
+            Some(channel) => packet.response(
+                (19, 1),
+                Message::DescribeTopicResponseV1(DescribeTopicResponseV1 {
+                    topic: self.topic_metadata(&channel).await,
+                }),
+            ),
+            None => error_response(packet, error::UNKNOWN_TOPIC, format!("unknown topic {}", name)),
+        }
+    }
+
+    async fn handle_delete_topic_request(&self, packet: Packet, name: &str) -> Packet {
+        if let Err(denied) = self.authorize(&packet, &Resource::topic(name), Operation::Delete) {
+            return denied;
+        }
+
+        if self.app.delete_channel(name).await {
+            info!("Deleted topic {}", name);
+            packet
+        } else {
+            error_response(&packet, error::UNKNOWN_TOPIC, format!("unknown topic {}", name))
+        }
+    }
+
+    /// Lists the consumer groups the connection principal may describe, along with the topics they
+    /// have offsets on.
+    async fn handle_list_groups_request(&self, packet: &Packet) -> Packet {
+        let mut topics: HashMap<u128, (String, Vec<String>)> = HashMap::new();
+        for config in self.app.list_channels().await {
+            for partition in 1..=config.partitions {
+                let channel = match self.app.get_channel(&(config.name.clone(), partition)).await {
+                    Some(channel) => channel,
+                    None => continue,
+                };
+
+                for id in channel.groups().await {
+                    let (_, group_topics) = topics
+                        .entry(id)
+                        .or_insert_with(|| (group_display_name(&channel, id), vec![]));
+                    if !group_topics.contains(&config.name) {
+                        group_topics.push(config.name.clone());
+                    }
+                }
+            }
+        }
+
+        let mut groups = topics
+            .into_values()
+            .filter(|(group, _)| {
+                self.app
+                    .authorize(self.principal(), &Resource::consumer_group(group), Operation::Describe)
+            })
+            .map(|(group, topics)| GroupListingV1 { group, topics })
+            .collect::<Vec<_>>();
+        groups.sort_by(|a, b| a.group.cmp(&b.group));
+
+        packet.response((22, 1), Message::ListGroupsResponseV1(ListGroupsResponseV1 { groups }))
+    }
+
+    async fn handle_describe_group_request(&self, packet: &Packet, group: &str) -> Packet {
+        if let Err(denied) = self.authorize(packet, &Resource::consumer_group(group), Operation::Describe) {
+            return denied;
+        }
+
+        let id = match parse_group_id(group) {
+            Some(id) => id,
+            None => consumer_group_id(group),
+        };
+
+        let mut offsets = vec![];
+        for config in self.app.list_channels().await {
+            for partition in 1..=config.partitions {
+                let channel = match self.app.get_channel(&(config.name.clone(), partition)).await {
+                    Some(channel) => channel,
+                    None => continue,
+                };
+
+                if channel.groups().await.contains(&id) {
+                    offsets.push(group_offset(&config.name, partition, &channel, id).await);
+                }
+            }
+        }
+
+        if offsets.is_empty() {
+            return error_response(packet, error::INVALID_REQUEST, format!("unknown group {}", group));
+        }
+
+        describe_group_response(packet, group, offsets)
+    }
+
+    /// Moves a consumer group on every partition of a topic, creating its offsets when the group
+    /// never consumed from it.
+    async fn handle_reset_group_offsets_request(&self, packet: &Packet, reset: &ResetGroupOffsetsRequestV1) -> Packet {
+        if let Err(denied) = self.authorize(packet, &Resource::consumer_group(&reset.group), Operation::Consume) {
+            return denied;
+        }
+
+        if let Err(denied) = self.authorize(packet, &Resource::topic(&reset.topic), Operation::Describe) {
+            return denied;
+        }
+
+        let config = match self.find_channel(&reset.topic).await {
+            Some(config) => config,
+            None => return error_response(packet, error::UNKNOWN_TOPIC, format!("unknown topic {}", reset.topic)),
+        };
+
+        let mut offsets = vec![];
+        for partition in 1..=config.partitions {
+            let channel = match self.app.get_channel(&(config.name.clone(), partition)).await {
+                Some(channel) => channel,
+                None => continue,
+            };
+
+            let id = match parse_group_id(&reset.group) {
+                Some(id) => id,
+                None => channel.register_group(&reset.group),
+            };
+
+            let offset = match reset.target {
+                RESET_TO_EARLIEST => channel.start_offset(),
+                RESET_TO_LATEST => channel.end_offset(),
+                RESET_TO_OFFSET => reset.offset.clamp(channel.start_offset(), channel.end_offset()),
+                target => {
+                    return error_response(
+                        packet,
+                        error::INVALID_REQUEST,
+                        format!("invalid reset target {}", target),
+                    )
+                }
+            };
+
+            if channel.seek(id, offset).await.is_err() {
+                return error_response(
+                    packet,
+                    error::INVALID_REQUEST,
+                    format!(
+                        "offset {} is past the end of {} partition {}",
+                        offset, config.name, partition
+                    ),
+                );
+            }
+
+            offsets.push(group_offset(&config.name, partition, &channel, id).await);
+        }
+
+        describe_group_response(packet, &reset.group, offsets)
+    }
+
+    async fn handle_commit_offset_request(&self, packet: &Packet, commit: &CommitOffsetRequestV1) -> Packet {
+        if let Err(denied) = self.authorize(packet, &Resource::consumer_group(&commit.group), Operation::Consume) {
+            return denied;
+        }
+
+        if let Err(denied) = self.authorize(packet, &Resource::topic(&commit.topic), Operation::Consume) {
+            return denied;
+        }
+
+        let channel = match self.app.get_channel(&(commit.topic.clone(), commit.partition)).await {
+            Some(channel) => channel,
+            None => {
+                return error_response(
+                    packet,
+                    error::UNKNOWN_TOPIC,
+                    format!("unknown topic {} partition {}", commit.topic, commit.partition),
+                )
+            }
+        };
+
+        let id = match parse_group_id(&commit.group) {
+            Some(id) => id,
+            None => channel.register_group(&commit.group),
+        };
+
+        if channel.commit_offset(id, commit.offset).await.is_err() {
+            return error_response(
+                packet,
+                error::INVALID_REQUEST,
+                format!(
+                    "offset {} is past the end of {} partition {}",
+                    commit.offset, commit.topic, commit.partition
+                ),
+            );
+        }
+
+        let offset = group_offset(&commit.topic, commit.partition, &channel, id).await;
+        describe_group_response(packet, &commit.group, vec![offset])
+    }
+
+    async fn find_channel(&self, name: &str) -> Option<ChannelConfig> {
+        self.app
+            .list_channels()
+            .await
+            .into_iter()
+            .find(|channel| channel.name == name)
+    }
+
+    async fn topic_metadata(&self, config: &ChannelConfig) -> TopicMetadataV1 {
+        let mut partitions = Vec::with_capacity(config.partitions.into());
+        for partition in 1..=config.partitions {
+            if let Some(channel) = self.app.get_channel(&(config.name.clone(), partition)).await {
+                partitions.push(PartitionMetadataV1 {
+                    partition,
+                    start_offset: channel.start_offset(),
+                    end_offset: channel.end_offset(),
+                });
+            }
+        }
+
+        TopicMetadataV1 {
+            name: config.name.clone(),
+            partitions,
+        }
+    }
+
+    async fn handle_unsubscribe_request(&self, state: Arc<ConnectionState<S>>, unsubscribe: &UnsubscribeRequestV1) {
+        if state.cancel_subscription(unsubscribe.stream_id) {
+            debug!("Cancelled subscription {}", unsubscribe.stream_id);
+            state.close_stream(unsubscribe.stream_id).await;
+        }
+    }
+
     fn handle_subscribe_topic_request(
         &self,
-        state: Arc<ConnectionState>,
+        state: Arc<ConnectionState<S>>,
         context_id: u32,
-        subscribe: super::messages::subscribe::SubscribeTopicRequestV1,
+        subscribe: SubscribeTopicRequestV2,
     ) {
         let handle = Handle::current();
 
         let app = self.app.clone();
-        handle.spawn(async move {
+        let task_state = state.clone();
+
+        // The lock is held while spawning so that a finished task can't try to remove its own entry
+        // before it was registered.
+        let mut subscriptions = state.subscriptions.lock().unwrap();
+        let task = handle.spawn(async move {
+            let state = task_state;
             let topic = subscribe.topic.to_string();
             let channel = app.get_channel(&(topic.clone(), 1u16)).await;
 
             if let Some(channel) = channel {
                 info!("Starting consuming from channel {:?}", &topic);
-                let consumer = channel.consumer(0);
+                let offset = match subscribe.offset {
+                    GROUP_POSITION => None,
+                    offset => Some((offset as u64).clamp(channel.start_offset(), channel.end_offset())),
+                };
+
+                // Subscriptions without a group each consume on their own, from the first stored
+                // record unless told otherwise.
+                let consumer = if subscribe.consumer_group_id.is_empty() {
+                    channel.private_consumer(offset.unwrap_or_else(|| channel.start_offset()))
+                } else {
+                    if let Some(offset) = offset {
+                        let _ = channel
+                            .seek(channel.register_group(&subscribe.consumer_group_id), offset)
+                            .await;
+                    }
+
+                    channel.group_consumer(&subscribe.consumer_group_id)
+                };
 
                 loop {
                     let records = consumer.consume().await;
 
-                    {
-                        let mut guard = state.sink.lock().await;
-
-                        let packet = Packet::new_stream_packet(
+                    // Records whose bytes no longer match their checksum end the subscription.
+                    let corrupted = records
+                        .iter()
+                        .find(|record| !record.is_valid())
+                        .map(|record| record.offset);
+                    let packet = match corrupted {
+                        Some(offset) => Packet::new_stream_packet(
                             context_id,
-                            (3, 1),
-                            Message::ConsumeV1(ConsumeV1 {
-                                topic: topic.clone(),
-                                records,
+                            (8, 1),
+                            Message::ErrorResponseV1(ErrorResponseV1 {
+                                code: error::CORRUPT_BATCH,
+                                message: format!("corrupted record at offset {} of topic {}", offset, topic),
                             }),
-                        );
+                        ),
+                        None => Packet::new_stream_packet(
+                            context_id,
+                            (3, 1),
+                            Message::ConsumeV1(ConsumeV1::from_stored(topic.clone(), records)),
+                        ),
+                    };
+
+                    if state.sink.lock().await.send(packet).await.is_err() {
+                        debug!("Stopping subscription {}, connection closed", context_id);
+                        break;
+                    }
 
-                        let _ = guard.send(packet).await;
+                    if corrupted.is_some() {
+                        state.close_stream(context_id).await;
+                        break;
                     }
                 }
+            } else {
+                state.close_stream(context_id).await;
             }
+
+            state.subscriptions.lock().unwrap().remove(&context_id);
+        });
+
+        subscriptions.insert(context_id, task);
+    }
+}
+
+/// Returns the registered name of a consumer group, or its id prefixed with `#` when it has none.
+fn group_display_name(channel: &Channel, id: u128) -> String {
+    channel.group_name(id).unwrap_or_else(|| format!("#{:032x}", id))
+}
+
+/// Parses a group named after its id, as listed by [`group_display_name`].
+fn parse_group_id(group: &str) -> Option<u128> {
+    let hex = group.strip_prefix('#')?;
+    if hex.len() != 32 {
+        return None;
+    }
+
+    u128::from_str_radix(hex, 16).ok()
+}
+
+async fn group_offset(topic: &str, partition: u16, channel: &Channel, id: u128) -> GroupOffsetV1 {
+    let (committed, position) = channel.offsets(id).await;
+
+    GroupOffsetV1 {
+        topic: topic.to_string(),
+        partition,
+        committed: committed.map(|offset| offset as i64).unwrap_or(NO_COMMITTED_OFFSET),
+        position,
+        end_offset: channel.end_offset(),
+    }
+}
+
+fn describe_group_response(packet: &Packet, group: &str, offsets: Vec<GroupOffsetV1>) -> Packet {
+    packet.response(
+        (24, 1),
+        Message::DescribeGroupResponseV1(DescribeGroupResponseV1 {
+            group: group.to_string(),
+            offsets,
+        }),
+    )
+}
+
+fn error_response(packet: &Packet, code: u16, message: String) -> Packet {
+    packet.response((8, 1), Message::ErrorResponseV1(ErrorResponseV1 { code, message }))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use crate::messages::connect::{ConnectRequestV1, ConnectRequestV2};
+    use crate::messages::group::{DescribeGroupRequestV1, ListGroupsRequestV1};
+    use crate::messages::health::HealthCheckRequestV1;
+    use crate::messages::produce::ProduceRequestV1;
+    use crate::messages::record::RecordV1;
+    use crate::messages::subscribe::SubscribeTopicRequestV1;
+    use crate::messages::topic::{DeleteTopicRequestV1, DescribeTopicRequestV1, ListTopicsRequestV1};
+    use crate::messages::RouteWithVersion;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn test_shutdown_closes_subscriptions_and_connection() {
+        let app = App::new();
+        app.create_channel(ChannelConfig {
+            name: "orders".to_string(),
+            partitions: 1,
+        })
+        .await
+        .unwrap();
+
+        let (client, server) = tokio::io::duplex(4096);
+        let mut handler = FlowConnector::new(app.clone()).connection_handler(server, "test".to_string());
+        let task = tokio::spawn(async move { handler.handle().await });
+
+        let mut framed = Framed::new(client, FlowCodec::new());
+        let subscribe = Packet::new(
+            (2, 1),
+            Message::SubscribeTopicRequestV1(SubscribeTopicRequestV1 {
+                topic: "orders".to_string(),
+                consumer_group_id: "billing".to_string(),
+            }),
+        );
+        let context_id = subscribe.context_id;
+        framed.send(subscribe).await.unwrap();
+
+        // A produce answered before the shutdown makes sure the subscription is running.
+        let produce = Packet::new(
+            (7, 1),
+            Message::ProduceRequestV1(ProduceRequestV1::new("orders".to_string(), vec![])),
+        );
+        framed.send(produce).await.unwrap();
+
+        while !matches!(
+            framed.next().await.unwrap().unwrap().message,
+            Message::ProduceResponseV1(_)
+        ) {}
+
+        app.shutdown();
+
+        let closed = tokio::time::timeout(Duration::from_secs(2), async {
+            let mut closed = vec![];
+            while let Some(packet) = framed.next().await {
+                let packet = packet.unwrap();
+                if let Message::StreamCloseV1(_) = packet.message {
+                    closed.push(packet.context_id);
+                }
+            }
+            closed
+        })
+        .await
+        .expect("connection wasn't closed");
+
+        assert_eq!(vec![context_id], closed);
+        assert!(task.await.unwrap().is_ok());
+    }
+
+    async fn request<S: FlowStream>(
+        framed: &mut Framed<S, FlowCodec>,
+        route: RouteWithVersion,
+        message: Message,
+    ) -> Message {
+        framed.send(Packet::new(route, message)).await.unwrap();
+        framed.next().await.unwrap().unwrap().message
+    }
+
+    #[tokio::test]
+    async fn test_topic_and_group_administration() {
+        let app = App::new();
+        app.create_channel(ChannelConfig {
+            name: "orders".to_string(),
+            partitions: 2,
+        })
+        .await
+        .unwrap();
+
+        let channel = app.get_channel(&("orders".to_string(), 1)).await.unwrap();
+        channel
+            .producer()
+            .produce(&mut vec![Record::from_value("a"), Record::from_value("b")])
+            .await;
+
+        let (client, server) = tokio::io::duplex(4096);
+        let mut handler = FlowConnector::new(app.clone()).connection_handler(server, "test".to_string());
+        tokio::spawn(async move { handler.handle().await });
+
+        let mut framed = Framed::new(client, FlowCodec::new());
+
+        let commit = |offset| {
+            Message::CommitOffsetRequestV1(CommitOffsetRequestV1 {
+                group: "billing".to_string(),
+                topic: "orders".to_string(),
+                partition: 1,
+                offset,
+            })
+        };
+        match request(&mut framed, (28, 1), commit(3)).await {
+            Message::ErrorResponseV1(e) => assert_eq!(error::INVALID_REQUEST, e.code),
+            message => panic!("unexpected response {:?}", message),
+        }
+        match request(&mut framed, (28, 1), commit(1)).await {
+            Message::DescribeGroupResponseV1(describe) => assert_eq!(1, describe.offsets[0].committed),
+            message => panic!("unexpected response {:?}", message),
+        }
+
+        let reset = request(
+            &mut framed,
+            (25, 1),
+            Message::ResetGroupOffsetsRequestV1(ResetGroupOffsetsRequestV1 {
+                group: "audit".to_string(),
+                topic: "orders".to_string(),
+                target: RESET_TO_LATEST,
+                offset: 0,
+            }),
+        )
+        .await;
+        match reset {
+            Message::DescribeGroupResponseV1(describe) => assert_eq!(
+                vec![(1, 2), (2, 0)],
+                describe
+                    .offsets
+                    .iter()
+                    .map(|offset| (offset.partition, offset.position))
+                    .collect::<Vec<_>>()
+            ),
+            message => panic!("unexpected response {:?}", message),
+        }
+
+        match request(
+            &mut framed,
+            (21, 1),
+            Message::ListGroupsRequestV1(ListGroupsRequestV1 {}),
+        )
+        .await
+        {
+            Message::ListGroupsResponseV1(list) => assert_eq!(
+                vec!["audit", "billing"],
+                list.groups.iter().map(|group| group.group.as_str()).collect::<Vec<_>>()
+            ),
+            message => panic!("unexpected response {:?}", message),
+        }
+
+        match request(
+            &mut framed,
+            (23, 1),
+            Message::DescribeGroupRequestV1(DescribeGroupRequestV1 {
+                group: "billing".to_string(),
+            }),
+        )
+        .await
+        {
+            Message::DescribeGroupResponseV1(describe) => assert_eq!(
+                vec![GroupOffsetV1 {
+                    topic: "orders".to_string(),
+                    partition: 1,
+                    committed: 1,
+                    position: 0,
+                    end_offset: 2,
+                }],
+                describe.offsets
+            ),
+            message => panic!("unexpected response {:?}", message),
+        }
+
+        match request(
+            &mut framed,
+            (18, 1),
+            Message::DescribeTopicRequestV1(DescribeTopicRequestV1 {
+                name: "orders".to_string(),
+            }),
+        )
+        .await
+        {
+            Message::DescribeTopicResponseV1(describe) => assert_eq!(
+                vec![(1, 0, 2), (2, 0, 0)],
+                describe
+                    .topic
+                    .partitions
+                    .iter()
+                    .map(|p| (p.partition, p.start_offset, p.end_offset))
+                    .collect::<Vec<_>>()
+            ),
+            message => panic!("unexpected response {:?}", message),
+        }
+
+        let delete = Message::DeleteTopicRequestV1(DeleteTopicRequestV1 {
+            name: "orders".to_string(),
         });
+        assert!(matches!(
+            request(&mut framed, (20, 1), delete.clone()).await,
+            Message::DeleteTopicRequestV1(_)
+        ));
+        assert!(matches!(
+            request(&mut framed, (20, 1), delete).await,
+            Message::ErrorResponseV1(ErrorResponseV1 {
+                code: error::UNKNOWN_TOPIC,
+                ..
+            })
+        ));
+
+        match request(
+            &mut framed,
+            (16, 1),
+            Message::ListTopicsRequestV1(ListTopicsRequestV1 {}),
+        )
+        .await
+        {
+            Message::ListTopicsResponseV1(list) => assert!(list.topics.is_empty()),
+            message => panic!("unexpected response {:?}", message),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_health_check() {
+        let app = App::new();
+        app.health().require("storage");
+
+        let (client, server) = tokio::io::duplex(4096);
+        let mut handler = FlowConnector::new(app.clone()).connection_handler(server, "test".to_string());
+        tokio::spawn(async move { handler.handle().await });
+
+        let mut framed = Framed::new(client, FlowCodec::new());
+        match request(
+            &mut framed,
+            (26, 1),
+            Message::HealthCheckRequestV1(HealthCheckRequestV1 {}),
+        )
+        .await
+        {
+            Message::HealthCheckResponseV1(health) => {
+                assert_eq!(0, health.ready);
+                assert_eq!(vec!["storage"], health.pending);
+            }
+            message => panic!("unexpected response {:?}", message),
+        }
+
+        app.health().pass("storage");
+        match request(
+            &mut framed,
+            (26, 1),
+            Message::HealthCheckRequestV1(HealthCheckRequestV1 {}),
+        )
+        .await
+        {
+            Message::HealthCheckResponseV1(health) => {
+                assert_eq!(1, health.ready);
+                assert!(health.pending.is_empty());
+            }
+            message => panic!("unexpected response {:?}", message),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_subscribe_resumes_from_offset() {
+        let app = App::new();
+        app.create_channel(ChannelConfig {
+            name: "orders".to_string(),
+            partitions: 1,
+        })
+        .await
+        .unwrap();
+
+        let channel = app.get_channel(&("orders".to_string(), 1)).await.unwrap();
+        channel
+            .producer()
+            .produce(&mut vec![
+                Record::from_value("a"),
+                Record::from_value("b"),
+                Record::from_value("c"),
+            ])
+            .await;
+
+        let (client, server) = tokio::io::duplex(4096);
+        let mut handler = FlowConnector::new(app.clone()).connection_handler(server, "test".to_string());
+        tokio::spawn(async move { handler.handle().await });
+
+        let mut framed = Framed::new(client, FlowCodec::new());
+        framed
+            .send(Packet::new(
+                (2, 2),
+                Message::SubscribeTopicRequestV2(SubscribeTopicRequestV2 {
+                    topic: "orders".to_string(),
+                    consumer_group_id: "billing".to_string(),
+                    offset: 1,
+                }),
+            ))
+            .await
+            .unwrap();
+
+        match framed.next().await.unwrap().unwrap().message {
+            Message::ConsumeV1(consume) => assert_eq!(
+                vec![1, 2],
+                consume.records.iter().map(|record| record.offset).collect::<Vec<_>>()
+            ),
+            message => panic!("unexpected message {:?}", message),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_subscriptions_without_group_consume_on_their_own() {
+        let app = App::new();
+        app.create_channel(ChannelConfig {
+            name: "orders".to_string(),
+            partitions: 1,
+        })
+        .await
+        .unwrap();
+
+        let channel = app.get_channel(&("orders".to_string(), 1)).await.unwrap();
+        channel
+            .producer()
+            .produce(&mut vec![Record::from_value("a"), Record::from_value("b")])
+            .await;
+
+        let (client, server) = tokio::io::duplex(4096);
+        let mut handler = FlowConnector::new(app.clone()).connection_handler(server, "test".to_string());
+        tokio::spawn(async move { handler.handle().await });
+        let mut framed = Framed::new(client, FlowCodec::new());
+
+        let resuming = Packet::new(
+            (2, 2),
+            Message::SubscribeTopicRequestV2(SubscribeTopicRequestV2 {
+                topic: "orders".to_string(),
+                consumer_group_id: String::new(),
+                offset: 1,
+            }),
+        );
+        let mut expected = HashMap::new();
+        for (packet, offsets) in [
+            (subscribe(""), vec![0, 1]),
+            (subscribe(""), vec![0, 1]),
+            (resuming, vec![1]),
+        ] {
+            expected.insert(packet.context_id, offsets);
+            framed.send(packet).await.unwrap();
+        }
+
+        for _ in 0..3 {
+            let packet = framed.next().await.unwrap().unwrap();
+            match packet.message {
+                Message::ConsumeV1(consume) => assert_eq!(
+                    expected.remove(&packet.context_id).unwrap(),
+                    consume.records.iter().map(|record| record.offset).collect::<Vec<_>>()
+                ),
+                message => panic!("unexpected message {:?}", message),
+            }
+        }
+
+        assert!(channel.groups().await.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_connect_negotiates_compression() {
+        let app = App::new();
+        app.create_channel(ChannelConfig {
+            name: "orders".to_string(),
+            partitions: 1,
+        })
+        .await
+        .unwrap();
+        let channel = app.get_channel(&("orders".to_string(), 1)).await.unwrap();
+        channel
+            .producer()
+            .produce(&mut vec![Record::from_value(vec![42u8; 4096])])
+            .await;
+
+        for (group, connect, compression) in [
+            (
+                "billing",
+                Packet::new((1, 1), Message::ConnectRequestV1(ConnectRequestV1 {})),
+                Compression::None,
+            ),
+            (
+                "audit",
+                Packet::new((1, 2), Message::ConnectRequestV2(ConnectRequestV2 { compression: 2 })),
+                Compression::Zstd,
+            ),
+        ] {
+            let (client, server) = tokio::io::duplex(4096);
+            let mut handler = FlowConnector::new(app.clone()).connection_handler(server, "test".to_string());
+            tokio::spawn(async move { handler.handle().await });
+
+            let mut framed = Framed::new(client, FlowCodec::new());
+            let route = connect.route();
+            framed.send(connect).await.unwrap();
+            assert_eq!(route, framed.next().await.unwrap().unwrap().route());
+
+            framed.send(subscribe(group)).await.unwrap();
+            let consume = framed.next().await.unwrap().unwrap();
+            assert!(matches!(consume.message, Message::ConsumeV1(_)));
+            assert_eq!(compression, consume.compression);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_subscribe_checks_stored_checksums() {
+        let app = App::new();
+        app.create_channel(ChannelConfig {
+            name: "orders".to_string(),
+            partitions: 1,
+        })
+        .await
+        .unwrap();
+
+        let (client, server) = tokio::io::duplex(4096);
+        let mut handler = FlowConnector::new(app.clone()).connection_handler(server, "test".to_string());
+        tokio::spawn(async move { handler.handle().await });
+        let mut framed = Framed::new(client, FlowCodec::new());
+
+        // Consumers get the checksum of the produced batch, offsets aside.
+        let produce = ProduceRequestV1::new(
+            "orders".to_string(),
+            vec![
+                RecordV1::from(Record::new("k", "a")),
+                RecordV1::from(Record::from_value("b")),
+            ],
+        );
+        let crc = produce.crc;
+        assert!(matches!(
+            request(&mut framed, (6, 1), Message::ProduceRequestV1(produce)).await,
+            Message::ProduceResponseV1(_)
+        ));
+
+        let billing = subscribe("billing");
+        let stream_id = billing.context_id;
+        framed.send(billing).await.unwrap();
+        match framed.next().await.unwrap().unwrap().message {
+            Message::ConsumeV1(consume) => {
+                assert_eq!(crc, consume.crc);
+                assert!(consume.is_valid());
+            }
+            message => panic!("unexpected message {:?}", message),
+        }
+
+        // A record whose bytes changed once stored ends the subscription.
+        let channel = app.get_channel(&("orders".to_string(), 1)).await.unwrap();
+        let corrupted = Record {
+            value: b"d".to_vec(),
+            ..Record::from_value("c")
+        };
+        channel.producer().produce(&mut vec![corrupted]).await;
+
+        let error = framed.next().await.unwrap().unwrap();
+        assert_eq!(stream_id, error.context_id);
+        assert!(matches!(
+            error.message,
+            Message::ErrorResponseV1(ErrorResponseV1 {
+                code: error::CORRUPT_BATCH,
+                ..
+            })
+        ));
+        assert!(matches!(
+            framed.next().await.unwrap().unwrap().message,
+            Message::StreamCloseV1(_)
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_produce_compressed_batch() {
+        let app = App::new();
+        app.create_channel(ChannelConfig {
+            name: "orders".to_string(),
+            partitions: 1,
+        })
+        .await
+        .unwrap();
+
+        let (client, server) = tokio::io::duplex(4096);
+        let mut handler = FlowConnector::new(app.clone()).connection_handler(server, "test".to_string());
+        tokio::spawn(async move { handler.handle().await });
+        let mut framed = Framed::new(client, FlowCodec::new());
+
+        let records = vec![Record::new("k", "a"), Record::from_value("b")];
+        let batch = RecordBatch::compress(&records, Compression::Zstd).unwrap();
+        let produce = ProduceBatchRequestV1::new("orders".to_string(), batch);
+        assert!(matches!(
+            request(&mut framed, (29, 1), Message::ProduceBatchRequestV1(produce.clone())).await,
+            Message::ProduceResponseV1(_)
+        ));
+
+        let channel = app.get_channel(&("orders".to_string(), 1)).await.unwrap();
+        assert_eq!(2, channel.end_offset());
+
+        let mut corrupted = produce.clone();
+        corrupted.payload[0] ^= 1;
+        let mut miscounted = ProduceBatchRequestV1 { count: 3, ..produce };
+        miscounted.crc = crc32c::crc32c(&miscounted.payload);
+        for produce in [corrupted, miscounted] {
+            assert!(matches!(
+                request(&mut framed, (29, 1), Message::ProduceBatchRequestV1(produce)).await,
+                Message::ErrorResponseV1(ErrorResponseV1 {
+                    code: error::CORRUPT_BATCH,
+                    ..
+                })
+            ));
+        }
+
+        framed.send(subscribe("billing")).await.unwrap();
+        match framed.next().await.unwrap().unwrap().message {
+            Message::ConsumeV1(consume) => {
+                assert_eq!(vec![0, 1], consume.records.iter().map(|r| r.offset).collect::<Vec<_>>());
+                assert_eq!(
+                    records.into_iter().map(|r| r.value).collect::<Vec<_>>(),
+                    consume.records.into_iter().map(|r| r.value).collect::<Vec<_>>()
+                );
+            }
+            message => panic!("unexpected message {:?}", message),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_produce_to_partition() {
+        let app = App::new();
+        app.create_channel(ChannelConfig {
+            name: "orders".to_string(),
+            partitions: 2,
+        })
+        .await
+        .unwrap();
+
+        let (client, server) = tokio::io::duplex(4096);
+        let mut handler = FlowConnector::new(app.clone()).connection_handler(server, "test".to_string());
+        tokio::spawn(async move { handler.handle().await });
+        let mut framed = Framed::new(client, FlowCodec::new());
+
+        let produce = |partition: u16| {
+            let records = vec![RecordV1::from(Record::from_value("a"))];
+            Message::ProduceRequestV2(ProduceRequestV2::new("orders".to_string(), partition, records))
+        };
+        assert!(matches!(
+            request(&mut framed, (6, 2), produce(2)).await,
+            Message::ProduceResponseV1(_)
+        ));
+
+        for (partition, end_offset) in [(1, 0), (2, 1)] {
+            let channel = app.get_channel(&("orders".to_string(), partition)).await.unwrap();
+            assert_eq!(end_offset, channel.end_offset());
+        }
+
+        assert!(matches!(
+            request(&mut framed, (6, 2), produce(3)).await,
+            Message::ErrorResponseV1(ErrorResponseV1 {
+                code: error::UNKNOWN_TOPIC,
+                ..
+            })
+        ));
+    }
+
+    fn subscribe(group: &str) -> Packet {
+        Packet::new(
+            (2, 1),
+            Message::SubscribeTopicRequestV1(SubscribeTopicRequestV1 {
+                topic: "orders".to_string(),
+                consumer_group_id: group.to_string(),
+            }),
+        )
+    }
+
+    #[tokio::test]
+    async fn test_unsubscribe_and_disconnect_cancel_subscriptions() {
+        let app = App::new();
+        app.create_channel(ChannelConfig {
+            name: "orders".to_string(),
+            partitions: 1,
+        })
+        .await
+        .unwrap();
+        let channel = app.get_channel(&("orders".to_string(), 1)).await.unwrap();
+        let position = |group: &str| {
+            let channel = channel.clone();
+            let id = consumer_group_id(group);
+            async move { channel.offsets(id).await.1 }
+        };
+
+        let (client, server) = tokio::io::duplex(4096);
+        let mut handler = FlowConnector::new(app.clone()).connection_handler(server, "test".to_string());
+        let task = tokio::spawn(async move { handler.handle().await });
+        let mut framed = Framed::new(client, FlowCodec::new());
+
+        let billing = subscribe("billing");
+        let stream_id = billing.context_id;
+        framed.send(billing).await.unwrap();
+        framed.send(subscribe("audit")).await.unwrap();
+
+        channel.producer().produce(&mut vec![Record::from_value("a")]).await;
+        for _ in 0..2 {
+            assert!(matches!(
+                framed.next().await.unwrap().unwrap().message,
+                Message::ConsumeV1(_)
+            ));
+        }
+
+        // The stream is closed before the request is answered, and no longer consumed from.
+        framed
+            .send(Packet::new(
+                (4, 1),
+                Message::UnsubscribeRequestV1(UnsubscribeRequestV1 { stream_id }),
+            ))
+            .await
+            .unwrap();
+        let closed = framed.next().await.unwrap().unwrap();
+        assert!(matches!(closed.message, Message::StreamCloseV1(_)));
+        assert_eq!(stream_id, closed.context_id);
+        assert!(matches!(
+            framed.next().await.unwrap().unwrap().message,
+            Message::UnsubscribeRequestV1(_)
+        ));
+
+        channel.producer().produce(&mut vec![Record::from_value("b")]).await;
+        assert!(matches!(
+            framed.next().await.unwrap().unwrap().message,
+            Message::ConsumeV1(_)
+        ));
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        assert_eq!(1, position("billing").await);
+        assert_eq!(2, position("audit").await);
+
+        // Subscriptions of a closed connection are cancelled along with it.
+        drop(framed);
+        assert!(task.await.unwrap().is_ok());
+
+        channel.producer().produce(&mut vec![Record::from_value("c")]).await;
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        assert_eq!(2, position("audit").await);
     }
 }
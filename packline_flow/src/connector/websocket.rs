@@ -0,0 +1,61 @@
+use std::net::SocketAddr;
+use std::sync::Arc;
+
+use async_trait::async_trait;
+use tracing::debug;
+
+use packline_core::connector::{TCPConnectionHandler, TCPConnectorHandler, TCPStream};
+
+use super::FlowConnector;
+use crate::websocket;
+
+/// Serves the flow protocol to clients connecting through WebSocket, carrying packets in binary
+/// frames. Plugs into a [`TCPConnector`](packline_core::connector::TCPConnector) listener, which
+/// also provides `wss://` when the listener has TLS enabled.
+pub struct FlowWebSocketConnector {
+    flow: Arc<FlowConnector>,
+}
+
+impl FlowWebSocketConnector {
+    pub fn new(flow: FlowConnector) -> FlowWebSocketConnector {
+        FlowWebSocketConnector { flow: Arc::new(flow) }
+    }
+}
+
+struct WebSocketUpgradeHandler {
+    flow: Arc<FlowConnector>,
+    addr: SocketAddr,
+    stream: Option<TCPStream>,
+}
+
+impl TCPConnectorHandler for FlowWebSocketConnector {
+    fn handle_connection(&self, conn: (TCPStream, SocketAddr)) -> Box<dyn TCPConnectionHandler> {
+        Box::new(WebSocketUpgradeHandler {
+            flow: self.flow.clone(),
+            addr: conn.1,
+            stream: Some(conn.0),
+        })
+    }
+
+    fn name(&self) -> &'static str {
+        "websocket"
+    }
+}
+
+#[async_trait]
+impl TCPConnectionHandler for WebSocketUpgradeHandler {
+    async fn handle(&mut self) -> Result<(), std::io::Error> {
+        let stream = match websocket::accept(self.stream.take().unwrap()).await {
+            Ok(stream) => stream,
+            Err(e) => {
+                debug!("WebSocket upgrade from {} failed: {}", self.addr, e);
+                return Ok(());
+            }
+        };
+
+        self.flow
+            .connection_handler(stream, self.addr.to_string())
+            .handle()
+            .await
+    }
+}
@@ -3,10 +3,13 @@ pub use schema::{DeserializableSchema, Schema, SerializableSchema, SizedSchema};
 
 mod handler;
 
+pub mod auth;
+pub mod broker;
 pub mod codec;
 pub mod connector;
 pub mod messages;
 pub mod schema;
+pub mod websocket;
 
 pub(crate) mod flow {
     pub use crate::schema::{DeserializableSchema, Schema, SerializableSchema, SizedSchema};
@@ -0,0 +1,75 @@
+use std::convert::TryFrom;
+
+use packline_core::app::acl::{AclRule, Operation, Permission, Resource, ResourceType};
+
+use crate::{FlowDeserializable, FlowSerializable, FlowSized};
+
+pub mod flow {
+    pub use crate::codec;
+    pub use crate::flow::*;
+}
+
+/// Wire representation of an [`AclRule`].
+#[derive(FlowDeserializable, FlowSerializable, FlowSized, Clone)]
+#[cfg_attr(debug_assertions, derive(Debug))]
+pub struct AclEntryV1 {
+    pub principal: String,
+    pub resource_type: u8,
+    pub resource_name: String,
+    pub operation: u8,
+    pub permission: u8,
+}
+
+#[derive(FlowDeserializable, FlowSerializable, FlowSized, Clone)]
+#[cfg_attr(debug_assertions, derive(Debug))]
+pub struct CreateAclRequestV1 {
+    pub acl: AclEntryV1,
+}
+
+#[derive(FlowDeserializable, FlowSerializable, FlowSized, Clone)]
+#[cfg_attr(debug_assertions, derive(Debug))]
+pub struct DeleteAclRequestV1 {
+    pub acl: AclEntryV1,
+}
+
+#[derive(FlowDeserializable, FlowSerializable, FlowSized, Clone)]
+#[cfg_attr(debug_assertions, derive(Debug))]
+pub struct ListAclsRequestV1 {}
+
+#[derive(FlowDeserializable, FlowSerializable, FlowSized, Clone)]
+#[cfg_attr(debug_assertions, derive(Debug))]
+pub struct ListAclsResponseV1 {
+    #[rustfmt::skip]
+    pub acls: Vec::<AclEntryV1>,
+}
+
+impl From<AclRule> for AclEntryV1 {
+    fn from(rule: AclRule) -> Self {
+        AclEntryV1 {
+            principal: rule.principal,
+            resource_type: rule.resource.resource_type as u8,
+            resource_name: rule.resource.name,
+            operation: rule.operation as u8,
+            permission: rule.permission as u8,
+        }
+    }
+}
+
+impl TryFrom<AclEntryV1> for AclRule {
+    type Error = std::io::Error;
+
+    fn try_from(entry: AclEntryV1) -> Result<Self, Self::Error> {
+        let rule = AclRule {
+            principal: entry.principal,
+            resource: Resource {
+                resource_type: ResourceType::try_from(entry.resource_type)?,
+                name: entry.resource_name,
+            },
+            operation: Operation::try_from(entry.operation)?,
+            permission: Permission::try_from(entry.permission)?,
+        };
+        rule.validate()?;
+
+        Ok(rule)
+    }
+}
@@ -0,0 +1,23 @@
+use crate::{FlowDeserializable, FlowSerializable, FlowSized};
+
+pub mod flow {
+    pub use crate::codec;
+    pub use crate::flow::*;
+}
+
+/// One step of an authentication exchange using the given SASL mechanism.
+#[derive(FlowDeserializable, FlowSerializable, FlowSized, Clone)]
+#[cfg_attr(debug_assertions, derive(Debug))]
+pub struct AuthenticateRequestV1 {
+    pub mechanism: String,
+
+    #[rustfmt::skip]
+    pub payload: Vec::<u8>,
+}
+
+#[derive(FlowDeserializable, FlowSerializable, FlowSized, Clone)]
+#[cfg_attr(debug_assertions, derive(Debug))]
+pub struct AuthenticateResponseV1 {
+    #[rustfmt::skip]
+    pub payload: Vec::<u8>,
+}
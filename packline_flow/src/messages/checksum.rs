@@ -0,0 +1,72 @@
+use packline_core::app::channel::Record;
+
+use super::record::RecordV1;
+
+/// CRC32C of a record batch, computed over the key and value of each record, prefixed with their
+/// big-endian length. Offsets are left out, as the broker assigns them once the batch is produced.
+pub fn records_checksum(records: &[RecordV1]) -> u32 {
+    records.iter().fold(0, |crc, record| {
+        crc32c::crc32c_combine(
+            crc,
+            Record::checksum(&record.key, &record.value),
+            8 + record.key.len() + record.value.len(),
+        )
+    })
+}
+
+/// Checksum of a batch of stored records, as [`records_checksum`] computes it, combined from the
+/// checksums kept with the records rather than from their bytes.
+pub fn stored_checksum(records: &[Record]) -> u32 {
+    records.iter().fold(0, |crc, record| {
+        crc32c::crc32c_combine(crc, record.crc, record.checksum_len())
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn record(offset: u64, key: &[u8], value: &[u8]) -> RecordV1 {
+        RecordV1 {
+            offset,
+            key: key.to_vec(),
+            value: value.to_vec(),
+        }
+    }
+
+    #[test]
+    fn test_records_checksum_matches_byte_checksum() {
+        let bytes = [0u8, 0, 0, 1, b'k', 0, 0, 0, 2, b'v', b'1', 0, 0, 0, 0, 0, 0, 0, 1, b'a'];
+        assert_eq!(
+            crc32c::crc32c(&bytes),
+            records_checksum(&[record(1, b"k", b"v1"), record(2, b"", b"a")])
+        );
+    }
+
+    #[test]
+    fn test_stored_checksum_matches_records_checksum() {
+        let records = [record(0, b"k", b"v1"), record(1, b"", b"a")];
+        let stored = records.iter().cloned().map(Record::from).collect::<Vec<_>>();
+
+        assert_eq!(records_checksum(&records), stored_checksum(&stored));
+        assert_eq!(0, stored_checksum(&[]));
+    }
+
+    #[test]
+    fn test_records_checksum_detects_changes() {
+        let records = [record(0, b"", b"a"), record(1, b"", b"b")];
+
+        assert_ne!(
+            records_checksum(&records),
+            records_checksum(&[record(0, b"", b"a"), record(1, b"", b"c")])
+        );
+        assert_ne!(
+            records_checksum(&records),
+            records_checksum(&[record(1, b"", b"b"), record(0, b"", b"a")])
+        );
+        assert_ne!(
+            records_checksum(&[record(0, b"ab", b"")]),
+            records_checksum(&[record(0, b"a", b"b")])
+        );
+    }
+}
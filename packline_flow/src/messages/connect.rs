@@ -8,3 +8,10 @@ pub mod flow {
 #[derive(FlowDeserializable, FlowSerializable, FlowSized, Clone)]
 #[cfg_attr(debug_assertions, derive(Debug))]
 pub struct ConnectRequestV1 {}
+
+#[derive(FlowDeserializable, FlowSerializable, FlowSized, Clone)]
+#[cfg_attr(debug_assertions, derive(Debug))]
+pub struct ConnectRequestV2 {
+    /// [`Compression`](crate::codec::Compression) the broker should use for packets sent on this connection.
+    pub compression: u8,
+}
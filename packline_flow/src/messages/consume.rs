@@ -1,3 +1,7 @@
+use packline_core::app::channel::Record;
+
+use super::checksum::{records_checksum, stored_checksum};
+use super::record::RecordV1;
 use crate::{FlowDeserializable, FlowSerializable, FlowSized};
 
 pub mod flow {
@@ -9,7 +13,32 @@ pub mod flow {
 #[cfg_attr(debug_assertions, derive(Debug))]
 pub struct ConsumeV1 {
     pub topic: String,
+    pub crc: u32,
 
     #[rustfmt::skip]
-    pub records: Vec::<u32>,
+    pub records: Vec::<RecordV1>,
+}
+
+impl ConsumeV1 {
+    pub fn new(topic: String, records: Vec<RecordV1>) -> ConsumeV1 {
+        ConsumeV1 {
+            topic,
+            crc: records_checksum(&records),
+            records,
+        }
+    }
+
+    /// Creates a batch of stored records, carrying the checksum combined from theirs instead of one
+    /// computed from their bytes.
+    pub fn from_stored(topic: String, records: Vec<Record>) -> ConsumeV1 {
+        ConsumeV1 {
+            topic,
+            crc: stored_checksum(&records),
+            records: records.into_iter().map(RecordV1::from).collect(),
+        }
+    }
+
+    pub fn is_valid(&self) -> bool {
+        self.crc == records_checksum(&self.records)
+    }
 }
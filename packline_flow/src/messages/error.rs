@@ -0,0 +1,21 @@
+use crate::{FlowDeserializable, FlowSerializable, FlowSized};
+
+pub mod flow {
+    pub use crate::codec;
+    pub use crate::flow::*;
+}
+
+pub const UNKNOWN_TOPIC: u16 = 1;
+pub const CORRUPT_BATCH: u16 = 2;
+pub const AUTHENTICATION_FAILED: u16 = 3;
+pub const AUTHENTICATION_REQUIRED: u16 = 4;
+pub const AUTHORIZATION_FAILED: u16 = 5;
+pub const INVALID_REQUEST: u16 = 6;
+
+/// Response sent instead of the regular one when the broker fails to handle a request.
+#[derive(FlowDeserializable, FlowSerializable, FlowSized, Clone)]
+#[cfg_attr(debug_assertions, derive(Debug))]
+pub struct ErrorResponseV1 {
+    pub code: u16,
+    pub message: String,
+}
@@ -0,0 +1,93 @@
+use crate::{FlowDeserializable, FlowSerializable, FlowSized};
+
+pub mod flow {
+    pub use crate::codec;
+    pub use crate::flow::*;
+}
+
+/// Value of [`GroupOffsetV1::committed`] when the group never committed an offset.
+pub const NO_COMMITTED_OFFSET: i64 = -1;
+
+/// Values of [`ResetGroupOffsetsRequestV1::target`].
+pub const RESET_TO_EARLIEST: u8 = 0;
+pub const RESET_TO_LATEST: u8 = 1;
+pub const RESET_TO_OFFSET: u8 = 2;
+
+#[derive(FlowDeserializable, FlowSerializable, FlowSized, Clone)]
+#[cfg_attr(debug_assertions, derive(Debug))]
+pub struct ListGroupsRequestV1 {}
+
+#[derive(FlowDeserializable, FlowSerializable, FlowSized, Clone)]
+#[cfg_attr(debug_assertions, derive(Debug))]
+pub struct ListGroupsResponseV1 {
+    #[rustfmt::skip]
+    pub groups: Vec::<GroupListingV1>,
+}
+
+/// Consumer group and the topics it has offsets on. Groups whose name the broker doesn't know are
+/// named after their id, as `#` followed by 32 hexadecimal digits.
+#[derive(FlowDeserializable, FlowSerializable, FlowSized, Clone, PartialEq, Eq)]
+#[cfg_attr(debug_assertions, derive(Debug))]
+pub struct GroupListingV1 {
+    pub group: String,
+
+    #[rustfmt::skip]
+    pub topics: Vec::<String>,
+}
+
+#[derive(FlowDeserializable, FlowSerializable, FlowSized, Clone)]
+#[cfg_attr(debug_assertions, derive(Debug))]
+pub struct DescribeGroupRequestV1 {
+    pub group: String,
+}
+
+#[derive(FlowDeserializable, FlowSerializable, FlowSized, Clone)]
+#[cfg_attr(debug_assertions, derive(Debug))]
+pub struct DescribeGroupResponseV1 {
+    pub group: String,
+
+    #[rustfmt::skip]
+    pub offsets: Vec::<GroupOffsetV1>,
+}
+
+#[derive(FlowDeserializable, FlowSerializable, FlowSized, Clone, PartialEq, Eq)]
+#[cfg_attr(debug_assertions, derive(Debug))]
+pub struct GroupOffsetV1 {
+    pub topic: String,
+    pub partition: u16,
+
+    /// Last committed offset, [`NO_COMMITTED_OFFSET`] when there's none.
+    pub committed: i64,
+
+    /// Offset of the next record the group consumes.
+    pub position: u64,
+    pub end_offset: u64,
+}
+
+/// Moves a group on every partition of a topic, answered with a [`DescribeGroupResponseV1`].
+#[derive(FlowDeserializable, FlowSerializable, FlowSized, Clone)]
+#[cfg_attr(debug_assertions, derive(Debug))]
+pub struct ResetGroupOffsetsRequestV1 {
+    pub group: String,
+    pub topic: String,
+
+    /// One of [`RESET_TO_EARLIEST`], [`RESET_TO_LATEST`] or [`RESET_TO_OFFSET`].
+    pub target: u8,
+
+    /// Offset moved to with [`RESET_TO_OFFSET`], ignored otherwise. Clamped to the offsets each
+    /// partition stores.
+    pub offset: u64,
+}
+
+/// Records the offset a group processed records up to on a partition, answered with a
+/// [`DescribeGroupResponseV1`] holding that partition.
+#[derive(FlowDeserializable, FlowSerializable, FlowSized, Clone)]
+#[cfg_attr(debug_assertions, derive(Debug))]
+pub struct CommitOffsetRequestV1 {
+    pub group: String,
+    pub topic: String,
+    pub partition: u16,
+
+    /// Offset of the next record to process, one past the last processed record.
+    pub offset: u64,
+}
@@ -0,0 +1,20 @@
+use crate::{FlowDeserializable, FlowSerializable, FlowSized};
+
+pub mod flow {
+    pub use crate::codec;
+    pub use crate::flow::*;
+}
+
+#[derive(FlowDeserializable, FlowSerializable, FlowSized, Clone)]
+#[cfg_attr(debug_assertions, derive(Debug))]
+pub struct HealthCheckRequestV1 {}
+
+/// Readiness of the broker. `ready` is `1` once every readiness check passed, `pending` names the
+/// checks that didn't.
+#[derive(FlowDeserializable, FlowSerializable, FlowSized, Clone)]
+#[cfg_attr(debug_assertions, derive(Debug))]
+pub struct HealthCheckResponseV1 {
+    pub ready: u8,
+    #[rustfmt::skip]
+    pub pending: Vec::<String>,
+}
@@ -1,17 +1,58 @@
+use crate::codec::decoder::ByteDecoder;
+use crate::codec::{Compression, DEFAULT_MAX_PACKET_SIZE};
 use crate::{DeserializableSchema, SerializableSchema, SizedSchema};
 use bytes::BytesMut;
 use rand::random;
-use std::convert::Infallible;
+use std::convert::{Infallible, TryFrom};
 
+pub mod acl;
+pub mod authenticate;
+pub mod checksum;
 pub mod connect;
 pub mod consume;
+pub mod error;
+pub mod group;
+pub mod health;
+pub mod produce;
+pub mod record;
 pub mod subscribe;
+pub mod topic;
+pub mod unsubscribe;
 
 #[derive(Debug, Clone)]
 pub enum Message {
     ConnectRequestV1(connect::ConnectRequestV1),
+    ConnectRequestV2(connect::ConnectRequestV2),
     SubscribeTopicRequestV1(subscribe::SubscribeTopicRequestV1),
+    SubscribeTopicRequestV2(subscribe::SubscribeTopicRequestV2),
     ConsumeV1(consume::ConsumeV1),
+    UnsubscribeRequestV1(unsubscribe::UnsubscribeRequestV1),
+    StreamCloseV1(unsubscribe::StreamCloseV1),
+    ProduceRequestV1(produce::ProduceRequestV1),
+    ProduceResponseV1(produce::ProduceResponseV1),
+    ErrorResponseV1(error::ErrorResponseV1),
+    AuthenticateRequestV1(authenticate::AuthenticateRequestV1),
+    AuthenticateResponseV1(authenticate::AuthenticateResponseV1),
+    CreateTopicRequestV1(topic::CreateTopicRequestV1),
+    CreateAclRequestV1(acl::CreateAclRequestV1),
+    DeleteAclRequestV1(acl::DeleteAclRequestV1),
+    ListAclsRequestV1(acl::ListAclsRequestV1),
+    ListAclsResponseV1(acl::ListAclsResponseV1),
+    ListTopicsRequestV1(topic::ListTopicsRequestV1),
+    ListTopicsResponseV1(topic::ListTopicsResponseV1),
+    DescribeTopicRequestV1(topic::DescribeTopicRequestV1),
+    DescribeTopicResponseV1(topic::DescribeTopicResponseV1),
+    DeleteTopicRequestV1(topic::DeleteTopicRequestV1),
+    ListGroupsRequestV1(group::ListGroupsRequestV1),
+    ListGroupsResponseV1(group::ListGroupsResponseV1),
+    DescribeGroupRequestV1(group::DescribeGroupRequestV1),
+    DescribeGroupResponseV1(group::DescribeGroupResponseV1),
+    ResetGroupOffsetsRequestV1(group::ResetGroupOffsetsRequestV1),
+    HealthCheckRequestV1(health::HealthCheckRequestV1),
+    HealthCheckResponseV1(health::HealthCheckResponseV1),
+    CommitOffsetRequestV1(group::CommitOffsetRequestV1),
+    ProduceBatchRequestV1(produce::ProduceBatchRequestV1),
+    ProduceRequestV2(produce::ProduceRequestV2),
     Invalid,
 }
 
@@ -19,8 +60,37 @@ impl SizedSchema for Message {
     fn size(&self) -> usize {
         match self {
             Message::ConnectRequestV1(m) => m.size(),
+            Message::ConnectRequestV2(m) => m.size(),
             Message::SubscribeTopicRequestV1(s) => s.size(),
+            Message::SubscribeTopicRequestV2(s) => s.size(),
             Message::ConsumeV1(c) => c.size(),
+            Message::UnsubscribeRequestV1(u) => u.size(),
+            Message::StreamCloseV1(c) => c.size(),
+            Message::ProduceRequestV1(p) => p.size(),
+            Message::ProduceResponseV1(p) => p.size(),
+            Message::ErrorResponseV1(e) => e.size(),
+            Message::AuthenticateRequestV1(a) => a.size(),
+            Message::AuthenticateResponseV1(a) => a.size(),
+            Message::CreateTopicRequestV1(c) => c.size(),
+            Message::CreateAclRequestV1(c) => c.size(),
+            Message::DeleteAclRequestV1(d) => d.size(),
+            Message::ListAclsRequestV1(l) => l.size(),
+            Message::ListAclsResponseV1(l) => l.size(),
+            Message::ListTopicsRequestV1(m) => m.size(),
+            Message::ListTopicsResponseV1(m) => m.size(),
+            Message::DescribeTopicRequestV1(m) => m.size(),
+            Message::DescribeTopicResponseV1(m) => m.size(),
+            Message::DeleteTopicRequestV1(m) => m.size(),
+            Message::ListGroupsRequestV1(m) => m.size(),
+            Message::ListGroupsResponseV1(m) => m.size(),
+            Message::DescribeGroupRequestV1(m) => m.size(),
+            Message::DescribeGroupResponseV1(m) => m.size(),
+            Message::ResetGroupOffsetsRequestV1(m) => m.size(),
+            Message::HealthCheckRequestV1(m) => m.size(),
+            Message::HealthCheckResponseV1(m) => m.size(),
+            Message::CommitOffsetRequestV1(m) => m.size(),
+            Message::ProduceBatchRequestV1(m) => m.size(),
+            Message::ProduceRequestV2(m) => m.size(),
             _ => 0,
         }
     }
@@ -32,8 +102,37 @@ impl SerializableSchema for Message {
     fn serialize(&self, encoder: &mut BytesMut) {
         match self {
             Message::ConnectRequestV1(m) => m.serialize(encoder),
+            Message::ConnectRequestV2(m) => m.serialize(encoder),
             Message::SubscribeTopicRequestV1(m) => m.serialize(encoder),
+            Message::SubscribeTopicRequestV2(m) => m.serialize(encoder),
             Message::ConsumeV1(m) => m.serialize(encoder),
+            Message::UnsubscribeRequestV1(m) => m.serialize(encoder),
+            Message::StreamCloseV1(m) => m.serialize(encoder),
+            Message::ProduceRequestV1(m) => m.serialize(encoder),
+            Message::ProduceResponseV1(m) => m.serialize(encoder),
+            Message::ErrorResponseV1(m) => m.serialize(encoder),
+            Message::AuthenticateRequestV1(m) => m.serialize(encoder),
+            Message::AuthenticateResponseV1(m) => m.serialize(encoder),
+            Message::CreateTopicRequestV1(m) => m.serialize(encoder),
+            Message::CreateAclRequestV1(m) => m.serialize(encoder),
+            Message::DeleteAclRequestV1(m) => m.serialize(encoder),
+            Message::ListAclsRequestV1(m) => m.serialize(encoder),
+            Message::ListAclsResponseV1(m) => m.serialize(encoder),
+            Message::ListTopicsRequestV1(m) => m.serialize(encoder),
+            Message::ListTopicsResponseV1(m) => m.serialize(encoder),
+            Message::DescribeTopicRequestV1(m) => m.serialize(encoder),
+            Message::DescribeTopicResponseV1(m) => m.serialize(encoder),
+            Message::DeleteTopicRequestV1(m) => m.serialize(encoder),
+            Message::ListGroupsRequestV1(m) => m.serialize(encoder),
+            Message::ListGroupsResponseV1(m) => m.serialize(encoder),
+            Message::DescribeGroupRequestV1(m) => m.serialize(encoder),
+            Message::DescribeGroupResponseV1(m) => m.serialize(encoder),
+            Message::ResetGroupOffsetsRequestV1(m) => m.serialize(encoder),
+            Message::HealthCheckRequestV1(m) => m.serialize(encoder),
+            Message::HealthCheckResponseV1(m) => m.serialize(encoder),
+            Message::CommitOffsetRequestV1(m) => m.serialize(encoder),
+            Message::ProduceBatchRequestV1(m) => m.serialize(encoder),
+            Message::ProduceRequestV2(m) => m.serialize(encoder),
             _ => (),
         };
     }
@@ -51,9 +150,15 @@ pub enum PacketType {
     Stream = 1,
 }
 
-impl From<u8> for PacketType {
-    fn from(packet_type: u8) -> Self {
-        unsafe { std::mem::transmute(packet_type) }
+impl TryFrom<u8> for PacketType {
+    type Error = std::io::Error;
+
+    fn try_from(packet_type: u8) -> Result<Self, Self::Error> {
+        match packet_type {
+            0 => Ok(PacketType::Request),
+            1 => Ok(PacketType::Stream),
+            _ => Err(invalid_data(format!("unknown packet type {}", packet_type))),
+        }
     }
 }
 
@@ -63,15 +168,23 @@ pub struct Packet {
     route: RouteWithVersion,
     pub context_id: u32,
     pub message: Message,
+
+    /// Compression requested for this packet's payload, overriding the codec default. On decoded
+    /// packets it holds the compression the payload was received with.
+    pub compression: Compression,
 }
 
 impl Packet {
+    /// Size of the fields following the packet length: type, flags, route, version and context id.
+    pub const HEADER_SIZE: usize = 10;
+
     pub fn new(route: RouteWithVersion, message: Message) -> Packet {
         Packet {
             packet_type: PacketType::Request,
             route,
             context_id: random::<u32>(),
             message,
+            compression: Compression::None,
         }
     }
 
@@ -81,6 +194,7 @@ impl Packet {
             route,
             context_id,
             message,
+            compression: Compression::None,
         }
     }
 
@@ -90,6 +204,7 @@ impl Packet {
             route,
             context_id,
             message,
+            compression: Compression::None,
         }
     }
 
@@ -99,69 +214,159 @@ impl Packet {
             route,
             context_id: self.context_id,
             message,
+            compression: Compression::None,
         }
     }
-}
 
-impl SizedSchema for Packet {
-    fn size(&self) -> usize {
-        (self.packet_type as u8).size()
-            + self.route.0.size()
-            + self.route.1.size()
-            + self.context_id.size()
-            + self.message.size()
+    pub fn route(&self) -> RouteWithVersion {
+        self.route
     }
-}
 
-impl DeserializableSchema for Packet {
-    type Error = Infallible;
-    type Item = Option<Packet>;
+    pub fn with_compression(mut self, compression: Compression) -> Packet {
+        self.compression = compression;
+        self
+    }
+
+    pub(crate) fn serialize_header(&self, compression: Compression, payload_size: usize, encoder: &mut BytesMut) {
+        ((Packet::HEADER_SIZE + payload_size) as i32).serialize(encoder);
+
+        (self.packet_type as u8).serialize(encoder);
+        (compression as u8).serialize(encoder);
+        self.route.0.serialize(encoder);
+        self.route.1.serialize(encoder);
+        self.context_id.serialize(encoder);
+    }
 
-    fn deserialize(decoder: &mut crate::codec::decoder::ByteDecoder) -> Result<Option<Packet>, Self::Error> {
+    /// Reads the next packet, or `None` until it was fully received. Packets larger than
+    /// `max_size`, length field excluded, are rejected before being buffered, as are payloads
+    /// decompressing past it.
+    pub(crate) fn decode(decoder: &mut ByteDecoder, max_size: usize) -> Result<Option<Packet>, std::io::Error> {
         if decoder.len() < 4 {
             return Ok(None);
         }
 
-        let size = i32::deserialize(decoder)? + 4;
+        let size = i32::deserialize(decoder).unwrap();
+        let size = match usize::try_from(size) {
+            Ok(size) if size < Packet::HEADER_SIZE => {
+                return Err(invalid_data(format!("packet size {} below header size", size)))
+            }
+            Ok(size) if size <= max_size => size,
+            _ => return Err(invalid_data(format!("invalid packet size {}", size))),
+        };
 
-        if decoder.len() < (size as usize) {
+        if decoder.len() < size + 4 {
             return Ok(None);
         }
 
-        let packet_type = PacketType::from(u8::deserialize(decoder)?);
-        let route = u16::deserialize(decoder)?;
-        let version = u16::deserialize(decoder)?;
-        let request_id = u32::deserialize(decoder)?;
+        let packet_type = PacketType::try_from(u8::deserialize(decoder).unwrap())?;
+        let compression = Compression::try_from(u8::deserialize(decoder).unwrap())?;
+        let route = u16::deserialize(decoder).unwrap();
+        let version = u16::deserialize(decoder).unwrap();
+        let request_id = u32::deserialize(decoder).unwrap();
 
-        let message = match (route, version) {
-            (1, 1) => Message::ConnectRequestV1(connect::ConnectRequestV1::deserialize(decoder).unwrap()),
-            (2, 1) => {
-                Message::SubscribeTopicRequestV1(subscribe::SubscribeTopicRequestV1::deserialize(decoder).unwrap())
+        let decompressed;
+        let payload = match compression {
+            Compression::None => decoder.next(size - Packet::HEADER_SIZE),
+            compression => {
+                decompressed =
+                    compression.decompress(decoder.next(size - Packet::HEADER_SIZE), max_size - Packet::HEADER_SIZE)?;
+                &decompressed
             }
-            (3, 1) => Message::ConsumeV1(consume::ConsumeV1::deserialize(decoder).unwrap()),
-            _ => Message::Invalid,
         };
 
+        let mut payload = ByteDecoder::new(payload);
+        let message = Packet::deserialize_message((route, version), &mut payload);
+        if payload.failed() {
+            return Err(invalid_data(format!(
+                "malformed payload for route {:?}",
+                (route, version)
+            )));
+        }
+
         Ok(Some(Packet {
             packet_type,
             route: (route, version),
             context_id: request_id,
             message,
+            compression,
         }))
     }
+
+    fn deserialize_message(route: RouteWithVersion, decoder: &mut ByteDecoder) -> Message {
+        match route {
+            (1, 1) => Message::ConnectRequestV1(connect::ConnectRequestV1::deserialize(decoder).unwrap()),
+            (1, 2) => Message::ConnectRequestV2(connect::ConnectRequestV2::deserialize(decoder).unwrap()),
+            (2, 1) => {
+                Message::SubscribeTopicRequestV1(subscribe::SubscribeTopicRequestV1::deserialize(decoder).unwrap())
+            }
+            (2, 2) => {
+                Message::SubscribeTopicRequestV2(subscribe::SubscribeTopicRequestV2::deserialize(decoder).unwrap())
+            }
+            (3, 1) => Message::ConsumeV1(consume::ConsumeV1::deserialize(decoder).unwrap()),
+            (4, 1) => Message::UnsubscribeRequestV1(unsubscribe::UnsubscribeRequestV1::deserialize(decoder).unwrap()),
+            (5, 1) => Message::StreamCloseV1(unsubscribe::StreamCloseV1::deserialize(decoder).unwrap()),
+            (6, 1) => Message::ProduceRequestV1(produce::ProduceRequestV1::deserialize(decoder).unwrap()),
+            (6, 2) => Message::ProduceRequestV2(produce::ProduceRequestV2::deserialize(decoder).unwrap()),
+            (7, 1) => Message::ProduceResponseV1(produce::ProduceResponseV1::deserialize(decoder).unwrap()),
+            (8, 1) => Message::ErrorResponseV1(error::ErrorResponseV1::deserialize(decoder).unwrap()),
+            (9, 1) => {
+                Message::AuthenticateRequestV1(authenticate::AuthenticateRequestV1::deserialize(decoder).unwrap())
+            }
+            (10, 1) => {
+                Message::AuthenticateResponseV1(authenticate::AuthenticateResponseV1::deserialize(decoder).unwrap())
+            }
+            (11, 1) => Message::CreateTopicRequestV1(topic::CreateTopicRequestV1::deserialize(decoder).unwrap()),
+            (12, 1) => Message::CreateAclRequestV1(acl::CreateAclRequestV1::deserialize(decoder).unwrap()),
+            (13, 1) => Message::DeleteAclRequestV1(acl::DeleteAclRequestV1::deserialize(decoder).unwrap()),
+            (14, 1) => Message::ListAclsRequestV1(acl::ListAclsRequestV1::deserialize(decoder).unwrap()),
+            (15, 1) => Message::ListAclsResponseV1(acl::ListAclsResponseV1::deserialize(decoder).unwrap()),
+            (16, 1) => Message::ListTopicsRequestV1(topic::ListTopicsRequestV1::deserialize(decoder).unwrap()),
+            (17, 1) => Message::ListTopicsResponseV1(topic::ListTopicsResponseV1::deserialize(decoder).unwrap()),
+            (18, 1) => Message::DescribeTopicRequestV1(topic::DescribeTopicRequestV1::deserialize(decoder).unwrap()),
+            (19, 1) => Message::DescribeTopicResponseV1(topic::DescribeTopicResponseV1::deserialize(decoder).unwrap()),
+            (20, 1) => Message::DeleteTopicRequestV1(topic::DeleteTopicRequestV1::deserialize(decoder).unwrap()),
+            (21, 1) => Message::ListGroupsRequestV1(group::ListGroupsRequestV1::deserialize(decoder).unwrap()),
+            (22, 1) => Message::ListGroupsResponseV1(group::ListGroupsResponseV1::deserialize(decoder).unwrap()),
+            (23, 1) => Message::DescribeGroupRequestV1(group::DescribeGroupRequestV1::deserialize(decoder).unwrap()),
+            (24, 1) => Message::DescribeGroupResponseV1(group::DescribeGroupResponseV1::deserialize(decoder).unwrap()),
+            (25, 1) => {
+                Message::ResetGroupOffsetsRequestV1(group::ResetGroupOffsetsRequestV1::deserialize(decoder).unwrap())
+            }
+            (26, 1) => Message::HealthCheckRequestV1(health::HealthCheckRequestV1::deserialize(decoder).unwrap()),
+            (27, 1) => Message::HealthCheckResponseV1(health::HealthCheckResponseV1::deserialize(decoder).unwrap()),
+            (28, 1) => Message::CommitOffsetRequestV1(group::CommitOffsetRequestV1::deserialize(decoder).unwrap()),
+            (29, 1) => Message::ProduceBatchRequestV1(produce::ProduceBatchRequestV1::deserialize(decoder).unwrap()),
+            _ => Message::Invalid,
+        }
+    }
+}
+
+impl SizedSchema for Packet {
+    fn size(&self) -> usize {
+        Packet::HEADER_SIZE + self.message.size()
+    }
+}
+
+impl DeserializableSchema for Packet {
+    type Error = std::io::Error;
+    type Item = Option<Packet>;
+
+    fn deserialize(decoder: &mut ByteDecoder) -> Result<Option<Packet>, Self::Error> {
+        Packet::decode(decoder, DEFAULT_MAX_PACKET_SIZE)
+    }
+}
+
+fn invalid_data(message: String) -> std::io::Error {
+    std::io::Error::new(std::io::ErrorKind::InvalidData, message)
 }
 
 impl SerializableSchema for Packet {
     type Error = Infallible;
 
+    /// Writes the packet with an uncompressed payload; compression is applied by
+    /// [`FlowCodec`](crate::codec::FlowCodec).
     fn serialize(&self, encoder: &mut BytesMut) {
-        (self.size() as i32).serialize(encoder);
-
-        (self.packet_type as u8).serialize(encoder);
-        self.route.0.serialize(encoder);
-        self.route.1.serialize(encoder);
-        self.context_id.serialize(encoder);
-
+        self.serialize_header(Compression::None, self.message.size(), encoder);
         self.message.serialize(encoder);
     }
 }
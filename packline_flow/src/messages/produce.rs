@@ -0,0 +1,107 @@
+use packline_core::app::channel::RecordBatch;
+
+use super::checksum::records_checksum;
+use super::record::RecordV1;
+use crate::{FlowDeserializable, FlowSerializable, FlowSized};
+
+pub mod flow {
+    pub use crate::codec;
+    pub use crate::flow::*;
+}
+
+#[derive(FlowDeserializable, FlowSerializable, FlowSized, Clone)]
+#[cfg_attr(debug_assertions, derive(Debug))]
+pub struct ProduceRequestV1 {
+    pub topic: String,
+    pub crc: u32,
+
+    #[rustfmt::skip]
+    pub records: Vec::<RecordV1>,
+}
+
+impl ProduceRequestV1 {
+    pub fn new(topic: String, records: Vec<RecordV1>) -> ProduceRequestV1 {
+        ProduceRequestV1 {
+            topic,
+            crc: records_checksum(&records),
+            records,
+        }
+    }
+
+    pub fn is_valid(&self) -> bool {
+        self.crc == records_checksum(&self.records)
+    }
+}
+
+/// Produces like [`ProduceRequestV1`], to the partition `partition` of the topic instead of the first
+/// one. Partitions are numbered from 1.
+#[derive(FlowDeserializable, FlowSerializable, FlowSized, Clone)]
+#[cfg_attr(debug_assertions, derive(Debug))]
+pub struct ProduceRequestV2 {
+    pub topic: String,
+    pub partition: u16,
+    pub crc: u32,
+
+    #[rustfmt::skip]
+    pub records: Vec::<RecordV1>,
+}
+
+impl ProduceRequestV2 {
+    pub fn new(topic: String, partition: u16, records: Vec<RecordV1>) -> ProduceRequestV2 {
+        ProduceRequestV2 {
+            topic,
+            partition,
+            crc: records_checksum(&records),
+            records,
+        }
+    }
+
+    pub fn is_valid(&self) -> bool {
+        self.crc == records_checksum(&self.records)
+    }
+}
+
+impl From<ProduceRequestV1> for ProduceRequestV2 {
+    fn from(produce: ProduceRequestV1) -> Self {
+        ProduceRequestV2 {
+            topic: produce.topic,
+            partition: 1,
+            crc: produce.crc,
+            records: produce.records,
+        }
+    }
+}
+
+/// Produces a [`RecordBatch`] compressed by the client, which the broker stores as is. The checksum
+/// covers the compressed payload, the records carrying their own.
+#[derive(FlowDeserializable, FlowSerializable, FlowSized, Clone)]
+#[cfg_attr(debug_assertions, derive(Debug))]
+pub struct ProduceBatchRequestV1 {
+    pub topic: String,
+    pub compression: u8,
+    pub count: u32,
+    pub crc: u32,
+
+    #[rustfmt::skip]
+    pub payload: Vec::<u8>,
+}
+
+impl ProduceBatchRequestV1 {
+    pub fn new(topic: String, batch: RecordBatch) -> ProduceBatchRequestV1 {
+        ProduceBatchRequestV1 {
+            topic,
+            compression: batch.compression as u8,
+            count: batch.count,
+            crc: crc32c::crc32c(&batch.payload),
+            payload: batch.payload,
+        }
+    }
+
+    pub fn is_valid(&self) -> bool {
+        self.crc == crc32c::crc32c(&self.payload)
+    }
+}
+
+#[derive(FlowDeserializable, FlowSerializable, FlowSized, Clone)]
+#[cfg_attr(debug_assertions, derive(Debug))]
+pub struct ProduceResponseV1 {}
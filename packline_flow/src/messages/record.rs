@@ -0,0 +1,40 @@
+use packline_core::app::channel::Record;
+
+use crate::{FlowDeserializable, FlowSerializable, FlowSized};
+
+pub mod flow {
+    pub use crate::codec;
+    pub use crate::flow::*;
+}
+
+/// Wire representation of a [`Record`]. The offset is ignored on produced records.
+#[derive(FlowDeserializable, FlowSerializable, FlowSized, Clone, PartialEq, Eq)]
+#[cfg_attr(debug_assertions, derive(Debug))]
+pub struct RecordV1 {
+    pub offset: u64,
+
+    #[rustfmt::skip]
+    pub key: Vec::<u8>,
+
+    #[rustfmt::skip]
+    pub value: Vec::<u8>,
+}
+
+impl From<Record> for RecordV1 {
+    fn from(record: Record) -> Self {
+        RecordV1 {
+            offset: record.offset,
+            key: record.key,
+            value: record.value,
+        }
+    }
+}
+
+impl From<RecordV1> for Record {
+    fn from(record: RecordV1) -> Self {
+        Record {
+            offset: record.offset,
+            ..Record::new(record.key, record.value)
+        }
+    }
+}
@@ -5,9 +5,35 @@ pub mod flow {
     pub use crate::flow::*;
 }
 
+/// Streams the records of a topic as a member of a consumer group. Subscriptions with an empty
+/// group consume on their own, from the first stored record, without any offset kept on the broker.
 #[derive(FlowDeserializable, FlowSerializable, FlowSized, Clone)]
 #[cfg_attr(debug_assertions, derive(Debug))]
 pub struct SubscribeTopicRequestV1 {
     pub topic: String,
     pub consumer_group_id: String,
 }
+
+/// Value of [`SubscribeTopicRequestV2::offset`] resuming from the position of the consumer group.
+pub const GROUP_POSITION: i64 = -1;
+
+/// Subscribes like [`SubscribeTopicRequestV1`], first moving the consumer group to `offset`, or to
+/// the closest offset the channel stores. Clients resuming a subscription send the offset of the
+/// first record they didn't receive.
+#[derive(FlowDeserializable, FlowSerializable, FlowSized, Clone)]
+#[cfg_attr(debug_assertions, derive(Debug))]
+pub struct SubscribeTopicRequestV2 {
+    pub topic: String,
+    pub consumer_group_id: String,
+    pub offset: i64,
+}
+
+impl From<SubscribeTopicRequestV1> for SubscribeTopicRequestV2 {
+    fn from(subscribe: SubscribeTopicRequestV1) -> Self {
+        SubscribeTopicRequestV2 {
+            topic: subscribe.topic,
+            consumer_group_id: subscribe.consumer_group_id,
+            offset: GROUP_POSITION,
+        }
+    }
+}
@@ -0,0 +1,61 @@
+use crate::{FlowDeserializable, FlowSerializable, FlowSized};
+
+pub mod flow {
+    pub use crate::codec;
+    pub use crate::flow::*;
+}
+
+#[derive(FlowDeserializable, FlowSerializable, FlowSized, Clone)]
+#[cfg_attr(debug_assertions, derive(Debug))]
+pub struct CreateTopicRequestV1 {
+    pub name: String,
+    pub partitions: u16,
+}
+
+#[derive(FlowDeserializable, FlowSerializable, FlowSized, Clone)]
+#[cfg_attr(debug_assertions, derive(Debug))]
+pub struct DeleteTopicRequestV1 {
+    pub name: String,
+}
+
+#[derive(FlowDeserializable, FlowSerializable, FlowSized, Clone)]
+#[cfg_attr(debug_assertions, derive(Debug))]
+pub struct ListTopicsRequestV1 {}
+
+#[derive(FlowDeserializable, FlowSerializable, FlowSized, Clone)]
+#[cfg_attr(debug_assertions, derive(Debug))]
+pub struct ListTopicsResponseV1 {
+    #[rustfmt::skip]
+    pub topics: Vec::<TopicMetadataV1>,
+}
+
+#[derive(FlowDeserializable, FlowSerializable, FlowSized, Clone)]
+#[cfg_attr(debug_assertions, derive(Debug))]
+pub struct DescribeTopicRequestV1 {
+    pub name: String,
+}
+
+#[derive(FlowDeserializable, FlowSerializable, FlowSized, Clone)]
+#[cfg_attr(debug_assertions, derive(Debug))]
+pub struct DescribeTopicResponseV1 {
+    pub topic: TopicMetadataV1,
+}
+
+#[derive(FlowDeserializable, FlowSerializable, FlowSized, Clone, PartialEq, Eq)]
+#[cfg_attr(debug_assertions, derive(Debug))]
+pub struct TopicMetadataV1 {
+    pub name: String,
+
+    #[rustfmt::skip]
+    pub partitions: Vec::<PartitionMetadataV1>,
+}
+
+/// Offsets of the records a partition still stores, `end_offset` being the one the next produced
+/// record gets.
+#[derive(FlowDeserializable, FlowSerializable, FlowSized, Clone, PartialEq, Eq)]
+#[cfg_attr(debug_assertions, derive(Debug))]
+pub struct PartitionMetadataV1 {
+    pub partition: u16,
+    pub start_offset: u64,
+    pub end_offset: u64,
+}
@@ -312,11 +312,18 @@ impl DeserializableSchema for String {
     #[inline(always)]
     fn deserialize(decoder: &mut crate::codec::decoder::ByteDecoder) -> Result<String, std::convert::Infallible> {
         let len = i64::deserialize(decoder).unwrap() as usize;
-        let mut buf = vec![0u8; len];
-
-        buf.copy_from_slice(decoder.next(len));
+        if len > decoder.remaining() {
+            decoder.fail();
+            return Ok(String::new());
+        }
 
-        Ok(String::from_utf8(buf).unwrap())
+        match String::from_utf8(decoder.next(len).to_vec()) {
+            Ok(value) => Ok(value),
+            Err(_) => {
+                decoder.fail();
+                Ok(String::new())
+            }
+        }
     }
 
     type Item = String;
@@ -343,11 +350,25 @@ impl<T: SerializableSchema> SerializableSchema for Vec<T> {
 impl<T: DeserializableSchema<Item = T, Error = std::convert::Infallible>> DeserializableSchema for Vec<T> {
     #[inline(always)]
     fn deserialize(decoder: &mut crate::codec::decoder::ByteDecoder) -> Result<Vec<T>, std::convert::Infallible> {
+        // Vectors longer than what is left to read are rejected, which also bounds the loop below
+        // for elements encoded on no byte.
         let len = i64::deserialize(decoder).unwrap() as usize;
-        let mut result = Vec::with_capacity(len);
+        if len > decoder.remaining() {
+            decoder.fail();
+            return Ok(vec![]);
+        }
 
+        // Decoded elements may take more memory than their encoding, so no more than the remaining
+        // bytes are reserved up front, the vector growing past that as needed.
+        let capacity = len.min(decoder.remaining() / std::mem::size_of::<T>().max(1));
+        let mut result = Vec::with_capacity(capacity);
         for _ in 0..len {
-            result.push(T::deserialize(decoder).unwrap());
+            let element = T::deserialize(decoder).unwrap();
+            if decoder.failed() {
+                break;
+            }
+
+            result.push(element);
         }
 
         Ok(result)
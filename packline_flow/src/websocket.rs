@@ -0,0 +1,155 @@
+use std::io::{Error, ErrorKind};
+use std::pin::Pin;
+use std::task::{ready, Context, Poll};
+
+use bytes::{Buf, Bytes};
+use futures::{Sink, Stream};
+use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
+use tokio_tungstenite::tungstenite::{self, Message};
+use tokio_tungstenite::WebSocketStream;
+
+/// Byte stream carried over binary WebSocket frames, so that [`FlowCodec`](crate::codec::FlowCodec)
+/// can run on a WebSocket connection like on any other transport.
+///
+/// Every write is sent as a single binary frame. Control frames are handled by the WebSocket layer
+/// and a close frame ends the stream.
+pub struct WSStream<S> {
+    inner: WebSocketStream<S>,
+    pending: Bytes,
+}
+
+/// Accepts a WebSocket upgrade on `stream`.
+pub async fn accept<S: AsyncRead + AsyncWrite + Unpin>(stream: S) -> Result<WSStream<S>, Error> {
+    let inner = tokio_tungstenite::accept_async(stream).await.map_err(into_io_error)?;
+    Ok(WSStream::new(inner))
+}
+
+/// Performs the WebSocket handshake for `url` on an already connected `stream`.
+pub async fn connect<S: AsyncRead + AsyncWrite + Unpin>(url: &str, stream: S) -> Result<WSStream<S>, Error> {
+    let (inner, _) = tokio_tungstenite::client_async(url, stream)
+        .await
+        .map_err(into_io_error)?;
+
+    Ok(WSStream::new(inner))
+}
+
+impl<S> WSStream<S> {
+    pub fn new(inner: WebSocketStream<S>) -> WSStream<S> {
+        WSStream {
+            inner,
+            pending: Bytes::new(),
+        }
+    }
+}
+
+impl<S: AsyncRead + AsyncWrite + Unpin> AsyncRead for WSStream<S> {
+    fn poll_read(self: Pin<&mut Self>, cx: &mut Context<'_>, buf: &mut ReadBuf<'_>) -> Poll<Result<(), Error>> {
+        let this = self.get_mut();
+
+        loop {
+            if !this.pending.is_empty() {
+                let len = buf.remaining().min(this.pending.len());
+                buf.put_slice(&this.pending[..len]);
+                this.pending.advance(len);
+
+                return Poll::Ready(Ok(()));
+            }
+
+            match ready!(Pin::new(&mut this.inner).poll_next(cx)) {
+                None | Some(Ok(Message::Close(_))) => return Poll::Ready(Ok(())),
+                Some(Ok(Message::Binary(data))) => this.pending = data,
+                Some(Ok(Message::Text(_))) => {
+                    return Poll::Ready(Err(Error::new(ErrorKind::InvalidData, "text frames are not supported")))
+                }
+                Some(Ok(_)) => continue,
+                Some(Err(e)) => return Poll::Ready(Err(into_io_error(e))),
+            }
+        }
+    }
+}
+
+impl<S: AsyncRead + AsyncWrite + Unpin> AsyncWrite for WSStream<S> {
+    fn poll_write(self: Pin<&mut Self>, cx: &mut Context<'_>, buf: &[u8]) -> Poll<Result<usize, Error>> {
+        let this = self.get_mut();
+
+        ready!(Pin::new(&mut this.inner).poll_ready(cx)).map_err(into_io_error)?;
+        Pin::new(&mut this.inner)
+            .start_send(Message::Binary(Bytes::copy_from_slice(buf)))
+            .map_err(into_io_error)?;
+
+        Poll::Ready(Ok(buf.len()))
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Error>> {
+        Pin::new(&mut self.get_mut().inner)
+            .poll_flush(cx)
+            .map_err(into_io_error)
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Error>> {
+        Pin::new(&mut self.get_mut().inner)
+            .poll_close(cx)
+            .map_err(into_io_error)
+    }
+}
+
+fn into_io_error(e: tungstenite::Error) -> Error {
+    match e {
+        tungstenite::Error::Io(e) => e,
+        tungstenite::Error::ConnectionClosed | tungstenite::Error::AlreadyClosed => {
+            Error::new(ErrorKind::BrokenPipe, e)
+        }
+        e => Error::other(e),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use futures::{SinkExt, StreamExt};
+    use tokio_util::codec::Framed;
+
+    use super::{accept, connect};
+    use crate::codec::FlowCodec;
+    use crate::messages::connect::ConnectRequestV1;
+    use crate::messages::{Message, Packet};
+
+    #[tokio::test]
+    async fn test_packets_round_trip_over_websocket() {
+        let (client, server) = tokio::io::duplex(4096);
+
+        let server = tokio::spawn(async move {
+            let mut framed = Framed::new(accept(server).await.unwrap(), FlowCodec::new());
+            let packet = framed.next().await.unwrap().unwrap();
+            framed.send(packet).await.unwrap();
+        });
+
+        let stream = connect("ws://localhost/", client).await.unwrap();
+        let mut framed = Framed::new(stream, FlowCodec::new());
+
+        let packet = Packet::new((1, 1), Message::ConnectRequestV1(ConnectRequestV1 {}));
+        let context_id = packet.context_id;
+        framed.send(packet).await.unwrap();
+
+        let response = framed.next().await.unwrap().unwrap();
+        assert_eq!(context_id, response.context_id);
+        assert!(matches!(response.message, Message::ConnectRequestV1(_)));
+
+        server.await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_close_ends_stream() {
+        let (client, server) = tokio::io::duplex(4096);
+
+        let server = tokio::spawn(async move {
+            let mut framed = Framed::new(accept(server).await.unwrap(), FlowCodec::new());
+            framed.next().await.is_none()
+        });
+
+        let stream = connect("ws://localhost/", client).await.unwrap();
+        let mut framed = Framed::new(stream, FlowCodec::new());
+        framed.close().await.unwrap();
+
+        assert!(server.await.unwrap());
+    }
+}
@@ -0,0 +1,1450 @@
+use std::io::{Error, ErrorKind};
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use async_trait::async_trait;
+use bytes::Bytes;
+use futures::{SinkExt, StreamExt};
+use tokio::io::{AsyncRead, AsyncWrite};
+use tokio_util::codec::{Framed, LengthDelimitedCodec};
+use tracing::{debug, info, warn};
+
+use packline_core::app::acl::{Operation, Resource, ANONYMOUS};
+use packline_core::app::channel::{consumer_group_id, Channel};
+use packline_core::app::{App, ChannelConfig};
+use packline_core::connector::{TCPConnectionHandler, TCPConnectorHandler, TCPStream};
+
+use crate::group::{GroupCoordinator, JoinRequest, JoinResponse};
+use crate::protocol::{api_key, error_code, is_supported, Reader, RequestHeader, Writer, SUPPORTED_VERSIONS};
+use crate::records::{decode_batches, encode_batch};
+
+/// Largest request accepted, as Kafka's default `socket.request.max.bytes`.
+const MAX_REQUEST_SIZE: usize = 100 * 1024 * 1024;
+
+/// Id of the single broker the connector presents itself as.
+const NODE_ID: i32 = 0;
+
+const CLUSTER_ID: &str = "packline";
+
+/// Interval fetches waiting for `min_bytes` look for new records at.
+const FETCH_POLL_INTERVAL: Duration = Duration::from_millis(10);
+
+/// Records read from a channel at a time while filling a fetch response.
+const FETCH_READ_RECORDS: usize = 500;
+
+/// Serves Kafka clients, mapping Kafka topics onto app channels of the same name and Kafka
+/// partition `n` onto channel partition `n + 1`.
+///
+/// The connector presents itself as a single broker that leads every partition and coordinates
+/// every group. Consumer group offsets are committed to the channels under the group name, see
+/// [`consumer_group_id`], so that they are shared with the other protocols.
+///
+/// Record batches are accepted uncompressed only. Record timestamps and headers, as well as the
+/// metadata of committed offsets, aren't stored. Clients act as the anonymous principal.
+pub struct KafkaConnector {
+    pub app: App,
+
+    /// Address returned to clients in metadata responses. Defaults to the address each connection
+    /// was accepted on, which has to be set when clients reach the listener through another one.
+    pub advertised_address: Option<(String, u16)>,
+
+    /// Creates the topics clients request metadata for when they don't exist yet.
+    pub auto_create_topics: bool,
+
+    /// Partitions of the topics created automatically.
+    pub default_partitions: u16,
+
+    coordinator: Arc<GroupCoordinator>,
+}
+
+impl KafkaConnector {
+    pub fn new(app: App) -> KafkaConnector {
+        KafkaConnector {
+            app,
+            advertised_address: None,
+            auto_create_topics: true,
+            default_partitions: 1,
+            coordinator: Arc::new(GroupCoordinator::new()),
+        }
+    }
+
+    pub fn with_advertised_address(mut self, host: &str, port: u16) -> KafkaConnector {
+        self.advertised_address = Some((host.to_string(), port));
+        self
+    }
+
+    /// Creates the handler running Kafka over `stream`. `peer` identifies the remote end in logs
+    /// and `address` is the one advertised to the client unless the connector sets one.
+    pub fn connection_handler<S: KafkaStream>(
+        &self,
+        stream: S,
+        peer: String,
+        address: (String, u16),
+    ) -> KafkaConnectionHandler<S> {
+        KafkaConnectionHandler {
+            app: self.app.clone(),
+            peer,
+            stream: Some(stream),
+            address: self.advertised_address.clone().unwrap_or(address),
+            auto_create_topics: self.auto_create_topics,
+            default_partitions: self.default_partitions.max(1),
+            coordinator: self.coordinator.clone(),
+        }
+    }
+}
+
+#[async_trait]
+impl TCPConnectorHandler for KafkaConnector {
+    fn handle_connection(&self, conn: (TCPStream, SocketAddr)) -> Box<dyn TCPConnectionHandler> {
+        let address = conn
+            .0
+            .local_addr()
+            .map(|address| (address.ip().to_string(), address.port()))
+            .unwrap_or_else(|_| ("localhost".to_string(), 9092));
+
+        Box::new(self.connection_handler(conn.0, conn.1.to_string(), address))
+    }
+
+    fn name(&self) -> &'static str {
+        "kafka"
+    }
+}
+
+/// Byte stream Kafka can run on.
+pub trait KafkaStream: AsyncRead + AsyncWrite + Send + Sync + Unpin + 'static {}
+
+impl<T: AsyncRead + AsyncWrite + Send + Sync + Unpin + 'static> KafkaStream for T {}
+
+pub struct KafkaConnectionHandler<S: KafkaStream> {
+    app: App,
+    peer: String,
+    stream: Option<S>,
+    address: (String, u16),
+    auto_create_topics: bool,
+    default_partitions: u16,
+    coordinator: Arc<GroupCoordinator>,
+}
+
+#[async_trait]
+impl<S: KafkaStream> TCPConnectionHandler for KafkaConnectionHandler<S> {
+    async fn handle(&mut self) -> Result<(), Error> {
+        let stream = self.stream.take().expect("connection already handled");
+        let codec = LengthDelimitedCodec::builder()
+            .length_field_length(4)
+            .max_frame_length(MAX_REQUEST_SIZE)
+            .new_codec();
+        let mut framed = Framed::new(stream, codec);
+
+        info!("Kafka client connected from {}", self.peer);
+
+        // Requests are answered one at a time, clients rely on responses coming in request order.
+        while let Some(frame) = framed.next().await {
+            let mut reader = Reader::new(frame?.freeze());
+            let header = RequestHeader::decode(&mut reader)?;
+
+            if header.api_key != api_key::API_VERSIONS && !is_supported(header.api_key, header.api_version) {
+                warn!(
+                    "Closing Kafka connection from {}, unsupported request {} v{}",
+                    self.peer, header.api_key, header.api_version
+                );
+                return Err(Error::new(ErrorKind::Unsupported, "unsupported Kafka request"));
+            }
+
+            debug!(
+                "Kafka request {} v{} from {}",
+                header.api_key, header.api_version, self.peer
+            );
+
+            if let Some(body) = self.handle_request(&header, &mut reader).await? {
+                let mut response = Writer::new();
+                response.i32(header.correlation_id).raw(&body.into_inner());
+                framed.send(response.into_inner().freeze()).await?;
+            }
+        }
+
+        info!("Kafka client from {} disconnected", self.peer);
+        Ok(())
+    }
+}
+
+/// Results of a partition, in the order of the request.
+type TopicResults<T> = Vec<(String, Vec<(i32, T)>)>;
+
+impl<S: KafkaStream> KafkaConnectionHandler<S> {
+    /// Handles a request, returning the response body or `None` when no response is sent.
+    async fn handle_request(&self, header: &RequestHeader, reader: &mut Reader) -> Result<Option<Writer>, Error> {
+        let version = header.api_version;
+        let response = match header.api_key {
+            api_key::API_VERSIONS => self.api_versions(version),
+            api_key::METADATA => self.metadata(version, reader).await?,
+            api_key::PRODUCE => return self.produce(version, reader).await,
+            api_key::FETCH => self.fetch(version, reader).await?,
+            api_key::LIST_OFFSETS => self.list_offsets(version, reader).await?,
+            api_key::FIND_COORDINATOR => self.find_coordinator(version, reader)?,
+            api_key::JOIN_GROUP => self.join_group(version, reader, header).await?,
+            api_key::SYNC_GROUP => self.sync_group(version, reader).await?,
+            api_key::HEARTBEAT => self.heartbeat(version, reader)?,
+            api_key::LEAVE_GROUP => self.leave_group(version, reader)?,
+            api_key::OFFSET_COMMIT => self.offset_commit(version, reader).await?,
+            api_key::OFFSET_FETCH => self.offset_fetch(version, reader).await?,
+            _ => unreachable!("unsupported requests are rejected before being handled"),
+        };
+
+        Ok(Some(response))
+    }
+
+    fn api_versions(&self, version: i16) -> Writer {
+        let mut writer = Writer::new();
+
+        // Clients start with the latest version they know. Unsupported versions are answered in
+        // the v0 format with the supported ones, so that the client retries with one of them.
+        let supported = is_supported(api_key::API_VERSIONS, version);
+        writer
+            .i16(match supported {
+                true => error_code::NONE,
+                false => error_code::UNSUPPORTED_VERSION,
+            })
+            .array(SUPPORTED_VERSIONS, |writer, (key, min, max)| {
+                writer.i16(*key).i16(*min).i16(*max);
+            });
+
+        if supported && version >= 1 {
+            writer.i32(0);
+        }
+
+        writer
+    }
+
+    async fn metadata(&self, version: i16, reader: &mut Reader) -> Result<Writer, Error> {
+        let requested = match reader.array_len()? {
+            Some(len) => Some((0..len).map(|_| reader.string()).collect::<Result<Vec<_>, _>>()?),
+            None => None,
+        };
+        let allow_auto_topic_creation = match version >= 4 {
+            true => reader.bool()?,
+            false => true,
+        };
+
+        // An empty list asks for every topic before v1, which introduced null for that.
+        let requested = match requested {
+            Some(topics) if version == 0 && topics.is_empty() => None,
+            requested => requested,
+        };
+
+        let topics = match requested {
+            Some(names) => {
+                let mut topics = Vec::with_capacity(names.len());
+                for name in names {
+                    let (code, partitions) = self.describe_topic(&name, allow_auto_topic_creation).await;
+                    topics.push((code, name, partitions));
+                }
+                topics
+            }
+            None => self
+                .app
+                .list_channels()
+                .await
+                .into_iter()
+                .filter(|channel| self.authorize_topic(&channel.name, Operation::Describe))
+                .map(|channel| (error_code::NONE, channel.name, channel.partitions))
+                .collect(),
+        };
+
+        let mut writer = Writer::new();
+        if version >= 3 {
+            writer.i32(0);
+        }
+
+        writer
+            .i32(1)
+            .i32(NODE_ID)
+            .string(&self.address.0)
+            .i32(self.address.1 as i32);
+        if version >= 1 {
+            writer.nullable_string(None);
+        }
+        if version >= 2 {
+            writer.nullable_string(Some(CLUSTER_ID));
+        }
+        if version >= 1 {
+            writer.i32(NODE_ID);
+        }
+
+        writer.array(&topics, |writer, (code, name, partitions)| {
+            writer.i16(*code).string(name);
+            if version >= 1 {
+                writer.bool(false);
+            }
+
+            writer.i32(*partitions as i32);
+            for partition in 0..*partitions as i32 {
+                writer.i16(error_code::NONE).i32(partition).i32(NODE_ID);
+                if version >= 7 {
+                    writer.i32(0);
+                }
+
+                writer.array(&[NODE_ID], |writer, node| {
+                    writer.i32(*node);
+                });
+                writer.array(&[NODE_ID], |writer, node| {
+                    writer.i32(*node);
+                });
+                if version >= 5 {
+                    writer.i32(0);
+                }
+            }
+
+            if version >= 8 {
+                writer.i32(i32::MIN);
+            }
+        });
+
+        if version >= 8 {
+            writer.i32(i32::MIN);
+        }
+
+        Ok(writer)
+    }
+
+    /// Returns the error code and partition count of a topic, creating it when allowed.
+    async fn describe_topic(&self, name: &str, allow_auto_topic_creation: bool) -> (i16, u16) {
+        if !self.authorize_topic(name, Operation::Describe) {
+            return (error_code::TOPIC_AUTHORIZATION_FAILED, 0);
+        }
+
+        if let Some(partitions) = self.partition_count(name).await {
+            return (error_code::NONE, partitions);
+        }
+
+        if !allow_auto_topic_creation || !self.auto_create_topics {
+            return (error_code::UNKNOWN_TOPIC_OR_PARTITION, 0);
+        }
+
+        if !is_valid_topic_name(name) {
+            return (error_code::INVALID_TOPIC_EXCEPTION, 0);
+        }
+
+        if !self.authorize_topic(name, Operation::Create) {
+            return (error_code::TOPIC_AUTHORIZATION_FAILED, 0);
+        }
+
+        // Creation fails when another connection created the topic meanwhile, which is fine.
+        let config = ChannelConfig {
+            name: name.to_string(),
+            partitions: self.default_partitions,
+        };
+        if self.app.create_channel(config).await.is_ok() {
+            info!("Created topic {} for Kafka client {}", name, self.peer);
+        }
+
+        match self.partition_count(name).await {
+            Some(partitions) => (error_code::NONE, partitions),
+            None => (error_code::UNKNOWN_TOPIC_OR_PARTITION, 0),
+        }
+    }
+
+    async fn produce(&self, version: i16, reader: &mut Reader) -> Result<Option<Writer>, Error> {
+        let _transactional_id = reader.nullable_string()?;
+        let acks = reader.i16()?;
+        let _timeout_ms = reader.i32()?;
+        let topics = reader.array(|reader| {
+            let name = reader.string()?;
+            let partitions = reader.array(|reader| Ok((reader.i32()?, reader.nullable_bytes()?)))?;
+            Ok((name, partitions))
+        })?;
+
+        let mut results: TopicResults<(i16, i64, i64)> = Vec::with_capacity(topics.len());
+        for (name, partitions) in topics {
+            let mut partition_results = Vec::with_capacity(partitions.len());
+            for (index, records) in partitions {
+                partition_results.push((index, self.produce_partition(&name, index, records).await));
+            }
+            results.push((name, partition_results));
+        }
+
+        // Producers asking for no acknowledgement don't wait for a response.
+        if acks == 0 {
+            return Ok(None);
+        }
+
+        let mut writer = Writer::new();
+        writer.array(&results, |writer, (name, partitions)| {
+            writer
+                .string(name)
+                .array(partitions, |writer, (index, (code, base_offset, log_start_offset))| {
+                    writer.i32(*index).i16(*code).i64(*base_offset).i64(-1);
+                    if version >= 5 {
+                        writer.i64(*log_start_offset);
+                    }
+                    if version >= 8 {
+                        writer.i32(0).nullable_string(None);
+                    }
+                });
+        });
+        writer.i32(0);
+
+        Ok(Some(writer))
+    }
+
+    /// Stores the records produced to a partition, returning the error code, the offset of the
+    /// first record and the log start offset.
+    async fn produce_partition(&self, topic: &str, index: i32, records: Option<Bytes>) -> (i16, i64, i64) {
+        if !self.authorize_topic(topic, Operation::Produce) {
+            return (error_code::TOPIC_AUTHORIZATION_FAILED, -1, -1);
+        }
+
+        let channel = match self.partition(topic, index).await {
+            Some(channel) => channel,
+            None => return (error_code::UNKNOWN_TOPIC_OR_PARTITION, -1, -1),
+        };
+
+        let mut records = match decode_batches(records.unwrap_or_default()) {
+            Ok(records) => records,
+            Err(e) => return (e.code(), -1, -1),
+        };
+
+        let base_offset = channel.end_offset() as i64;
+        if !records.is_empty() {
+            channel.producer().produce(&mut records).await;
+        }
+
+        (error_code::NONE, base_offset, start_offset(&channel) as i64)
+    }
+
+    async fn fetch(&self, version: i16, reader: &mut Reader) -> Result<Writer, Error> {
+        let _replica_id = reader.i32()?;
+        let max_wait = Duration::from_millis(reader.i32()?.max(0) as u64);
+        let min_bytes = reader.i32()?.max(0) as usize;
+        let max_bytes = reader.i32()?.max(0) as usize;
+        let _isolation_level = reader.i8()?;
+        if version >= 7 {
+            let _session_id = reader.i32()?;
+            let _session_epoch = reader.i32()?;
+        }
+
+        let topics = reader.array(|reader| {
+            let name = reader.string()?;
+            let partitions = reader.array(|reader| {
+                let index = reader.i32()?;
+                if version >= 9 {
+                    let _current_leader_epoch = reader.i32()?;
+                }
+                let fetch_offset = reader.i64()?;
+                if version >= 5 {
+                    let _log_start_offset = reader.i64()?;
+                }
+                let partition_max_bytes = reader.i32()?.max(0) as usize;
+                Ok((index, (fetch_offset, partition_max_bytes)))
+            })?;
+            Ok((name, partitions))
+        })?;
+
+        // Sessions aren't supported, forgotten topics and the rack are read and ignored.
+
+        let deadline = Instant::now() + max_wait;
+        let results = loop {
+            let (results, size) = self.read_partitions(&topics, max_bytes).await;
+
+            // Errors are reported right away, like Kafka does.
+            let failed = results
+                .iter()
+                .flat_map(|(_, partitions)| partitions)
+                .any(|(_, partition)| partition.error_code != error_code::NONE);
+
+            let now = Instant::now();
+            if size >= min_bytes || failed || now >= deadline {
+                break results;
+            }
+
+            tokio::time::sleep(FETCH_POLL_INTERVAL.min(deadline - now)).await;
+        };
+
+        let mut writer = Writer::new();
+        writer.i32(0);
+        if version >= 7 {
+            writer.i16(error_code::NONE).i32(0);
+        }
+
+        writer.array(&results, |writer, (name, partitions)| {
+            writer.string(name).array(partitions, |writer, (index, partition)| {
+                writer
+                    .i32(*index)
+                    .i16(partition.error_code)
+                    .i64(partition.high_watermark)
+                    .i64(partition.high_watermark);
+                if version >= 5 {
+                    writer.i64(partition.log_start_offset);
+                }
+
+                // No aborted transactions, records are never transactional.
+                writer.i32(-1);
+                if version >= 11 {
+                    writer.i32(-1);
+                }
+
+                writer.nullable_bytes(partition.records.as_deref());
+            });
+        });
+
+        Ok(writer)
+    }
+
+    /// Reads the records requested by a fetch, returning the results and the size of the records.
+    async fn read_partitions(
+        &self,
+        topics: &TopicResults<(i64, usize)>,
+        max_bytes: usize,
+    ) -> (TopicResults<FetchedPartition>, usize) {
+        let mut size = 0;
+        let mut results = Vec::with_capacity(topics.len());
+
+        for (name, partitions) in topics {
+            let mut partition_results = Vec::with_capacity(partitions.len());
+            for (index, (fetch_offset, partition_max_bytes)) in partitions {
+                let limit = (*partition_max_bytes).min(max_bytes.saturating_sub(size));
+                let partition = self.read_partition(name, *index, *fetch_offset, limit, size == 0).await;
+
+                size += partition.records.as_ref().map(|records| records.len()).unwrap_or(0);
+                partition_results.push((*index, partition));
+            }
+            results.push((name.clone(), partition_results));
+        }
+
+        (results, size)
+    }
+
+    /// Reads the records of a partition from `offset` fitting in `limit` bytes. When `first` is set
+    /// at least one record is read whatever its size, so that consumers can't get stuck on it.
+    async fn read_partition(
+        &self,
+        topic: &str,
+        index: i32,
+        offset: i64,
+        limit: usize,
+        first: bool,
+    ) -> FetchedPartition {
+        if !self.authorize_topic(topic, Operation::Consume) {
+            return FetchedPartition::error(error_code::TOPIC_AUTHORIZATION_FAILED);
+        }
+
+        let channel = match self.partition(topic, index).await {
+            Some(channel) => channel,
+            None => return FetchedPartition::error(error_code::UNKNOWN_TOPIC_OR_PARTITION),
+        };
+
+        let high_watermark = channel.end_offset();
+        let log_start_offset = start_offset(&channel);
+        let mut partition = FetchedPartition {
+            error_code: error_code::NONE,
+            high_watermark: high_watermark as i64,
+            log_start_offset: log_start_offset as i64,
+            records: None,
+        };
+
+        if offset < log_start_offset as i64 || offset > high_watermark as i64 {
+            partition.error_code = error_code::OFFSET_OUT_OF_RANGE;
+            return partition;
+        }
+
+        let mut records = vec![];
+        let mut size = 0;
+        let mut next = offset as u64;
+        'read: while next < high_watermark {
+            let read = channel.read(next, FETCH_READ_RECORDS);
+            if read.is_empty() {
+                break;
+            }
+
+            for record in read {
+                // Upper bound of the encoded record, batch overhead included.
+                let record_size = record.key.len() + record.value.len() + 32;
+                if size + record_size > limit && !(first && records.is_empty()) {
+                    break 'read;
+                }
+
+                size += record_size;
+                next = record.offset + 1;
+                records.push(record);
+            }
+        }
+
+        if !records.is_empty() {
+            channel.metrics().consumed(&records);
+            partition.records = Some(encode_batch(&records));
+        }
+
+        partition
+    }
+
+    async fn list_offsets(&self, version: i16, reader: &mut Reader) -> Result<Writer, Error> {
+        let _replica_id = reader.i32()?;
+        if version >= 2 {
+            let _isolation_level = reader.i8()?;
+        }
+
+        let topics = reader.array(|reader| {
+            let name = reader.string()?;
+            let partitions = reader.array(|reader| {
+                let index = reader.i32()?;
+                if version >= 4 {
+                    let _current_leader_epoch = reader.i32()?;
+                }
+                Ok((index, reader.i64()?))
+            })?;
+            Ok((name, partitions))
+        })?;
+
+        let mut results: TopicResults<(i16, i64)> = Vec::with_capacity(topics.len());
+        for (name, partitions) in topics {
+            let mut partition_results = Vec::with_capacity(partitions.len());
+            for (index, timestamp) in partitions {
+                partition_results.push((index, self.list_offset(&name, index, timestamp).await));
+            }
+            results.push((name, partition_results));
+        }
+
+        let mut writer = Writer::new();
+        if version >= 2 {
+            writer.i32(0);
+        }
+
+        writer.array(&results, |writer, (name, partitions)| {
+            writer
+                .string(name)
+                .array(partitions, |writer, (index, (code, offset))| {
+                    writer.i32(*index).i16(*code).i64(-1).i64(*offset);
+                    if version >= 4 {
+                        writer.i32(0);
+                    }
+                });
+        });
+
+        Ok(writer)
+    }
+
+    /// Returns the offset of a partition for a timestamp: -1 asks for the end of the partition and
+    /// -2 for its start. Channels don't keep timestamps, other timestamps don't match any offset.
+    async fn list_offset(&self, topic: &str, index: i32, timestamp: i64) -> (i16, i64) {
+        if !self.authorize_topic(topic, Operation::Describe) {
+            return (error_code::TOPIC_AUTHORIZATION_FAILED, -1);
+        }
+
+        let channel = match self.partition(topic, index).await {
+            Some(channel) => channel,
+            None => return (error_code::UNKNOWN_TOPIC_OR_PARTITION, -1),
+        };
+
+        match timestamp {
+            -1 => (error_code::NONE, channel.end_offset() as i64),
+            -2 => (error_code::NONE, start_offset(&channel) as i64),
+            _ => (error_code::NONE, -1),
+        }
+    }
+
+    fn find_coordinator(&self, version: i16, reader: &mut Reader) -> Result<Writer, Error> {
+        let key = reader.string()?;
+        let key_type = match version >= 1 {
+            true => reader.i8()?,
+            false => 0,
+        };
+
+        // Only consumer groups are coordinated, transactions aren't supported.
+        let code = if key_type != 0 {
+            error_code::COORDINATOR_NOT_AVAILABLE
+        } else if !self.authorize_group(&key, Operation::Describe) {
+            error_code::GROUP_AUTHORIZATION_FAILED
+        } else {
+            error_code::NONE
+        };
+
+        let mut writer = Writer::new();
+        if version >= 1 {
+            writer.i32(0);
+        }
+
+        writer.i16(code);
+        if version >= 1 {
+            writer.nullable_string(None);
+        }
+
+        writer.i32(NODE_ID).string(&self.address.0).i32(self.address.1 as i32);
+        Ok(writer)
+    }
+
+    async fn join_group(&self, version: i16, reader: &mut Reader, header: &RequestHeader) -> Result<Writer, Error> {
+        let group_id = reader.string()?;
+        let session_timeout = Duration::from_millis(reader.i32()?.max(0) as u64);
+        let rebalance_timeout = match version >= 1 {
+            true => Duration::from_millis(reader.i32()?.max(0) as u64),
+            false => session_timeout,
+        };
+        let member_id = reader.string()?;
+        if version >= 5 {
+            let _group_instance_id = reader.nullable_string()?;
+        }
+        let protocol_type = reader.string()?;
+        let protocols = reader.array(|reader| Ok((reader.string()?, reader.bytes()?)))?;
+
+        let response = match self.authorize_group(&group_id, Operation::Consume) {
+            true => {
+                self.coordinator
+                    .join(JoinRequest {
+                        group_id,
+                        member_id,
+                        client_id: header.client_id.clone().unwrap_or_else(|| "kafka".to_string()),
+                        session_timeout,
+                        rebalance_timeout,
+                        protocol_type,
+                        protocols,
+                    })
+                    .await
+            }
+            false => JoinResponse::error(error_code::GROUP_AUTHORIZATION_FAILED, member_id),
+        };
+
+        let mut writer = Writer::new();
+        if version >= 2 {
+            writer.i32(0);
+        }
+
+        writer
+            .i16(response.error_code)
+            .i32(response.generation_id)
+            .string(&response.protocol_name)
+            .string(&response.leader)
+            .string(&response.member_id)
+            .array(&response.members, |writer, (member_id, metadata)| {
+                writer.string(member_id);
+                if version >= 5 {
+                    writer.nullable_string(None);
+                }
+                writer.bytes(metadata);
+            });
+
+        Ok(writer)
+    }
+
+    async fn sync_group(&self, version: i16, reader: &mut Reader) -> Result<Writer, Error> {
+        let group_id = reader.string()?;
+        let generation_id = reader.i32()?;
+        let member_id = reader.string()?;
+        if version >= 3 {
+            let _group_instance_id = reader.nullable_string()?;
+        }
+        let assignments = reader.array(|reader| Ok((reader.string()?, reader.bytes()?)))?;
+
+        let (code, assignment) = match self.authorize_group(&group_id, Operation::Consume) {
+            true => {
+                let response = self
+                    .coordinator
+                    .sync(&group_id, generation_id, &member_id, assignments)
+                    .await;
+                (response.error_code, response.assignment)
+            }
+            false => (error_code::GROUP_AUTHORIZATION_FAILED, Bytes::new()),
+        };
+
+        let mut writer = Writer::new();
+        if version >= 1 {
+            writer.i32(0);
+        }
+
+        writer.i16(code).bytes(&assignment);
+        Ok(writer)
+    }
+
+    fn heartbeat(&self, version: i16, reader: &mut Reader) -> Result<Writer, Error> {
+        let group_id = reader.string()?;
+        let generation_id = reader.i32()?;
+        let member_id = reader.string()?;
+
+        let code = match self.authorize_group(&group_id, Operation::Consume) {
+            true => self.coordinator.heartbeat(&group_id, generation_id, &member_id),
+            false => error_code::GROUP_AUTHORIZATION_FAILED,
+        };
+
+        Ok(group_response(version, code))
+    }
+
+    fn leave_group(&self, version: i16, reader: &mut Reader) -> Result<Writer, Error> {
+        let group_id = reader.string()?;
+        let member_id = reader.string()?;
+
+        let code = match self.authorize_group(&group_id, Operation::Consume) {
+            true => self.coordinator.leave(&group_id, &member_id),
+            false => error_code::GROUP_AUTHORIZATION_FAILED,
+        };
+
+        Ok(group_response(version, code))
+    }
+
+    async fn offset_commit(&self, version: i16, reader: &mut Reader) -> Result<Writer, Error> {
+        let group_id = reader.string()?;
+        let (generation_id, member_id) = match version >= 1 {
+            true => (reader.i32()?, reader.string()?),
+            false => (-1, String::new()),
+        };
+        if version >= 7 {
+            let _group_instance_id = reader.nullable_string()?;
+        }
+        if (2..=4).contains(&version) {
+            let _retention_time_ms = reader.i64()?;
+        }
+
+        let topics = reader.array(|reader| {
+            let name = reader.string()?;
+            let partitions = reader.array(|reader| {
+                let index = reader.i32()?;
+                let offset = reader.i64()?;
+                if version >= 6 {
+                    let _committed_leader_epoch = reader.i32()?;
+                }
+                if version == 1 {
+                    let _commit_timestamp = reader.i64()?;
+                }
+                let _metadata = reader.nullable_string()?;
+                Ok((index, offset))
+            })?;
+            Ok((name, partitions))
+        })?;
+
+        let group_code = match self.authorize_group(&group_id, Operation::Consume) {
+            true => self.coordinator.validate_commit(&group_id, generation_id, &member_id),
+            false => error_code::GROUP_AUTHORIZATION_FAILED,
+        };
+
+        let mut results: TopicResults<i16> = Vec::with_capacity(topics.len());
+        for (name, partitions) in topics {
+            let mut partition_results = Vec::with_capacity(partitions.len());
+            for (index, offset) in partitions {
+                let code = match group_code {
+                    error_code::NONE => self.commit_offset(&group_id, &name, index, offset).await,
+                    code => code,
+                };
+                partition_results.push((index, code));
+            }
+            results.push((name, partition_results));
+        }
+
+        let mut writer = Writer::new();
+        if version >= 3 {
+            writer.i32(0);
+        }
+
+        writer.array(&results, |writer, (name, partitions)| {
+            writer.string(name).array(partitions, |writer, (index, code)| {
+                writer.i32(*index).i16(*code);
+            });
+        });
+
+        Ok(writer)
+    }
+
+    async fn commit_offset(&self, group_id: &str, topic: &str, index: i32, offset: i64) -> i16 {
+        if !self.authorize_topic(topic, Operation::Consume) {
+            return error_code::TOPIC_AUTHORIZATION_FAILED;
+        }
+
+        let channel = match self.partition(topic, index).await {
+            Some(channel) => channel,
+            None => return error_code::UNKNOWN_TOPIC_OR_PARTITION,
+        };
+
+        if offset < 0 {
+            return error_code::OFFSET_OUT_OF_RANGE;
+        }
+
+        match channel
+            .commit_offset(channel.register_group(group_id), offset as u64)
+            .await
+        {
+            Ok(()) => error_code::NONE,
+            Err(()) => error_code::OFFSET_OUT_OF_RANGE,
+        }
+    }
+
+    async fn offset_fetch(&self, version: i16, reader: &mut Reader) -> Result<Writer, Error> {
+        let group_id = reader.string()?;
+        let requested = match reader.array_len()? {
+            Some(len) => Some(
+                (0..len)
+                    .map(|_| Ok((reader.string()?, reader.array(|reader| reader.i32())?)))
+                    .collect::<Result<Vec<_>, Error>>()?,
+            ),
+            None => None,
+        };
+
+        let authorized = self.authorize_group(&group_id, Operation::Consume);
+        let group = consumer_group_id(&group_id);
+
+        let mut results: TopicResults<(i16, i64)> = vec![];
+        match requested {
+            // Before v2 a denied group is reported for each partition, later on for the whole request.
+            _ if !authorized && version >= 2 => {}
+            Some(topics) => {
+                for (name, partitions) in topics {
+                    let mut partition_results = Vec::with_capacity(partitions.len());
+                    for index in partitions {
+                        let result = match authorized {
+                            true => self.committed_offset(group, &name, index).await,
+                            false => (error_code::GROUP_AUTHORIZATION_FAILED, -1),
+                        };
+                        partition_results.push((index, result));
+                    }
+                    results.push((name, partition_results));
+                }
+            }
+            // Every partition the group committed an offset for.
+            None => {
+                for topic in self.app.list_channels().await {
+                    if !self.authorize_topic(&topic.name, Operation::Describe) {
+                        continue;
+                    }
+
+                    let mut partition_results = vec![];
+                    for index in 0..topic.partitions as i32 {
+                        if let Some(channel) = self.partition(&topic.name, index).await {
+                            if let (Some(offset), _) = channel.offsets(group).await {
+                                partition_results.push((index, (error_code::NONE, offset as i64)));
+                            }
+                        }
+                    }
+
+                    if !partition_results.is_empty() {
+                        results.push((topic.name, partition_results));
+                    }
+                }
+            }
+        }
+
+        let mut writer = Writer::new();
+        if version >= 3 {
+            writer.i32(0);
+        }
+
+        writer.array(&results, |writer, (name, partitions)| {
+            writer
+                .string(name)
+                .array(partitions, |writer, (index, (code, offset))| {
+                    writer.i32(*index).i64(*offset);
+                    if version >= 5 {
+                        writer.i32(-1);
+                    }
+                    writer.nullable_string(Some("")).i16(*code);
+                });
+        });
+
+        if version >= 2 {
+            writer.i16(match authorized {
+                true => error_code::NONE,
+                false => error_code::GROUP_AUTHORIZATION_FAILED,
+            });
+        }
+
+        Ok(writer)
+    }
+
+    /// Returns the offset committed by the group for a partition, -1 when it didn't commit any.
+    async fn committed_offset(&self, group: u128, topic: &str, index: i32) -> (i16, i64) {
+        if !self.authorize_topic(topic, Operation::Describe) {
+            return (error_code::TOPIC_AUTHORIZATION_FAILED, -1);
+        }
+
+        match self.partition(topic, index).await {
+            Some(channel) => match channel.offsets(group).await {
+                (Some(offset), _) => (error_code::NONE, offset as i64),
+                (None, _) => (error_code::NONE, -1),
+            },
+            None => (error_code::UNKNOWN_TOPIC_OR_PARTITION, -1),
+        }
+    }
+
+    async fn partition(&self, topic: &str, index: i32) -> Option<Channel> {
+        let partition = u16::try_from(index).ok()?.checked_add(1)?;
+        self.app.get_channel(&(topic.to_string(), partition)).await
+    }
+
+    async fn partition_count(&self, topic: &str) -> Option<u16> {
+        self.app
+            .list_channels()
+            .await
+            .into_iter()
+            .find(|channel| channel.name == topic)
+            .map(|channel| channel.partitions)
+    }
+
+    fn authorize_topic(&self, topic: &str, operation: Operation) -> bool {
+        self.app.authorize(ANONYMOUS, &Resource::topic(topic), operation)
+    }
+
+    fn authorize_group(&self, group: &str, operation: Operation) -> bool {
+        self.app
+            .authorize(ANONYMOUS, &Resource::consumer_group(group), operation)
+    }
+}
+
+struct FetchedPartition {
+    error_code: i16,
+    high_watermark: i64,
+    log_start_offset: i64,
+    records: Option<Bytes>,
+}
+
+impl FetchedPartition {
+    fn error(error_code: i16) -> FetchedPartition {
+        FetchedPartition {
+            error_code,
+            high_watermark: -1,
+            log_start_offset: -1,
+            records: None,
+        }
+    }
+}
+
+/// Response of the group APIs answering with an error code only.
+fn group_response(version: i16, code: i16) -> Writer {
+    let mut writer = Writer::new();
+    if version >= 1 {
+        writer.i32(0);
+    }
+
+    writer.i16(code);
+    writer
+}
+
+/// Returns the offset of the first record still kept by a channel.
+fn start_offset(channel: &Channel) -> u64 {
+    channel
+        .read(0, 1)
+        .first()
+        .map(|record| record.offset)
+        .unwrap_or_else(|| channel.end_offset())
+}
+
+/// Checks a topic name against the rules of Kafka, which doesn't allow names it can't use as a
+/// directory name.
+fn is_valid_topic_name(name: &str) -> bool {
+    !name.is_empty()
+        && name.len() <= 249
+        && name != "."
+        && name != ".."
+        && name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '.' | '_' | '-'))
+}
+
+#[cfg(test)]
+mod tests {
+    use packline_core::app::acl::{AclRule, Authorizer, Permission};
+    use packline_core::app::channel::Record;
+    use tokio::io::DuplexStream;
+
+    use super::*;
+
+    struct Client {
+        framed: Framed<DuplexStream, LengthDelimitedCodec>,
+        correlation_id: i32,
+    }
+
+    impl Client {
+        /// Sends a request and returns the body of its response.
+        async fn request(&mut self, api_key: i16, version: i16, body: Writer) -> Reader {
+            self.correlation_id += 1;
+
+            let mut request = Writer::new();
+            request
+                .i16(api_key)
+                .i16(version)
+                .i32(self.correlation_id)
+                .nullable_string(Some("test"))
+                .raw(&body.into_inner());
+            self.framed.send(request.into_inner().freeze()).await.unwrap();
+
+            let frame = tokio::time::timeout(Duration::from_secs(2), self.framed.next())
+                .await
+                .expect("timed out waiting for a response")
+                .unwrap()
+                .unwrap();
+
+            let mut reader = Reader::new(frame.freeze());
+            assert_eq!(self.correlation_id, reader.i32().unwrap());
+            reader
+        }
+    }
+
+    fn open(connector: &KafkaConnector) -> Client {
+        let (client, server) = tokio::io::duplex(64 * 1024);
+        let mut handler = connector.connection_handler(server, "test".to_string(), ("127.0.0.1".to_string(), 9092));
+        tokio::spawn(async move { handler.handle().await });
+
+        Client {
+            framed: Framed::new(client, LengthDelimitedCodec::new()),
+            correlation_id: 0,
+        }
+    }
+
+    /// Builds a v3+ produce request with a single partition.
+    fn produce_request(topic: &str, partition: i32, records: &[Record]) -> Writer {
+        let mut body = Writer::new();
+        body.nullable_string(None)
+            .i16(1)
+            .i32(1000)
+            .i32(1)
+            .string(topic)
+            .i32(1)
+            .i32(partition);
+        body.bytes(&encode_batch(records));
+        body
+    }
+
+    /// Builds a fetch request v4 with a single partition.
+    fn fetch_request(topic: &str, partition: i32, offset: i64, max_wait_ms: i32) -> Writer {
+        let mut body = Writer::new();
+        body.i32(-1).i32(max_wait_ms).i32(1).i32(1024 * 1024).i8(0);
+        body.i32(1)
+            .string(topic)
+            .i32(1)
+            .i32(partition)
+            .i64(offset)
+            .i32(1024 * 1024);
+        body
+    }
+
+    /// Reads the partition of a single partition fetch v4 response, returning its error code, high
+    /// watermark and records.
+    fn fetch_response(mut reader: Reader) -> (i16, i64, Vec<Record>) {
+        let _throttle_time_ms = reader.i32().unwrap();
+        assert_eq!(1, reader.i32().unwrap());
+        reader.string().unwrap();
+        assert_eq!(1, reader.i32().unwrap());
+        reader.i32().unwrap();
+
+        let code = reader.i16().unwrap();
+        let high_watermark = reader.i64().unwrap();
+        let _last_stable_offset = reader.i64().unwrap();
+        let _aborted_transactions = reader.i32().unwrap();
+        let records = reader.nullable_bytes().unwrap().unwrap_or_default();
+
+        (code, high_watermark, decode_batches(records).unwrap())
+    }
+
+    async fn create_topic(app: &App, name: &str, partitions: u16) {
+        app.create_channel(ChannelConfig {
+            name: name.to_string(),
+            partitions,
+        })
+        .await
+        .unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_api_versions() {
+        let connector = KafkaConnector::new(App::new());
+        let mut client = open(&connector);
+
+        let mut response = client.request(api_key::API_VERSIONS, 2, Writer::new()).await;
+        assert_eq!(error_code::NONE, response.i16().unwrap());
+        let versions = response
+            .array(|reader| Ok((reader.i16()?, reader.i16()?, reader.i16()?)))
+            .unwrap();
+        assert_eq!(SUPPORTED_VERSIONS, &versions[..]);
+
+        // Versions the connector doesn't know are answered in the v0 format.
+        let mut response = client.request(api_key::API_VERSIONS, 3, Writer::new()).await;
+        assert_eq!(error_code::UNSUPPORTED_VERSION, response.i16().unwrap());
+        assert_eq!(SUPPORTED_VERSIONS.len(), response.array_len().unwrap().unwrap());
+    }
+
+    #[tokio::test]
+    async fn test_metadata_creates_requested_topic() {
+        let app = App::new();
+        let connector = KafkaConnector::new(app.clone()).with_advertised_address("kafka.local", 19092);
+        let mut client = open(&connector);
+
+        let mut body = Writer::new();
+        body.i32(1).string("orders").bool(true);
+        let mut response = client.request(api_key::METADATA, 4, body).await;
+
+        let _throttle_time_ms = response.i32().unwrap();
+        assert_eq!(Some(1), response.array_len().unwrap());
+        assert_eq!(NODE_ID, response.i32().unwrap());
+        assert_eq!("kafka.local", response.string().unwrap());
+        assert_eq!(19092, response.i32().unwrap());
+        let _rack = response.nullable_string().unwrap();
+        assert_eq!(Some(CLUSTER_ID.to_string()), response.nullable_string().unwrap());
+        assert_eq!(NODE_ID, response.i32().unwrap());
+
+        assert_eq!(Some(1), response.array_len().unwrap());
+        assert_eq!(error_code::NONE, response.i16().unwrap());
+        assert_eq!("orders", response.string().unwrap());
+        assert!(!response.bool().unwrap());
+        assert_eq!(Some(1), response.array_len().unwrap());
+
+        assert!(app.get_channel(&("orders".to_string(), 1)).await.is_some());
+    }
+
+    #[tokio::test]
+    async fn test_metadata_without_auto_creation() {
+        let app = App::new();
+        let mut connector = KafkaConnector::new(app.clone());
+        connector.auto_create_topics = false;
+        let mut client = open(&connector);
+
+        let mut body = Writer::new();
+        body.i32(1).string("orders");
+        let mut response = client.request(api_key::METADATA, 1, body).await;
+
+        response
+            .array(|reader| {
+                Ok((
+                    reader.i32()?,
+                    reader.string()?,
+                    reader.i32()?,
+                    reader.nullable_string()?,
+                ))
+            })
+            .unwrap();
+        let _controller_id = response.i32().unwrap();
+        assert_eq!(Some(1), response.array_len().unwrap());
+        assert_eq!(error_code::UNKNOWN_TOPIC_OR_PARTITION, response.i16().unwrap());
+
+        assert!(app.list_channels().await.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_produce_and_fetch() {
+        let app = App::new();
+        create_topic(&app, "orders", 2).await;
+
+        let connector = KafkaConnector::new(app.clone());
+        let mut client = open(&connector);
+
+        let records = vec![Record::new("user-1", "first"), Record::from_value("second")];
+        let mut response = client
+            .request(api_key::PRODUCE, 3, produce_request("orders", 1, &records))
+            .await;
+
+        assert_eq!(Some(1), response.array_len().unwrap());
+        assert_eq!("orders", response.string().unwrap());
+        assert_eq!(Some(1), response.array_len().unwrap());
+        assert_eq!(1, response.i32().unwrap());
+        assert_eq!(error_code::NONE, response.i16().unwrap());
+        assert_eq!(0, response.i64().unwrap());
+
+        let channel = app.get_channel(&("orders".to_string(), 2)).await.unwrap();
+        assert_eq!(2, channel.end_offset());
+
+        let response = client
+            .request(api_key::FETCH, 4, fetch_request("orders", 1, 1, 0))
+            .await;
+        let (code, high_watermark, fetched) = fetch_response(response);
+
+        assert_eq!(error_code::NONE, code);
+        assert_eq!(2, high_watermark);
+        assert_eq!(
+            vec![(vec![], b"second".to_vec())],
+            fetched.into_iter().map(|r| (r.key, r.value)).collect::<Vec<_>>()
+        );
+
+        let response = client
+            .request(api_key::FETCH, 4, fetch_request("orders", 1, 5, 0))
+            .await;
+        assert_eq!(error_code::OFFSET_OUT_OF_RANGE, fetch_response(response).0);
+
+        let response = client
+            .request(api_key::FETCH, 4, fetch_request("orders", 2, 0, 0))
+            .await;
+        assert_eq!(error_code::UNKNOWN_TOPIC_OR_PARTITION, fetch_response(response).0);
+    }
+
+    #[tokio::test]
+    async fn test_fetch_waits_for_records() {
+        let app = App::new();
+        create_topic(&app, "orders", 1).await;
+
+        let connector = KafkaConnector::new(app.clone());
+        let mut client = open(&connector);
+
+        let channel = app.get_channel(&("orders".to_string(), 1)).await.unwrap();
+        tokio::spawn(async move {
+            tokio::time::sleep(Duration::from_millis(50)).await;
+            channel.producer().produce(&mut vec![Record::from_value("late")]).await;
+        });
+
+        let response = client
+            .request(api_key::FETCH, 4, fetch_request("orders", 0, 0, 1000))
+            .await;
+        let (code, _, fetched) = fetch_response(response);
+
+        assert_eq!(error_code::NONE, code);
+        assert_eq!(
+            vec![b"late".to_vec()],
+            fetched.into_iter().map(|r| r.value).collect::<Vec<_>>()
+        );
+    }
+
+    #[tokio::test]
+    async fn test_produce_is_authorized() {
+        let authorizer = Authorizer::new();
+        authorizer
+            .add_rule(AclRule {
+                principal: ANONYMOUS.to_string(),
+                resource: Resource::topic("orders"),
+                operation: Operation::Consume,
+                permission: Permission::Allow,
+            })
+            .unwrap();
+
+        let app = App::with_authorizer(authorizer);
+        create_topic(&app, "orders", 1).await;
+
+        let connector = KafkaConnector::new(app.clone());
+        let mut client = open(&connector);
+
+        let mut response = client
+            .request(
+                api_key::PRODUCE,
+                3,
+                produce_request("orders", 0, &[Record::from_value("value")]),
+            )
+            .await;
+        response.array_len().unwrap();
+        response.string().unwrap();
+        response.array_len().unwrap();
+        response.i32().unwrap();
+
+        assert_eq!(error_code::TOPIC_AUTHORIZATION_FAILED, response.i16().unwrap());
+        assert_eq!(
+            0,
+            app.get_channel(&("orders".to_string(), 1)).await.unwrap().end_offset()
+        );
+    }
+
+    #[tokio::test]
+    async fn test_list_offsets() {
+        let app = App::new();
+        create_topic(&app, "orders", 1).await;
+        let channel = app.get_channel(&("orders".to_string(), 1)).await.unwrap();
+        channel
+            .producer()
+            .produce(&mut vec![Record::from_value("a"), Record::from_value("b")])
+            .await;
+
+        let connector = KafkaConnector::new(app);
+        let mut client = open(&connector);
+
+        for (timestamp, expected) in [(-2, 0), (-1, 2), (1_600_000_000_000, -1)] {
+            let mut body = Writer::new();
+            body.i32(-1).i32(1).string("orders").i32(1).i32(0).i64(timestamp);
+            let mut response = client.request(api_key::LIST_OFFSETS, 1, body).await;
+
+            response.array_len().unwrap();
+            response.string().unwrap();
+            response.array_len().unwrap();
+            response.i32().unwrap();
+            assert_eq!(error_code::NONE, response.i16().unwrap());
+            let _timestamp = response.i64().unwrap();
+            assert_eq!(expected, response.i64().unwrap());
+        }
+    }
+
+    #[tokio::test]
+    async fn test_consumer_group_round_trip() {
+        let app = App::new();
+        create_topic(&app, "orders", 1).await;
+        app.get_channel(&("orders".to_string(), 1))
+            .await
+            .unwrap()
+            .producer()
+            .produce(&mut vec![Record::from_value("a"), Record::from_value("b")])
+            .await;
+
+        let connector = KafkaConnector::new(app.clone());
+        let mut client = open(&connector);
+
+        let mut body = Writer::new();
+        body.string("billing").i8(0);
+        let mut response = client.request(api_key::FIND_COORDINATOR, 1, body).await;
+        let _throttle_time_ms = response.i32().unwrap();
+        assert_eq!(error_code::NONE, response.i16().unwrap());
+        let _error_message = response.nullable_string().unwrap();
+        assert_eq!(NODE_ID, response.i32().unwrap());
+
+        let mut body = Writer::new();
+        body.string("billing")
+            .i32(10000)
+            .i32(10000)
+            .string("")
+            .string("consumer");
+        body.i32(1).string("range").bytes(b"metadata");
+        let mut response = client.request(api_key::JOIN_GROUP, 2, body).await;
+        let _throttle_time_ms = response.i32().unwrap();
+        assert_eq!(error_code::NONE, response.i16().unwrap());
+        let generation_id = response.i32().unwrap();
+        assert_eq!("range", response.string().unwrap());
+        let leader = response.string().unwrap();
+        let member_id = response.string().unwrap();
+        assert_eq!(leader, member_id);
+
+        let mut body = Writer::new();
+        body.string("billing").i32(generation_id).string(&member_id);
+        body.i32(1).string(&member_id).bytes(b"assignment");
+        let mut response = client.request(api_key::SYNC_GROUP, 1, body).await;
+        let _throttle_time_ms = response.i32().unwrap();
+        assert_eq!(error_code::NONE, response.i16().unwrap());
+        assert_eq!(&b"assignment"[..], &response.bytes().unwrap()[..]);
+
+        let mut body = Writer::new();
+        body.string("billing").i32(generation_id).string(&member_id);
+        let mut response = client.request(api_key::HEARTBEAT, 1, body).await;
+        let _throttle_time_ms = response.i32().unwrap();
+        assert_eq!(error_code::NONE, response.i16().unwrap());
+
+        let mut body = Writer::new();
+        body.string("billing").i32(generation_id).string(&member_id).i64(-1);
+        body.i32(1).string("orders").i32(1).i32(0).i64(2).nullable_string(None);
+        let mut response = client.request(api_key::OFFSET_COMMIT, 2, body).await;
+        response.array_len().unwrap();
+        response.string().unwrap();
+        response.array_len().unwrap();
+        response.i32().unwrap();
+        assert_eq!(error_code::NONE, response.i16().unwrap());
+
+        let channel = app.get_channel(&("orders".to_string(), 1)).await.unwrap();
+        assert_eq!(Some(2), channel.offsets(consumer_group_id("billing")).await.0);
+
+        for (partition, expected) in [(0, 2), (1, -1)] {
+            let mut body = Writer::new();
+            body.string("billing").i32(1).string("orders").i32(1).i32(partition);
+            let mut response = client.request(api_key::OFFSET_FETCH, 2, body).await;
+
+            response.array_len().unwrap();
+            response.string().unwrap();
+            response.array_len().unwrap();
+            assert_eq!(partition, response.i32().unwrap());
+            assert_eq!(expected, response.i64().unwrap());
+        }
+
+        let mut body = Writer::new();
+        body.string("billing").string(&member_id);
+        let mut response = client.request(api_key::LEAVE_GROUP, 1, body).await;
+        let _throttle_time_ms = response.i32().unwrap();
+        assert_eq!(error_code::NONE, response.i16().unwrap());
+    }
+
+    #[tokio::test]
+    async fn test_unsupported_version_closes_connection() {
+        let connector = KafkaConnector::new(App::new());
+        let mut client = open(&connector);
+
+        let mut request = Writer::new();
+        request.i16(api_key::FETCH).i16(12).i32(1).nullable_string(None);
+        client.framed.send(request.into_inner().freeze()).await.unwrap();
+
+        let next = tokio::time::timeout(Duration::from_secs(2), client.framed.next())
+            .await
+            .unwrap();
+        assert!(next.is_none());
+    }
+}
@@ -0,0 +1,623 @@
+use std::collections::BTreeMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use bytes::Bytes;
+use tokio::sync::oneshot;
+
+use crate::protocol::error_code;
+
+/// Keeps the membership of the consumer groups coordinated by the connector, running the join and
+/// sync rounds that assign partitions to the members of a group.
+///
+/// Members are only tracked in memory. Their sessions expire lazily: a member that stopped
+/// heartbeating is removed when another member of its group heartbeats, or when a rebalance it
+/// doesn't rejoin times out.
+#[derive(Default)]
+pub struct GroupCoordinator {
+    groups: Mutex<BTreeMap<String, Group>>,
+    next_member_id: AtomicU64,
+}
+
+#[derive(Clone, Debug)]
+pub struct JoinRequest {
+    pub group_id: String,
+
+    /// Empty for a member joining for the first time, which gets an id assigned.
+    pub member_id: String,
+    pub client_id: String,
+    pub session_timeout: Duration,
+    pub rebalance_timeout: Duration,
+    pub protocol_type: String,
+
+    /// Assignment protocols supported by the member, in order of preference, with their metadata.
+    pub protocols: Vec<(String, Bytes)>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct JoinResponse {
+    pub error_code: i16,
+    pub generation_id: i32,
+    pub protocol_name: String,
+    pub leader: String,
+    pub member_id: String,
+
+    /// Members of the group with their metadata, only sent to the leader.
+    pub members: Vec<(String, Bytes)>,
+}
+
+impl JoinResponse {
+    pub(crate) fn error(error_code: i16, member_id: String) -> JoinResponse {
+        JoinResponse {
+            error_code,
+            generation_id: -1,
+            protocol_name: String::new(),
+            leader: String::new(),
+            member_id,
+            members: vec![],
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SyncResponse {
+    pub error_code: i16,
+    pub assignment: Bytes,
+}
+
+impl SyncResponse {
+    fn error(error_code: i16) -> SyncResponse {
+        SyncResponse {
+            error_code,
+            assignment: Bytes::new(),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum GroupState {
+    Empty,
+    PreparingRebalance,
+    CompletingRebalance,
+    Stable,
+}
+
+struct Group {
+    state: GroupState,
+    generation: i32,
+    protocol_type: Option<String>,
+    leader: Option<String>,
+    members: BTreeMap<String, Member>,
+}
+
+struct Member {
+    session_timeout: Duration,
+    protocols: Vec<(String, Bytes)>,
+    assignment: Bytes,
+    last_seen: Instant,
+
+    pending_join: Option<oneshot::Sender<JoinResponse>>,
+    pending_sync: Option<oneshot::Sender<SyncResponse>>,
+}
+
+impl GroupCoordinator {
+    pub fn new() -> GroupCoordinator {
+        GroupCoordinator::default()
+    }
+
+    /// Joins a member to its group, waiting until every known member rejoined or the rebalance
+    /// timeout of the member elapsed, in which case the members that didn't rejoin are removed.
+    pub async fn join(&self, request: JoinRequest) -> JoinResponse {
+        let group_id = request.group_id.clone();
+        let rebalance_timeout = request.rebalance_timeout;
+
+        let (member_id, mut receiver) = match self.register_join(request) {
+            Ok(registered) => registered,
+            Err(response) => return response,
+        };
+
+        match tokio::time::timeout(rebalance_timeout, &mut receiver).await {
+            Ok(response) => response,
+            Err(_) => {
+                self.force_rebalance(&group_id);
+                receiver.await
+            }
+        }
+        .unwrap_or_else(|_| JoinResponse::error(error_code::REBALANCE_IN_PROGRESS, member_id))
+    }
+
+    fn register_join(&self, request: JoinRequest) -> Result<(String, oneshot::Receiver<JoinResponse>), JoinResponse> {
+        let mut groups = self.groups.lock().unwrap();
+        let group = groups.entry(request.group_id.clone()).or_insert_with(Group::new);
+
+        if !group.members.is_empty() && group.protocol_type.as_deref() != Some(request.protocol_type.as_str()) {
+            return Err(JoinResponse::error(
+                error_code::INCONSISTENT_GROUP_PROTOCOL,
+                request.member_id,
+            ));
+        }
+
+        if !request.member_id.is_empty() && !group.members.contains_key(&request.member_id) {
+            return Err(JoinResponse::error(error_code::UNKNOWN_MEMBER_ID, request.member_id));
+        }
+
+        let shares_protocol = |member: &Member| {
+            member
+                .protocols
+                .iter()
+                .any(|(name, _)| request.protocols.iter().any(|(other, _)| name == other))
+        };
+        if request.protocols.is_empty()
+            || group
+                .members
+                .iter()
+                .any(|(id, member)| *id != request.member_id && !shares_protocol(member))
+        {
+            return Err(JoinResponse::error(
+                error_code::INCONSISTENT_GROUP_PROTOCOL,
+                request.member_id,
+            ));
+        }
+
+        let member_id = match request.member_id.is_empty() {
+            true => format!(
+                "{}-{}",
+                request.client_id,
+                self.next_member_id.fetch_add(1, Ordering::Relaxed)
+            ),
+            false => request.member_id,
+        };
+
+        let (sender, receiver) = oneshot::channel();
+        let member = group.members.entry(member_id.clone()).or_insert_with(|| Member {
+            session_timeout: request.session_timeout,
+            protocols: vec![],
+            assignment: Bytes::new(),
+            last_seen: Instant::now(),
+            pending_join: None,
+            pending_sync: None,
+        });
+
+        member.session_timeout = request.session_timeout;
+        member.protocols = request.protocols;
+        member.last_seen = Instant::now();
+        member.pending_join = Some(sender);
+
+        group.protocol_type = Some(request.protocol_type);
+        if group.state != GroupState::PreparingRebalance {
+            group.prepare_rebalance();
+        }
+
+        if group.all_rejoined() {
+            group.complete_rebalance();
+        }
+
+        Ok((member_id, receiver))
+    }
+
+    /// Completes the rebalance of a group without waiting any longer for its members to rejoin.
+    fn force_rebalance(&self, group_id: &str) {
+        let mut groups = self.groups.lock().unwrap();
+        let group = match groups.get_mut(group_id) {
+            Some(group) if group.state == GroupState::PreparingRebalance => group,
+            _ => return,
+        };
+
+        group.members.retain(|_, member| member.pending_join.is_some());
+        group.complete_rebalance();
+    }
+
+    /// Hands the member its assignment for the generation, waiting for the leader to send the
+    /// assignments when the member isn't the leader.
+    pub async fn sync(
+        &self,
+        group_id: &str,
+        generation_id: i32,
+        member_id: &str,
+        assignments: Vec<(String, Bytes)>,
+    ) -> SyncResponse {
+        let (receiver, timeout) = {
+            let mut groups = self.groups.lock().unwrap();
+            let group = match groups.get_mut(group_id) {
+                Some(group) => group,
+                None => return SyncResponse::error(error_code::UNKNOWN_MEMBER_ID),
+            };
+
+            if let Err(code) = group.check_member(generation_id, member_id) {
+                return SyncResponse::error(code);
+            }
+
+            match group.state {
+                GroupState::Empty | GroupState::PreparingRebalance => {
+                    return SyncResponse::error(error_code::REBALANCE_IN_PROGRESS)
+                }
+                GroupState::Stable => {
+                    let member = group.members.get_mut(member_id).unwrap();
+                    member.last_seen = Instant::now();
+
+                    return SyncResponse {
+                        error_code: error_code::NONE,
+                        assignment: member.assignment.clone(),
+                    };
+                }
+                GroupState::CompletingRebalance => {}
+            }
+
+            if group.leader.as_deref() == Some(member_id) {
+                return group.complete_sync(member_id, assignments);
+            }
+
+            let (sender, receiver) = oneshot::channel();
+            let member = group.members.get_mut(member_id).unwrap();
+            member.last_seen = Instant::now();
+            member.pending_sync = Some(sender);
+
+            (receiver, member.session_timeout)
+        };
+
+        match tokio::time::timeout(timeout, receiver).await {
+            Ok(Ok(response)) => response,
+            _ => SyncResponse::error(error_code::REBALANCE_IN_PROGRESS),
+        }
+    }
+
+    /// Keeps the session of a member alive, telling it to rejoin when its group is rebalancing.
+    pub fn heartbeat(&self, group_id: &str, generation_id: i32, member_id: &str) -> i16 {
+        let mut groups = self.groups.lock().unwrap();
+        let group = match groups.get_mut(group_id) {
+            Some(group) => group,
+            None => return error_code::UNKNOWN_MEMBER_ID,
+        };
+
+        group.expire_members();
+
+        if let Err(code) = group.check_member(generation_id, member_id) {
+            return code;
+        }
+
+        group.members.get_mut(member_id).unwrap().last_seen = Instant::now();
+        match group.state {
+            GroupState::PreparingRebalance => error_code::REBALANCE_IN_PROGRESS,
+            _ => error_code::NONE,
+        }
+    }
+
+    /// Removes a member from its group, rebalancing the remaining members.
+    pub fn leave(&self, group_id: &str, member_id: &str) -> i16 {
+        let mut groups = self.groups.lock().unwrap();
+        let group = match groups.get_mut(group_id) {
+            Some(group) => group,
+            None => return error_code::UNKNOWN_MEMBER_ID,
+        };
+
+        if group.members.remove(member_id).is_none() {
+            return error_code::UNKNOWN_MEMBER_ID;
+        }
+
+        group.members_changed();
+        error_code::NONE
+    }
+
+    /// Checks that offsets can be committed by the member for the generation. Commits with a
+    /// negative generation come from consumers managing their partitions themselves and are always
+    /// accepted.
+    pub fn validate_commit(&self, group_id: &str, generation_id: i32, member_id: &str) -> i16 {
+        if generation_id < 0 {
+            return error_code::NONE;
+        }
+
+        let mut groups = self.groups.lock().unwrap();
+        let group = match groups.get_mut(group_id) {
+            Some(group) => group,
+            None => return error_code::UNKNOWN_MEMBER_ID,
+        };
+
+        if let Err(code) = group.check_member(generation_id, member_id) {
+            return code;
+        }
+
+        group.members.get_mut(member_id).unwrap().last_seen = Instant::now();
+        match group.state {
+            GroupState::PreparingRebalance => error_code::REBALANCE_IN_PROGRESS,
+            _ => error_code::NONE,
+        }
+    }
+}
+
+impl Group {
+    fn new() -> Group {
+        Group {
+            state: GroupState::Empty,
+            generation: 0,
+            protocol_type: None,
+            leader: None,
+            members: BTreeMap::new(),
+        }
+    }
+
+    fn check_member(&self, generation_id: i32, member_id: &str) -> Result<(), i16> {
+        if !self.members.contains_key(member_id) {
+            return Err(error_code::UNKNOWN_MEMBER_ID);
+        }
+
+        if generation_id != self.generation {
+            return Err(error_code::ILLEGAL_GENERATION);
+        }
+
+        Ok(())
+    }
+
+    fn all_rejoined(&self) -> bool {
+        self.members.values().all(|member| member.pending_join.is_some())
+    }
+
+    /// Starts a rebalance, telling the members waiting for their assignment to rejoin instead.
+    fn prepare_rebalance(&mut self) {
+        self.state = GroupState::PreparingRebalance;
+
+        for member in self.members.values_mut() {
+            if let Some(sender) = member.pending_sync.take() {
+                let _ = sender.send(SyncResponse::error(error_code::REBALANCE_IN_PROGRESS));
+            }
+        }
+    }
+
+    /// Starts the next generation with the members that rejoined, picking the leader and the
+    /// assignment protocol and answering their join requests.
+    fn complete_rebalance(&mut self) {
+        self.generation += 1;
+
+        if self.members.is_empty() {
+            self.state = GroupState::Empty;
+            self.leader = None;
+            return;
+        }
+
+        let leader = match &self.leader {
+            Some(leader) if self.members.contains_key(leader) => leader.clone(),
+            _ => self.members.keys().next().unwrap().clone(),
+        };
+
+        // The leader's preferred protocol that every member supports, joining guarantees that at
+        // least one protocol is shared.
+        let protocol = self.members[&leader]
+            .protocols
+            .iter()
+            .map(|(name, _)| name.clone())
+            .find(|name| {
+                self.members
+                    .values()
+                    .all(|member| member.protocols.iter().any(|(other, _)| other == name))
+            })
+            .unwrap_or_default();
+
+        let members = self
+            .members
+            .iter()
+            .map(|(id, member)| {
+                let metadata = member
+                    .protocols
+                    .iter()
+                    .find(|(name, _)| *name == protocol)
+                    .map(|(_, metadata)| metadata.clone())
+                    .unwrap_or_default();
+                (id.clone(), metadata)
+            })
+            .collect::<Vec<_>>();
+
+        self.state = GroupState::CompletingRebalance;
+        self.leader = Some(leader.clone());
+
+        for (id, member) in self.members.iter_mut() {
+            member.last_seen = Instant::now();
+            member.assignment = Bytes::new();
+
+            if let Some(sender) = member.pending_join.take() {
+                let _ = sender.send(JoinResponse {
+                    error_code: error_code::NONE,
+                    generation_id: self.generation,
+                    protocol_name: protocol.clone(),
+                    leader: leader.clone(),
+                    member_id: id.clone(),
+                    members: match *id == leader {
+                        true => members.clone(),
+                        false => vec![],
+                    },
+                });
+            }
+        }
+    }
+
+    /// Stores the assignments sent by the leader and hands them to the waiting members.
+    fn complete_sync(&mut self, leader: &str, assignments: Vec<(String, Bytes)>) -> SyncResponse {
+        for (id, assignment) in assignments {
+            if let Some(member) = self.members.get_mut(&id) {
+                member.assignment = assignment;
+            }
+        }
+
+        self.state = GroupState::Stable;
+        for member in self.members.values_mut() {
+            member.last_seen = Instant::now();
+
+            if let Some(sender) = member.pending_sync.take() {
+                let _ = sender.send(SyncResponse {
+                    error_code: error_code::NONE,
+                    assignment: member.assignment.clone(),
+                });
+            }
+        }
+
+        SyncResponse {
+            error_code: error_code::NONE,
+            assignment: self.members[leader].assignment.clone(),
+        }
+    }
+
+    /// Removes the members whose session expired. Members are only expired while the group isn't
+    /// rebalancing, rebalances drop the members that don't rejoin in time instead.
+    fn expire_members(&mut self) {
+        if self.state == GroupState::PreparingRebalance {
+            return;
+        }
+
+        let now = Instant::now();
+        let count = self.members.len();
+        self.members
+            .retain(|_, member| now.duration_since(member.last_seen) <= member.session_timeout);
+
+        if self.members.len() != count {
+            self.members_changed();
+        }
+    }
+
+    fn members_changed(&mut self) {
+        if self.members.is_empty() {
+            self.state = GroupState::Empty;
+            self.leader = None;
+            return;
+        }
+
+        if self.state != GroupState::PreparingRebalance {
+            self.prepare_rebalance();
+        }
+
+        if self.all_rejoined() {
+            self.complete_rebalance();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use super::*;
+
+    fn join_request(member_id: &str, rebalance_timeout: Duration) -> JoinRequest {
+        JoinRequest {
+            group_id: "billing".to_string(),
+            member_id: member_id.to_string(),
+            client_id: "consumer".to_string(),
+            session_timeout: Duration::from_secs(10),
+            rebalance_timeout,
+            protocol_type: "consumer".to_string(),
+            protocols: vec![("range".to_string(), Bytes::from_static(b"metadata"))],
+        }
+    }
+
+    #[tokio::test]
+    async fn test_single_member_join_sync_heartbeat() {
+        let coordinator = GroupCoordinator::new();
+
+        let joined = coordinator.join(join_request("", Duration::from_secs(10))).await;
+        assert_eq!(error_code::NONE, joined.error_code);
+        assert_eq!(1, joined.generation_id);
+        assert_eq!("range", joined.protocol_name);
+        assert_eq!(joined.member_id, joined.leader);
+        assert_eq!(
+            vec![(joined.member_id.clone(), Bytes::from_static(b"metadata"))],
+            joined.members
+        );
+
+        let assignment = vec![(joined.member_id.clone(), Bytes::from_static(b"orders-0"))];
+        let synced = coordinator.sync("billing", 1, &joined.member_id, assignment).await;
+        assert_eq!(error_code::NONE, synced.error_code);
+        assert_eq!(Bytes::from_static(b"orders-0"), synced.assignment);
+
+        assert_eq!(error_code::NONE, coordinator.heartbeat("billing", 1, &joined.member_id));
+        assert_eq!(
+            error_code::ILLEGAL_GENERATION,
+            coordinator.heartbeat("billing", 0, &joined.member_id)
+        );
+        assert_eq!(
+            error_code::UNKNOWN_MEMBER_ID,
+            coordinator.heartbeat("billing", 1, "other")
+        );
+    }
+
+    #[tokio::test]
+    async fn test_second_member_triggers_rebalance() {
+        let coordinator = Arc::new(GroupCoordinator::new());
+
+        let first = coordinator.join(join_request("", Duration::from_secs(10))).await;
+        coordinator.sync("billing", 1, &first.member_id, vec![]).await;
+
+        let second = tokio::spawn({
+            let coordinator = coordinator.clone();
+            async move { coordinator.join(join_request("", Duration::from_secs(10))).await }
+        });
+
+        tokio::time::sleep(Duration::from_millis(20)).await;
+        assert_eq!(
+            error_code::REBALANCE_IN_PROGRESS,
+            coordinator.heartbeat("billing", 1, &first.member_id)
+        );
+
+        let first = coordinator
+            .join(join_request(&first.member_id, Duration::from_secs(10)))
+            .await;
+        let second = second.await.unwrap();
+
+        assert_eq!(2, first.generation_id);
+        assert_eq!(2, second.generation_id);
+        assert_eq!(first.member_id, second.leader);
+        assert_eq!(2, first.members.len());
+        assert!(second.members.is_empty());
+
+        let follower = tokio::spawn({
+            let coordinator = coordinator.clone();
+            let member_id = second.member_id.clone();
+            async move { coordinator.sync("billing", 2, &member_id, vec![]).await }
+        });
+
+        tokio::time::sleep(Duration::from_millis(20)).await;
+        let assignments = vec![
+            (first.member_id.clone(), Bytes::from_static(b"a")),
+            (second.member_id.clone(), Bytes::from_static(b"b")),
+        ];
+        let leader = coordinator.sync("billing", 2, &first.member_id, assignments).await;
+
+        assert_eq!(Bytes::from_static(b"a"), leader.assignment);
+        assert_eq!(Bytes::from_static(b"b"), follower.await.unwrap().assignment);
+    }
+
+    #[tokio::test]
+    async fn test_rebalance_timeout_removes_members_not_rejoining() {
+        let coordinator = GroupCoordinator::new();
+
+        let first = coordinator.join(join_request("", Duration::from_secs(10))).await;
+        coordinator.sync("billing", 1, &first.member_id, vec![]).await;
+
+        let second = coordinator.join(join_request("", Duration::from_millis(50))).await;
+
+        assert_eq!(error_code::NONE, second.error_code);
+        assert_eq!(2, second.generation_id);
+        assert_eq!(second.member_id, second.leader);
+        assert_eq!(
+            error_code::UNKNOWN_MEMBER_ID,
+            coordinator.heartbeat("billing", 2, &first.member_id)
+        );
+    }
+
+    #[tokio::test]
+    async fn test_leave_empties_group() {
+        let coordinator = GroupCoordinator::new();
+
+        let joined = coordinator.join(join_request("", Duration::from_secs(10))).await;
+
+        assert_eq!(error_code::NONE, coordinator.leave("billing", &joined.member_id));
+        assert_eq!(
+            error_code::UNKNOWN_MEMBER_ID,
+            coordinator.leave("billing", &joined.member_id)
+        );
+        assert_eq!(
+            error_code::UNKNOWN_MEMBER_ID,
+            coordinator
+                .join(join_request(&joined.member_id, Duration::from_secs(10)))
+                .await
+                .error_code
+        );
+    }
+}
@@ -0,0 +1,4 @@
+pub mod connector;
+pub mod group;
+pub mod protocol;
+pub mod records;
@@ -0,0 +1,375 @@
+use std::io::{Error, ErrorKind};
+
+use bytes::{BufMut, Bytes, BytesMut};
+
+/// Keys identifying the Kafka APIs served by the connector.
+pub mod api_key {
+    pub const PRODUCE: i16 = 0;
+    pub const FETCH: i16 = 1;
+    pub const LIST_OFFSETS: i16 = 2;
+    pub const METADATA: i16 = 3;
+    pub const OFFSET_COMMIT: i16 = 8;
+    pub const OFFSET_FETCH: i16 = 9;
+    pub const FIND_COORDINATOR: i16 = 10;
+    pub const JOIN_GROUP: i16 = 11;
+    pub const HEARTBEAT: i16 = 12;
+    pub const LEAVE_GROUP: i16 = 13;
+    pub const SYNC_GROUP: i16 = 14;
+    pub const API_VERSIONS: i16 = 18;
+}
+
+/// Error codes sent back to clients, named as in the Kafka protocol.
+pub mod error_code {
+    pub const NONE: i16 = 0;
+    pub const OFFSET_OUT_OF_RANGE: i16 = 1;
+    pub const CORRUPT_MESSAGE: i16 = 2;
+    pub const UNKNOWN_TOPIC_OR_PARTITION: i16 = 3;
+    pub const COORDINATOR_NOT_AVAILABLE: i16 = 15;
+    pub const INVALID_TOPIC_EXCEPTION: i16 = 17;
+    pub const ILLEGAL_GENERATION: i16 = 22;
+    pub const INCONSISTENT_GROUP_PROTOCOL: i16 = 23;
+    pub const UNKNOWN_MEMBER_ID: i16 = 25;
+    pub const REBALANCE_IN_PROGRESS: i16 = 27;
+    pub const TOPIC_AUTHORIZATION_FAILED: i16 = 29;
+    pub const GROUP_AUTHORIZATION_FAILED: i16 = 30;
+    pub const UNSUPPORTED_VERSION: i16 = 35;
+    pub const UNSUPPORTED_FOR_MESSAGE_FORMAT: i16 = 43;
+    pub const UNSUPPORTED_COMPRESSION_TYPE: i16 = 76;
+}
+
+/// Versions served for each API as `(api key, min version, max version)`.
+///
+/// Only the versions predating flexible encoding (compact strings and tagged fields) are
+/// supported, which every client still negotiates down to.
+pub const SUPPORTED_VERSIONS: &[(i16, i16, i16)] = &[
+    (api_key::PRODUCE, 3, 8),
+    (api_key::FETCH, 4, 11),
+    (api_key::LIST_OFFSETS, 1, 5),
+    (api_key::METADATA, 0, 8),
+    (api_key::OFFSET_COMMIT, 0, 7),
+    (api_key::OFFSET_FETCH, 1, 5),
+    (api_key::FIND_COORDINATOR, 0, 2),
+    (api_key::JOIN_GROUP, 0, 5),
+    (api_key::HEARTBEAT, 0, 3),
+    (api_key::LEAVE_GROUP, 0, 2),
+    (api_key::SYNC_GROUP, 0, 3),
+    (api_key::API_VERSIONS, 0, 2),
+];
+
+/// Checks whether `version` of the API identified by `api_key` is served.
+pub fn is_supported(api_key: i16, version: i16) -> bool {
+    SUPPORTED_VERSIONS
+        .iter()
+        .any(|(key, min, max)| *key == api_key && (*min..=*max).contains(&version))
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct RequestHeader {
+    pub api_key: i16,
+    pub api_version: i16,
+    pub correlation_id: i32,
+    pub client_id: Option<String>,
+}
+
+impl RequestHeader {
+    pub fn decode(reader: &mut Reader) -> Result<RequestHeader, Error> {
+        Ok(RequestHeader {
+            api_key: reader.i16()?,
+            api_version: reader.i16()?,
+            correlation_id: reader.i32()?,
+            client_id: reader.nullable_string()?,
+        })
+    }
+}
+
+/// Reads the primitive types of the Kafka protocol from a request body.
+pub struct Reader {
+    buf: Bytes,
+}
+
+impl Reader {
+    pub fn new(buf: Bytes) -> Reader {
+        Reader { buf }
+    }
+
+    pub fn remaining(&self) -> usize {
+        self.buf.len()
+    }
+
+    pub fn bool(&mut self) -> Result<bool, Error> {
+        Ok(self.i8()? != 0)
+    }
+
+    pub fn i8(&mut self) -> Result<i8, Error> {
+        Ok(self.take(1)?[0] as i8)
+    }
+
+    pub fn i16(&mut self) -> Result<i16, Error> {
+        Ok(i16::from_be_bytes(self.take(2)?[..].try_into().unwrap()))
+    }
+
+    pub fn i32(&mut self) -> Result<i32, Error> {
+        Ok(i32::from_be_bytes(self.take(4)?[..].try_into().unwrap()))
+    }
+
+    pub fn u32(&mut self) -> Result<u32, Error> {
+        Ok(u32::from_be_bytes(self.take(4)?[..].try_into().unwrap()))
+    }
+
+    pub fn i64(&mut self) -> Result<i64, Error> {
+        Ok(i64::from_be_bytes(self.take(8)?[..].try_into().unwrap()))
+    }
+
+    /// Reads a zigzag encoded variable length integer, as used inside record batches.
+    pub fn varint(&mut self) -> Result<i32, Error> {
+        let value = self.varlong()?;
+        i32::try_from(value).map_err(|_| invalid("varint out of range"))
+    }
+
+    pub fn varlong(&mut self) -> Result<i64, Error> {
+        let mut value: u64 = 0;
+        for shift in (0..64).step_by(7) {
+            let byte = self.take(1)?[0];
+            value |= ((byte & 0x7F) as u64) << shift;
+
+            if byte & 0x80 == 0 {
+                return Ok((value >> 1) as i64 ^ -((value & 1) as i64));
+            }
+        }
+
+        Err(invalid("varint too long"))
+    }
+
+    pub fn string(&mut self) -> Result<String, Error> {
+        self.nullable_string()?.ok_or_else(|| invalid("unexpected null string"))
+    }
+
+    pub fn nullable_string(&mut self) -> Result<Option<String>, Error> {
+        let len = self.i16()?;
+        if len < 0 {
+            return Ok(None);
+        }
+
+        let bytes = self.take(len as usize)?;
+        String::from_utf8(bytes.to_vec())
+            .map(Some)
+            .map_err(|_| invalid("string isn't valid UTF-8"))
+    }
+
+    pub fn bytes(&mut self) -> Result<Bytes, Error> {
+        self.nullable_bytes()?.ok_or_else(|| invalid("unexpected null bytes"))
+    }
+
+    pub fn nullable_bytes(&mut self) -> Result<Option<Bytes>, Error> {
+        let len = self.i32()?;
+        if len < 0 {
+            return Ok(None);
+        }
+
+        self.take(len as usize).map(Some)
+    }
+
+    /// Reads the length of an array, `None` for a null array.
+    pub fn array_len(&mut self) -> Result<Option<usize>, Error> {
+        let len = self.i32()?;
+        if len < 0 {
+            return Ok(None);
+        }
+
+        // Every element takes at least a byte, which bounds the allocations a request can cause.
+        if len as usize > self.remaining() {
+            return Err(invalid("array longer than the request"));
+        }
+
+        Ok(Some(len as usize))
+    }
+
+    /// Reads an array, decoding its elements with `read`. A null array is read as an empty one.
+    pub fn array<T>(&mut self, mut read: impl FnMut(&mut Reader) -> Result<T, Error>) -> Result<Vec<T>, Error> {
+        let len = self.array_len()?.unwrap_or(0);
+        (0..len).map(|_| read(self)).collect()
+    }
+
+    pub fn take(&mut self, len: usize) -> Result<Bytes, Error> {
+        if len > self.buf.len() {
+            return Err(Error::new(ErrorKind::UnexpectedEof, "request is truncated"));
+        }
+
+        Ok(self.buf.split_to(len))
+    }
+}
+
+/// Writes the primitive types of the Kafka protocol into a response body.
+#[derive(Default)]
+pub struct Writer {
+    buf: BytesMut,
+}
+
+impl Writer {
+    pub fn new() -> Writer {
+        Writer::default()
+    }
+
+    pub fn into_inner(self) -> BytesMut {
+        self.buf
+    }
+
+    pub fn bool(&mut self, value: bool) -> &mut Writer {
+        self.i8(value as i8)
+    }
+
+    pub fn i8(&mut self, value: i8) -> &mut Writer {
+        self.buf.put_i8(value);
+        self
+    }
+
+    pub fn i16(&mut self, value: i16) -> &mut Writer {
+        self.buf.put_i16(value);
+        self
+    }
+
+    pub fn i32(&mut self, value: i32) -> &mut Writer {
+        self.buf.put_i32(value);
+        self
+    }
+
+    pub fn u32(&mut self, value: u32) -> &mut Writer {
+        self.buf.put_u32(value);
+        self
+    }
+
+    pub fn i64(&mut self, value: i64) -> &mut Writer {
+        self.buf.put_i64(value);
+        self
+    }
+
+    pub fn varint(&mut self, value: i32) -> &mut Writer {
+        self.varlong(value as i64)
+    }
+
+    pub fn varlong(&mut self, value: i64) -> &mut Writer {
+        let mut value = ((value << 1) ^ (value >> 63)) as u64;
+        while value >= 0x80 {
+            self.buf.put_u8((value as u8) | 0x80);
+            value >>= 7;
+        }
+
+        self.buf.put_u8(value as u8);
+        self
+    }
+
+    pub fn string(&mut self, value: &str) -> &mut Writer {
+        self.buf.put_i16(value.len() as i16);
+        self.buf.put_slice(value.as_bytes());
+        self
+    }
+
+    pub fn nullable_string(&mut self, value: Option<&str>) -> &mut Writer {
+        match value {
+            Some(value) => self.string(value),
+            None => self.i16(-1),
+        }
+    }
+
+    pub fn bytes(&mut self, value: &[u8]) -> &mut Writer {
+        self.buf.put_i32(value.len() as i32);
+        self.buf.put_slice(value);
+        self
+    }
+
+    pub fn nullable_bytes(&mut self, value: Option<&[u8]>) -> &mut Writer {
+        match value {
+            Some(value) => self.bytes(value),
+            None => self.i32(-1),
+        }
+    }
+
+    /// Writes `items` as an array, encoding each one with `write`.
+    pub fn array<T>(&mut self, items: &[T], mut write: impl FnMut(&mut Writer, &T)) -> &mut Writer {
+        self.buf.put_i32(items.len() as i32);
+        for item in items {
+            write(self, item);
+        }
+        self
+    }
+
+    pub fn raw(&mut self, value: &[u8]) -> &mut Writer {
+        self.buf.put_slice(value);
+        self
+    }
+
+    pub fn len(&self) -> usize {
+        self.buf.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.buf.is_empty()
+    }
+}
+
+pub(crate) fn invalid(message: &str) -> Error {
+    Error::new(ErrorKind::InvalidData, message.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_primitives_round_trip() {
+        let mut writer = Writer::new();
+        writer
+            .i16(-2)
+            .i32(70000)
+            .i64(-1)
+            .string("orders")
+            .nullable_string(None)
+            .bytes(b"value")
+            .nullable_bytes(None)
+            .array(&[1, 2], |writer, value| {
+                writer.i32(*value);
+            });
+
+        let mut reader = Reader::new(writer.into_inner().freeze());
+
+        assert_eq!(-2, reader.i16().unwrap());
+        assert_eq!(70000, reader.i32().unwrap());
+        assert_eq!(-1, reader.i64().unwrap());
+        assert_eq!("orders", reader.string().unwrap());
+        assert_eq!(None, reader.nullable_string().unwrap());
+        assert_eq!(&b"value"[..], &reader.bytes().unwrap()[..]);
+        assert_eq!(None, reader.nullable_bytes().unwrap());
+        assert_eq!(vec![1, 2], reader.array(|reader| reader.i32()).unwrap());
+        assert_eq!(0, reader.remaining());
+    }
+
+    #[test]
+    fn test_varint_round_trip() {
+        for value in [0, 1, -1, 63, -64, 64, 300, i32::MAX, i32::MIN] {
+            let mut writer = Writer::new();
+            writer.varint(value);
+
+            let mut reader = Reader::new(writer.into_inner().freeze());
+            assert_eq!(value, reader.varint().unwrap());
+        }
+
+        let mut writer = Writer::new();
+        writer.varint(-1).varint(300);
+        assert_eq!(&[0x01, 0xD8, 0x04][..], &writer.into_inner()[..]);
+    }
+
+    #[test]
+    fn test_truncated_request_fails() {
+        let mut reader = Reader::new(Bytes::from_static(&[0, 5, b'a']));
+
+        assert!(reader.string().is_err());
+    }
+
+    #[test]
+    fn test_is_supported() {
+        assert!(is_supported(api_key::FETCH, 11));
+        assert!(!is_supported(api_key::FETCH, 12));
+        assert!(!is_supported(api_key::API_VERSIONS, 3));
+        assert!(!is_supported(42, 0));
+    }
+}
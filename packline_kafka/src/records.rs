@@ -0,0 +1,236 @@
+use std::io::Error;
+
+use bytes::Bytes;
+
+use packline_core::app::channel::Record;
+
+use crate::protocol::{error_code, Reader, Writer};
+
+/// Magic byte of the record batch format, the only one accepted.
+const MAGIC: i8 = 2;
+
+/// Size of the batch fields preceding the ones covered by the CRC.
+const BATCH_HEADER_LEN: usize = 8 + 4 + 4 + 1 + 4;
+
+const COMPRESSION_MASK: i16 = 0x07;
+const CONTROL_BATCH_FLAG: i16 = 0x20;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RecordBatchError {
+    /// The batch is truncated or its CRC doesn't match.
+    Corrupt,
+
+    /// The batch uses the message set formats preceding record batches.
+    UnsupportedMagic,
+
+    /// The batch is compressed, records are only accepted uncompressed.
+    UnsupportedCompression,
+}
+
+impl RecordBatchError {
+    pub fn code(&self) -> i16 {
+        match self {
+            RecordBatchError::Corrupt => error_code::CORRUPT_MESSAGE,
+            RecordBatchError::UnsupportedMagic => error_code::UNSUPPORTED_FOR_MESSAGE_FORMAT,
+            RecordBatchError::UnsupportedCompression => error_code::UNSUPPORTED_COMPRESSION_TYPE,
+        }
+    }
+}
+
+impl From<Error> for RecordBatchError {
+    fn from(_: Error) -> Self {
+        RecordBatchError::Corrupt
+    }
+}
+
+/// Decodes the records of the batches produced to a partition.
+///
+/// Timestamps and headers aren't kept by channels and are dropped. Control batches, written by
+/// transactional producers, are skipped.
+pub fn decode_batches(data: Bytes) -> Result<Vec<Record>, RecordBatchError> {
+    let mut reader = Reader::new(data);
+    let mut records = vec![];
+
+    while reader.remaining() > 0 {
+        let _base_offset = reader.i64()?;
+        let batch_len = reader.i32()?;
+        if batch_len < 0 {
+            return Err(RecordBatchError::Corrupt);
+        }
+
+        let mut batch = Reader::new(reader.take(batch_len as usize)?);
+        let _partition_leader_epoch = batch.i32()?;
+        if batch.i8()? != MAGIC {
+            return Err(RecordBatchError::UnsupportedMagic);
+        }
+
+        let crc = batch.u32()?;
+        let body = batch.take(batch.remaining())?;
+        if crc32c::crc32c(&body) != crc {
+            return Err(RecordBatchError::Corrupt);
+        }
+
+        let mut body = Reader::new(body);
+        let attributes = body.i16()?;
+        if attributes & COMPRESSION_MASK != 0 {
+            return Err(RecordBatchError::UnsupportedCompression);
+        }
+
+        if attributes & CONTROL_BATCH_FLAG != 0 {
+            continue;
+        }
+
+        // Last offset delta, timestamps, producer id and epoch and base sequence.
+        body.take(4 + 8 + 8 + 8 + 2 + 4)?;
+
+        let count = body.i32()?;
+        for _ in 0..count.max(0) {
+            records.push(decode_record(&mut body)?);
+        }
+    }
+
+    Ok(records)
+}
+
+fn decode_record(reader: &mut Reader) -> Result<Record, RecordBatchError> {
+    let len = reader.varint()?;
+    if len < 0 {
+        return Err(RecordBatchError::Corrupt);
+    }
+
+    let mut record = Reader::new(reader.take(len as usize)?);
+    let _attributes = record.i8()?;
+    let _timestamp_delta = record.varlong()?;
+    let _offset_delta = record.varint()?;
+
+    let key = varint_bytes(&mut record)?.unwrap_or_default();
+    let value = varint_bytes(&mut record)?.unwrap_or_default();
+
+    // Headers are still validated, so that a malformed record isn't stored.
+    for _ in 0..record.varint()?.max(0) {
+        varint_bytes(&mut record)?;
+        varint_bytes(&mut record)?;
+    }
+
+    Ok(Record::new(key.to_vec(), value.to_vec()))
+}
+
+fn varint_bytes(reader: &mut Reader) -> Result<Option<Bytes>, RecordBatchError> {
+    let len = reader.varint()?;
+    if len < 0 {
+        return Ok(None);
+    }
+
+    Ok(Some(reader.take(len as usize)?))
+}
+
+/// Encodes `records`, which have to be consecutive, as a single uncompressed batch.
+///
+/// Records without a key are sent with a null key. Timestamps aren't known and are sent as -1.
+pub fn encode_batch(records: &[Record]) -> Bytes {
+    let base_offset = records.first().map(|record| record.offset).unwrap_or(0);
+
+    let mut body = Writer::new();
+    body.i16(0)
+        .i32(records.len().saturating_sub(1) as i32)
+        .i64(-1)
+        .i64(-1)
+        .i64(-1)
+        .i16(-1)
+        .i32(-1)
+        .i32(records.len() as i32);
+
+    for record in records {
+        let mut encoded = Writer::new();
+        encoded.i8(0).varlong(0).varint((record.offset - base_offset) as i32);
+
+        if record.key.is_empty() {
+            encoded.varint(-1);
+        } else {
+            encoded.varint(record.key.len() as i32).raw(&record.key);
+        }
+
+        encoded.varint(record.value.len() as i32).raw(&record.value).varint(0);
+
+        body.varint(encoded.len() as i32).raw(&encoded.into_inner());
+    }
+
+    let body = body.into_inner();
+
+    let mut batch = Writer::new();
+    batch
+        .i64(base_offset as i64)
+        .i32((BATCH_HEADER_LEN - 12 + body.len()) as i32)
+        .i32(0)
+        .i8(MAGIC)
+        .u32(crc32c::crc32c(&body))
+        .raw(&body);
+
+    batch.into_inner().freeze()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn stored(records: &[(&str, &str)], base_offset: u64) -> Vec<Record> {
+        records
+            .iter()
+            .enumerate()
+            .map(|(i, (key, value))| Record {
+                offset: base_offset + i as u64,
+                ..Record::new(key.as_bytes(), value.as_bytes())
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_batch_round_trip() {
+        let records = stored(&[("", "first"), ("user-1", "second")], 7);
+
+        let decoded = decode_batches(encode_batch(&records)).unwrap();
+
+        assert_eq!(
+            records
+                .iter()
+                .map(|r| (r.key.clone(), r.value.clone()))
+                .collect::<Vec<_>>(),
+            decoded
+                .iter()
+                .map(|r| (r.key.clone(), r.value.clone()))
+                .collect::<Vec<_>>()
+        );
+    }
+
+    #[test]
+    fn test_decode_rejects_corrupt_batch() {
+        let mut batch = encode_batch(&stored(&[("", "value")], 0)).to_vec();
+        let last = batch.len() - 2;
+        batch[last] ^= 0xFF;
+
+        assert_eq!(
+            Err(RecordBatchError::Corrupt),
+            decode_batches(Bytes::from(batch.clone()))
+        );
+        assert_eq!(
+            Err(RecordBatchError::Corrupt),
+            decode_batches(Bytes::from(batch[..batch.len() - 1].to_vec()))
+        );
+    }
+
+    #[test]
+    fn test_decode_rejects_compressed_batch() {
+        let batch = encode_batch(&stored(&[("", "value")], 0));
+        let mut body = batch[BATCH_HEADER_LEN..].to_vec();
+        body[1] |= 0x01;
+
+        let mut compressed = batch[..BATCH_HEADER_LEN - 4].to_vec();
+        compressed.extend_from_slice(&crc32c::crc32c(&body).to_be_bytes());
+        compressed.extend_from_slice(&body);
+
+        assert_eq!(
+            Err(RecordBatchError::UnsupportedCompression),
+            decode_batches(Bytes::from(compressed))
+        );
+    }
+}
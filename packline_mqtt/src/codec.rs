@@ -0,0 +1,675 @@
+use std::convert::TryFrom;
+use std::io::{Error, ErrorKind};
+
+use bytes::{Buf, BufMut, Bytes, BytesMut};
+use tokio_util::codec::{Decoder, Encoder};
+
+use crate::packet::{
+    ConnAck, Connect, ConnectReturnCode, Packet, ProtocolVersion, Publish, QoS, SubAck, Subscribe, SubscribeReturnCode,
+    UnsubAck, Unsubscribe, Will,
+};
+
+/// Packets larger than this are refused, their size is announced before their content so a
+/// misbehaving client could otherwise make the broker buffer anything.
+pub const DEFAULT_MAX_PACKET_SIZE: usize = 16 * 1024 * 1024;
+
+const PROTOCOL_NAME: &str = "MQTT";
+
+const CONNECT: u8 = 1;
+const CONNACK: u8 = 2;
+const PUBLISH: u8 = 3;
+const PUBACK: u8 = 4;
+const PUBREC: u8 = 5;
+const PUBREL: u8 = 6;
+const PUBCOMP: u8 = 7;
+const SUBSCRIBE: u8 = 8;
+const SUBACK: u8 = 9;
+const UNSUBSCRIBE: u8 = 10;
+const UNSUBACK: u8 = 11;
+const PINGREQ: u8 = 12;
+const PINGRESP: u8 = 13;
+const DISCONNECT: u8 = 14;
+
+/// Frames MQTT 3.1.1 and 5 control packets.
+///
+/// The codec starts out speaking MQTT 3.1.1 and switches to the version of the first CONNECT
+/// packet it decodes or encodes. MQTT 5 properties are skipped when decoding and sent empty.
+#[derive(Debug)]
+pub struct MQTTCodec {
+    version: ProtocolVersion,
+    max_packet_size: usize,
+}
+
+impl MQTTCodec {
+    pub fn new() -> MQTTCodec {
+        MQTTCodec {
+            version: ProtocolVersion::V311,
+            max_packet_size: DEFAULT_MAX_PACKET_SIZE,
+        }
+    }
+
+    pub fn version(&self) -> ProtocolVersion {
+        self.version
+    }
+
+    pub fn set_max_packet_size(&mut self, max_packet_size: usize) {
+        self.max_packet_size = max_packet_size;
+    }
+
+    fn decode_packet(&mut self, header: u8, body: Bytes) -> Result<Packet, Error> {
+        let mut reader = Reader { buf: body };
+        let flags = header & 0x0F;
+
+        let packet = match header >> 4 {
+            CONNECT => {
+                let connect = self.decode_connect(&mut reader)?;
+                if let Some(version) = ProtocolVersion::from_level(connect.protocol_level) {
+                    self.version = version;
+                }
+
+                Packet::Connect(connect)
+            }
+            CONNACK => {
+                let session_present = reader.u8()? & 0x01 == 0x01;
+                let code = reader.u8()?;
+                let code = ConnectReturnCode::from_code(code)
+                    .ok_or_else(|| invalid(format!("unknown CONNACK code {}", code)))?;
+
+                Packet::ConnAck(ConnAck { session_present, code })
+            }
+            PUBLISH => {
+                let qos = QoS::try_from((flags >> 1) & 0x03).map_err(|_| invalid("invalid PUBLISH QoS"))?;
+                let topic = reader.string()?;
+                let packet_id = match qos {
+                    QoS::AtMostOnce => None,
+                    _ => Some(reader.u16()?),
+                };
+                self.skip_properties(&mut reader)?;
+
+                Packet::Publish(Publish {
+                    dup: flags & 0x08 == 0x08,
+                    qos,
+                    retain: flags & 0x01 == 0x01,
+                    topic,
+                    packet_id,
+                    payload: reader.buf,
+                })
+            }
+            PUBACK => Packet::PubAck(reader.u16()?),
+            PUBREC => Packet::PubRec(reader.u16()?),
+            PUBREL => Packet::PubRel(reader.u16()?),
+            PUBCOMP => Packet::PubComp(reader.u16()?),
+            SUBSCRIBE => {
+                let packet_id = reader.u16()?;
+                self.skip_properties(&mut reader)?;
+
+                let mut filters = Vec::new();
+                while reader.buf.has_remaining() {
+                    let filter = reader.string()?;
+                    // MQTT 5 packs more subscription options in the upper bits.
+                    let qos = QoS::try_from(reader.u8()? & 0x03).map_err(|_| invalid("invalid SUBSCRIBE QoS"))?;
+                    filters.push((filter, qos));
+                }
+
+                if filters.is_empty() {
+                    return Err(invalid("SUBSCRIBE without topic filters"));
+                }
+
+                Packet::Subscribe(Subscribe { packet_id, filters })
+            }
+            SUBACK => {
+                let packet_id = reader.u16()?;
+                self.skip_properties(&mut reader)?;
+
+                let codes = reader
+                    .buf
+                    .iter()
+                    .map(|code| SubscribeReturnCode::from_code(*code))
+                    .collect();
+                Packet::SubAck(SubAck { packet_id, codes })
+            }
+            UNSUBSCRIBE => {
+                let packet_id = reader.u16()?;
+                self.skip_properties(&mut reader)?;
+
+                let mut filters = Vec::new();
+                while reader.buf.has_remaining() {
+                    filters.push(reader.string()?);
+                }
+
+                Packet::Unsubscribe(Unsubscribe { packet_id, filters })
+            }
+            UNSUBACK => {
+                let packet_id = reader.u16()?;
+                self.skip_properties(&mut reader)?;
+
+                Packet::UnsubAck(UnsubAck {
+                    packet_id,
+                    count: reader.buf.remaining(),
+                })
+            }
+            PINGREQ => Packet::PingReq,
+            PINGRESP => Packet::PingResp,
+            DISCONNECT => Packet::Disconnect,
+            packet_type => return Err(invalid(format!("unsupported packet type {}", packet_type))),
+        };
+
+        Ok(packet)
+    }
+
+    fn decode_connect(&self, reader: &mut Reader) -> Result<Connect, Error> {
+        let protocol_name = reader.string()?;
+        if protocol_name != PROTOCOL_NAME {
+            return Err(invalid(format!("unknown protocol {}", protocol_name)));
+        }
+
+        let protocol_level = reader.u8()?;
+        let flags = reader.u8()?;
+        let keep_alive = reader.u16()?;
+
+        // Nothing past the protocol level can be relied upon for unknown versions.
+        let version = match ProtocolVersion::from_level(protocol_level) {
+            Some(version) => version,
+            None => {
+                return Ok(Connect {
+                    protocol_level,
+                    client_id: String::new(),
+                    clean_session: true,
+                    keep_alive,
+                    will: None,
+                    username: None,
+                    password: None,
+                })
+            }
+        };
+
+        if version == ProtocolVersion::V5 {
+            reader.skip_properties()?;
+        }
+
+        let client_id = reader.string()?;
+
+        let will = if flags & 0x04 == 0x04 {
+            if version == ProtocolVersion::V5 {
+                reader.skip_properties()?;
+            }
+
+            Some(Will {
+                topic: reader.string()?,
+                payload: reader.binary()?,
+                qos: QoS::try_from((flags >> 3) & 0x03).map_err(|_| invalid("invalid will QoS"))?,
+                retain: flags & 0x20 == 0x20,
+            })
+        } else {
+            None
+        };
+
+        let username = if flags & 0x80 == 0x80 {
+            Some(reader.string()?)
+        } else {
+            None
+        };
+        let password = if flags & 0x40 == 0x40 {
+            Some(reader.binary()?)
+        } else {
+            None
+        };
+
+        Ok(Connect {
+            protocol_level,
+            client_id,
+            clean_session: flags & 0x02 == 0x02,
+            keep_alive,
+            will,
+            username,
+            password,
+        })
+    }
+
+    fn skip_properties(&self, reader: &mut Reader) -> Result<(), Error> {
+        match self.version {
+            ProtocolVersion::V311 => Ok(()),
+            // Properties are optional in acknowledgements, which may end right after the packet id.
+            ProtocolVersion::V5 if !reader.buf.has_remaining() => Ok(()),
+            ProtocolVersion::V5 => reader.skip_properties(),
+        }
+    }
+
+    fn encode_packet(&mut self, packet: Packet, body: &mut BytesMut) -> u8 {
+        let v5 = self.version == ProtocolVersion::V5;
+
+        match packet {
+            Packet::Connect(connect) => {
+                if let Some(version) = ProtocolVersion::from_level(connect.protocol_level) {
+                    self.version = version;
+                }
+                let v5 = self.version == ProtocolVersion::V5;
+
+                let mut flags = 0u8;
+                if connect.clean_session {
+                    flags |= 0x02;
+                }
+                if let Some(will) = &connect.will {
+                    flags |= 0x04 | ((will.qos as u8) << 3);
+                    if will.retain {
+                        flags |= 0x20;
+                    }
+                }
+                if connect.password.is_some() {
+                    flags |= 0x40;
+                }
+                if connect.username.is_some() {
+                    flags |= 0x80;
+                }
+
+                put_string(body, PROTOCOL_NAME);
+                body.put_u8(connect.protocol_level);
+                body.put_u8(flags);
+                body.put_u16(connect.keep_alive);
+                put_properties(body, v5);
+
+                put_string(body, &connect.client_id);
+                if let Some(will) = &connect.will {
+                    put_properties(body, v5);
+                    put_string(body, &will.topic);
+                    put_binary(body, &will.payload);
+                }
+                if let Some(username) = &connect.username {
+                    put_string(body, username);
+                }
+                if let Some(password) = &connect.password {
+                    put_binary(body, password);
+                }
+
+                CONNECT << 4
+            }
+            Packet::ConnAck(connack) => {
+                body.put_u8(connack.session_present as u8);
+                body.put_u8(connack.code.code(self.version));
+                put_properties(body, v5);
+
+                CONNACK << 4
+            }
+            Packet::Publish(publish) => {
+                put_string(body, &publish.topic);
+                if let Some(packet_id) = publish.packet_id {
+                    body.put_u16(packet_id);
+                }
+                put_properties(body, v5);
+                body.put_slice(&publish.payload);
+
+                (PUBLISH << 4) | ((publish.dup as u8) << 3) | ((publish.qos as u8) << 1) | publish.retain as u8
+            }
+            Packet::PubAck(packet_id) => {
+                body.put_u16(packet_id);
+                PUBACK << 4
+            }
+            Packet::PubRec(packet_id) => {
+                body.put_u16(packet_id);
+                PUBREC << 4
+            }
+            Packet::PubRel(packet_id) => {
+                body.put_u16(packet_id);
+                (PUBREL << 4) | 0x02
+            }
+            Packet::PubComp(packet_id) => {
+                body.put_u16(packet_id);
+                PUBCOMP << 4
+            }
+            Packet::Subscribe(subscribe) => {
+                body.put_u16(subscribe.packet_id);
+                put_properties(body, v5);
+                for (filter, qos) in &subscribe.filters {
+                    put_string(body, filter);
+                    body.put_u8(*qos as u8);
+                }
+
+                (SUBSCRIBE << 4) | 0x02
+            }
+            Packet::SubAck(suback) => {
+                body.put_u16(suback.packet_id);
+                put_properties(body, v5);
+                for code in &suback.codes {
+                    body.put_u8(code.code(self.version));
+                }
+
+                SUBACK << 4
+            }
+            Packet::Unsubscribe(unsubscribe) => {
+                body.put_u16(unsubscribe.packet_id);
+                put_properties(body, v5);
+                for filter in &unsubscribe.filters {
+                    put_string(body, filter);
+                }
+
+                (UNSUBSCRIBE << 4) | 0x02
+            }
+            Packet::UnsubAck(unsuback) => {
+                body.put_u16(unsuback.packet_id);
+                if v5 {
+                    put_properties(body, v5);
+                    body.put_bytes(0x00, unsuback.count);
+                }
+
+                UNSUBACK << 4
+            }
+            Packet::PingReq => PINGREQ << 4,
+            Packet::PingResp => PINGRESP << 4,
+            Packet::Disconnect => DISCONNECT << 4,
+        }
+    }
+}
+
+impl Default for MQTTCodec {
+    fn default() -> Self {
+        MQTTCodec::new()
+    }
+}
+
+impl Decoder for MQTTCodec {
+    type Item = Packet;
+    type Error = Error;
+
+    fn decode(&mut self, src: &mut BytesMut) -> Result<Option<Self::Item>, Self::Error> {
+        if src.len() < 2 {
+            return Ok(None);
+        }
+
+        // The remaining length takes up to four bytes, seven bits each.
+        let mut length = 0usize;
+        let mut header_size = 1;
+        loop {
+            if header_size > 4 {
+                return Err(invalid("malformed remaining length"));
+            }
+
+            let byte = match src.get(header_size) {
+                Some(byte) => *byte,
+                None => return Ok(None),
+            };
+
+            length |= ((byte & 0x7F) as usize) << (7 * (header_size - 1));
+            header_size += 1;
+
+            if byte & 0x80 == 0 {
+                break;
+            }
+        }
+
+        if length > self.max_packet_size {
+            return Err(invalid(format!("packet of {} bytes exceeds the maximum size", length)));
+        }
+
+        if src.len() < header_size + length {
+            src.reserve(header_size + length - src.len());
+            return Ok(None);
+        }
+
+        let header = src[0];
+        src.advance(header_size);
+        let body = src.split_to(length).freeze();
+
+        self.decode_packet(header, body).map(Some)
+    }
+}
+
+impl Encoder<Packet> for MQTTCodec {
+    type Error = Error;
+
+    fn encode(&mut self, item: Packet, dst: &mut BytesMut) -> Result<(), Self::Error> {
+        let mut body = BytesMut::new();
+        let header = self.encode_packet(item, &mut body);
+
+        if body.len() > self.max_packet_size {
+            return Err(invalid(format!(
+                "packet of {} bytes exceeds the maximum size",
+                body.len()
+            )));
+        }
+
+        dst.reserve(body.len() + 5);
+        dst.put_u8(header);
+        put_variable_int(dst, body.len());
+        dst.put_slice(&body);
+
+        Ok(())
+    }
+}
+
+struct Reader {
+    buf: Bytes,
+}
+
+impl Reader {
+    fn ensure(&self, size: usize) -> Result<(), Error> {
+        if self.buf.remaining() < size {
+            return Err(invalid("packet is shorter than its content"));
+        }
+
+        Ok(())
+    }
+
+    fn u8(&mut self) -> Result<u8, Error> {
+        self.ensure(1)?;
+        Ok(self.buf.get_u8())
+    }
+
+    fn u16(&mut self) -> Result<u16, Error> {
+        self.ensure(2)?;
+        Ok(self.buf.get_u16())
+    }
+
+    fn binary(&mut self) -> Result<Bytes, Error> {
+        let length = self.u16()? as usize;
+        self.ensure(length)?;
+        Ok(self.buf.split_to(length))
+    }
+
+    fn string(&mut self) -> Result<String, Error> {
+        let bytes = self.binary()?;
+        String::from_utf8(bytes.to_vec()).map_err(|_| invalid("string is not valid UTF-8"))
+    }
+
+    fn variable_int(&mut self) -> Result<usize, Error> {
+        let mut value = 0usize;
+        for i in 0..4 {
+            let byte = self.u8()?;
+            value |= ((byte & 0x7F) as usize) << (7 * i);
+
+            if byte & 0x80 == 0 {
+                return Ok(value);
+            }
+        }
+
+        Err(invalid("malformed variable byte integer"))
+    }
+
+    fn skip_properties(&mut self) -> Result<(), Error> {
+        let length = self.variable_int()?;
+        self.ensure(length)?;
+        self.buf.advance(length);
+        Ok(())
+    }
+}
+
+fn put_variable_int(dst: &mut BytesMut, mut value: usize) {
+    loop {
+        let mut byte = (value & 0x7F) as u8;
+        value >>= 7;
+        if value > 0 {
+            byte |= 0x80;
+        }
+
+        dst.put_u8(byte);
+        if value == 0 {
+            break;
+        }
+    }
+}
+
+fn put_binary(dst: &mut BytesMut, value: &[u8]) {
+    dst.put_u16(value.len() as u16);
+    dst.put_slice(value);
+}
+
+fn put_string(dst: &mut BytesMut, value: &str) {
+    put_binary(dst, value.as_bytes());
+}
+
+fn put_properties(dst: &mut BytesMut, v5: bool) {
+    if v5 {
+        put_variable_int(dst, 0);
+    }
+}
+
+fn invalid<E: Into<Box<dyn std::error::Error + Send + Sync>>>(error: E) -> Error {
+    Error::new(ErrorKind::InvalidData, error)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn round_trip(codec: &mut MQTTCodec, packet: Packet) -> Packet {
+        let mut buf = BytesMut::new();
+        codec.encode(packet, &mut buf).unwrap();
+        codec.decode(&mut buf).unwrap().unwrap()
+    }
+
+    fn connect(protocol_level: u8) -> Packet {
+        Packet::Connect(Connect {
+            protocol_level,
+            client_id: "sensor-1".to_string(),
+            clean_session: true,
+            keep_alive: 30,
+            will: Some(Will {
+                topic: "sensors/1/status".to_string(),
+                payload: Bytes::from_static(b"offline"),
+                qos: QoS::AtLeastOnce,
+                retain: true,
+            }),
+            username: Some("alice".to_string()),
+            password: Some(Bytes::from_static(b"secret")),
+        })
+    }
+
+    #[test]
+    fn test_packets_round_trip() {
+        for protocol_level in [4, 5] {
+            let mut codec = MQTTCodec::new();
+            let packets = vec![
+                connect(protocol_level),
+                Packet::ConnAck(ConnAck {
+                    session_present: false,
+                    code: ConnectReturnCode::NotAuthorized,
+                }),
+                Packet::Publish(Publish {
+                    dup: true,
+                    qos: QoS::AtLeastOnce,
+                    retain: true,
+                    topic: "sensors/1/temperature".to_string(),
+                    packet_id: Some(7),
+                    payload: Bytes::from_static(b"21.5"),
+                }),
+                Packet::Publish(Publish {
+                    dup: false,
+                    qos: QoS::AtMostOnce,
+                    retain: false,
+                    topic: "sensors".to_string(),
+                    packet_id: None,
+                    payload: Bytes::new(),
+                }),
+                Packet::PubAck(1),
+                Packet::PubRec(2),
+                Packet::PubRel(3),
+                Packet::PubComp(4),
+                Packet::Subscribe(Subscribe {
+                    packet_id: 5,
+                    filters: vec![("sensors/+/temperature".to_string(), QoS::AtLeastOnce)],
+                }),
+                Packet::SubAck(SubAck {
+                    packet_id: 5,
+                    codes: vec![
+                        SubscribeReturnCode::Granted(QoS::AtLeastOnce),
+                        SubscribeReturnCode::Failure,
+                    ],
+                }),
+                Packet::Unsubscribe(Unsubscribe {
+                    packet_id: 6,
+                    filters: vec!["sensors/#".to_string()],
+                }),
+                Packet::PingReq,
+                Packet::PingResp,
+                Packet::Disconnect,
+            ];
+
+            for packet in packets {
+                assert_eq!(packet.clone(), round_trip(&mut codec, packet));
+            }
+        }
+    }
+
+    #[test]
+    fn test_connect_selects_protocol_version() {
+        let mut codec = MQTTCodec::new();
+        round_trip(&mut codec, connect(5));
+        assert_eq!(ProtocolVersion::V5, codec.version());
+
+        let suback = Packet::SubAck(SubAck {
+            packet_id: 1,
+            codes: vec![SubscribeReturnCode::InvalidFilter],
+        });
+        let mut buf = BytesMut::new();
+        codec.encode(suback, &mut buf).unwrap();
+
+        // Packet id, empty properties and the MQTT 5 specific reason code.
+        assert_eq!(&[0x90, 4, 0, 1, 0, 0x8F], &buf[..]);
+    }
+
+    #[test]
+    fn test_decode_waits_for_complete_packet() {
+        let mut codec = MQTTCodec::new();
+        let mut buf = BytesMut::new();
+        codec
+            .encode(
+                Packet::Publish(Publish {
+                    dup: false,
+                    qos: QoS::AtMostOnce,
+                    retain: false,
+                    topic: "t".to_string(),
+                    packet_id: None,
+                    payload: Bytes::from(vec![1u8; 200]),
+                }),
+                &mut buf,
+            )
+            .unwrap();
+
+        let mut partial = buf.split_to(2);
+        assert!(codec.decode(&mut partial).unwrap().is_none());
+
+        partial.unsplit(buf);
+        assert!(matches!(codec.decode(&mut partial).unwrap(), Some(Packet::Publish(_))));
+        assert!(partial.is_empty());
+    }
+
+    #[test]
+    fn test_decode_unknown_protocol_level() {
+        let mut codec = MQTTCodec::new();
+        let mut buf = BytesMut::from(&[0x10, 10, 0, 4, b'M', b'Q', b'T', b'T', 3, 0x02, 0, 60][..]);
+
+        match codec.decode(&mut buf).unwrap() {
+            Some(Packet::Connect(connect)) => assert_eq!(3, connect.protocol_level),
+            packet => panic!("unexpected packet {:?}", packet),
+        }
+        assert_eq!(ProtocolVersion::V311, codec.version());
+    }
+
+    #[test]
+    fn test_decode_rejects_oversized_packet() {
+        let mut codec = MQTTCodec::new();
+        codec.set_max_packet_size(16);
+
+        let mut buf = BytesMut::from(&[0x30, 0x7F][..]);
+        assert_eq!(ErrorKind::InvalidData, codec.decode(&mut buf).unwrap_err().kind());
+    }
+}
@@ -0,0 +1,958 @@
+use std::collections::{HashMap, HashSet};
+use std::io::{Error, ErrorKind};
+use std::net::SocketAddr;
+use std::sync::atomic::{AtomicU16, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex as StdMutex};
+use std::time::Duration;
+
+use async_trait::async_trait;
+use bytes::Bytes;
+use futures::stream::{SplitSink, StreamExt};
+use futures::SinkExt;
+use tokio::io::{AsyncRead, AsyncWrite};
+use tokio::sync::{Mutex, Notify};
+use tokio::task::{JoinHandle, JoinSet};
+use tokio_util::codec::Framed;
+use tracing::{debug, info, warn};
+
+use packline_core::app::acl::{Operation, Resource, ANONYMOUS};
+use packline_core::app::channel::consumer::Consumer;
+use packline_core::app::channel::{consumer_group_id, Channel, Record};
+use packline_core::app::{App, ChannelConfig};
+use packline_core::connector::{TCPConnectionHandler, TCPConnectorHandler, TCPStream};
+use packline_flow::auth::CredentialStore;
+
+use crate::codec::MQTTCodec;
+use crate::packet::{
+    ConnAck, Connect, ConnectReturnCode, Packet, ProtocolVersion, Publish, QoS, SubAck, Subscribe, SubscribeReturnCode,
+    UnsubAck, Unsubscribe, Will,
+};
+use crate::topic;
+
+/// Time a client has to send CONNECT after opening the connection.
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Interval wildcard subscriptions look for topics created after they started.
+const TOPIC_DISCOVERY_INTERVAL: Duration = Duration::from_millis(500);
+
+/// Time records are collected for before being delivered to subscribers.
+const DELIVERY_LINGER: Duration = Duration::from_millis(5);
+
+/// Serves MQTT 3.1.1 and 5 clients, mapping MQTT topics onto app channels of the same name.
+///
+/// Publishing to a topic that has no channel yet creates it with a single partition. Subscribers
+/// receive the records produced after they subscribed, whichever protocol they were produced with.
+/// QoS 0 and 1 are granted to subscriptions, QoS 2 publishes are accepted and acknowledged with the
+/// exactly once handshake.
+///
+/// Retained messages are kept in memory by the connector. Sessions aren't persisted, every
+/// connection starts a clean session.
+pub struct MQTTConnector {
+    pub app: App,
+
+    /// Requires clients to log in with a username and password from the store when set. Otherwise
+    /// clients act as the anonymous principal whatever username they send.
+    pub credentials: Option<Arc<CredentialStore>>,
+
+    broker: Arc<BrokerState>,
+}
+
+impl MQTTConnector {
+    pub fn new(app: App) -> MQTTConnector {
+        MQTTConnector {
+            app,
+            credentials: None,
+            broker: Arc::new(BrokerState::default()),
+        }
+    }
+
+    pub fn with_credentials(mut self, credentials: CredentialStore) -> MQTTConnector {
+        self.credentials = Some(Arc::new(credentials));
+        self
+    }
+
+    /// Creates the handler running MQTT over `stream`. `peer` identifies the remote end in logs.
+    pub fn connection_handler<S: MQTTStream>(&self, stream: S, peer: String) -> MQTTConnectionHandler<S> {
+        MQTTConnectionHandler {
+            app: self.app.clone(),
+            peer,
+            stream: Some(stream),
+            credentials: self.credentials.clone(),
+            broker: self.broker.clone(),
+        }
+    }
+}
+
+#[async_trait]
+impl TCPConnectorHandler for MQTTConnector {
+    fn handle_connection(&self, conn: (TCPStream, SocketAddr)) -> Box<dyn TCPConnectionHandler> {
+        Box::new(self.connection_handler(conn.0, conn.1.to_string()))
+    }
+
+    fn name(&self) -> &'static str {
+        "mqtt"
+    }
+}
+
+/// Byte stream MQTT can run on.
+pub trait MQTTStream: AsyncRead + AsyncWrite + Send + Sync + Unpin + 'static {}
+
+impl<T: AsyncRead + AsyncWrite + Send + Sync + Unpin + 'static> MQTTStream for T {}
+
+/// State shared by the connections of a connector.
+#[derive(Default)]
+struct BrokerState {
+    retained: StdMutex<HashMap<String, Bytes>>,
+    sessions: StdMutex<HashMap<String, Session>>,
+    next_connection_id: AtomicU64,
+}
+
+struct Session {
+    connection_id: u64,
+    takeover: Arc<Notify>,
+}
+
+impl BrokerState {
+    /// Registers the connection as the one of `client_id`, telling the previous connection of the
+    /// same client to close.
+    fn register(&self, client_id: &str, connection_id: u64) -> Arc<Notify> {
+        let takeover = Arc::new(Notify::new());
+        let session = Session {
+            connection_id,
+            takeover: takeover.clone(),
+        };
+
+        if let Some(previous) = self.sessions.lock().unwrap().insert(client_id.to_string(), session) {
+            previous.takeover.notify_one();
+        }
+
+        takeover
+    }
+
+    fn unregister(&self, client_id: &str, connection_id: u64) {
+        let mut sessions = self.sessions.lock().unwrap();
+        if sessions.get(client_id).map(|session| session.connection_id) == Some(connection_id) {
+            sessions.remove(client_id);
+        }
+    }
+
+    fn retain(&self, topic: &str, payload: Bytes) {
+        let mut retained = self.retained.lock().unwrap();
+
+        // An empty retained message clears the one kept for the topic.
+        if payload.is_empty() {
+            retained.remove(topic);
+        } else {
+            retained.insert(topic.to_string(), payload);
+        }
+    }
+
+    fn retained(&self, filter: &str) -> Vec<(String, Bytes)> {
+        let retained = self.retained.lock().unwrap();
+        retained
+            .iter()
+            .filter(|(topic, _)| topic::matches(filter, topic))
+            .map(|(topic, payload)| (topic.clone(), payload.clone()))
+            .collect()
+    }
+}
+
+pub struct MQTTConnectionHandler<S: MQTTStream> {
+    app: App,
+    peer: String,
+    stream: Option<S>,
+
+    credentials: Option<Arc<CredentialStore>>,
+    broker: Arc<BrokerState>,
+}
+
+type MQTTSink<S> = Arc<Mutex<SplitSink<Framed<S, MQTTCodec>, Packet>>>;
+
+struct ConnectionState<S: MQTTStream> {
+    app: App,
+    broker: Arc<BrokerState>,
+    principal: String,
+    client_id: String,
+
+    sink: MQTTSink<S>,
+    packet_ids: Arc<AtomicU16>,
+    subscriptions: HashMap<String, JoinHandle<()>>,
+
+    /// QoS 2 publishes already produced, waiting for the client to release their packet id.
+    pending_releases: HashSet<u16>,
+}
+
+#[async_trait]
+impl<S: MQTTStream> TCPConnectionHandler for MQTTConnectionHandler<S> {
+    async fn handle(&mut self) -> Result<(), Error> {
+        debug!("New MQTT Connection: {}", self.peer);
+
+        let mut framed = Framed::new(self.stream.take().unwrap(), MQTTCodec::new());
+        let connect = match tokio::time::timeout(CONNECT_TIMEOUT, framed.next()).await {
+            Ok(Some(Ok(Packet::Connect(connect)))) => connect,
+            Ok(Some(Err(e))) => {
+                debug!("Failed to read CONNECT from {}: {}", self.peer, e);
+                return Ok(());
+            }
+            _ => {
+                debug!("{} didn't start with CONNECT", self.peer);
+                return Ok(());
+            }
+        };
+
+        let connection_id = self.broker.next_connection_id.fetch_add(1, Ordering::Relaxed);
+        let (client_id, principal) = match self.accept(&connect, connection_id).await {
+            Ok(accepted) => accepted,
+            Err(code) => {
+                debug!("Refused MQTT connection from {}: {:?}", self.peer, code);
+                let _ = framed
+                    .send(Packet::ConnAck(ConnAck {
+                        session_present: false,
+                        code,
+                    }))
+                    .await;
+                return Ok(());
+            }
+        };
+
+        let takeover = self.broker.register(&client_id, connection_id);
+        framed
+            .send(Packet::ConnAck(ConnAck {
+                session_present: false,
+                code: ConnectReturnCode::Accepted,
+            }))
+            .await?;
+
+        info!("MQTT client {} connected from {}", client_id, self.peer);
+
+        let (sink, mut stream) = framed.split();
+        let mut state = ConnectionState {
+            app: self.app.clone(),
+            broker: self.broker.clone(),
+            principal,
+            client_id,
+            sink: Arc::new(Mutex::new(sink)),
+            packet_ids: Arc::new(AtomicU16::new(1)),
+            subscriptions: HashMap::new(),
+            pending_releases: HashSet::new(),
+        };
+
+        // Clients have one and a half keep alive periods to send something.
+        let keep_alive = Duration::from_millis(connect.keep_alive as u64 * 1500);
+        let mut will = connect.will;
+
+        loop {
+            let next = async {
+                match connect.keep_alive {
+                    0 => Some(stream.next().await),
+                    _ => tokio::time::timeout(keep_alive, stream.next()).await.ok(),
+                }
+            };
+
+            let packet = tokio::select! {
+                _ = takeover.notified() => {
+                    debug!("MQTT client {} connected again, closing {}", state.client_id, self.peer);
+                    break;
+                }
+                packet = next => packet,
+            };
+
+            let packet = match packet {
+                None => {
+                    debug!("MQTT client {} keep alive expired", state.client_id);
+                    break;
+                }
+                Some(None) => break,
+                Some(Some(Err(e))) => {
+                    debug!("Failed to read from MQTT connection: {}", e);
+                    break;
+                }
+                Some(Some(Ok(packet))) => packet,
+            };
+
+            match state.handle_packet(packet).await {
+                Ok(true) => {}
+                Ok(false) => {
+                    will = None;
+                    break;
+                }
+                Err(e) => {
+                    warn!("Closing MQTT connection of {}: {}", state.client_id, e);
+                    break;
+                }
+            }
+        }
+
+        for (_, subscription) in state.subscriptions.drain() {
+            subscription.abort();
+        }
+
+        if let Some(will) = will {
+            state.publish_will(will).await;
+        }
+
+        self.broker.unregister(&state.client_id, connection_id);
+
+        debug!("MQTT connection finished");
+        Ok(())
+    }
+}
+
+impl<S: MQTTStream> MQTTConnectionHandler<S> {
+    /// Checks the CONNECT packet, returning the client id and the principal the connection acts as.
+    async fn accept(&self, connect: &Connect, connection_id: u64) -> Result<(String, String), ConnectReturnCode> {
+        if ProtocolVersion::from_level(connect.protocol_level).is_none() {
+            return Err(ConnectReturnCode::UnacceptableProtocolVersion);
+        }
+
+        let client_id = match connect.client_id.as_str() {
+            "" if connect.clean_session => format!("packline-{}", connection_id),
+            "" => return Err(ConnectReturnCode::IdentifierRejected),
+            client_id => client_id.to_string(),
+        };
+
+        let principal = match &self.credentials {
+            Some(credentials) => {
+                let username = connect.username.as_ref();
+                let password = connect.password.as_ref().and_then(|p| std::str::from_utf8(p).ok());
+
+                match (username, password) {
+                    (Some(username), Some(password)) if credentials.verify(username, password).await => {
+                        username.clone()
+                    }
+                    _ => return Err(ConnectReturnCode::BadUsernameOrPassword),
+                }
+            }
+            None => ANONYMOUS.to_string(),
+        };
+
+        Ok((client_id, principal))
+    }
+}
+
+impl<S: MQTTStream> ConnectionState<S> {
+    /// Handles a packet from the client. Returns false when the client disconnected.
+    async fn handle_packet(&mut self, packet: Packet) -> Result<bool, Error> {
+        match packet {
+            Packet::Publish(publish) => self.handle_publish(publish).await?,
+            Packet::PubRel(packet_id) => {
+                self.pending_releases.remove(&packet_id);
+                self.send(Packet::PubComp(packet_id)).await?;
+            }
+            // Deliveries aren't retried, so acknowledgements need no tracking.
+            Packet::PubAck(_) | Packet::PubRec(_) | Packet::PubComp(_) => {}
+            Packet::Subscribe(subscribe) => self.handle_subscribe(subscribe).await?,
+            Packet::Unsubscribe(unsubscribe) => self.handle_unsubscribe(unsubscribe).await?,
+            Packet::PingReq => self.send(Packet::PingResp).await?,
+            Packet::Disconnect => return Ok(false),
+            packet => {
+                return Err(Error::new(
+                    ErrorKind::InvalidData,
+                    format!("unexpected packet {:?}", packet),
+                ))
+            }
+        }
+
+        Ok(true)
+    }
+
+    async fn handle_publish(&mut self, publish: Publish) -> Result<(), Error> {
+        match (publish.qos, publish.packet_id) {
+            (QoS::AtMostOnce, _) => self.publish(&publish.topic, publish.payload, publish.retain).await,
+            (QoS::AtLeastOnce, Some(packet_id)) => {
+                self.publish(&publish.topic, publish.payload, publish.retain).await?;
+                self.send(Packet::PubAck(packet_id)).await
+            }
+            (QoS::ExactlyOnce, Some(packet_id)) => {
+                // A publish sent again before its release was already produced.
+                if self.pending_releases.insert(packet_id) {
+                    self.publish(&publish.topic, publish.payload, publish.retain).await?;
+                }
+
+                self.send(Packet::PubRec(packet_id)).await
+            }
+            _ => Err(Error::new(ErrorKind::InvalidData, "PUBLISH without packet id")),
+        }
+    }
+
+    async fn handle_subscribe(&mut self, subscribe: Subscribe) -> Result<(), Error> {
+        let mut codes = Vec::with_capacity(subscribe.filters.len());
+        let mut accepted = Vec::new();
+
+        for (filter, qos) in subscribe.filters {
+            let code = if !topic::is_valid_filter(&filter) {
+                SubscribeReturnCode::InvalidFilter
+            } else if !topic::is_wildcard(&filter) && !self.can_consume(&filter) {
+                SubscribeReturnCode::NotAuthorized
+            } else {
+                let qos = qos.min(QoS::AtLeastOnce);
+                self.subscribe(&filter, qos).await;
+                accepted.push((filter, qos));
+
+                SubscribeReturnCode::Granted(qos)
+            };
+
+            codes.push(code);
+        }
+
+        self.send(Packet::SubAck(SubAck {
+            packet_id: subscribe.packet_id,
+            codes,
+        }))
+        .await?;
+
+        for (filter, qos) in accepted {
+            for (topic, payload) in self.broker.retained(&filter) {
+                if !self.can_consume(&topic) {
+                    continue;
+                }
+
+                let packet_id = (qos != QoS::AtMostOnce).then(|| next_packet_id(&self.packet_ids));
+                self.send(Packet::Publish(Publish {
+                    dup: false,
+                    qos,
+                    retain: true,
+                    topic,
+                    packet_id,
+                    payload,
+                }))
+                .await?;
+            }
+        }
+
+        Ok(())
+    }
+
+    async fn handle_unsubscribe(&mut self, unsubscribe: Unsubscribe) -> Result<(), Error> {
+        for filter in &unsubscribe.filters {
+            if let Some(subscription) = self.subscriptions.remove(filter) {
+                subscription.abort();
+            }
+        }
+
+        self.send(Packet::UnsubAck(UnsubAck {
+            packet_id: unsubscribe.packet_id,
+            count: unsubscribe.filters.len(),
+        }))
+        .await
+    }
+
+    /// Starts delivering the records of the topics matched by `filter`, replacing any previous
+    /// subscription to the same filter.
+    async fn subscribe(&mut self, filter: &str, qos: QoS) {
+        if let Some(subscription) = self.subscriptions.remove(filter) {
+            subscription.abort();
+        }
+
+        let mut subscription = Subscription {
+            app: self.app.clone(),
+            principal: self.principal.clone(),
+            filter: filter.to_string(),
+            group: consumer_group_id(&format!("mqtt/{}/{}", self.client_id, filter)),
+            qos,
+            sink: self.sink.clone(),
+            packet_ids: self.packet_ids.clone(),
+            topics: HashSet::new(),
+            deliveries: JoinSet::new(),
+        };
+
+        // Topics existing at this point are joined before the SUBACK is sent, so that anything
+        // published after it is delivered.
+        subscription.discover(true).await;
+
+        let task = tokio::spawn(subscription.run());
+        self.subscriptions.insert(filter.to_string(), task);
+    }
+
+    /// Produces a message published by the client to the channel of `topic`, creating the channel
+    /// when needed.
+    async fn publish(&self, topic: &str, payload: Bytes, retain: bool) -> Result<(), Error> {
+        if !topic::is_valid_topic(topic) {
+            return Err(Error::new(ErrorKind::InvalidData, format!("invalid topic {:?}", topic)));
+        }
+
+        if !self
+            .app
+            .authorize(&self.principal, &Resource::topic(topic), Operation::Produce)
+        {
+            return Err(not_allowed(Operation::Produce, topic));
+        }
+
+        let channel = match self.app.get_channel(&(topic.to_string(), 1)).await {
+            Some(channel) => channel,
+            None => self.create_channel(topic).await?,
+        };
+
+        if retain {
+            self.broker.retain(topic, payload.clone());
+        }
+
+        channel
+            .producer()
+            .produce(&mut vec![Record::from_value(payload.to_vec())])
+            .await;
+
+        Ok(())
+    }
+
+    async fn create_channel(&self, topic: &str) -> Result<Channel, Error> {
+        if !self
+            .app
+            .authorize(&self.principal, &Resource::topic(topic), Operation::Create)
+        {
+            return Err(not_allowed(Operation::Create, topic));
+        }
+
+        // Another connection may have created it in the meantime, which is fine.
+        let _ = self
+            .app
+            .create_channel(ChannelConfig {
+                name: topic.to_string(),
+                partitions: 1,
+            })
+            .await;
+
+        self.app
+            .get_channel(&(topic.to_string(), 1))
+            .await
+            .ok_or_else(|| Error::other(format!("failed to create channel {}", topic)))
+    }
+
+    async fn publish_will(&self, will: Will) {
+        debug!("Publishing will of MQTT client {} to {}", self.client_id, will.topic);
+
+        if let Err(e) = self.publish(&will.topic, will.payload, will.retain).await {
+            warn!("Failed to publish will of {}: {}", self.client_id, e);
+        }
+    }
+
+    fn can_consume(&self, topic: &str) -> bool {
+        self.app
+            .authorize(&self.principal, &Resource::topic(topic), Operation::Consume)
+    }
+
+    async fn send(&self, packet: Packet) -> Result<(), Error> {
+        self.sink.lock().await.send(packet).await
+    }
+}
+
+/// Delivers the records of the topics matched by a filter to a connection.
+struct Subscription<S: MQTTStream> {
+    app: App,
+    principal: String,
+    filter: String,
+    group: u128,
+    qos: QoS,
+
+    sink: MQTTSink<S>,
+    packet_ids: Arc<AtomicU16>,
+
+    topics: HashSet<String>,
+    deliveries: JoinSet<()>,
+}
+
+impl<S: MQTTStream> Subscription<S> {
+    async fn run(mut self) {
+        loop {
+            tokio::select! {
+                // Deliveries only end once the connection can't be written to anymore.
+                _ = self.deliveries.join_next(), if !self.deliveries.is_empty() => return,
+                _ = tokio::time::sleep(TOPIC_DISCOVERY_INTERVAL) => {}
+            }
+
+            self.discover(false).await;
+        }
+    }
+
+    /// Starts delivering from the matching topics not delivered yet. Topics that existed when the
+    /// subscription was made are delivered from their end, later ones from their start.
+    async fn discover(&mut self, existing: bool) {
+        for config in self.app.list_channels().await {
+            if !topic::matches(&self.filter, &config.name) || self.topics.contains(&config.name) {
+                continue;
+            }
+
+            if !self
+                .app
+                .authorize(&self.principal, &Resource::topic(&config.name), Operation::Consume)
+            {
+                continue;
+            }
+
+            let channel = match self.app.get_channel(&(config.name.clone(), 1)).await {
+                Some(channel) => channel,
+                None => continue,
+            };
+
+            if existing {
+                let _ = channel.seek(self.group, channel.end_offset()).await;
+            }
+
+            debug!("MQTT subscription {} delivering from {}", self.filter, config.name);
+
+            let consumer = channel.consumer(self.group).with_timeout(DELIVERY_LINGER);
+            self.deliveries.spawn(deliver(
+                consumer,
+                config.name.clone(),
+                self.qos,
+                self.sink.clone(),
+                self.packet_ids.clone(),
+            ));
+            self.topics.insert(config.name);
+        }
+    }
+}
+
+async fn deliver<S: MQTTStream>(
+    consumer: Consumer,
+    topic: String,
+    qos: QoS,
+    sink: MQTTSink<S>,
+    packet_ids: Arc<AtomicU16>,
+) {
+    loop {
+        let records = consumer.consume().await;
+
+        let mut sink = sink.lock().await;
+        for record in records {
+            let packet_id = (qos != QoS::AtMostOnce).then(|| next_packet_id(&packet_ids));
+            let publish = Packet::Publish(Publish {
+                dup: false,
+                qos,
+                retain: false,
+                topic: topic.clone(),
+                packet_id,
+                payload: Bytes::from(record.value),
+            });
+
+            if sink.feed(publish).await.is_err() {
+                return;
+            }
+        }
+
+        if sink.flush().await.is_err() {
+            return;
+        }
+    }
+}
+
+/// Returns the next packet id of the connection. Ids wrap around, skipping zero which is reserved.
+fn next_packet_id(packet_ids: &AtomicU16) -> u16 {
+    loop {
+        let packet_id = packet_ids.fetch_add(1, Ordering::Relaxed);
+        if packet_id != 0 {
+            return packet_id;
+        }
+    }
+}
+
+fn not_allowed(operation: Operation, topic: &str) -> Error {
+    Error::new(
+        ErrorKind::PermissionDenied,
+        format!("not allowed to {} {}", operation, topic),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use packline_core::app::acl::{AclRule, Authorizer, Permission};
+    use tokio::io::DuplexStream;
+
+    use super::*;
+
+    type Client = Framed<DuplexStream, MQTTCodec>;
+
+    fn connect_packet(client_id: &str) -> Connect {
+        Connect {
+            protocol_level: 4,
+            client_id: client_id.to_string(),
+            clean_session: true,
+            keep_alive: 0,
+            will: None,
+            username: None,
+            password: None,
+        }
+    }
+
+    fn open(connector: &MQTTConnector) -> Client {
+        let (client, server) = tokio::io::duplex(64 * 1024);
+        let mut handler = connector.connection_handler(server, "test".to_string());
+        tokio::spawn(async move { handler.handle().await });
+
+        Framed::new(client, MQTTCodec::new())
+    }
+
+    async fn connect(connector: &MQTTConnector, connect: Connect) -> (Client, ConnectReturnCode) {
+        let mut client = open(connector);
+        client.send(Packet::Connect(connect)).await.unwrap();
+
+        match next(&mut client).await {
+            Some(Packet::ConnAck(connack)) => (client, connack.code),
+            packet => panic!("unexpected packet {:?}", packet),
+        }
+    }
+
+    async fn next(client: &mut Client) -> Option<Packet> {
+        tokio::time::timeout(Duration::from_secs(2), client.next())
+            .await
+            .expect("timed out waiting for a packet")
+            .map(|packet| packet.unwrap())
+    }
+
+    fn publish(topic: &str, qos: QoS, packet_id: Option<u16>, payload: &'static [u8]) -> Packet {
+        Packet::Publish(Publish {
+            dup: false,
+            qos,
+            retain: false,
+            topic: topic.to_string(),
+            packet_id,
+            payload: Bytes::from_static(payload),
+        })
+    }
+
+    async fn subscribe(client: &mut Client, filter: &str, qos: QoS) -> Vec<SubscribeReturnCode> {
+        client
+            .send(Packet::Subscribe(Subscribe {
+                packet_id: 1,
+                filters: vec![(filter.to_string(), qos)],
+            }))
+            .await
+            .unwrap();
+
+        match next(client).await {
+            Some(Packet::SubAck(suback)) => suback.codes,
+            packet => panic!("unexpected packet {:?}", packet),
+        }
+    }
+
+    fn received(packet: Option<Packet>) -> Publish {
+        match packet {
+            Some(Packet::Publish(publish)) => publish,
+            packet => panic!("unexpected packet {:?}", packet),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_publish_and_subscribe_with_wildcard() {
+        let connector = MQTTConnector::new(App::new());
+        let (mut subscriber, _) = connect(&connector, connect_packet("subscriber")).await;
+        let (mut publisher, code) = connect(&connector, connect_packet("publisher")).await;
+        assert_eq!(ConnectReturnCode::Accepted, code);
+
+        assert_eq!(
+            vec![SubscribeReturnCode::Granted(QoS::AtLeastOnce)],
+            subscribe(&mut subscriber, "sensors/+/temperature", QoS::ExactlyOnce).await
+        );
+
+        publisher
+            .send(publish("sensors/1/temperature", QoS::AtLeastOnce, Some(9), b"21.5"))
+            .await
+            .unwrap();
+        assert_eq!(Some(Packet::PubAck(9)), next(&mut publisher).await);
+
+        publisher
+            .send(publish("sensors/1/humidity", QoS::AtMostOnce, None, b"40"))
+            .await
+            .unwrap();
+        publisher
+            .send(publish("sensors/2/temperature", QoS::AtMostOnce, None, b"19"))
+            .await
+            .unwrap();
+
+        let first = received(next(&mut subscriber).await);
+        assert_eq!("sensors/1/temperature", first.topic);
+        assert_eq!(QoS::AtLeastOnce, first.qos);
+        assert!(first.packet_id.is_some());
+        assert_eq!(Bytes::from_static(b"21.5"), first.payload);
+
+        let second = received(next(&mut subscriber).await);
+        assert_eq!("sensors/2/temperature", second.topic);
+        assert_eq!(Bytes::from_static(b"19"), second.payload);
+    }
+
+    #[tokio::test]
+    async fn test_subscribers_only_receive_new_messages() {
+        let app = App::new();
+        let connector = MQTTConnector::new(app.clone());
+        let (mut client, _) = connect(&connector, connect_packet("client")).await;
+
+        client
+            .send(publish("orders", QoS::AtLeastOnce, Some(1), b"old"))
+            .await
+            .unwrap();
+        assert_eq!(Some(Packet::PubAck(1)), next(&mut client).await);
+
+        subscribe(&mut client, "orders", QoS::AtMostOnce).await;
+        client
+            .send(publish("orders", QoS::AtMostOnce, None, b"new"))
+            .await
+            .unwrap();
+
+        assert_eq!(Bytes::from_static(b"new"), received(next(&mut client).await).payload);
+    }
+
+    #[tokio::test]
+    async fn test_retained_message_is_sent_on_subscribe() {
+        let connector = MQTTConnector::new(App::new());
+        let (mut publisher, _) = connect(&connector, connect_packet("publisher")).await;
+
+        let mut retained = publish("devices/1/status", QoS::AtLeastOnce, Some(1), b"online");
+        if let Packet::Publish(publish) = &mut retained {
+            publish.retain = true;
+        }
+        publisher.send(retained).await.unwrap();
+        assert_eq!(Some(Packet::PubAck(1)), next(&mut publisher).await);
+
+        let (mut subscriber, _) = connect(&connector, connect_packet("subscriber")).await;
+        subscribe(&mut subscriber, "devices/#", QoS::AtMostOnce).await;
+
+        let publish = received(next(&mut subscriber).await);
+        assert!(publish.retain);
+        assert_eq!("devices/1/status", publish.topic);
+        assert_eq!(Bytes::from_static(b"online"), publish.payload);
+    }
+
+    #[tokio::test]
+    async fn test_qos2_publish_handshake() {
+        let connector = MQTTConnector::new(App::new());
+        let (mut client, _) = connect(&connector, connect_packet("client")).await;
+
+        client
+            .send(publish("orders", QoS::ExactlyOnce, Some(3), b"1"))
+            .await
+            .unwrap();
+        assert_eq!(Some(Packet::PubRec(3)), next(&mut client).await);
+
+        client.send(Packet::PubRel(3)).await.unwrap();
+        assert_eq!(Some(Packet::PubComp(3)), next(&mut client).await);
+    }
+
+    #[tokio::test]
+    async fn test_ping_and_disconnect() {
+        let connector = MQTTConnector::new(App::new());
+        let (mut client, _) = connect(&connector, connect_packet("client")).await;
+
+        client.send(Packet::PingReq).await.unwrap();
+        assert_eq!(Some(Packet::PingResp), next(&mut client).await);
+
+        client.send(Packet::Disconnect).await.unwrap();
+        assert_eq!(None, next(&mut client).await);
+    }
+
+    #[tokio::test]
+    async fn test_keep_alive_expiry_publishes_will() {
+        let connector = MQTTConnector::new(App::new());
+        let (mut subscriber, _) = connect(&connector, connect_packet("subscriber")).await;
+        subscribe(&mut subscriber, "devices/+/status", QoS::AtMostOnce).await;
+
+        let mut connect_packet = connect_packet("device");
+        connect_packet.keep_alive = 1;
+        connect_packet.will = Some(Will {
+            topic: "devices/1/status".to_string(),
+            payload: Bytes::from_static(b"offline"),
+            qos: QoS::AtMostOnce,
+            retain: false,
+        });
+        let (mut device, _) = connect(&connector, connect_packet).await;
+
+        // The device never pings, so the broker closes the connection after 1.5 seconds.
+        assert_eq!(None, next(&mut device).await);
+
+        let publish = received(next(&mut subscriber).await);
+        assert_eq!("devices/1/status", publish.topic);
+        assert_eq!(Bytes::from_static(b"offline"), publish.payload);
+    }
+
+    #[tokio::test]
+    async fn test_connect_rejections() {
+        let mut store = CredentialStore::new();
+        store.add_user("alice", "secret").await;
+        let connector = MQTTConnector::new(App::new()).with_credentials(store);
+
+        let mut packet = connect_packet("client");
+        packet.protocol_level = 3;
+        assert_eq!(
+            ConnectReturnCode::UnacceptableProtocolVersion,
+            connect(&connector, packet).await.1
+        );
+
+        let mut packet = connect_packet("");
+        packet.clean_session = false;
+        assert_eq!(
+            ConnectReturnCode::IdentifierRejected,
+            connect(&connector, packet).await.1
+        );
+
+        let mut packet = connect_packet("client");
+        packet.username = Some("alice".to_string());
+        packet.password = Some(Bytes::from_static(b"wrong"));
+        assert_eq!(
+            ConnectReturnCode::BadUsernameOrPassword,
+            connect(&connector, packet).await.1
+        );
+
+        let mut packet = connect_packet("client");
+        packet.protocol_level = 5;
+        packet.username = Some("alice".to_string());
+        packet.password = Some(Bytes::from_static(b"secret"));
+        assert_eq!(ConnectReturnCode::Accepted, connect(&connector, packet).await.1);
+    }
+
+    #[tokio::test]
+    async fn test_subscribe_checks_filters_and_authorization() {
+        let authorizer = Authorizer::new();
+        authorizer
+            .add_rule(AclRule {
+                principal: ANONYMOUS.to_string(),
+                resource: Resource::topic("public"),
+                operation: Operation::Consume,
+                permission: Permission::Allow,
+            })
+            .unwrap();
+        let connector = MQTTConnector::new(App::with_authorizer(authorizer));
+        let (mut client, _) = connect(&connector, connect_packet("client")).await;
+
+        client
+            .send(Packet::Subscribe(Subscribe {
+                packet_id: 4,
+                filters: vec![
+                    ("public".to_string(), QoS::AtMostOnce),
+                    ("private".to_string(), QoS::AtMostOnce),
+                    ("bad/#/filter".to_string(), QoS::AtMostOnce),
+                ],
+            }))
+            .await
+            .unwrap();
+
+        assert_eq!(
+            Some(Packet::SubAck(SubAck {
+                packet_id: 4,
+                codes: vec![
+                    SubscribeReturnCode::Granted(QoS::AtMostOnce),
+                    SubscribeReturnCode::Failure,
+                    SubscribeReturnCode::Failure,
+                ],
+            })),
+            next(&mut client).await
+        );
+
+        // Publishing without permission closes the connection.
+        client
+            .send(publish("public", QoS::AtMostOnce, None, b"1"))
+            .await
+            .unwrap();
+        assert_eq!(None, next(&mut client).await);
+    }
+
+    #[tokio::test]
+    async fn test_second_connection_takes_over_client_id() {
+        let connector = MQTTConnector::new(App::new());
+        let (mut first, _) = connect(&connector, connect_packet("device")).await;
+        let (mut second, _) = connect(&connector, connect_packet("device")).await;
+
+        assert_eq!(None, next(&mut first).await);
+
+        second.send(Packet::PingReq).await.unwrap();
+        assert_eq!(Some(Packet::PingResp), next(&mut second).await);
+    }
+}
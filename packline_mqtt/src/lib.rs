@@ -0,0 +1,4 @@
+pub mod codec;
+pub mod connector;
+pub mod packet;
+pub mod topic;
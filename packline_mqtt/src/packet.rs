@@ -0,0 +1,203 @@
+use std::convert::TryFrom;
+
+use bytes::Bytes;
+
+/// MQTT protocol revision negotiated by a connection's CONNECT packet.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ProtocolVersion {
+    V311,
+    V5,
+}
+
+impl ProtocolVersion {
+    pub fn level(&self) -> u8 {
+        match self {
+            ProtocolVersion::V311 => 4,
+            ProtocolVersion::V5 => 5,
+        }
+    }
+
+    pub fn from_level(level: u8) -> Option<ProtocolVersion> {
+        match level {
+            4 => Some(ProtocolVersion::V311),
+            5 => Some(ProtocolVersion::V5),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+#[repr(u8)]
+pub enum QoS {
+    AtMostOnce = 0,
+    AtLeastOnce = 1,
+    ExactlyOnce = 2,
+}
+
+impl TryFrom<u8> for QoS {
+    type Error = ();
+
+    fn try_from(value: u8) -> Result<Self, Self::Error> {
+        match value {
+            0 => Ok(QoS::AtMostOnce),
+            1 => Ok(QoS::AtLeastOnce),
+            2 => Ok(QoS::ExactlyOnce),
+            _ => Err(()),
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Packet {
+    Connect(Connect),
+    ConnAck(ConnAck),
+    Publish(Publish),
+    PubAck(u16),
+    PubRec(u16),
+    PubRel(u16),
+    PubComp(u16),
+    Subscribe(Subscribe),
+    SubAck(SubAck),
+    Unsubscribe(Unsubscribe),
+    UnsubAck(UnsubAck),
+    PingReq,
+    PingResp,
+    Disconnect,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Connect {
+    /// Protocol level requested by the client. Only the levels of [`ProtocolVersion`] are
+    /// accepted, the packet is still decoded for others so that the client can be told.
+    pub protocol_level: u8,
+    pub client_id: String,
+    pub clean_session: bool,
+
+    /// Seconds the client may stay silent before the connection is considered dead, zero disables
+    /// the check.
+    pub keep_alive: u16,
+    pub will: Option<Will>,
+    pub username: Option<String>,
+    pub password: Option<Bytes>,
+}
+
+/// Message published on behalf of a client whose connection ends without a DISCONNECT.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Will {
+    pub topic: String,
+    pub payload: Bytes,
+    pub qos: QoS,
+    pub retain: bool,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ConnectReturnCode {
+    Accepted,
+    UnacceptableProtocolVersion,
+    IdentifierRejected,
+    BadUsernameOrPassword,
+    NotAuthorized,
+}
+
+impl ConnectReturnCode {
+    /// Returns the code sent in CONNACK, which differs between protocol versions.
+    pub fn code(&self, version: ProtocolVersion) -> u8 {
+        match (self, version) {
+            (ConnectReturnCode::Accepted, _) => 0x00,
+            (ConnectReturnCode::UnacceptableProtocolVersion, ProtocolVersion::V311) => 0x01,
+            (ConnectReturnCode::IdentifierRejected, ProtocolVersion::V311) => 0x02,
+            (ConnectReturnCode::BadUsernameOrPassword, ProtocolVersion::V311) => 0x04,
+            (ConnectReturnCode::NotAuthorized, ProtocolVersion::V311) => 0x05,
+            (ConnectReturnCode::UnacceptableProtocolVersion, ProtocolVersion::V5) => 0x84,
+            (ConnectReturnCode::IdentifierRejected, ProtocolVersion::V5) => 0x85,
+            (ConnectReturnCode::BadUsernameOrPassword, ProtocolVersion::V5) => 0x86,
+            (ConnectReturnCode::NotAuthorized, ProtocolVersion::V5) => 0x87,
+        }
+    }
+
+    pub fn from_code(code: u8) -> Option<ConnectReturnCode> {
+        match code {
+            0x00 => Some(ConnectReturnCode::Accepted),
+            0x01 | 0x84 => Some(ConnectReturnCode::UnacceptableProtocolVersion),
+            0x02 | 0x85 => Some(ConnectReturnCode::IdentifierRejected),
+            0x04 | 0x86 => Some(ConnectReturnCode::BadUsernameOrPassword),
+            0x05 | 0x87 => Some(ConnectReturnCode::NotAuthorized),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ConnAck {
+    pub session_present: bool,
+    pub code: ConnectReturnCode,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Publish {
+    pub dup: bool,
+    pub qos: QoS,
+    pub retain: bool,
+    pub topic: String,
+
+    /// Set for QoS 1 and 2 publishes only.
+    pub packet_id: Option<u16>,
+    pub payload: Bytes,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Subscribe {
+    pub packet_id: u16,
+    pub filters: Vec<(String, QoS)>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SubscribeReturnCode {
+    Granted(QoS),
+    Failure,
+    NotAuthorized,
+    InvalidFilter,
+}
+
+impl SubscribeReturnCode {
+    /// Returns the code sent in SUBACK. MQTT 3.1.1 only has a single failure code.
+    pub fn code(&self, version: ProtocolVersion) -> u8 {
+        match (self, version) {
+            (SubscribeReturnCode::Granted(qos), _) => *qos as u8,
+            (_, ProtocolVersion::V311) | (SubscribeReturnCode::Failure, _) => 0x80,
+            (SubscribeReturnCode::NotAuthorized, ProtocolVersion::V5) => 0x87,
+            (SubscribeReturnCode::InvalidFilter, ProtocolVersion::V5) => 0x8F,
+        }
+    }
+
+    pub fn from_code(code: u8) -> SubscribeReturnCode {
+        match QoS::try_from(code) {
+            Ok(qos) => SubscribeReturnCode::Granted(qos),
+            Err(_) => match code {
+                0x87 => SubscribeReturnCode::NotAuthorized,
+                0x8F => SubscribeReturnCode::InvalidFilter,
+                _ => SubscribeReturnCode::Failure,
+            },
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SubAck {
+    pub packet_id: u16,
+    pub codes: Vec<SubscribeReturnCode>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Unsubscribe {
+    pub packet_id: u16,
+    pub filters: Vec<String>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct UnsubAck {
+    pub packet_id: u16,
+
+    /// Number of filters of the acknowledged UNSUBSCRIBE, MQTT 5 answers each one.
+    pub count: usize,
+}
@@ -0,0 +1,96 @@
+/// Checks that `topic` can be published to: non-empty and without wildcards.
+pub fn is_valid_topic(topic: &str) -> bool {
+    !topic.is_empty() && !topic.contains(['+', '#', '\0'])
+}
+
+/// Checks that `filter` is a valid subscription. `+` has to fill a whole level and `#` the last
+/// one.
+pub fn is_valid_filter(filter: &str) -> bool {
+    if filter.is_empty() || filter.contains('\0') {
+        return false;
+    }
+
+    let levels = filter.split('/').collect::<Vec<_>>();
+    levels.iter().enumerate().all(|(i, level)| match *level {
+        "+" => true,
+        "#" => i == levels.len() - 1,
+        level => !level.contains(['+', '#']),
+    })
+}
+
+/// Checks whether `topic` is matched by `filter`, which has to be valid.
+///
+/// Topics starting with `$` are reserved for the broker and aren't matched by filters starting
+/// with a wildcard.
+pub fn matches(filter: &str, topic: &str) -> bool {
+    if topic.starts_with('$') && filter.starts_with(['+', '#']) {
+        return false;
+    }
+
+    let mut topic_levels = topic.split('/');
+    for level in filter.split('/') {
+        match (level, topic_levels.next()) {
+            ("#", _) => return true,
+            (_, None) => return false,
+            ("+", Some(_)) => continue,
+            (level, Some(topic_level)) if level == topic_level => continue,
+            _ => return false,
+        }
+    }
+
+    topic_levels.next().is_none()
+}
+
+/// Checks whether `filter` contains wildcards, and can thus match more than one topic.
+pub fn is_wildcard(filter: &str) -> bool {
+    filter.contains(['+', '#'])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_is_valid_filter() {
+        for filter in ["sensors", "sensors/+/temperature", "sensors/#", "#", "+", "/+", "+/+"] {
+            assert!(is_valid_filter(filter), "{}", filter);
+        }
+
+        for filter in ["", "sensors/#/temperature", "sensors+", "sensors/te#", "#/"] {
+            assert!(!is_valid_filter(filter), "{}", filter);
+        }
+    }
+
+    #[test]
+    fn test_is_valid_topic() {
+        assert!(is_valid_topic("sensors/1/temperature"));
+        assert!(!is_valid_topic(""));
+        assert!(!is_valid_topic("sensors/+"));
+        assert!(!is_valid_topic("sensors/#"));
+    }
+
+    #[test]
+    fn test_matches() {
+        let cases = [
+            ("sensors/+/temperature", "sensors/1/temperature", true),
+            ("sensors/+/temperature", "sensors/1/humidity", false),
+            ("sensors/+/temperature", "sensors/temperature", false),
+            ("sensors/#", "sensors", true),
+            ("sensors/#", "sensors/1/temperature", true),
+            ("sensors/#", "devices/1", false),
+            ("#", "sensors/1", true),
+            ("+", "sensors", true),
+            ("+", "sensors/1", false),
+            ("sensors", "sensors", true),
+            ("sensors", "sensors/1", false),
+            ("/+", "/sensors", true),
+            ("#", "$SYS/uptime", false),
+            ("+/uptime", "$SYS/uptime", false),
+            ("$SYS/#", "$SYS/uptime", true),
+        ];
+
+        for (filter, topic, expected) in cases {
+            assert_eq!(expected, matches(filter, topic), "{} {}", filter, topic);
+        }
+    }
+}
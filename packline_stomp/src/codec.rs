@@ -0,0 +1,320 @@
+use std::io::{Error, ErrorKind};
+
+use bytes::{Buf, BufMut, BytesMut};
+use tokio_util::codec::{Decoder, Encoder};
+
+use crate::frame::{Command, Frame};
+
+/// Largest frame accepted by default, headers included.
+pub const DEFAULT_MAX_FRAME_SIZE: usize = 4 * 1024 * 1024;
+
+/// Decodes and encodes STOMP 1.2 frames.
+///
+/// Frames with a `content-length` header are read up to that length, so that their body can hold
+/// NUL octets, others up to the first NUL. Ends of line between frames are decoded as heart-beats.
+pub struct STOMPCodec {
+    max_frame_size: usize,
+}
+
+impl STOMPCodec {
+    pub fn new() -> STOMPCodec {
+        STOMPCodec {
+            max_frame_size: DEFAULT_MAX_FRAME_SIZE,
+        }
+    }
+
+    pub fn with_max_frame_size(mut self, max_frame_size: usize) -> STOMPCodec {
+        self.max_frame_size = max_frame_size;
+        self
+    }
+
+    /// Waits for more of a frame, unless it already exceeds the maximum frame size.
+    fn incomplete(&self, src: &BytesMut) -> Result<Option<Frame>, Error> {
+        match src.len() > self.max_frame_size {
+            true => Err(invalid("frame exceeds the maximum frame size")),
+            false => Ok(None),
+        }
+    }
+}
+
+impl Default for STOMPCodec {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Decoder for STOMPCodec {
+    type Item = Frame;
+    type Error = Error;
+
+    fn decode(&mut self, src: &mut BytesMut) -> Result<Option<Self::Item>, Self::Error> {
+        match src.first() {
+            None => return Ok(None),
+            Some(b'\n') => {
+                src.advance(1);
+                return Ok(Some(Frame::heartbeat()));
+            }
+            Some(b'\r') if src.len() < 2 => return Ok(None),
+            Some(b'\r') if src[1] == b'\n' => {
+                src.advance(2);
+                return Ok(Some(Frame::heartbeat()));
+            }
+            _ => {}
+        }
+
+        let (head_len, body_start) = match find_head_end(src) {
+            Some(end) => end,
+            None => return self.incomplete(src),
+        };
+
+        let head = std::str::from_utf8(&src[..head_len]).map_err(|_| invalid("frame headers aren't valid UTF-8"))?;
+        let mut lines = head.split('\n').map(|line| line.strip_suffix('\r').unwrap_or(line));
+
+        let command = lines.next().unwrap_or_default();
+        let command = Command::parse(command).ok_or_else(|| invalid(&format!("unknown command {:?}", command)))?;
+
+        let mut headers = vec![];
+        for line in lines {
+            let (name, value) = line
+                .split_once(':')
+                .ok_or_else(|| invalid(&format!("malformed header {:?}", line)))?;
+
+            headers.push(match command.escapes_headers() {
+                true => (unescape(name)?, unescape(value)?),
+                false => (name.to_string(), value.to_string()),
+            });
+        }
+
+        let content_length = headers
+            .iter()
+            .find(|(name, _)| name == "content-length")
+            .map(|(_, value)| value.parse::<usize>().map_err(|_| invalid("invalid content-length")))
+            .transpose()?;
+
+        let body_end = match content_length {
+            Some(length) => {
+                let frame_end = body_start
+                    .checked_add(length)
+                    .and_then(|end| end.checked_add(1))
+                    .filter(|end| *end <= self.max_frame_size)
+                    .ok_or_else(|| invalid("frame exceeds the maximum frame size"))?;
+
+                if src.len() < frame_end {
+                    src.reserve(frame_end - src.len());
+                    return Ok(None);
+                }
+
+                if src[body_start + length] != 0 {
+                    return Err(invalid("frame body isn't followed by a NUL octet"));
+                }
+
+                body_start + length
+            }
+            None => match src[body_start..].iter().position(|byte| *byte == 0) {
+                Some(position) => body_start + position,
+                None => return self.incomplete(src),
+            },
+        };
+
+        let mut frame = src.split_to(body_end + 1);
+        frame.truncate(body_end);
+        let body = frame.split_off(body_start).freeze();
+
+        Ok(Some(Frame { command, headers, body }))
+    }
+}
+
+impl Encoder<Frame> for STOMPCodec {
+    type Error = Error;
+
+    fn encode(&mut self, frame: Frame, dst: &mut BytesMut) -> Result<(), Self::Error> {
+        if frame.command == Command::Heartbeat {
+            dst.put_u8(b'\n');
+            return Ok(());
+        }
+
+        dst.put_slice(frame.command.as_str().as_bytes());
+        dst.put_u8(b'\n');
+
+        for (name, value) in &frame.headers {
+            match frame.command.escapes_headers() {
+                true => {
+                    escape(name, dst);
+                    dst.put_u8(b':');
+                    escape(value, dst);
+                }
+                false => {
+                    dst.put_slice(name.as_bytes());
+                    dst.put_u8(b':');
+                    dst.put_slice(value.as_bytes());
+                }
+            }
+            dst.put_u8(b'\n');
+        }
+
+        if !frame.body.is_empty() && frame.header("content-length").is_none() {
+            dst.put_slice(format!("content-length:{}\n", frame.body.len()).as_bytes());
+        }
+
+        dst.put_u8(b'\n');
+        dst.put_slice(&frame.body);
+        dst.put_u8(0);
+
+        Ok(())
+    }
+}
+
+/// Returns the length of the command and header lines, without the blank line ending them, and
+/// the position the body starts at.
+fn find_head_end(src: &[u8]) -> Option<(usize, usize)> {
+    let mut line_start = 0;
+    for (i, byte) in src.iter().enumerate() {
+        if *byte != b'\n' {
+            continue;
+        }
+
+        let line = &src[line_start..i];
+        if line_start > 0 && (line.is_empty() || line == b"\r") {
+            return Some((line_start - 1, i + 1));
+        }
+
+        line_start = i + 1;
+    }
+
+    None
+}
+
+fn escape(value: &str, dst: &mut BytesMut) {
+    for c in value.chars() {
+        match c {
+            '\\' => dst.put_slice(b"\\\\"),
+            '\n' => dst.put_slice(b"\\n"),
+            '\r' => dst.put_slice(b"\\r"),
+            ':' => dst.put_slice(b"\\c"),
+            c => dst.put_slice(c.encode_utf8(&mut [0; 4]).as_bytes()),
+        }
+    }
+}
+
+fn unescape(value: &str) -> Result<String, Error> {
+    let mut unescaped = String::with_capacity(value.len());
+    let mut chars = value.chars();
+
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            unescaped.push(c);
+            continue;
+        }
+
+        unescaped.push(match chars.next() {
+            Some('\\') => '\\',
+            Some('n') => '\n',
+            Some('r') => '\r',
+            Some('c') => ':',
+            _ => return Err(invalid("undefined escape sequence in header")),
+        });
+    }
+
+    Ok(unescaped)
+}
+
+fn invalid(message: &str) -> Error {
+    Error::new(ErrorKind::InvalidData, message.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use bytes::Bytes;
+
+    use super::*;
+
+    fn decode_all(codec: &mut STOMPCodec, buf: &mut BytesMut) -> Vec<Frame> {
+        let mut frames = vec![];
+        while let Some(frame) = codec.decode(buf).unwrap() {
+            frames.push(frame);
+        }
+
+        frames
+    }
+
+    #[test]
+    fn test_frame_round_trip() {
+        let frames = vec![
+            Frame::new(Command::Send)
+                .with_header("destination", "/queue/orders")
+                .with_header("note", "a:b\\c\nd")
+                .with_body(Bytes::from_static(b"with\0nul")),
+            Frame::heartbeat(),
+            Frame::new(Command::Connect).with_header("login", "a:b"),
+            Frame::new(Command::Disconnect),
+        ];
+
+        let mut codec = STOMPCodec::new();
+        let mut buf = BytesMut::new();
+        for frame in frames.clone() {
+            codec.encode(frame, &mut buf).unwrap();
+        }
+
+        let decoded = decode_all(&mut codec, &mut buf);
+
+        assert_eq!(Some("a:b\\c\nd"), decoded[0].header("note"));
+        assert_eq!(Some("8"), decoded[0].header("content-length"));
+        assert_eq!(frames[0].body, decoded[0].body);
+        assert_eq!(frames[1..], decoded[1..]);
+    }
+
+    #[test]
+    fn test_decode_without_content_length() {
+        let mut codec = STOMPCodec::new();
+        let mut buf = BytesMut::from(&b"SEND\r\ndestination:a\r\ndestination:b\r\n\r\nhello\0\n\nSEND"[..]);
+
+        let frames = decode_all(&mut codec, &mut buf);
+
+        assert_eq!(3, frames.len());
+        assert_eq!(Some("a"), frames[0].header("destination"));
+        assert_eq!(Bytes::from_static(b"hello"), frames[0].body);
+        assert_eq!(vec![Frame::heartbeat(), Frame::heartbeat()], frames[1..]);
+        assert_eq!(&b"SEND"[..], &buf[..]);
+    }
+
+    #[test]
+    fn test_decode_waits_for_whole_frame() {
+        let mut codec = STOMPCodec::new();
+        let mut buf = BytesMut::from(&b"SEND\ncontent-length:5\n\nhel"[..]);
+        assert_eq!(None, codec.decode(&mut buf).unwrap());
+
+        buf.extend_from_slice(b"lo\0");
+        let frame = codec.decode(&mut buf).unwrap().unwrap();
+        assert_eq!(Bytes::from_static(b"hello"), frame.body);
+        assert!(buf.is_empty());
+    }
+
+    #[test]
+    fn test_decode_rejects_malformed_frames() {
+        let cases: [&[u8]; 4] = [
+            b"PUBLISH\n\n\0",
+            b"SEND\nno-colon\n\n\0",
+            b"SEND\nbad:\\t\n\n\0",
+            b"SEND\ncontent-length:2\n\nabc\0",
+        ];
+
+        for case in cases {
+            let mut codec = STOMPCodec::new();
+            assert!(codec.decode(&mut BytesMut::from(case)).is_err(), "{:?}", case);
+        }
+
+        let mut codec = STOMPCodec::new().with_max_frame_size(16);
+        assert!(codec.decode(&mut BytesMut::from(&[b'a'; 17][..])).is_err());
+    }
+
+    #[test]
+    fn test_decode_rejects_oversized_content_length() {
+        for length in [usize::MAX, usize::MAX - 10, 1 << 40] {
+            let mut codec = STOMPCodec::new();
+            let mut buf = BytesMut::from(format!("SEND\ncontent-length:{}\n\n\0", length).as_bytes());
+
+            let err = codec.decode(&mut buf).unwrap_err();
+            assert_eq!(ErrorKind::InvalidData, err.kind());
+        }
+    }
+}
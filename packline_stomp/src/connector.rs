@@ -0,0 +1,838 @@
+use std::collections::{BTreeSet, HashMap};
+use std::io::{Error, ErrorKind};
+use std::net::SocketAddr;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex as StdMutex};
+use std::time::Duration;
+
+use async_trait::async_trait;
+use futures::stream::{SplitSink, StreamExt};
+use futures::SinkExt;
+use tokio::io::{AsyncRead, AsyncWrite};
+use tokio::sync::Mutex;
+use tokio::task::JoinHandle;
+use tokio::time::Interval;
+use tokio_util::codec::Framed;
+use tracing::{debug, info, warn};
+
+use packline_core::app::acl::{Operation, Resource, ANONYMOUS};
+use packline_core::app::channel::{consumer_group_id, Channel, Record};
+use packline_core::app::{App, ChannelConfig};
+use packline_core::connector::{TCPConnectionHandler, TCPConnectorHandler, TCPStream};
+use packline_flow::auth::CredentialStore;
+
+use crate::codec::STOMPCodec;
+use crate::frame::{Command, Frame, VERSION};
+
+/// Time a client has to send CONNECT after opening the connection.
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Shortest interval heart-beats are sent and expected at, in milliseconds.
+const HEARTBEAT_INTERVAL: u64 = 10_000;
+
+/// Time records are collected for before being delivered to subscribers.
+const DELIVERY_LINGER: Duration = Duration::from_millis(5);
+
+/// Prefix of the destinations whose subscribers share a consumer group.
+const QUEUE_PREFIX: &str = "/queue/";
+
+/// Prefix of the destinations whose subscribers each receive every message.
+const TOPIC_PREFIX: &str = "/topic/";
+
+/// Serves STOMP 1.2 clients, mapping destinations onto app channels.
+///
+/// A destination names the channel of the same name, without its `/queue/` or `/topic/` prefix.
+/// Sending to or subscribing to a destination that has no channel yet creates it with a single
+/// partition.
+///
+/// Subscriptions to `/queue/` destinations share the consumer group `stomp/<channel>`: their
+/// subscribers compete for the messages and resume from the last one acknowledged. Subscriptions
+/// to other destinations get a consumer group of their own and receive the messages sent after
+/// they subscribed.
+///
+/// Messages of `client` and `client-individual` subscriptions that aren't acknowledged when they
+/// are unsubscribed, or that are nacked, are delivered again. Transactions aren't supported.
+pub struct STOMPConnector {
+    pub app: App,
+
+    /// Requires clients to log in with a login and passcode from the store when set. Otherwise
+    /// clients act as the anonymous principal whatever login they send.
+    pub credentials: Option<Arc<CredentialStore>>,
+
+    next_connection_id: Arc<AtomicU64>,
+}
+
+impl STOMPConnector {
+    pub fn new(app: App) -> STOMPConnector {
+        STOMPConnector {
+            app,
+            credentials: None,
+            next_connection_id: Arc::new(AtomicU64::new(0)),
+        }
+    }
+
+    pub fn with_credentials(mut self, credentials: CredentialStore) -> STOMPConnector {
+        self.credentials = Some(Arc::new(credentials));
+        self
+    }
+
+    /// Creates the handler running STOMP over `stream`. `peer` identifies the remote end in logs.
+    pub fn connection_handler<S: STOMPStream>(&self, stream: S, peer: String) -> STOMPConnectionHandler<S> {
+        STOMPConnectionHandler {
+            app: self.app.clone(),
+            peer,
+            stream: Some(stream),
+            credentials: self.credentials.clone(),
+            connection_id: self.next_connection_id.fetch_add(1, Ordering::Relaxed),
+        }
+    }
+}
+
+#[async_trait]
+impl TCPConnectorHandler for STOMPConnector {
+    fn handle_connection(&self, conn: (TCPStream, SocketAddr)) -> Box<dyn TCPConnectionHandler> {
+        Box::new(self.connection_handler(conn.0, conn.1.to_string()))
+    }
+
+    fn name(&self) -> &'static str {
+        "stomp"
+    }
+}
+
+/// Byte stream STOMP can run on.
+pub trait STOMPStream: AsyncRead + AsyncWrite + Send + Sync + Unpin + 'static {}
+
+impl<T: AsyncRead + AsyncWrite + Send + Sync + Unpin + 'static> STOMPStream for T {}
+
+pub struct STOMPConnectionHandler<S: STOMPStream> {
+    app: App,
+    peer: String,
+    stream: Option<S>,
+
+    credentials: Option<Arc<CredentialStore>>,
+    connection_id: u64,
+}
+
+type STOMPSink<S> = Arc<Mutex<SplitSink<Framed<S, STOMPCodec>, Frame>>>;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum AckMode {
+    Auto,
+
+    /// An ACK acknowledges the message and every message delivered before it.
+    Client,
+
+    /// An ACK only acknowledges the message it names.
+    ClientIndividual,
+}
+
+struct Subscription {
+    channel: Channel,
+    group: u128,
+    ack: AckMode,
+    pending: Arc<StdMutex<Pending>>,
+    task: JoinHandle<()>,
+}
+
+/// Messages of a subscription delivered and not acknowledged yet.
+#[derive(Default)]
+struct Pending {
+    outstanding: BTreeSet<u64>,
+
+    /// Offset following the last message delivered.
+    next_offset: u64,
+}
+
+struct ConnectionState<S: STOMPStream> {
+    app: App,
+    principal: String,
+    connection_id: u64,
+
+    sink: STOMPSink<S>,
+    subscriptions: HashMap<String, Subscription>,
+}
+
+#[async_trait]
+impl<S: STOMPStream> TCPConnectionHandler for STOMPConnectionHandler<S> {
+    async fn handle(&mut self) -> Result<(), Error> {
+        debug!("New STOMP Connection: {}", self.peer);
+
+        let mut framed = Framed::new(self.stream.take().unwrap(), STOMPCodec::new());
+        let connect = loop {
+            match tokio::time::timeout(CONNECT_TIMEOUT, framed.next()).await {
+                Ok(Some(Ok(frame))) if frame.command == Command::Heartbeat => continue,
+                Ok(Some(Ok(frame))) if matches!(frame.command, Command::Connect | Command::Stomp) => break frame,
+                Ok(Some(Ok(frame))) => {
+                    let message = format!("expected CONNECT, received {}", frame.command);
+                    let _ = framed.send(error_frame(&message, &frame)).await;
+                    return Ok(());
+                }
+                Ok(Some(Err(e))) => {
+                    debug!("Failed to read CONNECT from {}: {}", self.peer, e);
+                    return Ok(());
+                }
+                _ => {
+                    debug!("{} didn't start with CONNECT", self.peer);
+                    return Ok(());
+                }
+            }
+        };
+
+        let principal = match self.accept(&connect).await {
+            Ok(principal) => principal,
+            Err(message) => {
+                debug!("Refused STOMP connection from {}: {}", self.peer, message);
+                let _ = framed
+                    .send(error_frame(&message, &connect).with_header("version", VERSION))
+                    .await;
+                return Ok(());
+            }
+        };
+
+        // Heart-beats are sent at the slowest of what the client wants and what's offered.
+        let (client_sends, client_wants) = heartbeat(&connect);
+        let send_interval = (client_wants > 0).then(|| client_wants.max(HEARTBEAT_INTERVAL));
+        let receive_interval = (client_sends > 0).then(|| client_sends.max(HEARTBEAT_INTERVAL));
+
+        let connected = Frame::new(Command::Connected)
+            .with_header("version", VERSION)
+            .with_header("server", concat!("packline/", env!("CARGO_PKG_VERSION")))
+            .with_header("session", self.connection_id.to_string())
+            .with_header("heart-beat", format!("{},{}", HEARTBEAT_INTERVAL, HEARTBEAT_INTERVAL));
+        framed.send(connected).await?;
+
+        info!("STOMP client {} connected from {}", principal, self.peer);
+
+        let (sink, mut stream) = framed.split();
+        let mut state = ConnectionState {
+            app: self.app.clone(),
+            principal,
+            connection_id: self.connection_id,
+            sink: Arc::new(Mutex::new(sink)),
+            subscriptions: HashMap::new(),
+        };
+
+        let mut heartbeats = send_interval.map(|interval| tokio::time::interval(Duration::from_millis(interval)));
+
+        loop {
+            // Clients have two heart-beat intervals to send something.
+            let next = async {
+                match receive_interval {
+                    None => Some(stream.next().await),
+                    Some(interval) => tokio::time::timeout(Duration::from_millis(interval * 2), stream.next())
+                        .await
+                        .ok(),
+                }
+            };
+
+            let frame = tokio::select! {
+                frame = next => frame,
+                _ = tick(&mut heartbeats) => {
+                    match state.send(Frame::heartbeat()).await {
+                        Ok(()) => continue,
+                        Err(_) => break,
+                    }
+                }
+            };
+
+            let frame = match frame {
+                None => {
+                    debug!("STOMP client {} heart-beat expired", state.principal);
+                    break;
+                }
+                Some(None) => break,
+                Some(Some(Err(e))) => {
+                    debug!("Failed to read from STOMP connection: {}", e);
+                    let _ = state
+                        .send(Frame::new(Command::Error).with_header("message", e.to_string()))
+                        .await;
+                    break;
+                }
+                Some(Some(Ok(frame))) => frame,
+            };
+
+            match state.handle_frame(&frame).await {
+                Ok(true) => {}
+                Ok(false) => break,
+                Err(e) => {
+                    warn!("Closing STOMP connection of {}: {}", state.principal, e);
+                    let _ = state.send(error_frame(&e.to_string(), &frame)).await;
+                    break;
+                }
+            }
+        }
+
+        for (_, subscription) in state.subscriptions.drain() {
+            subscription.cancel().await;
+        }
+
+        debug!("STOMP connection finished");
+        Ok(())
+    }
+}
+
+impl<S: STOMPStream> STOMPConnectionHandler<S> {
+    /// Checks the CONNECT frame, returning the principal the connection acts as.
+    async fn accept(&self, connect: &Frame) -> Result<String, String> {
+        // Clients not sending the versions they accept only speak STOMP 1.0.
+        let versions = connect.header("accept-version").unwrap_or("1.0");
+        if !versions.split(',').any(|version| version.trim() == VERSION) {
+            return Err(format!("supported protocol versions are {}", VERSION));
+        }
+
+        let credentials = match &self.credentials {
+            Some(credentials) => credentials,
+            None => return Ok(ANONYMOUS.to_string()),
+        };
+
+        match (connect.header("login"), connect.header("passcode")) {
+            (Some(login), Some(passcode)) if credentials.verify(login, passcode).await => Ok(login.to_string()),
+            _ => Err("login refused".to_string()),
+        }
+    }
+}
+
+impl<S: STOMPStream> ConnectionState<S> {
+    /// Handles a frame from the client. Returns false when the client disconnected.
+    async fn handle_frame(&mut self, frame: &Frame) -> Result<bool, Error> {
+        if frame.header("transaction").is_some() {
+            return Err(invalid("transactions aren't supported"));
+        }
+
+        match frame.command {
+            Command::Heartbeat => return Ok(true),
+            Command::Send => self.handle_send(frame).await?,
+            Command::Subscribe => self.handle_subscribe(frame).await?,
+            Command::Unsubscribe => {
+                let id = required(frame, "id")?;
+                let subscription = self
+                    .subscriptions
+                    .remove(id)
+                    .ok_or_else(|| invalid(&format!("no subscription {}", id)))?;
+
+                subscription.cancel().await;
+            }
+            Command::Ack => self.handle_ack(frame, false).await?,
+            Command::Nack => self.handle_ack(frame, true).await?,
+            Command::Disconnect => {
+                self.receipt(frame).await?;
+                return Ok(false);
+            }
+            Command::Begin | Command::Commit | Command::Abort => {
+                return Err(invalid("transactions aren't supported"));
+            }
+            command => return Err(invalid(&format!("unexpected {} frame", command))),
+        }
+
+        self.receipt(frame).await?;
+        Ok(true)
+    }
+
+    async fn handle_send(&self, frame: &Frame) -> Result<(), Error> {
+        let destination = required(frame, "destination")?;
+        let name = channel_name(destination)?;
+
+        if !self
+            .app
+            .authorize(&self.principal, &Resource::topic(name), Operation::Produce)
+        {
+            return Err(not_allowed(Operation::Produce, name));
+        }
+
+        let channel = self.channel(name).await?;
+        channel
+            .producer()
+            .produce(&mut vec![Record::from_value(frame.body.to_vec())])
+            .await;
+
+        Ok(())
+    }
+
+    async fn handle_subscribe(&mut self, frame: &Frame) -> Result<(), Error> {
+        let id = required(frame, "id")?;
+        let destination = required(frame, "destination")?;
+        let name = channel_name(destination)?;
+
+        let ack = match frame.header("ack").unwrap_or("auto") {
+            "auto" => AckMode::Auto,
+            "client" => AckMode::Client,
+            "client-individual" => AckMode::ClientIndividual,
+            ack => return Err(invalid(&format!("unknown ack mode {}", ack))),
+        };
+
+        if self.subscriptions.contains_key(id) {
+            return Err(invalid(&format!("subscription {} already exists", id)));
+        }
+
+        if !self
+            .app
+            .authorize(&self.principal, &Resource::topic(name), Operation::Consume)
+        {
+            return Err(not_allowed(Operation::Consume, name));
+        }
+
+        let channel = self.channel(name).await?;
+        let group = match destination.starts_with(QUEUE_PREFIX) {
+            true => channel.register_group(&format!("stomp/{}", name)),
+            false => {
+                let group = consumer_group_id(&format!("stomp/{}/{}", self.connection_id, id));
+                let _ = channel.seek(group, channel.end_offset()).await;
+                group
+            }
+        };
+
+        let pending = Arc::new(StdMutex::new(Pending {
+            outstanding: BTreeSet::new(),
+            next_offset: channel.offsets(group).await.1,
+        }));
+
+        let task = tokio::spawn(deliver(
+            channel.clone(),
+            group,
+            id.to_string(),
+            destination.to_string(),
+            ack,
+            pending.clone(),
+            self.sink.clone(),
+        ));
+
+        debug!(
+            "STOMP client {} subscribed to {} as {}",
+            self.principal, destination, id
+        );
+
+        self.subscriptions.insert(
+            id.to_string(),
+            Subscription {
+                channel,
+                group,
+                ack,
+                pending,
+                task,
+            },
+        );
+
+        Ok(())
+    }
+
+    /// Acknowledges or rejects the message named by the `id` header, the `ack` header of its
+    /// MESSAGE frame. Rejected messages are delivered again, along with the ones delivered after.
+    async fn handle_ack(&mut self, frame: &Frame, nack: bool) -> Result<(), Error> {
+        let id = required(frame, "id")?;
+        let (offset, subscription_id) = id
+            .split_once(':')
+            .and_then(|(offset, subscription)| Some((offset.parse::<u64>().ok()?, subscription)))
+            .ok_or_else(|| invalid(&format!("unknown message {}", id)))?;
+
+        let subscription = self
+            .subscriptions
+            .get(subscription_id)
+            .filter(|subscription| subscription.ack != AckMode::Auto)
+            .ok_or_else(|| invalid(&format!("unknown message {}", id)))?;
+
+        let committed = {
+            let mut pending = subscription.pending.lock().unwrap();
+            if !pending.outstanding.contains(&offset) {
+                return Err(invalid(&format!("unknown message {}", id)));
+            }
+
+            match (nack, subscription.ack) {
+                (true, _) => {
+                    pending.outstanding.split_off(&offset);
+                    pending.next_offset = offset;
+                }
+                (false, AckMode::Client) => pending.outstanding = pending.outstanding.split_off(&(offset + 1)),
+                (false, _) => {
+                    pending.outstanding.remove(&offset);
+                }
+            }
+
+            pending.outstanding.first().copied().unwrap_or(pending.next_offset)
+        };
+
+        if nack {
+            let _ = subscription.channel.seek(subscription.group, offset).await;
+        }
+
+        let _ = subscription.channel.commit_offset(subscription.group, committed).await;
+        Ok(())
+    }
+
+    /// Returns the channel of `name`, creating it when needed.
+    async fn channel(&self, name: &str) -> Result<Channel, Error> {
+        if let Some(channel) = self.app.get_channel(&(name.to_string(), 1)).await {
+            return Ok(channel);
+        }
+
+        if !self
+            .app
+            .authorize(&self.principal, &Resource::topic(name), Operation::Create)
+        {
+            return Err(not_allowed(Operation::Create, name));
+        }
+
+        // Another connection may have created it in the meantime, which is fine.
+        let _ = self
+            .app
+            .create_channel(ChannelConfig {
+                name: name.to_string(),
+                partitions: 1,
+            })
+            .await;
+
+        self.app
+            .get_channel(&(name.to_string(), 1))
+            .await
+            .ok_or_else(|| Error::other(format!("failed to create channel {}", name)))
+    }
+
+    async fn receipt(&self, frame: &Frame) -> Result<(), Error> {
+        match frame.header("receipt") {
+            Some(receipt) => {
+                self.send(Frame::new(Command::Receipt).with_header("receipt-id", receipt))
+                    .await
+            }
+            None => Ok(()),
+        }
+    }
+
+    async fn send(&self, frame: Frame) -> Result<(), Error> {
+        self.sink.lock().await.send(frame).await
+    }
+}
+
+impl Subscription {
+    /// Stops delivering, moving the consumer group back to the first message not acknowledged so
+    /// that it's delivered again.
+    async fn cancel(self) {
+        self.task.abort();
+
+        let first = self.pending.lock().unwrap().outstanding.first().copied();
+        if let Some(offset) = first {
+            let _ = self.channel.seek(self.group, offset).await;
+        }
+    }
+}
+
+async fn deliver<S: STOMPStream>(
+    channel: Channel,
+    group: u128,
+    subscription: String,
+    destination: String,
+    ack: AckMode,
+    pending: Arc<StdMutex<Pending>>,
+    sink: STOMPSink<S>,
+) {
+    let consumer = channel.consumer(group).with_timeout(DELIVERY_LINGER);
+
+    loop {
+        let records = consumer.consume().await;
+        let next_offset = match records.last() {
+            Some(record) => record.offset + 1,
+            None => continue,
+        };
+
+        let mut sink = sink.lock().await;
+        for record in records {
+            let mut message = Frame::new(Command::Message)
+                .with_header("subscription", subscription.as_str())
+                .with_header("message-id", record.offset.to_string())
+                .with_header("destination", destination.as_str());
+
+            if ack != AckMode::Auto {
+                let mut pending = pending.lock().unwrap();
+                pending.outstanding.insert(record.offset);
+                pending.next_offset = record.offset + 1;
+
+                message = message.with_header("ack", format!("{}:{}", record.offset, subscription));
+            }
+
+            if sink.feed(message.with_body(record.value)).await.is_err() {
+                return;
+            }
+        }
+
+        if sink.flush().await.is_err() {
+            return;
+        }
+
+        if ack == AckMode::Auto {
+            let _ = channel.commit_offset(group, next_offset).await;
+        }
+    }
+}
+
+async fn tick(heartbeats: &mut Option<Interval>) {
+    match heartbeats {
+        Some(interval) => {
+            interval.tick().await;
+        }
+        None => std::future::pending().await,
+    }
+}
+
+/// Returns the intervals, in milliseconds, the client can send heart-beats at and wants to receive
+/// them at. Zero means none.
+fn heartbeat(connect: &Frame) -> (u64, u64) {
+    connect
+        .header("heart-beat")
+        .and_then(|heartbeat| heartbeat.split_once(','))
+        .and_then(|(sends, wants)| Some((sends.trim().parse().ok()?, wants.trim().parse().ok()?)))
+        .unwrap_or((0, 0))
+}
+
+fn channel_name(destination: &str) -> Result<&str, Error> {
+    let name = destination
+        .strip_prefix(QUEUE_PREFIX)
+        .or_else(|| destination.strip_prefix(TOPIC_PREFIX))
+        .unwrap_or(destination);
+
+    match name.is_empty() {
+        true => Err(invalid(&format!("invalid destination {:?}", destination))),
+        false => Ok(name),
+    }
+}
+
+fn required<'a>(frame: &'a Frame, header: &str) -> Result<&'a str, Error> {
+    frame
+        .header(header)
+        .ok_or_else(|| invalid(&format!("missing {} header", header)))
+}
+
+fn error_frame(message: &str, cause: &Frame) -> Frame {
+    let mut error = Frame::new(Command::Error).with_header("message", message);
+    if let Some(receipt) = cause.header("receipt") {
+        error = error.with_header("receipt-id", receipt);
+    }
+
+    error
+}
+
+fn invalid(message: &str) -> Error {
+    Error::new(ErrorKind::InvalidData, message.to_string())
+}
+
+fn not_allowed(operation: Operation, topic: &str) -> Error {
+    Error::new(
+        ErrorKind::PermissionDenied,
+        format!("not allowed to {} {}", operation, topic),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use bytes::Bytes;
+    use tokio::io::DuplexStream;
+
+    use super::*;
+
+    type Client = Framed<DuplexStream, STOMPCodec>;
+
+    fn open(connector: &STOMPConnector) -> Client {
+        let (client, server) = tokio::io::duplex(64 * 1024);
+        let mut handler = connector.connection_handler(server, "test".to_string());
+        tokio::spawn(async move { handler.handle().await });
+
+        Framed::new(client, STOMPCodec::new())
+    }
+
+    async fn connect(connector: &STOMPConnector, connect: Frame) -> (Client, Frame) {
+        let mut client = open(connector);
+        client.send(connect).await.unwrap();
+
+        let frame = next(&mut client).await.expect("connection closed");
+        (client, frame)
+    }
+
+    fn connect_frame() -> Frame {
+        Frame::new(Command::Connect)
+            .with_header("accept-version", "1.1,1.2")
+            .with_header("host", "localhost")
+    }
+
+    async fn next(client: &mut Client) -> Option<Frame> {
+        tokio::time::timeout(Duration::from_secs(2), client.next())
+            .await
+            .expect("timed out waiting for a frame")
+            .map(|frame| frame.unwrap())
+    }
+
+    /// Sends a frame and waits for its receipt.
+    async fn call(client: &mut Client, frame: Frame) {
+        client.send(frame.with_header("receipt", "r")).await.unwrap();
+
+        let receipt = next(client).await.unwrap();
+        assert_eq!(Command::Receipt, receipt.command, "{:?}", receipt);
+        assert_eq!(Some("r"), receipt.header("receipt-id"));
+    }
+
+    fn send(destination: &str, body: &'static [u8]) -> Frame {
+        Frame::new(Command::Send)
+            .with_header("destination", destination)
+            .with_body(Bytes::from_static(body))
+    }
+
+    fn subscribe(id: &str, destination: &str, ack: &str) -> Frame {
+        Frame::new(Command::Subscribe)
+            .with_header("id", id)
+            .with_header("destination", destination)
+            .with_header("ack", ack)
+    }
+
+    async fn message(client: &mut Client) -> Frame {
+        let frame = next(client).await.unwrap();
+        assert_eq!(Command::Message, frame.command, "{:?}", frame);
+
+        frame
+    }
+
+    #[tokio::test]
+    async fn test_send_and_subscribe() {
+        let connector = STOMPConnector::new(App::new());
+
+        let (mut client, connected) = connect(&connector, connect_frame()).await;
+        assert_eq!(Command::Connected, connected.command);
+        assert_eq!(Some(VERSION), connected.header("version"));
+
+        call(&mut client, subscribe("0", "/topic/orders", "auto")).await;
+        call(&mut client, send("/topic/orders", b"first")).await;
+
+        let message = message(&mut client).await;
+        assert_eq!(Some("0"), message.header("subscription"));
+        assert_eq!(Some("/topic/orders"), message.header("destination"));
+        assert_eq!(None, message.header("ack"));
+        assert_eq!(Bytes::from_static(b"first"), message.body);
+
+        let channel = connector.app.get_channel(&("orders".to_string(), 1)).await.unwrap();
+        assert_eq!(1, channel.end_offset());
+    }
+
+    #[tokio::test]
+    async fn test_topic_subscribers_receive_only_new_messages() {
+        let connector = STOMPConnector::new(App::new());
+        let (mut publisher, _) = connect(&connector, connect_frame()).await;
+        call(&mut publisher, send("/topic/orders", b"old")).await;
+
+        let (mut first, _) = connect(&connector, connect_frame()).await;
+        let (mut second, _) = connect(&connector, connect_frame()).await;
+        call(&mut first, subscribe("a", "/topic/orders", "auto")).await;
+        call(&mut second, subscribe("b", "/topic/orders", "auto")).await;
+
+        call(&mut publisher, send("/topic/orders", b"new")).await;
+
+        assert_eq!(Bytes::from_static(b"new"), message(&mut first).await.body);
+        assert_eq!(Bytes::from_static(b"new"), message(&mut second).await.body);
+    }
+
+    #[tokio::test]
+    async fn test_unacknowledged_queue_messages_are_delivered_again() {
+        let connector = STOMPConnector::new(App::new());
+        let (mut client, _) = connect(&connector, connect_frame()).await;
+
+        call(&mut client, send("/queue/jobs", b"first")).await;
+        call(&mut client, send("/queue/jobs", b"second")).await;
+        call(&mut client, subscribe("0", "/queue/jobs", "client-individual")).await;
+
+        let first = message(&mut client).await;
+        let second = message(&mut client).await;
+        assert_eq!(Bytes::from_static(b"second"), second.body);
+
+        let ack = Frame::new(Command::Ack).with_header("id", second.header("ack").unwrap());
+        call(&mut client, ack).await;
+
+        // Only the message not acknowledged is delivered to the next subscriber.
+        call(&mut client, Frame::new(Command::Disconnect)).await;
+        assert_eq!(None, next(&mut client).await);
+
+        let (mut client, _) = connect(&connector, connect_frame()).await;
+        call(&mut client, subscribe("0", "/queue/jobs", "client")).await;
+
+        let again = message(&mut client).await;
+        assert_eq!(first.header("message-id"), again.header("message-id"));
+        assert_eq!(Bytes::from_static(b"first"), again.body);
+    }
+
+    #[tokio::test]
+    async fn test_nack_delivers_message_again() {
+        let connector = STOMPConnector::new(App::new());
+        let (mut client, _) = connect(&connector, connect_frame()).await;
+
+        call(&mut client, subscribe("0", "/queue/jobs", "client")).await;
+        call(&mut client, send("/queue/jobs", b"job")).await;
+
+        let message = message(&mut client).await;
+        let nack = Frame::new(Command::Nack).with_header("id", message.header("ack").unwrap());
+        client.send(nack).await.unwrap();
+
+        let again = self::message(&mut client).await;
+        assert_eq!(Bytes::from_static(b"job"), again.body);
+        assert_eq!(message.header("ack"), again.header("ack"));
+
+        let ack = Frame::new(Command::Ack).with_header("id", again.header("ack").unwrap());
+        call(&mut client, ack).await;
+
+        let channel = connector.app.get_channel(&("jobs".to_string(), 1)).await.unwrap();
+        assert_eq!(Some(1), channel.offsets(consumer_group_id("stomp/jobs")).await.0);
+    }
+
+    #[tokio::test]
+    async fn test_errors_close_the_connection() {
+        let connector = STOMPConnector::new(App::new());
+        let (mut client, _) = connect(&connector, connect_frame()).await;
+
+        let ack = Frame::new(Command::Ack).with_header("id", "7:missing");
+        client.send(ack.with_header("receipt", "r")).await.unwrap();
+
+        let error = next(&mut client).await.unwrap();
+        assert_eq!(Command::Error, error.command);
+        assert_eq!(Some("r"), error.header("receipt-id"));
+        assert_eq!(None, next(&mut client).await);
+    }
+
+    #[tokio::test]
+    async fn test_connect_checks_version_and_credentials() {
+        let mut store = CredentialStore::new();
+        store.add_user("alice", "secret").await;
+        let connector = STOMPConnector::new(App::new()).with_credentials(store);
+
+        let (_, error) = connect(&connector, Frame::new(Command::Connect).with_header("login", "alice")).await;
+        assert_eq!(Command::Error, error.command);
+        assert_eq!(Some(VERSION), error.header("version"));
+
+        let frame = connect_frame()
+            .with_header("login", "alice")
+            .with_header("passcode", "wrong");
+        let (_, error) = connect(&connector, frame).await;
+        assert_eq!(Command::Error, error.command);
+
+        let frame = connect_frame()
+            .with_header("login", "alice")
+            .with_header("passcode", "secret")
+            .with_header("heart-beat", "0,0");
+        let (_, connected) = connect(&connector, frame).await;
+        assert_eq!(Command::Connected, connected.command);
+    }
+
+    #[tokio::test]
+    async fn test_acl_denies_send() {
+        use packline_core::app::acl::{AclRule, Authorizer, Permission};
+
+        let authorizer = Authorizer::new();
+        authorizer
+            .add_rule(AclRule {
+                principal: ANONYMOUS.to_string(),
+                resource: Resource::topic("allowed"),
+                operation: Operation::Produce,
+                permission: Permission::Allow,
+            })
+            .unwrap();
+        let connector = STOMPConnector::new(App::with_authorizer(authorizer));
+        let (mut client, _) = connect(&connector, connect_frame()).await;
+
+        client.send(send("/queue/denied", b"job")).await.unwrap();
+
+        let error = next(&mut client).await.unwrap();
+        assert_eq!(Command::Error, error.command);
+        assert!(error.header("message").unwrap().contains("not allowed"));
+    }
+}
@@ -0,0 +1,128 @@
+use std::fmt::{Display, Formatter};
+
+use bytes::Bytes;
+
+/// Protocol version spoken by the connector.
+pub const VERSION: &str = "1.2";
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Command {
+    Connect,
+    Stomp,
+    Connected,
+    Send,
+    Subscribe,
+    Unsubscribe,
+    Ack,
+    Nack,
+    Begin,
+    Commit,
+    Abort,
+    Disconnect,
+    Message,
+    Receipt,
+    Error,
+
+    /// End of line sent between frames to keep the connection alive. Not a command of the
+    /// protocol, heart-beats are decoded as frames so that connections see them.
+    Heartbeat,
+}
+
+impl Command {
+    pub fn parse(command: &str) -> Option<Command> {
+        let command = match command {
+            "CONNECT" => Command::Connect,
+            "STOMP" => Command::Stomp,
+            "CONNECTED" => Command::Connected,
+            "SEND" => Command::Send,
+            "SUBSCRIBE" => Command::Subscribe,
+            "UNSUBSCRIBE" => Command::Unsubscribe,
+            "ACK" => Command::Ack,
+            "NACK" => Command::Nack,
+            "BEGIN" => Command::Begin,
+            "COMMIT" => Command::Commit,
+            "ABORT" => Command::Abort,
+            "DISCONNECT" => Command::Disconnect,
+            "MESSAGE" => Command::Message,
+            "RECEIPT" => Command::Receipt,
+            "ERROR" => Command::Error,
+            _ => return None,
+        };
+
+        Some(command)
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Command::Connect => "CONNECT",
+            Command::Stomp => "STOMP",
+            Command::Connected => "CONNECTED",
+            Command::Send => "SEND",
+            Command::Subscribe => "SUBSCRIBE",
+            Command::Unsubscribe => "UNSUBSCRIBE",
+            Command::Ack => "ACK",
+            Command::Nack => "NACK",
+            Command::Begin => "BEGIN",
+            Command::Commit => "COMMIT",
+            Command::Abort => "ABORT",
+            Command::Disconnect => "DISCONNECT",
+            Command::Message => "MESSAGE",
+            Command::Receipt => "RECEIPT",
+            Command::Error => "ERROR",
+            Command::Heartbeat => "",
+        }
+    }
+
+    /// Headers of CONNECT and CONNECTED frames aren't escaped, so that STOMP 1.0 peers can
+    /// negotiate the version.
+    pub fn escapes_headers(&self) -> bool {
+        !matches!(self, Command::Connect | Command::Connected)
+    }
+}
+
+impl Display for Command {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.as_str())
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Frame {
+    pub command: Command,
+
+    /// Headers in the order they were sent. A header can be repeated, the first occurrence is
+    /// the one that applies.
+    pub headers: Vec<(String, String)>,
+    pub body: Bytes,
+}
+
+impl Frame {
+    pub fn new(command: Command) -> Frame {
+        Frame {
+            command,
+            headers: vec![],
+            body: Bytes::new(),
+        }
+    }
+
+    pub fn heartbeat() -> Frame {
+        Frame::new(Command::Heartbeat)
+    }
+
+    pub fn with_header<V: Into<String>>(mut self, name: &str, value: V) -> Frame {
+        self.headers.push((name.to_string(), value.into()));
+        self
+    }
+
+    pub fn with_body<B: Into<Bytes>>(mut self, body: B) -> Frame {
+        self.body = body.into();
+        self
+    }
+
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|(header, _)| header == name)
+            .map(|(_, value)| value.as_str())
+    }
+}